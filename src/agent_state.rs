@@ -376,8 +376,8 @@ impl AgentStateDb {
         let mut tags = Vec::new();
         for result in iter {
             let (key, _) = result?;
-            if key.starts_with("tag:") {
-                tags.push(key[4..].to_string());
+            if let Some(tag) = key.strip_prefix("tag:") {
+                tags.push(tag.to_string());
             }
         }
         Ok(tags)
@@ -458,7 +458,7 @@ impl AgentStateDb {
         let mut latest: Option<SessionContext> = None;
         for result in iter {
             let (_, ctx) = result?;
-            if latest.as_ref().map_or(true, |l| ctx.started_at > l.started_at) {
+            if latest.as_ref().is_none_or(|l| ctx.started_at > l.started_at) {
                 latest = Some(ctx);
             }
         }
@@ -0,0 +1,147 @@
+// SPF Smart Gateway - Approval Queue
+// Copyright 2026 Joseph Stone - All Rights Reserved
+//
+// Calls whose tier is marked requires_approval do not run on first sight.
+// They are queued in SESSION.DB with an ID, the agent receives "PENDING <id>",
+// and a human approves or denies via `spf-smart-gate approvals`.
+// An approved call runs exactly once, and only with identical parameters.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Lifecycle of a held call
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApprovalStatus {
+    /// Waiting for a human decision
+    Pending,
+    /// Approved — next identical call may run once
+    Approved,
+    /// Denied — identical calls are refused
+    Denied,
+    /// Approved and already executed
+    Consumed,
+}
+
+/// A tool call held for human approval
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalRequest {
+    pub id: String,
+    pub tool: String,
+    pub args: Value,
+    /// sha256 over tool name + canonical arguments
+    pub fingerprint: String,
+    pub tier: String,
+    pub c: u64,
+    /// Gate message at the time the call was held
+    pub message: String,
+    pub status: ApprovalStatus,
+    pub created_at: DateTime<Utc>,
+    pub decided_at: Option<DateTime<Utc>>,
+}
+
+impl ApprovalRequest {
+    /// Create a new pending request for a tool call
+    pub fn new(tool: &str, args: &Value, tier: &str, c: u64, message: &str) -> Self {
+        let fingerprint = fingerprint(tool, args);
+        let created_at = Utc::now();
+
+        let mut hasher = Sha256::new();
+        hasher.update(fingerprint.as_bytes());
+        hasher.update(created_at.timestamp_nanos_opt().unwrap_or(0).to_le_bytes());
+        let id = hex::encode(hasher.finalize())[..10].to_string();
+
+        Self {
+            id,
+            tool: tool.to_string(),
+            args: args.clone(),
+            fingerprint,
+            tier: tier.to_string(),
+            c,
+            message: message.to_string(),
+            status: ApprovalStatus::Pending,
+            created_at,
+            decided_at: None,
+        }
+    }
+
+    /// One-line summary for CLI listings
+    pub fn summary(&self) -> String {
+        let args = self.args.to_string();
+        let args: String = if args.chars().count() > 120 {
+            format!("{}…", args.chars().take(120).collect::<String>())
+        } else {
+            args
+        };
+        format!(
+            "{}  {:<9} {:<8} C={:<8} {} {}",
+            self.id,
+            format!("{:?}", self.status).to_uppercase(),
+            self.tier,
+            self.c,
+            self.tool,
+            args
+        )
+    }
+}
+
+/// Stable fingerprint for a tool call.
+/// serde_json orders object keys, so argument order does not matter.
+pub fn fingerprint(tool: &str, args: &Value) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(tool.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(args.to_string().as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SpfStorage;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn fingerprint_ignores_key_order() {
+        let a = json!({"file_path": "/x", "content": "y"});
+        let b: Value = serde_json::from_str(r#"{"content": "y", "file_path": "/x"}"#).unwrap();
+        assert_eq!(fingerprint("spf_write", &a), fingerprint("spf_write", &b));
+    }
+
+    #[test]
+    fn fingerprint_differs_on_params_and_tool() {
+        let a = json!({"command": "ls"});
+        let b = json!({"command": "ls -la"});
+        assert_ne!(fingerprint("spf_bash", &a), fingerprint("spf_bash", &b));
+        assert_ne!(fingerprint("spf_bash", &a), fingerprint("spf_read", &a));
+    }
+
+    #[test]
+    fn queue_roundtrip_through_storage() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let storage = SpfStorage::open(dir.path())?;
+        let args = json!({"command": "git push --force"});
+
+        let req = ApprovalRequest::new("spf_bash", &args, "CRITICAL", 12345, "held");
+        assert_eq!(req.status, ApprovalStatus::Pending);
+        storage.put_approval(&req)?;
+
+        let found = storage.find_approval(&fingerprint("spf_bash", &args))?.unwrap();
+        assert_eq!(found.id, req.id);
+
+        let decided = storage.decide_approval(&req.id, ApprovalStatus::Approved)?.unwrap();
+        assert_eq!(decided.status, ApprovalStatus::Approved);
+        assert!(decided.decided_at.is_some());
+
+        assert_eq!(storage.list_approvals()?.len(), 1);
+        assert!(storage.get_approval("missing")?.is_none());
+        Ok(())
+    }
+}
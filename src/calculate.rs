@@ -19,6 +19,33 @@ pub struct ComplexityResult {
     pub requires_approval: bool,
 }
 
+/// Per-term breakdown of C = basic^p + deps^p + complex^p + files×m
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ComplexityTerms {
+    pub basic: u64,
+    pub dependencies: u64,
+    pub complex: u64,
+    pub files: u64,
+    /// basic ^ basic_power
+    pub basic_term: u64,
+    /// dependencies ^ deps_power
+    pub deps_term: u64,
+    /// complex ^ complex_power
+    pub complex_term: u64,
+    /// files × files_multiplier
+    pub files_term: u64,
+}
+
+impl ComplexityTerms {
+    /// Sum of all terms — the C value
+    pub fn c(&self) -> u64 {
+        self.basic_term
+            .saturating_add(self.deps_term)
+            .saturating_add(self.complex_term)
+            .saturating_add(self.files_term)
+    }
+}
+
 /// Input parameters for complexity calculation
 /// EXTENDED: Supports ALL tool types — brain, rag, glob, grep, web
 #[derive(Debug, Clone, Deserialize, Default)]
@@ -29,10 +56,14 @@ pub struct ToolParams {
     pub old_string: Option<String>,
     pub new_string: Option<String>,
     pub replace_all: Option<bool>,
+    // Patch — unified diff (spf_apply_patch)
+    pub patch: Option<String>,
     // Write
     pub content: Option<String>,
     // Bash
     pub command: Option<String>,
+    /// Directory the command runs in (shell session); None = gateway cwd
+    pub cwd: Option<String>,
     // Search (glob/grep)
     pub query: Option<String>,
     pub pattern: Option<String>,
@@ -54,6 +85,12 @@ pub struct ToolParams {
 // files×10: scales linearly with affected file count
 // ============================================================================
 
+/// Complex factor from dangerous-pattern severity (0-4 scale)
+/// 1-3 → 1, 4-6 → 2, 7-9 → 3, 10 → 4
+fn calc_severity_factor(severity: u8) -> u64 {
+    (severity as u64).div_ceil(3).min(4)
+}
+
 /// Calculate dynamic complexity factor (0-4 scale)
 /// This is the primary lever for tier escalation via ^10 exponent
 fn calc_complex_factor(content_len: u64, has_risk: bool, is_architectural: bool) -> u64 {
@@ -118,6 +155,11 @@ fn has_risk_indicators(content: &str) -> bool {
 
 /// Calculate complexity value C for a tool call
 pub fn calculate_c(tool: &str, params: &ToolParams, config: &SpfConfig) -> u64 {
+    calculate_terms(tool, params, config).c()
+}
+
+/// Calculate the individual terms of C for a tool call
+pub fn calculate_terms(tool: &str, params: &ToolParams, config: &SpfConfig) -> ComplexityTerms {
     let (basic, dependencies, complex_factor, files) = match tool {
         "Edit" | "spf_edit" => {
             let old_str = params.old_string.as_deref().unwrap_or("");
@@ -144,6 +186,31 @@ pub fn calculate_c(tool: &str, params: &ToolParams, config: &SpfConfig) -> u64 {
             (basic, deps, complex, files)
         }
 
+        "spf_apply_patch" => {
+            let stats = params.patch.as_deref().map(crate::patch::stats).unwrap_or_default();
+            let changed = stats.added + stats.removed;
+            let file_path = params.file_path.as_deref().unwrap_or("");
+
+            let basic = config.complexity_weights.edit.basic + changed * 2;
+
+            // Dependencies: every further hunk is another spot that must stay consistent
+            let deps = 1 + stats.hunks.saturating_sub(1).min(4);
+
+            // Complex factor: same size bands as string edits at ~40 bytes a line,
+            // risk words counted in the lines the patch adds
+            let added: String = params.patch.as_deref()
+                .and_then(|text| crate::patch::parse(text).ok())
+                .unwrap_or_default()
+                .iter()
+                .map(|file| file.added())
+                .collect();
+            let has_risk = has_risk_indicators(&added);
+            let is_arch = is_architectural_file(file_path);
+            let complex = calc_complex_factor(changed * 40, has_risk, is_arch);
+
+            (basic, deps, complex, stats.files.max(1))
+        }
+
         "Write" | "spf_write" => {
             let content = params.content.as_deref().unwrap_or("");
             let content_len = content.len() as u64;
@@ -166,11 +233,13 @@ pub fn calculate_c(tool: &str, params: &ToolParams, config: &SpfConfig) -> u64 {
             (basic, deps, complex, 1u64)
         }
 
-        "Bash" | "spf_bash" => {
+        "Bash" | "spf_bash" | "spf_bash_start" => {
             let cmd = params.command.as_deref().unwrap_or("");
 
-            // Check dangerous commands
-            let is_dangerous = config.dangerous_commands.iter().any(|d| cmd.contains(d.as_str()));
+            // Dangerous patterns, weighted by CONFIG.DB severity
+            let severity = config.command_severity(cmd);
+            let severity_complex = calc_severity_factor(severity);
+            let is_dangerous = severity > 0 && severity >= config.severity_thresholds.critical;
             // Check git operations
             let is_git = cmd.contains("git push") || cmd.contains("git reset")
                 || cmd.contains("git rebase") || cmd.contains("git merge");
@@ -187,19 +256,19 @@ pub fn calculate_c(tool: &str, params: &ToolParams, config: &SpfConfig) -> u64 {
             if is_dangerous {
                 let w = &config.complexity_weights.bash_dangerous;
                 // Dangerous = high complex factor
-                (w.basic, w.dependencies + pipe_count + chain_count, 3u64.max(w.complex), files)
+                (w.basic, w.dependencies + pipe_count + chain_count, severity_complex.max(w.complex), files)
             } else if is_git {
                 let w = &config.complexity_weights.bash_git;
                 // Git operations: complex=2 minimum (1024 added to C)
-                (w.basic, w.dependencies + pipe_count, 2u64.max(w.complex), files)
+                (w.basic, w.dependencies + pipe_count, 2u64.max(w.complex).max(severity_complex), files)
             } else if is_piped {
                 let w = &config.complexity_weights.bash_piped;
                 // Piped: complexity scales with pipe count
-                let complex = (1 + pipe_count).min(3);
+                let complex = (1 + pipe_count).min(3).max(severity_complex);
                 (w.basic, w.dependencies + pipe_count + chain_count, complex, files)
             } else {
                 let w = &config.complexity_weights.bash_simple;
-                (w.basic, w.dependencies, w.complex, files)
+                (w.basic, w.dependencies, w.complex.max(severity_complex), files)
             }
         }
 
@@ -265,9 +334,13 @@ pub fn calculate_c(tool: &str, params: &ToolParams, config: &SpfConfig) -> u64 {
         // === NOTEBOOK ===
         "notebook_edit" | "spf_notebook_edit" => (15, 2, 0, 1),
 
+        // === UNDO (restores earlier writes) ===
+        "spf_undo" => (15, 2, 0, 1),
+
         // === STATUS (low complexity) ===
         "status" | "spf_status" | "session" | "spf_session" |
-        "calculate" | "spf_calculate" => (5, 0, 0, 1),
+        "calculate" | "spf_calculate" | "explain" | "spf_explain" |
+        "spf_bash_output" | "spf_bash_kill" => (5, 0, 0, 1),
 
         // === UNKNOWN — default high for safety ===
         _ => {
@@ -278,12 +351,16 @@ pub fn calculate_c(tool: &str, params: &ToolParams, config: &SpfConfig) -> u64 {
 
     // Apply formula: C = (basic ^ power) + (deps ^ power) + (complex ^ power) + (files × mult)
     // HARDCODE: Saturating math prevents overflow — system never breaks
-    let c = basic.saturating_pow(config.formula.basic_power)
-        .saturating_add(dependencies.saturating_pow(config.formula.deps_power))
-        .saturating_add(complex_factor.saturating_pow(config.formula.complex_power))
-        .saturating_add(files.saturating_mul(config.formula.files_multiplier));
-
-    c
+    ComplexityTerms {
+        basic,
+        dependencies,
+        complex: complex_factor,
+        files,
+        basic_term: basic.saturating_pow(config.formula.basic_power),
+        deps_term: dependencies.saturating_pow(config.formula.deps_power),
+        complex_term: complex_factor.saturating_pow(config.formula.complex_power),
+        files_term: files.saturating_mul(config.formula.files_multiplier),
+    }
 }
 
 /// Apply master formula: a_optimal(C) = W_eff × (1 - 1/ln(C + e))
@@ -349,6 +426,21 @@ mod tests {
         assert!(result.c >= 10000);
     }
 
+    #[test]
+    fn severity_raises_complex_factor() {
+        let mut config = default_config();
+        config.dangerous_commands.push("killall".to_string());
+        config.dangerous_severity.insert("killall".to_string(), 4);
+        let params = ToolParams { command: Some("killall node".to_string()), ..Default::default() };
+        let terms = calculate_terms("spf_bash", &params, &config);
+        assert_eq!(terms.complex, 2);
+
+        config.dangerous_severity.insert("killall".to_string(), 1);
+        let terms = calculate_terms("spf_bash", &params, &config);
+        assert_eq!(terms.complex, 1);
+        assert_eq!(calculate("spf_bash", &params, &config).tier, "SIMPLE");
+    }
+
     #[test]
     fn status_tool_is_minimal_complexity() {
         let config = default_config();
@@ -368,6 +460,18 @@ mod tests {
         assert!(c >= 2000, "Unknown tool C={} should be >= 2000 (LIGHT+)", c);
     }
 
+    #[test]
+    fn terms_sum_to_c() {
+        let config = default_config();
+        let params = ToolParams::default();
+        let terms = calculate_terms("totally_unknown_tool", &params, &config);
+        assert_eq!(terms.basic_term, 20);
+        assert_eq!(terms.deps_term, 2187);
+        assert_eq!(terms.complex_term, 1);
+        assert_eq!(terms.files_term, 10);
+        assert_eq!(terms.c(), calculate_c("totally_unknown_tool", &params, &config));
+    }
+
     #[test]
     fn a_optimal_within_bounds() {
         let config = default_config();
@@ -392,4 +496,31 @@ mod tests {
         assert!(!has_risk_indicators("create a new file"));
         assert!(!has_risk_indicators("read the documentation"));
     }
+
+    #[test]
+    fn patch_complexity_follows_hunks_and_lines() {
+        let config = default_config();
+        let hunk = |at: usize, lines: usize| format!(
+            "@@ -{},{} +{},{} @@\n{}{}", at, lines, at, lines,
+            "-old\n".repeat(lines), "+new\n".repeat(lines)
+        );
+        let patch = |body: String| ToolParams {
+            file_path: Some("/w/notes.txt".to_string()),
+            patch: Some(format!("--- a/notes.txt\n+++ b/notes.txt\n{}", body)),
+            ..Default::default()
+        };
+        let small = calculate_terms("spf_apply_patch", &patch(hunk(1, 1)), &config);
+        let wide = calculate_terms("spf_apply_patch", &patch(hunk(1, 1) + &hunk(10, 1) + &hunk(20, 1)), &config);
+        let long = calculate_terms("spf_apply_patch", &patch(hunk(1, 40)), &config);
+        assert_eq!(small.dependencies, 1);
+        assert_eq!(wide.dependencies, 3);
+        assert!(long.basic > wide.basic && long.complex > small.complex);
+        assert!(small.c() < wide.c() && small.c() < long.c());
+        assert_eq!(calculate("spf_apply_patch", &patch(hunk(1, 1)), &config).tier, "SIMPLE");
+
+        // Risk words in added lines raise the complex factor; in removed lines they do not
+        let risky = calculate_terms("spf_apply_patch", &patch("@@ -1,1 +1,1 @@\n-old\n+sudo rm -rf build\n".to_string()), &config);
+        let removed = calculate_terms("spf_apply_patch", &patch("@@ -1,1 +1,1 @@\n-sudo rm -rf build\n+old\n".to_string()), &config);
+        assert!(risky.complex > removed.complex);
+    }
 }
@@ -3,23 +3,64 @@
 //
 // Loads SPF rules, tiers, formulas, blocked paths. Defaults stored in LMDB.
 
+use crate::pathmatch::{self, PathPrecedence, PathVerdict};
+use crate::session::Session;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::Path;
 
+/// Default dangerous command patterns and their severity (1-10)
+pub const DEFAULT_DANGEROUS_PATTERNS: &[(&str, u8)] = &[
+    ("rm -rf /", 10),
+    ("rm -rf ~", 10),
+    ("dd if=", 9),
+    ("> /dev/", 9),
+    ("chmod 777", 7),
+    ("curl | sh", 8),
+    ("wget | sh", 8),
+    ("curl|sh", 8),
+    ("wget|sh", 8),
+];
+
 /// Master SPF configuration loaded from CONFIG LMDB
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SpfConfig {
     pub version: String,
     pub enforce_mode: EnforceMode,
+    /// Path rules — prefixes, globs or `re:` regexes (see pathmatch.rs)
     pub allowed_paths: Vec<String>,
     pub blocked_paths: Vec<String>,
+    /// How allowed and blocked rules combine when both match
+    #[serde(default)]
+    pub path_precedence: PathPrecedence,
     pub require_read_before_edit: bool,
     pub max_write_size: usize,
     pub tiers: TierConfig,
     pub formula: FormulaConfig,
     pub complexity_weights: ComplexityWeights,
     pub dangerous_commands: Vec<String>,
+    /// Severity (1-10) per dangerous_commands pattern — unlisted patterns count as 10
+    #[serde(default)]
+    pub dangerous_severity: BTreeMap<String, u8>,
+    /// Which severities block, force CRITICAL, or only warn
+    #[serde(default)]
+    pub severity_thresholds: SeverityThresholds,
+    /// Cumulative complexity allowed per session
+    #[serde(default)]
+    pub session_budget: SessionBudget,
+    /// Environment passed to spawned commands
+    #[serde(default)]
+    pub exec_env: ExecEnv,
+    /// Response size budget per tool before output spills to a file
+    #[serde(default)]
+    pub output_limits: OutputLimits,
     pub git_force_patterns: Vec<String>,
+    /// Tool allowlist and ordered rules (CONFIG.DB "spf:policy")
+    #[serde(default)]
+    pub policy: crate::policy::Policy,
+    /// Policy under trial — evaluated in Audit mode only (CONFIG.DB "spf:policy_candidate")
+    #[serde(default)]
+    pub candidate_policy: Option<crate::policy::Policy>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -27,6 +68,209 @@ pub struct SpfConfig {
 pub enum EnforceMode {
     Soft,
     Max,
+    /// Max enforcement + shadow evaluation of the candidate policy
+    Audit,
+}
+
+/// Severity bands for dangerous command patterns (CONFIG.DB "spf:severity_thresholds")
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SeverityThresholds {
+    /// At or above: hard block
+    pub block: u8,
+    /// At or above (and below block): force CRITICAL tier + approval. Below: warn only.
+    pub critical: u8,
+}
+
+impl Default for SeverityThresholds {
+    fn default() -> Self {
+        Self { block: 8, critical: 5 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeverityBand {
+    Warn,
+    Critical,
+    Block,
+}
+
+impl SeverityThresholds {
+    pub fn band(&self, severity: u8) -> SeverityBand {
+        if severity >= self.block {
+            SeverityBand::Block
+        } else if severity >= self.critical {
+            SeverityBand::Critical
+        } else {
+            SeverityBand::Warn
+        }
+    }
+}
+
+/// Per-session complexity budget (CONFIG.DB "spf:session_budget").
+/// Zero / absent limits are unlimited.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SessionBudget {
+    /// Maximum total C across the session
+    #[serde(default)]
+    pub max_total_c: u64,
+    /// Maximum number of calls per tier, e.g. {"MEDIUM": 200}
+    #[serde(default)]
+    pub max_per_tier: BTreeMap<String, u64>,
+    /// What happens to calls once the budget is spent
+    #[serde(default)]
+    pub on_exhausted: BudgetAction,
+}
+
+/// Environment for spawned commands (CONFIG.DB "spf:exec_env").
+/// Children start empty; only allowlisted names are copied from the gateway.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExecEnv {
+    /// Names passed through — exact, or a prefix ending in `*` (e.g. "LC_*")
+    pub allow: Vec<String>,
+    /// Names matching this regex are never passed through, even if allowlisted
+    pub deny_pattern: String,
+}
+
+impl Default for ExecEnv {
+    fn default() -> Self {
+        Self {
+            allow: [
+                "PATH", "HOME", "USER", "LOGNAME", "SHELL", "LANG", "LANGUAGE", "LC_*",
+                "TERM", "TZ", "PREFIX", "ANDROID_ROOT", "ANDROID_DATA",
+            ].iter().map(|s| s.to_string()).collect(),
+            deny_pattern: "(?i)(KEY|TOKEN|SECRET|PASSW|CREDENTIAL|AUTH|COOKIE|SESSION|PRIVATE)".to_string(),
+        }
+    }
+}
+
+impl ExecEnv {
+    /// Build a child environment from the gateway's `vars`.
+    /// `extra` holds per-project additions: "NAME" passes NAME through like an
+    /// allowlist entry, "NAME=value" sets it explicitly (not subject to deny).
+    /// An invalid deny pattern denies every inherited name.
+    pub fn build<I>(&self, vars: I, extra: &[String]) -> BTreeMap<String, String>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let deny = regex::Regex::new(&self.deny_pattern).ok();
+        let listed = |name: &str, list: &[String]| list.iter().any(|entry| match entry.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => entry == name,
+        });
+        let pass_through: Vec<String> = extra.iter().filter(|e| !e.contains('=')).cloned().collect();
+
+        let mut env: BTreeMap<String, String> = vars.into_iter()
+            .filter(|(name, _)| listed(name, &self.allow) || listed(name, &pass_through))
+            .filter(|(name, _)| deny.as_ref().is_some_and(|re| !re.is_match(name)))
+            .collect();
+        for (name, value) in extra.iter().filter_map(|e| e.split_once('=')) {
+            env.insert(name.to_string(), value.to_string());
+        }
+        env
+    }
+}
+
+/// Response size budget for tool output (CONFIG.DB "spf:output_limits").
+/// A call may return min(cap, a_optimal_tokens × bytes_per_token) bytes, and
+/// the tool's line cap scaled by the same fraction; the rest spills to a file.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OutputLimits {
+    /// Approximate bytes per token when scaling by the tier's a_optimal_tokens
+    pub bytes_per_token: u64,
+    /// Floor for the scaled byte budget
+    pub min_bytes: u64,
+    /// Caps per tool; unlisted tools use `default`
+    pub tools: BTreeMap<String, OutputCap>,
+    pub default: OutputCap,
+}
+
+/// Most bytes and lines a tool returns inline
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OutputCap {
+    pub max_bytes: u64,
+    pub max_lines: u64,
+}
+
+impl Default for OutputLimits {
+    fn default() -> Self {
+        let cap = |max_bytes, max_lines| OutputCap { max_bytes, max_lines };
+        Self {
+            bytes_per_token: 4,
+            min_bytes: 4096,
+            tools: [
+                ("spf_bash", cap(64 * 1024, 2000)),
+                ("spf_grep", cap(64 * 1024, 500)),
+                ("spf_glob", cap(16 * 1024, 100)),
+                ("spf_read", cap(128 * 1024, 2000)),
+            ].into_iter().map(|(tool, cap)| (tool.to_string(), cap)).collect(),
+            default: cap(64 * 1024, 2000),
+        }
+    }
+}
+
+impl OutputLimits {
+    /// Budget for one call of `tool` at a tier allowing `a_optimal_tokens`
+    pub fn budget(&self, tool: &str, a_optimal_tokens: u64) -> OutputCap {
+        let cap = self.tools.get(tool).copied().unwrap_or(self.default);
+        let scaled = a_optimal_tokens.saturating_mul(self.bytes_per_token);
+        let max_bytes = scaled.max(self.min_bytes).min(cap.max_bytes);
+        // Lines shrink with bytes; u128 keeps the product exact
+        let max_lines = (cap.max_lines as u128 * max_bytes as u128 / cap.max_bytes.max(1) as u128) as u64;
+        OutputCap { max_bytes, max_lines: max_lines.max(1) }
+    }
+}
+
+/// Mask an environment value for display — length only
+pub fn mask_env_value(value: &str) -> String {
+    format!("**** ({} chars)", value.chars().count())
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetAction {
+    /// Force CRITICAL tier + approval
+    #[default]
+    Escalate,
+    /// Refuse the call
+    Block,
+}
+
+impl SessionBudget {
+    /// Why a call of `c` at `tier` would overrun the budget, if it would
+    pub fn exceeded(&self, session: &Session, c: u64, tier: &str) -> Option<String> {
+        if self.max_total_c > 0 && session.complexity_total.saturating_add(c) > self.max_total_c {
+            return Some(format!(
+                "session C budget exhausted ({} used + {} > {})",
+                session.complexity_total, c, self.max_total_c
+            ));
+        }
+        let limit = self.max_per_tier.get(tier).copied().unwrap_or(0);
+        let used = session.tier_counts.get(tier).copied().unwrap_or(0);
+        if limit > 0 && used >= limit {
+            return Some(format!("session {} budget exhausted ({}/{} calls)", tier, used, limit));
+        }
+        None
+    }
+
+    /// Remaining budget, e.g. "C 3000/10000 left | MEDIUM 5/20 left"
+    pub fn remaining(&self, session: &Session) -> String {
+        let mut parts = Vec::new();
+        if self.max_total_c > 0 {
+            parts.push(format!(
+                "C {}/{} left",
+                self.max_total_c.saturating_sub(session.complexity_total), self.max_total_c
+            ));
+        }
+        for (tier, limit) in self.max_per_tier.iter().filter(|(_, l)| **l > 0) {
+            let used = session.tier_counts.get(tier).copied().unwrap_or(0);
+            parts.push(format!("{} {}/{} left", tier, limit.saturating_sub(used), limit));
+        }
+        if parts.is_empty() {
+            "unlimited".to_string()
+        } else {
+            parts.join(" | ")
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -124,12 +368,13 @@ impl Default for SpfConfig {
                 }
                 paths
             },
+            path_precedence: PathPrecedence::default(),
             require_read_before_edit: true,
             max_write_size: 100_000,
             tiers: TierConfig {
-                simple: TierThreshold { max_c: 500, analyze_percent: 40, build_percent: 60, requires_approval: true },
-                light: TierThreshold { max_c: 2000, analyze_percent: 60, build_percent: 40, requires_approval: true },
-                medium: TierThreshold { max_c: 10000, analyze_percent: 75, build_percent: 25, requires_approval: true },
+                simple: TierThreshold { max_c: 500, analyze_percent: 40, build_percent: 60, requires_approval: false },
+                light: TierThreshold { max_c: 2000, analyze_percent: 60, build_percent: 40, requires_approval: false },
+                medium: TierThreshold { max_c: 10000, analyze_percent: 75, build_percent: 25, requires_approval: false },
                 critical: TierThreshold { max_c: u64::MAX, analyze_percent: 95, build_percent: 5, requires_approval: true },
             },
             formula: FormulaConfig {
@@ -154,22 +399,23 @@ impl Default for SpfConfig {
                 search: ToolWeight { basic: 8, dependencies: 2, complex: 0, files: 1 },
                 unknown: ToolWeight { basic: 20, dependencies: 3, complex: 1, files: 1 },
             },
-            dangerous_commands: vec![
-                "rm -rf /".to_string(),
-                "rm -rf ~".to_string(),
-                "dd if=".to_string(),
-                "> /dev/".to_string(),
-                "chmod 777".to_string(),
-                "curl | sh".to_string(),
-                "wget | sh".to_string(),
-                "curl|sh".to_string(),
-                "wget|sh".to_string(),
-            ],
+            dangerous_commands: DEFAULT_DANGEROUS_PATTERNS.iter()
+                .map(|(pattern, _)| pattern.to_string())
+                .collect(),
+            dangerous_severity: DEFAULT_DANGEROUS_PATTERNS.iter()
+                .map(|(pattern, severity)| (pattern.to_string(), *severity))
+                .collect(),
+            severity_thresholds: SeverityThresholds::default(),
+            session_budget: SessionBudget::default(),
+            exec_env: ExecEnv::default(),
+            output_limits: OutputLimits::default(),
             git_force_patterns: vec![
                 "--force".to_string(),
                 "--hard".to_string(),
                 "-f".to_string(),
             ],
+            policy: crate::policy::Policy::default(),
+            candidate_policy: None,
         }
     }
 }
@@ -194,6 +440,97 @@ impl SpfConfig {
         Ok(())
     }
 
+    /// Semantic check before a config is put into service.
+    /// Returns one line per problem; empty means valid.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        let tiers = [
+            ("simple", &self.tiers.simple),
+            ("light", &self.tiers.light),
+            ("medium", &self.tiers.medium),
+            ("critical", &self.tiers.critical),
+        ];
+        for pair in tiers.windows(2) {
+            let ((lo_name, lo), (hi_name, hi)) = (pair[0], pair[1]);
+            if lo.max_c >= hi.max_c {
+                problems.push(format!(
+                    "tiers: {}.max_c ({}) must be below {}.max_c ({})",
+                    lo_name, lo.max_c, hi_name, hi.max_c
+                ));
+            }
+        }
+        for (name, tier) in tiers {
+            if tier.analyze_percent > 100 || tier.build_percent > 100 {
+                problems.push(format!("tiers: {} percentages exceed 100", name));
+            }
+        }
+        if !self.tiers.critical.requires_approval {
+            problems.push("tiers: critical must require approval".to_string());
+        }
+
+        if !(self.formula.w_eff.is_finite() && self.formula.w_eff > 0.0) {
+            problems.push(format!("formula: w_eff must be positive (got {})", self.formula.w_eff));
+        }
+        let bands = &self.severity_thresholds;
+        if bands.critical == 0 || bands.critical > bands.block {
+            problems.push(format!(
+                "severity_thresholds: need 1 <= critical ({}) <= block ({})",
+                bands.critical, bands.block
+            ));
+        }
+        for tier in self.session_budget.max_per_tier.keys() {
+            if crate::policy::tier_rank(tier).is_none() {
+                problems.push(format!("session_budget: unknown tier '{}'", tier));
+            }
+        }
+        if let Err(e) = regex::Regex::new(&self.exec_env.deny_pattern) {
+            problems.push(format!("exec_env: invalid deny_pattern: {}", e));
+        }
+        if self.exec_env.allow.iter().any(|name| name.is_empty() || name.contains('=')) {
+            problems.push("exec_env: allow entries must be variable names".to_string());
+        }
+        let limits = &self.output_limits;
+        if limits.bytes_per_token == 0 {
+            problems.push("output_limits: bytes_per_token must be positive".to_string());
+        }
+        for (tool, cap) in limits.tools.iter().map(|(t, c)| (t.as_str(), c)).chain([("default", &limits.default)]) {
+            if cap.max_bytes == 0 || cap.max_lines == 0 {
+                problems.push(format!("output_limits: {} caps must be positive", tool));
+            }
+        }
+        if self.max_write_size == 0 {
+            problems.push("max_write_size is 0 — every write would be blocked".to_string());
+        }
+        if self.allowed_paths.is_empty() {
+            problems.push("allowed_paths is empty — every write would be blocked".to_string());
+        }
+        for (list, patterns) in [("allowed_paths", &self.allowed_paths), ("blocked_paths", &self.blocked_paths)] {
+            problems.extend(pathmatch::check(patterns).into_iter().map(|p| format!("{}: {}", list, p)));
+        }
+
+        problems.extend(self.policy.check().into_iter().map(|p| format!("policy: {}", p)));
+        if let Some(ref candidate) = self.candidate_policy {
+            problems.extend(candidate.check().into_iter().map(|p| format!("candidate policy: {}", p)));
+        }
+        problems
+    }
+
+    /// Dangerous patterns a command contains, with their severity.
+    /// Matches the raw command and its whitespace-normalized form.
+    pub fn dangerous_matches(&self, command: &str) -> Vec<(&str, u8)> {
+        let normalized: String = command.split_whitespace().collect::<Vec<_>>().join(" ");
+        self.dangerous_commands.iter()
+            .filter(|p| command.contains(p.as_str()) || normalized.contains(p.as_str()))
+            .map(|p| (p.as_str(), self.dangerous_severity.get(p).copied().unwrap_or(10)))
+            .collect()
+    }
+
+    /// Highest severity among matched dangerous patterns (0 = none)
+    pub fn command_severity(&self, command: &str) -> u8 {
+        self.dangerous_matches(command).iter().map(|(_, s)| *s).max().unwrap_or(0)
+    }
+
     /// Get tier for a given complexity value
     /// CRITICAL tier requires explicit user approval. Lower tiers protected by other layers.
     pub fn get_tier(&self, c: u64) -> (&str, u8, u8, bool) {
@@ -208,7 +545,8 @@ impl SpfConfig {
         }
     }
 
-    /// Check if a path is blocked (with canonicalization to prevent traversal bypass)
+    /// Check if a path is blocked (with canonicalization to prevent traversal bypass).
+    /// Rules are component-aware, so "/home/u" never matches "/home/u2".
     pub fn is_path_blocked(&self, path: &str) -> bool {
         let canonical = match std::fs::canonicalize(path) {
             Ok(p) => p.to_string_lossy().to_string(),
@@ -219,7 +557,8 @@ impl SpfConfig {
                 path.to_string()
             }
         };
-        self.blocked_paths.iter().any(|blocked| canonical.starts_with(blocked))
+        pathmatch::verdict(&canonical, &self.allowed_paths, &self.blocked_paths, self.path_precedence)
+            == PathVerdict::Blocked
     }
 
     /// Check if a path is allowed (with canonicalization to prevent traversal bypass)
@@ -233,7 +572,8 @@ impl SpfConfig {
                 path.to_string()
             }
         };
-        self.allowed_paths.iter().any(|allowed| canonical.starts_with(allowed))
+        pathmatch::verdict(&canonical, &self.allowed_paths, &self.blocked_paths, self.path_precedence)
+            == PathVerdict::Allowed
     }
 }
 
@@ -275,6 +615,66 @@ mod tests {
         assert_eq!(config.enforce_mode, EnforceMode::Max);
     }
 
+    #[test]
+    fn validate_flags_broken_config() {
+        let mut config = SpfConfig::default();
+        assert!(config.validate().is_empty(), "{:?}", config.validate());
+
+        config.tiers.light.max_c = 100;
+        config.tiers.critical.requires_approval = false;
+        config.allowed_paths.clear();
+        let problems = config.validate();
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].contains("simple.max_c (500) must be below light.max_c (100)"));
+    }
+
+    #[test]
+    fn severity_bands_and_matches() {
+        let mut config = SpfConfig::default();
+        let bands = &config.severity_thresholds;
+        assert_eq!(bands.band(10), SeverityBand::Block);
+        assert_eq!(bands.band(8), SeverityBand::Block);
+        assert_eq!(bands.band(7), SeverityBand::Critical);
+        assert_eq!(bands.band(5), SeverityBand::Critical);
+        assert_eq!(bands.band(4), SeverityBand::Warn);
+
+        assert_eq!(config.command_severity("rm  -rf   / --no-preserve-root"), 10);
+        assert_eq!(config.command_severity("chmod 777 x"), 7);
+        assert_eq!(config.command_severity("ls"), 0);
+
+        // Patterns without a recorded severity are treated as maximal
+        config.dangerous_commands.push("shutdown".to_string());
+        assert_eq!(config.command_severity("shutdown -h now"), 10);
+    }
+
+    #[test]
+    fn session_budget_tracks_cumulative_totals() {
+        let budget = SessionBudget {
+            max_total_c: 1000,
+            max_per_tier: BTreeMap::from([("LIGHT".to_string(), 2)]),
+            on_exhausted: BudgetAction::Block,
+        };
+        let mut session = Session::new();
+        assert_eq!(budget.remaining(&session), "C 1000/1000 left | LIGHT 2/2 left");
+        assert!(budget.exceeded(&session, 600, "LIGHT").is_none());
+
+        session.record_complexity("spf_write", 600, "LIGHT");
+        session.record_complexity("spf_write", 100, "LIGHT");
+        assert!(budget.exceeded(&session, 400, "SIMPLE").unwrap().contains("C budget"));
+        assert!(budget.exceeded(&session, 10, "LIGHT").unwrap().contains("LIGHT budget"));
+        assert!(budget.exceeded(&session, 10, "SIMPLE").is_none());
+        assert_eq!(budget.remaining(&session), "C 300/1000 left | LIGHT 0/2 left");
+
+        // Totals outlive the truncated history
+        for _ in 0..150 {
+            session.record_complexity("spf_read", 1, "SIMPLE");
+        }
+        assert_eq!(session.complexity_history.len(), 100);
+        assert_eq!(session.complexity_total, 850);
+        assert_eq!(session.tier_counts["SIMPLE"], 150);
+        assert_eq!(SessionBudget::default().remaining(&session), "unlimited");
+    }
+
     #[test]
     fn blocked_paths_include_system_dirs() {
         let config = SpfConfig::default();
@@ -282,5 +682,54 @@ mod tests {
         assert!(config.is_path_blocked("/tmp/evil.sh"));
         assert!(config.is_path_blocked("/etc/passwd"));
         assert!(config.is_path_blocked("/usr/bin/something"));
+        assert!(!config.is_path_blocked("/tmpfile-that-does-not-exist"));
+    }
+
+    #[test]
+    fn path_rules_use_precedence() {
+        let mut config = SpfConfig {
+            allowed_paths: vec!["/srv/app/".to_string(), "/srv/app/.secrets/public".to_string()],
+            blocked_paths: vec!["/srv/app/.secrets".to_string(), "**/*.pem".to_string()],
+            ..Default::default()
+        };
+
+        assert!(config.is_path_allowed("/srv/app/main.rs"));
+        assert!(config.is_path_blocked("/srv/app/.secrets/public/x"));
+        assert!(config.is_path_blocked("/srv/app/certs/server.pem"));
+        assert!(!config.is_path_allowed("/srv/app2/main.rs"));
+
+        config.path_precedence = PathPrecedence::MostSpecific;
+        assert!(config.is_path_allowed("/srv/app/.secrets/public/x"));
+        assert!(config.is_path_blocked("/srv/app/.secrets/key"));
+
+        config.blocked_paths.push("re:(".to_string());
+        assert!(config.validate().iter().any(|p| p.starts_with("blocked_paths: invalid regex")));
+    }
+
+    #[test]
+    fn exec_env_allowlists_and_scrubs_secrets() {
+        let vars = [
+            ("PATH", "/usr/bin"), ("LC_ALL", "C"), ("BRAVE_API_KEY", "k"),
+            ("GITHUB_TOKEN", "t"), ("EDITOR", "vi"), ("CARGO_HOME", "/c"),
+        ].map(|(k, v)| (k.to_string(), v.to_string()));
+        let mut exec_env = ExecEnv::default();
+        exec_env.allow.push("BRAVE_API_KEY".to_string()); // deny wins over allow
+
+        let env = exec_env.build(vars.clone(), &[]);
+        assert_eq!(env.keys().collect::<Vec<_>>(), ["LC_ALL", "PATH"]);
+
+        // Project additions: pass-through is still scrubbed, explicit values are set
+        let extra = ["CARGO_HOME".to_string(), "GITHUB_TOKEN".to_string(), "DEPLOY_TOKEN=abc".to_string()];
+        let env = exec_env.build(vars.clone(), &extra);
+        assert_eq!(env.get("CARGO_HOME").map(String::as_str), Some("/c"));
+        assert!(!env.contains_key("GITHUB_TOKEN"));
+        assert_eq!(env.get("DEPLOY_TOKEN").map(String::as_str), Some("abc"));
+        assert_eq!(mask_env_value("abc"), "**** (3 chars)");
+
+        // A broken deny pattern fails closed and is reported
+        exec_env.deny_pattern = "(".to_string();
+        assert!(exec_env.build(vars, &[]).is_empty());
+        let config = SpfConfig { exec_env, ..Default::default() };
+        assert!(config.validate().iter().any(|p| p.starts_with("exec_env: invalid deny_pattern")));
     }
 }
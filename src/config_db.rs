@@ -6,20 +6,122 @@
 //
 // Database: SPF_CONFIG
 // Storage: ~/SPFsmartGATE/LIVE/CONFIG/CONFIG.DB/
+//
+// Every mutation is recorded as a numbered revision ("history"): actor,
+// timestamp, a full snapshot and the diff against the previous revision.
+// `spf-smart-gate config history|diff|rollback` reads and restores them.
+// Each recorded revision also bumps the generation counter ("meta"), which
+// the serve loop polls to hot-reload SpfConfig between requests.
 
 use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
 use heed::types::*;
-use heed::{Database, Env, EnvOpenOptions};
+use heed::{Database, Env, EnvOpenOptions, RoTxn, RwTxn};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::path::Path;
+use std::sync::Mutex;
 
 // Import config types from canonical source (config.rs) - NO DUPLICATES
 use crate::config::{
     EnforceMode, TierThreshold, TierConfig, FormulaConfig,
-    ToolWeight, ComplexityWeights, SpfConfig,
+    ToolWeight, ComplexityWeights, SpfConfig, SeverityThresholds, SessionBudget, ExecEnv, OutputLimits,
+    DEFAULT_DANGEROUS_PATTERNS,
 };
+use crate::pathmatch::{self, PathPrecedence, PathVerdict};
+use crate::policy::Policy;
+
+const MAX_DB_SIZE: usize = 32 * 1024 * 1024; // 32MB - config is small, history is bounded below
+
+/// Stored bytes of revision history — oldest revisions are pruned beyond
+/// this, the newest is always kept. Leaves the map room for copy-on-write.
+const HISTORY_MAX_BYTES: usize = 4 * 1024 * 1024;
+
+/// Full contents of CONFIG.DB at one revision
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConfigSnapshot {
+    /// namespace:key → stored value
+    pub config: BTreeMap<String, String>,
+    /// "allowed:path" / "blocked:path"
+    pub paths: Vec<String>,
+    /// pattern → severity
+    pub patterns: BTreeMap<String, u8>,
+}
+
+impl ConfigSnapshot {
+    /// Flatten into comparable leaves. JSON values expand to dotted keys,
+    /// e.g. "spf:tiers.light.max_c" → "2000".
+    fn leaves(&self) -> BTreeMap<String, String> {
+        fn walk(prefix: &str, value: &Value, out: &mut BTreeMap<String, String>) {
+            match value {
+                Value::Object(map) => {
+                    for (k, v) in map {
+                        walk(&format!("{}.{}", prefix, k), v, out);
+                    }
+                }
+                Value::Array(items) => {
+                    for (i, v) in items.iter().enumerate() {
+                        walk(&format!("{}[{}]", prefix, i), v, out);
+                    }
+                }
+                Value::String(s) => { out.insert(prefix.to_string(), s.clone()); }
+                other => { out.insert(prefix.to_string(), other.to_string()); }
+            }
+        }
+
+        let mut out = BTreeMap::new();
+        for (key, raw) in &self.config {
+            match serde_json::from_str::<Value>(raw) {
+                Ok(value) => walk(key, &value, &mut out),
+                Err(_) => { out.insert(key.clone(), raw.clone()); }
+            }
+        }
+        for rule in &self.paths {
+            out.insert(format!("path {}", rule), String::new());
+        }
+        for (pattern, severity) in &self.patterns {
+            out.insert(format!("pattern {:?}", pattern), severity.to_string());
+        }
+        out
+    }
 
-const MAX_DB_SIZE: usize = 10 * 1024 * 1024; // 10MB - config is small
+    /// Changes from self to other, one line each: "+ key = v", "- key = v", "~ key: a → b"
+    pub fn diff(&self, other: &ConfigSnapshot) -> Vec<String> {
+        let show = |key: &str, value: &str| {
+            if value.is_empty() { key.to_string() } else { format!("{} = {}", key, value) }
+        };
+        let before = self.leaves();
+        let after = other.leaves();
+        let mut changes = Vec::new();
+        for (key, old) in &before {
+            match after.get(key) {
+                None => changes.push(format!("- {}", show(key, old))),
+                Some(new) if new != old => changes.push(format!("~ {}: {} → {}", key, old, new)),
+                _ => {}
+            }
+        }
+        for (key, new) in &after {
+            if !before.contains_key(key) {
+                changes.push(format!("+ {}", show(key, new)));
+            }
+        }
+        changes
+    }
+}
+
+/// One recorded config mutation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigRevision {
+    pub rev: u64,
+    /// CLI command and user, "boot", "baseline" or "api"
+    pub actor: String,
+    pub timestamp: DateTime<Utc>,
+    /// Diff against the previous revision
+    pub changes: Vec<String>,
+    /// CONFIG.DB contents after this revision
+    pub snapshot: ConfigSnapshot,
+}
 
 /// LMDB-backed SPF configuration storage
 pub struct SpfConfigDb {
@@ -30,6 +132,17 @@ pub struct SpfConfigDb {
     paths: Database<Str, SerdeBincode<bool>>,
     /// Dangerous patterns: pattern → severity (1-10)
     patterns: Database<Str, SerdeBincode<u8>>,
+    /// Revision history: zero-padded rev → ConfigRevision JSON
+    history: Database<Str, Str>,
+    /// Bookkeeping outside the snapshot ("generation")
+    meta: Database<Str, SerdeBincode<u64>>,
+    /// Actor of the open with_revision() batch. None = every write is its own revision.
+    batch: Mutex<Option<String>>,
+}
+
+/// LMDB ran out of map space (the transaction is dead and must be retried)
+fn is_map_full(e: &anyhow::Error) -> bool {
+    matches!(e.downcast_ref::<heed::Error>(), Some(heed::Error::Mdb(heed::MdbError::MapFull)))
 }
 
 // ============================================================================
@@ -39,11 +152,15 @@ pub struct SpfConfigDb {
 impl SpfConfigDb {
     /// Open or create config LMDB at given path
     pub fn open(path: &Path) -> Result<Self> {
+        Self::open_sized(path, MAX_DB_SIZE)
+    }
+
+    fn open_sized(path: &Path, map_size: usize) -> Result<Self> {
         std::fs::create_dir_all(path)?;
 
         let env = unsafe {
             EnvOpenOptions::new()
-                .map_size(MAX_DB_SIZE)
+                .map_size(map_size)
                 .max_dbs(8)
                 .open(path)?
         };
@@ -52,10 +169,66 @@ impl SpfConfigDb {
         let config = env.create_database(&mut wtxn, Some("config"))?;
         let paths = env.create_database(&mut wtxn, Some("paths"))?;
         let patterns = env.create_database(&mut wtxn, Some("patterns"))?;
+        let history = env.create_database(&mut wtxn, Some("history"))?;
+        let meta = env.create_database(&mut wtxn, Some("meta"))?;
         wtxn.commit()?;
 
         log::info!("SPF Config LMDB opened at {:?}", path);
-        Ok(Self { env, config, paths, patterns })
+        Ok(Self { env, config, paths, patterns, history, meta, batch: Mutex::new(None) })
+    }
+
+    /// Run a mutation in one write transaction. Outside a with_revision()
+    /// batch the change is recorded as its own revision in the same transaction.
+    fn write<R>(&self, f: impl Fn(&mut RwTxn) -> Result<R>) -> Result<R> {
+        let batched = self.batch.lock().map(|b| b.is_some()).unwrap_or(false);
+        self.commit_shedding_history(|wtxn| {
+            if !batched {
+                self.ensure_baseline(wtxn)?;
+            }
+            let out = f(wtxn)?;
+            if !batched {
+                self.record_revision(wtxn, "api")?;
+            }
+            Ok(out)
+        })
+    }
+
+    /// Run `f` in a write transaction and commit. A full map aborts the
+    /// transaction, so history must never make the config unwritable: drop
+    /// the oldest half of it and run `f` again, down to the newest revision.
+    fn commit_shedding_history<R>(&self, f: impl Fn(&mut RwTxn) -> Result<R>) -> Result<R> {
+        loop {
+            let attempt = self.env.write_txn().map_err(anyhow::Error::from).and_then(|mut wtxn| {
+                let out = f(&mut wtxn)?;
+                wtxn.commit()?;
+                Ok(out)
+            });
+            match attempt {
+                Err(e) if is_map_full(&e) && self.shed_history()? => {
+                    log::warn!("CONFIG.DB full — dropped the oldest revisions and retried");
+                }
+                other => return other,
+            }
+        }
+    }
+
+    /// Delete the oldest half of history, keeping the newest revision.
+    /// False when there is nothing left to drop.
+    fn shed_history(&self) -> Result<bool> {
+        let mut wtxn = self.env.write_txn()?;
+        let count = self.history.len(&wtxn)? as usize;
+        if count <= 1 {
+            return Ok(false);
+        }
+        let oldest = self.history.iter(&wtxn)?
+            .take(count.div_ceil(2).min(count - 1))
+            .map(|entry| entry.map(|(key, _)| key.to_string()))
+            .collect::<heed::Result<Vec<_>>>()?;
+        for key in &oldest {
+            self.history.delete(&mut wtxn, key)?;
+        }
+        wtxn.commit()?;
+        Ok(true)
     }
 
     // ========================================================================
@@ -72,10 +245,7 @@ impl SpfConfigDb {
     /// Set a config value
     pub fn set(&self, namespace: &str, key: &str, value: &str) -> Result<()> {
         let full_key = format!("{}:{}", namespace, key);
-        let mut wtxn = self.env.write_txn()?;
-        self.config.put(&mut wtxn, &full_key, value)?;
-        wtxn.commit()?;
-        Ok(())
+        self.write(|wtxn| Ok(self.config.put(wtxn, &full_key, value)?))
     }
 
     /// Get typed config value (deserialize from JSON)
@@ -96,31 +266,24 @@ impl SpfConfigDb {
     // PATH RULES
     // ========================================================================
 
-    /// Add an allowed path
+    /// Add an allowed path rule (prefix, glob or `re:` regex)
     pub fn allow_path(&self, path: &str) -> Result<()> {
+        pathmatch::PathPattern::parse(path).map_err(|e| anyhow!(e))?;
         let key = format!("allowed:{}", path);
-        let mut wtxn = self.env.write_txn()?;
-        self.paths.put(&mut wtxn, &key, &true)?;
-        wtxn.commit()?;
-        Ok(())
+        self.write(|wtxn| Ok(self.paths.put(wtxn, &key, &true)?))
     }
 
-    /// Add a blocked path
+    /// Add a blocked path rule (prefix, glob or `re:` regex)
     pub fn block_path(&self, path: &str) -> Result<()> {
+        pathmatch::PathPattern::parse(path).map_err(|e| anyhow!(e))?;
         let key = format!("blocked:{}", path);
-        let mut wtxn = self.env.write_txn()?;
-        self.paths.put(&mut wtxn, &key, &true)?;
-        wtxn.commit()?;
-        Ok(())
+        self.write(|wtxn| Ok(self.paths.put(wtxn, &key, &true)?))
     }
 
     /// Remove a path rule
     pub fn remove_path_rule(&self, rule_type: &str, path: &str) -> Result<bool> {
         let key = format!("{}:{}", rule_type, path);
-        let mut wtxn = self.env.write_txn()?;
-        let deleted = self.paths.delete(&mut wtxn, &key)?;
-        wtxn.commit()?;
-        Ok(deleted)
+        self.write(|wtxn| Ok(self.paths.delete(wtxn, &key)?))
     }
 
     /// Check if path is allowed (with canonicalization to prevent traversal bypass)
@@ -134,22 +297,10 @@ impl SpfConfigDb {
                 path.to_string()
             }
         };
-        let rtxn = self.env.read_txn()?;
-        let iter = self.paths.iter(&rtxn)?;
-
-        for result in iter {
-            let (key, _) = result?;
-            if key.starts_with("allowed:") {
-                let allowed_path = &key[8..]; // Skip "allowed:"
-                if canonical.starts_with(allowed_path) {
-                    return Ok(true);
-                }
-            }
-        }
-        Ok(false)
+        Ok(self.path_verdict(&canonical)? == PathVerdict::Allowed)
     }
 
-    /// Check if path is blocked (matches any blocked prefix)
+    /// Check if path is blocked (same matcher and precedence as SpfConfig)
     pub fn is_path_blocked(&self, path: &str) -> Result<bool> {
         let canonical = match std::fs::canonicalize(path) {
             Ok(p) => p.to_string_lossy().to_string(),
@@ -160,20 +311,30 @@ impl SpfConfigDb {
                 path.to_string()
             }
         };
+        Ok(self.path_verdict(&canonical)? == PathVerdict::Blocked)
+    }
 
-        let rtxn = self.env.read_txn()?;
-        let iter = self.paths.iter(&rtxn)?;
-
-        for result in iter {
-            let (key, _) = result?;
-            if key.starts_with("blocked:") {
-                let blocked_path = &key[8..]; // Skip "blocked:"
-                if canonical.starts_with(blocked_path) {
-                    return Ok(true);
-                }
+    fn path_verdict(&self, canonical: &str) -> Result<PathVerdict> {
+        let mut allowed = Vec::new();
+        let mut blocked = Vec::new();
+        for (rule_type, path) in self.list_path_rules()? {
+            match rule_type.as_str() {
+                "allowed" => allowed.push(path),
+                "blocked" => blocked.push(path),
+                _ => {}
             }
         }
-        Ok(false)
+        Ok(pathmatch::verdict(canonical, &allowed, &blocked, self.get_path_precedence()?))
+    }
+
+    /// Get path rule precedence (default: deny overrides)
+    pub fn get_path_precedence(&self) -> Result<PathPrecedence> {
+        Ok(self.get_typed::<PathPrecedence>("spf", "path_precedence")?.unwrap_or_default())
+    }
+
+    /// Set path rule precedence
+    pub fn set_path_precedence(&self, precedence: PathPrecedence) -> Result<()> {
+        self.set_typed("spf", "path_precedence", &precedence)
     }
 
     /// List all path rules
@@ -197,10 +358,7 @@ impl SpfConfigDb {
 
     /// Add a dangerous pattern with severity (1-10)
     pub fn add_dangerous_pattern(&self, pattern: &str, severity: u8) -> Result<()> {
-        let mut wtxn = self.env.write_txn()?;
-        self.patterns.put(&mut wtxn, pattern, &severity.min(10))?;
-        wtxn.commit()?;
-        Ok(())
+        self.write(|wtxn| Ok(self.patterns.put(wtxn, pattern, &severity.min(10))?))
     }
 
     /// Check if command matches any dangerous pattern, returns severity
@@ -231,6 +389,46 @@ impl SpfConfigDb {
         Ok(patterns)
     }
 
+    /// Get severity thresholds. Falls back to block ≥8, CRITICAL ≥5.
+    pub fn get_severity_thresholds(&self) -> Result<SeverityThresholds> {
+        Ok(self.get_typed::<SeverityThresholds>("spf", "severity_thresholds")?.unwrap_or_default())
+    }
+
+    /// Set severity thresholds
+    pub fn set_severity_thresholds(&self, thresholds: &SeverityThresholds) -> Result<()> {
+        self.set_typed("spf", "severity_thresholds", thresholds)
+    }
+
+    /// Get the per-session complexity budget. Falls back to unlimited.
+    pub fn get_session_budget(&self) -> Result<SessionBudget> {
+        Ok(self.get_typed::<SessionBudget>("spf", "session_budget")?.unwrap_or_default())
+    }
+
+    /// Set the per-session complexity budget
+    pub fn set_session_budget(&self, budget: &SessionBudget) -> Result<()> {
+        self.set_typed("spf", "session_budget", budget)
+    }
+
+    /// Get the spawned-command environment policy. Falls back to the default allowlist.
+    pub fn get_exec_env(&self) -> Result<ExecEnv> {
+        Ok(self.get_typed::<ExecEnv>("spf", "exec_env")?.unwrap_or_default())
+    }
+
+    /// Set the spawned-command environment policy
+    pub fn set_exec_env(&self, exec_env: &ExecEnv) -> Result<()> {
+        self.set_typed("spf", "exec_env", exec_env)
+    }
+
+    /// Get the tool output budget. Compiled defaults until one is set.
+    pub fn get_output_limits(&self) -> Result<OutputLimits> {
+        Ok(self.get_typed::<OutputLimits>("spf", "output_limits")?.unwrap_or_default())
+    }
+
+    /// Set the tool output budget
+    pub fn set_output_limits(&self, limits: &OutputLimits) -> Result<()> {
+        self.set_typed("spf", "output_limits", limits)
+    }
+
     // ========================================================================
     // TIER CONFIG
     // ========================================================================
@@ -323,6 +521,217 @@ impl SpfConfigDb {
         self.set_typed("spf", "enforce_mode", mode)
     }
 
+    // ========================================================================
+    // POLICY
+    // ========================================================================
+
+    /// Get policy document. Falls back to the built-in policy if none stored.
+    pub fn get_policy(&self) -> Result<Policy> {
+        Ok(self.get_typed::<Policy>("spf", "policy")?.unwrap_or_default())
+    }
+
+    /// Set policy document. Rejects policies that fail the semantic check.
+    pub fn set_policy(&self, policy: &Policy) -> Result<()> {
+        let problems = policy.check();
+        if !problems.is_empty() {
+            return Err(anyhow!("Invalid policy:\n  {}", problems.join("\n  ")));
+        }
+        self.set_typed("spf", "policy", policy)
+    }
+
+    /// Get the candidate policy evaluated in Audit mode, if any
+    pub fn get_candidate_policy(&self) -> Result<Option<Policy>> {
+        self.get_typed::<Policy>("spf", "policy_candidate")
+    }
+
+    /// Set the candidate policy. Same semantic check as the active policy.
+    pub fn set_candidate_policy(&self, policy: &Policy) -> Result<()> {
+        let problems = policy.check();
+        if !problems.is_empty() {
+            return Err(anyhow!("Invalid candidate policy:\n  {}", problems.join("\n  ")));
+        }
+        self.set_typed("spf", "policy_candidate", policy)
+    }
+
+    /// Remove the candidate policy
+    pub fn clear_candidate_policy(&self) -> Result<bool> {
+        self.write(|wtxn| Ok(self.config.delete(wtxn, "spf:policy_candidate")?))
+    }
+
+    // ========================================================================
+    // REVISION HISTORY
+    // ========================================================================
+
+    /// Group every write made by `f` into one revision attributed to `actor`.
+    /// Nested calls join the outer batch. Partial changes are still recorded if `f` fails.
+    pub fn with_revision<R>(&self, actor: &str, f: impl FnOnce(&Self) -> Result<R>) -> Result<R> {
+        {
+            let mut batch = self.batch.lock().map_err(|_| anyhow!("config batch lock poisoned"))?;
+            if batch.is_some() {
+                drop(batch);
+                return f(self);
+            }
+            self.commit_shedding_history(|wtxn| self.ensure_baseline(wtxn))?;
+            *batch = Some(actor.to_string());
+        }
+
+        let out = f(self);
+
+        if let Ok(mut batch) = self.batch.lock() {
+            *batch = None;
+        }
+        self.commit_shedding_history(|wtxn| self.record_revision(wtxn, actor))?;
+        out
+    }
+
+    /// Current contents of CONFIG.DB
+    pub fn snapshot(&self) -> Result<ConfigSnapshot> {
+        let rtxn = self.env.read_txn()?;
+        self.snapshot_txn(&rtxn)
+    }
+
+    fn snapshot_txn(&self, txn: &RoTxn) -> Result<ConfigSnapshot> {
+        let mut snap = ConfigSnapshot::default();
+        for result in self.config.iter(txn)? {
+            let (key, value) = result?;
+            snap.config.insert(key.to_string(), value.to_string());
+        }
+        for result in self.paths.iter(txn)? {
+            let (key, _) = result?;
+            snap.paths.push(key.to_string());
+        }
+        for result in self.patterns.iter(txn)? {
+            let (pattern, severity) = result?;
+            snap.patterns.insert(pattern.to_string(), severity);
+        }
+        Ok(snap)
+    }
+
+    fn latest_revision_txn(&self, txn: &RoTxn) -> Result<Option<ConfigRevision>> {
+        match self.history.last(txn)? {
+            Some((_, json)) => Ok(Some(serde_json::from_str(json)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Databases written before history existed get their current state
+    /// recorded as the "baseline" revision, so the first change can be undone.
+    fn ensure_baseline(&self, wtxn: &mut RwTxn) -> Result<()> {
+        if self.history.first(wtxn)?.is_some() {
+            return Ok(());
+        }
+        let snapshot = self.snapshot_txn(wtxn)?;
+        if snapshot == ConfigSnapshot::default() {
+            return Ok(());
+        }
+        self.put_revision(wtxn, &ConfigRevision {
+            rev: 1,
+            actor: "baseline".to_string(),
+            timestamp: Utc::now(),
+            changes: ConfigSnapshot::default().diff(&snapshot),
+            snapshot,
+        })
+    }
+
+    /// Record the current state as a new revision if it differs from the last one
+    fn record_revision(&self, wtxn: &mut RwTxn, actor: &str) -> Result<Option<u64>> {
+        let snapshot = self.snapshot_txn(wtxn)?;
+        let previous = self.latest_revision_txn(wtxn)?;
+        let (rev, changes) = match previous {
+            Some(prev) => (prev.rev + 1, prev.snapshot.diff(&snapshot)),
+            None => (1, ConfigSnapshot::default().diff(&snapshot)),
+        };
+        if changes.is_empty() {
+            return Ok(None);
+        }
+        self.put_revision(wtxn, &ConfigRevision {
+            rev,
+            actor: actor.to_string(),
+            timestamp: Utc::now(),
+            changes,
+            snapshot,
+        })?;
+        let generation = self.meta.get(wtxn, "generation")?.unwrap_or(0);
+        self.meta.put(wtxn, "generation", &(generation + 1))?;
+        Ok(Some(rev))
+    }
+
+    /// Change counter — bumped once per recorded revision (a whole
+    /// with_revision() batch counts as one). Running servers poll it to reload.
+    pub fn generation(&self) -> Result<u64> {
+        let rtxn = self.env.read_txn()?;
+        Ok(self.meta.get(&rtxn, "generation")?.unwrap_or(0))
+    }
+
+    fn put_revision(&self, wtxn: &mut RwTxn, revision: &ConfigRevision) -> Result<()> {
+        let key = format!("{:010}", revision.rev);
+        self.history.put(wtxn, &key, &serde_json::to_string(revision)?)?;
+
+        // Oldest first until the rest fits the byte budget; the newest stays
+        let sizes = self.history.iter(wtxn)?
+            .map(|entry| entry.map(|(key, json)| (key.to_string(), json.len())))
+            .collect::<heed::Result<Vec<_>>>()?;
+        let mut total: usize = sizes.iter().map(|(_, len)| len).sum();
+        for (oldest, len) in &sizes[..sizes.len() - 1] {
+            if total <= HISTORY_MAX_BYTES {
+                break;
+            }
+            self.history.delete(wtxn, oldest)?;
+            total -= len;
+        }
+        Ok(())
+    }
+
+    /// Get one revision
+    pub fn get_revision(&self, rev: u64) -> Result<Option<ConfigRevision>> {
+        let rtxn = self.env.read_txn()?;
+        match self.history.get(&rtxn, &format!("{:010}", rev))? {
+            Some(json) => Ok(Some(serde_json::from_str(json)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Most recent revision
+    pub fn latest_revision(&self) -> Result<Option<ConfigRevision>> {
+        let rtxn = self.env.read_txn()?;
+        self.latest_revision_txn(&rtxn)
+    }
+
+    /// All revisions, oldest first
+    pub fn list_revisions(&self) -> Result<Vec<ConfigRevision>> {
+        let rtxn = self.env.read_txn()?;
+        let mut revisions = Vec::new();
+        for result in self.history.iter(&rtxn)? {
+            let (_, json) = result?;
+            revisions.push(serde_json::from_str(json)?);
+        }
+        Ok(revisions)
+    }
+
+    /// Restore CONFIG.DB to the snapshot of `rev` in a single transaction.
+    /// The rollback itself is recorded as a new revision. Returns its number,
+    /// or None if the current state already matches.
+    pub fn rollback(&self, rev: u64, actor: &str) -> Result<Option<u64>> {
+        let target = self.get_revision(rev)?
+            .ok_or_else(|| anyhow!("Revision {} not found", rev))?;
+
+        self.commit_shedding_history(|wtxn| {
+            self.config.clear(wtxn)?;
+            self.paths.clear(wtxn)?;
+            self.patterns.clear(wtxn)?;
+            for (key, value) in &target.snapshot.config {
+                self.config.put(wtxn, key, value)?;
+            }
+            for rule in &target.snapshot.paths {
+                self.paths.put(wtxn, rule, &true)?;
+            }
+            for (pattern, severity) in &target.snapshot.patterns {
+                self.patterns.put(wtxn, pattern, severity)?;
+            }
+            self.record_revision(wtxn, actor)
+        })
+    }
+
     // ========================================================================
     // MIGRATION
     // ========================================================================
@@ -395,15 +804,13 @@ impl SpfConfigDb {
         self.block_path(&format!("{}/scripts/", root))?;
 
         // Default dangerous patterns
-        self.add_dangerous_pattern("rm -rf /", 10)?;
-        self.add_dangerous_pattern("rm -rf ~", 10)?;
-        self.add_dangerous_pattern("dd if=", 9)?;
-        self.add_dangerous_pattern("> /dev/", 9)?;
-        self.add_dangerous_pattern("chmod 777", 7)?;
-        self.add_dangerous_pattern("curl | sh", 8)?;
-        self.add_dangerous_pattern("wget | sh", 8)?;
-        self.add_dangerous_pattern("curl|sh", 8)?;
-        self.add_dangerous_pattern("wget|sh", 8)?;
+        for (pattern, severity) in DEFAULT_DANGEROUS_PATTERNS {
+            self.add_dangerous_pattern(pattern, *severity)?;
+        }
+        self.set_severity_thresholds(&SeverityThresholds::default())?;
+
+        // Default policy — reproduces the compiled allowlist
+        self.set_policy(&Policy::default())?;
 
         log::info!("SPF Config LMDB initialized with defaults");
         Ok(())
@@ -417,10 +824,12 @@ impl SpfConfigDb {
         let mut changed = false;
 
         // === APPROVAL POLICY (edit here to change) ===
+        // Enforced by the MCP approval queue — every tier marked true holds
+        // calls until a human runs `spf-smart-gate approvals approve <id>`.
         let policy: [(&str, bool); 4] = [
-            ("SIMPLE",   true),
-            ("LIGHT",    true),
-            ("MEDIUM",   true),
+            ("SIMPLE",   false),
+            ("LIGHT",    false),
+            ("MEDIUM",   false),
             ("CRITICAL", true),
         ];
 
@@ -445,8 +854,22 @@ impl SpfConfigDb {
         }
 
         // Keep version current
-        self.set("spf", "version", "2.0.0")?;
+        if self.get("spf", "version")?.as_deref() != Some("2.0.0") {
+            self.set("spf", "version", "2.0.0")?;
+        }
+
+        Ok(())
+    }
 
+    /// Upgrade the stored policy with built-in tools added since it was written.
+    /// Runs on every boot alongside sync_tier_approval.
+    pub fn sync_policy(&self) -> Result<()> {
+        if let Some(mut policy) = self.get_typed::<Policy>("spf", "policy")? {
+            if policy.upgrade() {
+                self.set_typed("spf", "policy", &policy)?;
+                log::info!("SPF policy upgraded to version {}", policy.version);
+            }
+        }
         Ok(())
     }
 
@@ -467,8 +890,17 @@ impl SpfConfigDb {
     /// This is the PRIMARY config loading method - replaces JSON file loading.
     pub fn load_full_config(&self) -> Result<SpfConfig> {
         // Ensure defaults exist, then sync approval policy from code
-        self.init_defaults()?;
-        self.sync_tier_approval()?;
+        self.with_revision("boot", |db| {
+            db.init_defaults()?;
+            db.sync_tier_approval()?;
+            db.sync_policy()
+        })?;
+        self.read_full_config()
+    }
+
+    /// Assemble SpfConfig from what is stored, without initializing or syncing.
+    /// Used for hot-reload — never writes.
+    pub fn read_full_config(&self) -> Result<SpfConfig> {
 
         // Collect path rules
         let path_rules = self.list_path_rules()?;
@@ -482,10 +914,10 @@ impl SpfConfigDb {
             }
         }
 
-        // Collect dangerous commands
-        let dangerous_commands: Vec<String> = self.list_dangerous_patterns()?
-            .into_iter()
-            .map(|(pattern, _)| pattern)
+        // Collect dangerous commands with their severity
+        let dangerous_patterns = self.list_dangerous_patterns()?;
+        let dangerous_commands: Vec<String> = dangerous_patterns.iter()
+            .map(|(pattern, _)| pattern.clone())
             .collect();
 
         // Get scalar values
@@ -501,17 +933,150 @@ impl SpfConfigDb {
             enforce_mode: self.get_enforce_mode()?,
             allowed_paths,
             blocked_paths,
+            path_precedence: self.get_path_precedence()?,
             require_read_before_edit: require_read,
             max_write_size: max_write,
             tiers: self.get_tiers()?,
             formula: self.get_formula()?,
             complexity_weights: self.get_weights()?,
             dangerous_commands,
+            dangerous_severity: dangerous_patterns.into_iter().collect(),
+            severity_thresholds: self.get_severity_thresholds()?,
+            session_budget: self.get_session_budget()?,
+            exec_env: self.get_exec_env()?,
+            output_limits: self.get_output_limits()?,
             git_force_patterns: vec![
                 "--force".to_string(),
                 "--hard".to_string(),
                 "-f".to_string(),
             ],
+            policy: self.get_policy()?,
+            candidate_policy: self.get_candidate_policy()?,
         })
     }
 }
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn mutations_are_recorded_and_rolled_back() -> Result<()> {
+        let dir = tempdir()?;
+        let db = SpfConfigDb::open(dir.path())?;
+        db.load_full_config()?;
+        let boot = db.list_revisions()?;
+        assert_eq!(boot.len(), 1);
+        assert_eq!(boot[0].actor, "boot");
+
+        // Reloading without changes records nothing
+        db.load_full_config()?;
+        assert_eq!(db.list_revisions()?.len(), 1);
+
+        db.with_revision("config-import test", |db| {
+            let mut tiers = db.get_tiers()?;
+            tiers.light.max_c = 3000;
+            db.set_tiers(&tiers)?;
+            db.block_path("/srv/")?;
+            db.add_dangerous_pattern("shutdown", 9)
+        })?;
+        db.allow_path("/opt/")?;
+
+        let revisions = db.list_revisions()?;
+        assert_eq!(revisions.len(), 3);
+        let import = &revisions[1];
+        assert_eq!(import.actor, "config-import test");
+        assert!(import.changes.contains(&"~ spf:tiers.light.max_c: 2000 → 3000".to_string()));
+        assert!(import.changes.contains(&"+ path blocked:/srv/".to_string()));
+        assert!(import.changes.contains(&"+ pattern \"shutdown\" = 9".to_string()));
+        assert_eq!(revisions[2].actor, "api");
+
+        let restored = db.rollback(1, "rollback")?;
+        assert_eq!(restored, Some(4));
+        assert_eq!(db.get_tiers()?.light.max_c, 2000);
+        assert!(!db.list_path_rules()?.iter().any(|(_, p)| p == "/srv/" || p == "/opt/"));
+        assert_eq!(db.check_dangerous("shutdown now")?, None);
+        assert_eq!(db.snapshot()?, revisions[0].snapshot);
+
+        assert!(db.rollback(99, "rollback").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn generation_counts_recorded_revisions() -> Result<()> {
+        let dir = tempdir()?;
+        let db = SpfConfigDb::open(dir.path())?;
+        assert_eq!(db.generation()?, 0);
+        db.load_full_config()?;
+        let booted = db.generation()?;
+        assert_eq!(booted, 1);
+
+        // Reading and no-op writes leave the generation alone
+        db.read_full_config()?;
+        db.load_full_config()?;
+        db.set_enforce_mode(&db.get_enforce_mode()?)?;
+        assert_eq!(db.generation()?, booted);
+
+        // A batch is one generation
+        db.with_revision("test", |db| {
+            db.block_path("/srv/")?;
+            db.set_enforce_mode(&EnforceMode::Soft)
+        })?;
+        assert_eq!(db.generation()?, booted + 1);
+        assert_eq!(db.read_full_config()?.enforce_mode, EnforceMode::Soft);
+        Ok(())
+    }
+
+    #[test]
+    fn history_stays_bounded_and_config_reloads() -> Result<()> {
+        use crate::policy::{PolicyRule, RuleAction};
+        // ~60KB policy per revision; 120 imports would be ~7MB of snapshots
+        let import = |db: &SpfConfigDb, i: usize| -> Result<()> {
+            db.with_revision("config-import test", |db| {
+                let mut policy = db.get_policy()?;
+                policy.rules = (0..600).map(|n| PolicyRule {
+                    tool: "spf_write".to_string(),
+                    path: Some(format!("/srv/project-{}/protected/{}/", n, i)),
+                    min_tier: None,
+                    action: RuleAction::Deny,
+                    reason: "bulk import".to_string(),
+                }).collect();
+                db.set_policy(&policy)
+            })
+        };
+        let history_bytes = |db: &SpfConfigDb| -> Result<usize> {
+            Ok(db.list_revisions()?.iter().map(|r| serde_json::to_string(r).map(|j| j.len())).sum::<serde_json::Result<usize>>()?)
+        };
+
+        let dir = tempdir()?;
+        let db = SpfConfigDb::open(dir.path())?;
+        db.load_full_config()?;
+        for i in 0..120 {
+            import(&db, i)?;
+        }
+        assert!(history_bytes(&db)? <= HISTORY_MAX_BYTES);
+        let latest = db.latest_revision()?.expect("history kept");
+        assert_eq!(latest.snapshot, db.snapshot()?);
+        drop(db);
+        let db = SpfConfigDb::open(dir.path())?;
+        assert_eq!(db.load_full_config()?.policy.rules.len(), 600);
+
+        // A map too small for the byte budget sheds history instead of failing
+        let dir = tempdir()?;
+        let db = SpfConfigDb::open_sized(dir.path(), 2 * 1024 * 1024)?;
+        db.load_full_config()?;
+        for i in 0..60 {
+            import(&db, i)?;
+        }
+        drop(db);
+        let db = SpfConfigDb::open_sized(dir.path(), 2 * 1024 * 1024)?;
+        assert_eq!(db.load_full_config()?.policy.rules.len(), 600);
+        assert_eq!(db.latest_revision()?.expect("newest kept").snapshot, db.snapshot()?);
+        Ok(())
+    }
+}
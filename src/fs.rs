@@ -473,6 +473,19 @@ impl SpfFs {
         Ok(())
     }
 
+    /// Set the permission bits recorded for a file or directory
+    pub fn set_mode(&self, path: &str, mode: u32) -> Result<()> {
+        let path = normalize_path(path);
+        let mut meta = self.stat(&path)?
+            .ok_or_else(|| anyhow!("Not found: {}", path))?;
+        meta.mode = mode;
+
+        let mut wtxn = self.env.write_txn()?;
+        self.metadata.put(&mut wtxn, &path, &meta)?;
+        wtxn.commit()?;
+        Ok(())
+    }
+
     // ========================================================================
     // VECTOR INDEX (Reverse RAG Lookup)
     // ========================================================================
@@ -600,7 +613,7 @@ fn unix_now() -> i64 {
 }
 
 /// SHA256 hash as hex string
-fn sha256_hex(data: &[u8]) -> String {
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
     let mut hasher = Sha256::new();
     hasher.update(data);
     let result = hasher.finalize();
@@ -3,14 +3,17 @@
 //
 // Every tool call passes through here. Calculate -> Validate -> Allow/Warn.
 // Max mode: violations warn + force CRITICAL tier. Never blocks — escalates.
-// Enforcement: compiled validation rules, write whitelist, path blocking,
-// Build Anchor protocol, content inspection. No runtime config bypass.
+// Enforcement: policy-routed validation (policy.rs), write whitelist, path
+// blocking, Build Anchor protocol, content inspection. The fs hard-block and
+// write validators are compiled in — policy cannot bypass them.
 
 use chrono::Utc;
-use crate::calculate::{self, ComplexityResult, ToolParams};
-use crate::config::{EnforceMode, SpfConfig};
+use crate::calculate::{self, ComplexityResult, ComplexityTerms, ToolParams};
+use crate::config::{BudgetAction, EnforceMode, SeverityBand, SpfConfig};
 use crate::inspect;
+use crate::policy::{self, InspectField, Policy, RuleAction, Validator};
 use crate::session::Session;
+use crate::shadow::{ShadowOutcome, ShadowRecord};
 use crate::validate;
 use serde::{Deserialize, Serialize};
 
@@ -23,15 +26,75 @@ pub struct GateDecision {
     pub warnings: Vec<String>,
     pub errors: Vec<String>,
     pub message: String,
+    /// Audit mode: where the candidate policy disagreed with this decision
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<ShadowRecord>,
+    /// Structured explanation — only populated by explain()
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace: Option<GateTrace>,
+}
+
+/// One pipeline stage in a decision trace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceStep {
+    /// rate_limit, calculate, budget, validate, inspect, policy_rule, escalate
+    pub stage: String,
+    /// Validator, inspector, rule or tier that ran
+    pub name: String,
+    /// pass, warn, block, escalate, require_approval
+    pub outcome: String,
+    /// Matched patterns, rule text or counters
+    pub detail: Vec<String>,
+}
+
+impl TraceStep {
+    fn new(stage: &str, name: &str, outcome: &str, detail: Vec<String>) -> Self {
+        Self {
+            stage: stage.to_string(),
+            name: name.to_string(),
+            outcome: outcome.to_string(),
+            detail,
+        }
+    }
+}
+
+/// Why a call got its decision and tier
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GateTrace {
+    pub terms: ComplexityTerms,
+    pub steps: Vec<TraceStep>,
+}
+
+impl GateTrace {
+    /// Plain-text rendering for MCP and CLI output
+    pub fn render(&self, config: &SpfConfig) -> String {
+        let f = &config.formula;
+        let t = &self.terms;
+        let mut out = format!(
+            "C = basic^{} + deps^{} + complex^{} + files×{}\n  = {}^{} + {}^{} + {}^{} + {}×{}\n  = {} + {} + {} + {} = {}\n",
+            f.basic_power, f.deps_power, f.complex_power, f.files_multiplier,
+            t.basic, f.basic_power, t.dependencies, f.deps_power,
+            t.complex, f.complex_power, t.files, f.files_multiplier,
+            t.basic_term, t.deps_term, t.complex_term, t.files_term, t.c(),
+        );
+        for step in &self.steps {
+            out.push_str(&format!("[{}] {} → {}\n", step.stage, step.name, step.outcome.to_uppercase()));
+            for d in &step.detail {
+                out.push_str(&format!("    {}\n", d));
+            }
+        }
+        out
+    }
 }
 
 /// Human-readable summary of what the action will do.
 /// Used for logging and audit output.
 fn format_params(tool: &str, params: &ToolParams) -> String {
     match tool {
-        "Bash" | "spf_bash" => {
-            format!("Command: {}", params.command.as_deref().unwrap_or("(none)"))
-        }
+        "Bash" | "spf_bash" | "spf_bash_start" => match params.cwd {
+            Some(ref cwd) => format!("Command: {} | Cwd: {}", params.command.as_deref().unwrap_or("(none)"), cwd),
+            None => format!("Command: {}", params.command.as_deref().unwrap_or("(none)")),
+        },
         "Read" | "spf_read" => {
             format!("File: {}", params.file_path.as_deref().unwrap_or("(none)"))
         }
@@ -49,6 +112,12 @@ fn format_params(tool: &str, params: &ToolParams) -> String {
                 params.file_path.as_deref().unwrap_or("(none)"),
                 old_preview, new_preview)
         }
+        "spf_apply_patch" => {
+            let stats = crate::patch::stats(params.patch.as_deref().unwrap_or(""));
+            format!("File: {} | Patch: {} hunks, +{} -{} lines",
+                params.file_path.as_deref().unwrap_or("(none)"),
+                stats.hunks, stats.added, stats.removed)
+        }
         "Glob" | "spf_glob" => {
             format!("Pattern: {} | Path: {}",
                 params.command.as_deref().unwrap_or("*"),
@@ -76,6 +145,39 @@ fn format_params(tool: &str, params: &ToolParams) -> String {
     }
 }
 
+/// Run the validate.rs check a tool is routed to
+fn run_validator(
+    validator: Validator,
+    params: &ToolParams,
+    config: &SpfConfig,
+    session: &Session,
+) -> validate::ValidationResult {
+    let file_path = params.file_path.as_deref().unwrap_or("unknown");
+    match validator {
+        Validator::None => validate::ValidationResult::ok(),
+        Validator::Read => validate::validate_read(file_path, config),
+        Validator::Edit => validate::validate_edit(file_path, config, session),
+        Validator::Write => {
+            let content_len = params.content.as_ref().map(|c| c.len()).unwrap_or(0);
+            validate::validate_write(file_path, content_len, config, session)
+        }
+        // content_len unknown pre-download — pass 0, path checks still enforce
+        Validator::Download => validate::validate_write(file_path, 0, config, session),
+        Validator::Bash => {
+            let command = params.command.as_deref().unwrap_or("");
+            validate::validate_bash_in(command, params.cwd.as_deref(), config)
+        }
+    }
+}
+
+/// Force complexity to CRITICAL tier (approval required)
+fn escalate_to_critical(complexity: &mut ComplexityResult, config: &SpfConfig) {
+    complexity.tier = "CRITICAL".to_string();
+    complexity.analyze_percent = config.tiers.critical.analyze_percent;
+    complexity.build_percent = config.tiers.critical.build_percent;
+    complexity.requires_approval = true;
+}
+
 // ========================================================================
 // GATE PROCESS — primary enforcement
 // ========================================================================
@@ -83,17 +185,91 @@ fn format_params(tool: &str, params: &ToolParams) -> String {
 /// Process a tool call through the gate
 ///
 /// Pipeline:
-/// 1. Calculate complexity (C, tier, allocation)
+/// 1. Calculate complexity (C, tier, allocation), charge it against the session budget
 /// 2. Validate against rules (blocked paths, Build Anchor, write whitelist, dangerous cmds)
-/// 3. Content inspection on Write/Edit
+/// 3. Content inspection on Write/Edit, then ordered policy rules
 /// 4. Max mode: if warnings present, escalate to CRITICAL tier (warn, don't block)
 /// 5. Return allow/block decision
+///
+/// Audit mode enforces like Max and additionally evaluates the candidate
+/// policy; disagreements are attached as `shadow`. The active decision governs.
 pub fn process(
     tool: &str,
     params: &ToolParams,
     config: &SpfConfig,
     session: &Session,
 ) -> GateDecision {
+    run(tool, params, config, session, false)
+}
+
+/// Same as process(), with a structured trace of every stage attached
+pub fn explain(
+    tool: &str,
+    params: &ToolParams,
+    config: &SpfConfig,
+    session: &Session,
+) -> GateDecision {
+    run(tool, params, config, session, true)
+}
+
+fn run(
+    tool: &str,
+    params: &ToolParams,
+    config: &SpfConfig,
+    session: &Session,
+    with_trace: bool,
+) -> GateDecision {
+    let (mut decision, trace) = evaluate(tool, params, config, &config.policy, session);
+    if with_trace {
+        decision.trace = Some(trace);
+    }
+
+    if config.enforce_mode == EnforceMode::Audit {
+        if let Some(ref candidate) = config.candidate_policy {
+            let (shadow, _) = evaluate(tool, params, config, candidate, session);
+            decision.shadow = ShadowRecord::compare(
+                tool,
+                params.file_path.as_deref(),
+                outcome(&decision),
+                outcome(&shadow),
+                shadow.errors,
+            );
+        }
+    }
+
+    decision
+}
+
+fn outcome(decision: &GateDecision) -> ShadowOutcome {
+    ShadowOutcome {
+        allowed: decision.allowed,
+        tier: decision.complexity.tier.clone(),
+        requires_approval: decision.complexity.requires_approval,
+    }
+}
+
+/// Verdict for a validation-style stage
+fn verdict(result: &validate::ValidationResult) -> &'static str {
+    if !result.valid {
+        "block"
+    } else if result.warnings.is_empty() {
+        "pass"
+    } else {
+        "warn"
+    }
+}
+
+/// Run the full pipeline against one policy document
+fn evaluate(
+    tool: &str,
+    params: &ToolParams,
+    config: &SpfConfig,
+    policy: &Policy,
+    session: &Session,
+) -> (GateDecision, GateTrace) {
+    let terms = calculate::calculate_terms(tool, params, config);
+    let mut steps = Vec::new();
+
     // Rate limiting — max operations per minute by category
     let now = Utc::now();
     let one_minute_ago = now - chrono::Duration::seconds(60);
@@ -101,16 +277,18 @@ pub fn process(
         .filter(|ts| **ts > one_minute_ago)
         .count();
 
-    let max_per_minute = match tool {
-        "Write" | "spf_write" | "Edit" | "spf_edit" |
-        "Bash" | "spf_bash" | "spf_web_download" | "spf_notebook_edit" => 60,
-        "spf_web_fetch" | "spf_web_search" | "spf_web_api" => 30,
-        _ => 120,  // reads, search, status — more lenient
-    };
+    let max_per_minute = policy.rate_limit(tool) as usize;
+    let rate_limited = recent_count >= max_per_minute;
+    steps.push(TraceStep::new(
+        "rate_limit",
+        tool,
+        if rate_limited { "block" } else { "pass" },
+        vec![format!("{} calls in last minute (max {})", recent_count, max_per_minute)],
+    ));
 
-    if recent_count >= max_per_minute {
+    if rate_limited {
         let msg = format!("RATE LIMITED: {} calls in last minute (max {})", recent_count, max_per_minute);
-        return GateDecision {
+        let decision = GateDecision {
             allowed: false,
             tool: tool.to_string(),
             complexity: ComplexityResult {
@@ -125,125 +303,184 @@ pub fn process(
             warnings: vec![],
             errors: vec![msg.clone()],
             message: format!("BLOCKED | {} | {}", tool, msg),
+            shadow: None,
+            trace: None,
         };
+        return (decision, GateTrace { terms, steps });
     }
 
     // Step 1: Calculate complexity
     let mut complexity = calculate::calculate(tool, params, config);
+    steps.push(TraceStep::new(
+        "calculate",
+        &complexity.tier,
+        "pass",
+        vec![format!("C={} requires_approval={}", complexity.c, complexity.requires_approval)],
+    ));
 
     let mut warnings = Vec::new();
     let mut errors = Vec::new();
 
+    // Step 1b: Session budget — cumulative C and per-tier call counts
+    let mut budget_denied = false;
+    let budget = &config.session_budget;
+    if let Some(reason) = budget.exceeded(session, complexity.c, &complexity.tier) {
+        let outcome = match budget.on_exhausted {
+            BudgetAction::Escalate => {
+                escalate_to_critical(&mut complexity, config);
+                warnings.push(format!("BUDGET: {} — escalated to CRITICAL", reason));
+                "escalate"
+            }
+            BudgetAction::Block => {
+                budget_denied = true;
+                errors.push(format!("BLOCKED: {}", reason));
+                "block"
+            }
+        };
+        steps.push(TraceStep::new("budget", "session_budget", outcome, vec![reason]));
+    }
+
     // Step 2: Validate against rules
-    let validation = match tool {
-        "Edit" | "spf_edit" => {
-            let file_path = params.file_path.as_deref().unwrap_or("unknown");
-            validate::validate_edit(file_path, config, session)
-        }
-        "Write" | "spf_write" => {
-            let file_path = params.file_path.as_deref().unwrap_or("unknown");
-            let content_len = params.content.as_ref().map(|c| c.len()).unwrap_or(0);
-            validate::validate_write(file_path, content_len, config, session)
-        }
-        "Bash" | "spf_bash" => {
-            let command = params.command.as_deref().unwrap_or("");
-            validate::validate_bash(command, config)
-        }
-        "Read" | "spf_read" => {
-            let file_path = params.file_path.as_deref().unwrap_or("unknown");
-            validate::validate_read(file_path, config)
-        }
-        "spf_web_download" => {
-            let file_path = params.file_path.as_deref().unwrap_or("unknown");
-            // content_len unknown pre-download — pass 0, path checks still enforce
-            validate::validate_write(file_path, 0, config, session)
-        }
-        "spf_notebook_edit" => {
-            let file_path = params.file_path.as_deref().unwrap_or("unknown");
-            let content_len = params.content.as_ref().map(|c| c.len()).unwrap_or(0);
-            validate::validate_write(file_path, content_len, config, session)
-        }
+    // Compiled core first: hard-blocked tools never reach the policy
+    let entry = policy.tool(tool);
+    let (validation, validator_name) = if policy::is_hard_blocked(tool) {
         // HARD BLOCK — spf_fs_* tools are USER/SYSTEM-ONLY, never allow via MCP
-        "spf_fs_import" | "spf_fs_export" |
-        "spf_fs_exists" | "spf_fs_stat" | "spf_fs_ls" | "spf_fs_read" |
-        "spf_fs_write" | "spf_fs_mkdir" | "spf_fs_rm" | "spf_fs_rename" => {
-            validate::ValidationResult {
-                valid: false,
-                warnings: vec![],
-                errors: vec![format!("BLOCKED: {} is a user/system-only command — not available to AI agents", tool)],
-            }
-        }
-        // Known tools that don't need path/write validation — explicitly allowed
-        "spf_calculate" | "spf_status" | "spf_session" |
-        "spf_glob" | "spf_grep" |
-        "spf_web_search" | "spf_web_fetch" | "spf_web_api" |
-        "spf_brain_search" | "spf_brain_store" | "spf_brain_context" |
-        "spf_brain_index" | "spf_brain_list" | "spf_brain_status" |
-        "spf_brain_recall" | "spf_brain_list_docs" | "spf_brain_get_doc" |
-        "spf_rag_collect_web" | "spf_rag_collect_file" | "spf_rag_collect_folder" |
-        "spf_rag_collect_drop" | "spf_rag_index_gathered" | "spf_rag_dedupe" |
-        "spf_rag_status" | "spf_rag_list_gathered" | "spf_rag_bandwidth_status" |
-        "spf_rag_fetch_url" | "spf_rag_collect_rss" | "spf_rag_list_feeds" |
-        "spf_rag_pending_searches" | "spf_rag_fulfill_search" |
-        "spf_rag_smart_search" | "spf_rag_auto_fetch_gaps" |
-        "spf_config_paths" | "spf_config_stats" |
-        "spf_projects_list" | "spf_projects_get" | "spf_projects_set" |
-        "spf_projects_delete" | "spf_projects_stats" |
-        "spf_tmp_list" | "spf_tmp_stats" | "spf_tmp_get" | "spf_tmp_active" |
-        "spf_agent_stats" | "spf_agent_memory_search" | "spf_agent_memory_by_tag" |
-        "spf_agent_session_info" | "spf_agent_context"
-            => validate::ValidationResult::ok(),
+        (validate::ValidationResult {
+            valid: false,
+            warnings: vec![],
+            errors: vec![format!("BLOCKED: {} is a user/system-only command — not available to AI agents", tool)],
+            write_targets: vec![],
+        }, "hard_block (compiled core)".to_string())
+    } else if let Some(entry) = entry {
+        // Mutating tools always run their write validator, whatever the policy says
+        let (validator, source) = match policy::core_validator(tool) {
+            Some(core) => (core, "compiled core"),
+            None => (entry.validator, "policy"),
+        };
+        (run_validator(validator, params, config, session), format!("{:?} ({})", validator, source))
+    } else {
         // DEFAULT DENY — unknown tools blocked until explicitly added to allowlist
-        _ => {
-            validate::ValidationResult {
-                valid: false,
-                warnings: vec![],
-                errors: vec![format!("BLOCKED: unknown tool '{}' — not in gate allowlist", tool)],
-            }
-        }
+        (validate::ValidationResult {
+            valid: false,
+            warnings: vec![],
+            errors: vec![format!("BLOCKED: unknown tool '{}' — not in gate allowlist", tool)],
+            write_targets: vec![],
+        }, "default_deny".to_string())
     };
+    steps.push(TraceStep::new(
+        "validate",
+        &validator_name,
+        verdict(&validation),
+        validation.errors.iter().chain(&validation.warnings).cloned().collect(),
+    ));
 
     warnings.extend(validation.warnings);
     errors.extend(validation.errors);
 
-    // Step 3: Content inspection on Write/Edit operations
-    let inspection = match tool {
-        "Write" | "spf_write" => {
-            let file_path = params.file_path.as_deref().unwrap_or("unknown");
-            let content = params.content.as_deref().unwrap_or("");
-            inspect::inspect_content(content, file_path, config)
-        }
-        "Edit" | "spf_edit" => {
-            let file_path = params.file_path.as_deref().unwrap_or("unknown");
-            let new_string = params.new_string.as_deref().unwrap_or("");
-            inspect::inspect_content(new_string, file_path, config)
+    // Step 2b: Dangerous-command severity — the middle band forces CRITICAL + approval
+    // (the block band is already an error from validate_bash, the low band only warns)
+    if entry.is_some() && policy::core_validator(tool) == Some(Validator::Bash) {
+        let command = params.command.as_deref().unwrap_or("");
+        let severity = config.command_severity(command);
+        if severity > 0 && config.severity_thresholds.band(severity) == SeverityBand::Critical {
+            escalate_to_critical(&mut complexity, config);
+            steps.push(TraceStep::new(
+                "severity",
+                &format!("severity {}", severity),
+                "escalate",
+                vec![format!(
+                    "critical ≥{}, block ≥{}",
+                    config.severity_thresholds.critical, config.severity_thresholds.block
+                )],
+            ));
         }
-        "spf_notebook_edit" => {
+    }
+
+    // Step 3: Content inspection on Write/Edit operations
+    // Safe: unknown tools already blocked by validation above (allowed = valid && valid)
+    let inspection = match entry.map(|e| e.inspect) {
+        Some(field @ (InspectField::Content | InspectField::NewString)) => {
             let file_path = params.file_path.as_deref().unwrap_or("unknown");
-            let content = params.content.as_deref().unwrap_or("");
-            inspect::inspect_content(content, file_path, config)
+            let content = if field == InspectField::Content {
+                params.content.as_deref().unwrap_or("")
+            } else {
+                params.new_string.as_deref().unwrap_or("")
+            };
+            let result = inspect::inspect_content(content, file_path, config);
+            steps.push(TraceStep::new(
+                "inspect",
+                &format!("{:?}", field),
+                verdict(&result),
+                result.errors.iter().chain(&result.warnings).cloned().collect(),
+            ));
+            result
         }
-        // Safe: unknown tools already blocked by validation above (allowed = valid && valid)
         _ => validate::ValidationResult::ok(),
     };
 
     warnings.extend(inspection.warnings);
     errors.extend(inspection.errors);
 
+    // Step 3b: Policy rules — first match wins
+    let mut policy_denied = false;
+    if let Some((index, rule)) = policy.first_match(tool, params.file_path.as_deref(), &complexity.tier) {
+        let reason = if rule.reason.is_empty() {
+            format!("rule for '{}'", rule.tool)
+        } else {
+            rule.reason.clone()
+        };
+        let outcome = match rule.action {
+            RuleAction::Deny => {
+                policy_denied = true;
+                errors.push(format!("BLOCKED: policy — {}", reason));
+                "block"
+            }
+            RuleAction::Warn => {
+                warnings.push(format!("POLICY: {}", reason));
+                "warn"
+            }
+            RuleAction::Escalate => {
+                escalate_to_critical(&mut complexity, config);
+                warnings.push(format!("POLICY: {} — escalated to CRITICAL", reason));
+                "escalate"
+            }
+            RuleAction::RequireApproval => {
+                complexity.requires_approval = true;
+                warnings.push(format!("POLICY: {} — approval required", reason));
+                "require_approval"
+            }
+        };
+        steps.push(TraceStep::new(
+            "policy_rule",
+            &format!("rules[{}]", index),
+            outcome,
+            vec![
+                format!(
+                    "tool={} path={} min_tier={}",
+                    rule.tool,
+                    rule.path.as_deref().unwrap_or("*"),
+                    rule.min_tier.as_deref().unwrap_or("any"),
+                ),
+                reason,
+            ],
+        ));
+    }
+
     // Step 4: Max mode escalation — if any "MAX TIER:" warnings present,
-    // force complexity to CRITICAL tier instead of blocking
-    if config.enforce_mode == EnforceMode::Max {
-        let has_max_warnings = warnings.iter().any(|w| w.starts_with("MAX TIER:"));
-        if has_max_warnings {
-            complexity.tier = "CRITICAL".to_string();
-            complexity.analyze_percent = config.tiers.critical.analyze_percent;
-            complexity.build_percent = config.tiers.critical.build_percent;
-            complexity.requires_approval = true;
+    // force complexity to CRITICAL tier instead of blocking (Audit enforces as Max)
+    if matches!(config.enforce_mode, EnforceMode::Max | EnforceMode::Audit) {
+        let triggers: Vec<String> = warnings.iter()
+            .filter(|w| w.starts_with("MAX TIER:"))
+            .cloned()
+            .collect();
+        if !triggers.is_empty() {
+            escalate_to_critical(&mut complexity, config);
             warnings.push("ESCALATED TO CRITICAL TIER — Max mode enforcement".to_string());
+            steps.push(TraceStep::new("escalate", "max_mode", "escalate", triggers));
         }
     }
 
-    let allowed = validation.valid && inspection.valid;
+    let allowed = validation.valid && inspection.valid && !policy_denied && !budget_denied;
 
     // Build message with action details
     let details = format_params(tool, params);
@@ -262,14 +499,17 @@ pub fn process(
         )
     };
 
-    GateDecision {
+    let decision = GateDecision {
         allowed,
         tool: tool.to_string(),
         complexity,
         warnings,
         errors,
         message,
-    }
+        shadow: None,
+        trace: None,
+    };
+    (decision, GateTrace { terms, steps })
 }
 
 // ============================================================================
@@ -286,6 +526,25 @@ mod tests {
         SpfConfig::default()
     }
 
+    #[test]
+    fn mid_severity_forces_critical_approval() {
+        let mut config = default_config();
+        config.enforce_mode = EnforceMode::Soft;
+        config.dangerous_commands.push("reboot".to_string());
+        config.dangerous_severity.insert("reboot".to_string(), 6);
+        let session = Session::new();
+        let params = ToolParams { command: Some("reboot".to_string()), ..Default::default() };
+
+        let decision = explain("spf_bash", &params, &config, &session);
+        assert!(decision.allowed, "{:?}", decision.errors);
+        assert_eq!(decision.complexity.tier, "CRITICAL");
+        assert!(decision.complexity.requires_approval);
+        assert!(decision.trace.unwrap().steps.iter().any(|s| s.stage == "severity"));
+
+        config.dangerous_severity.insert("reboot".to_string(), 9);
+        assert!(!process("spf_bash", &params, &config, &session).allowed);
+    }
+
     #[test]
     fn allowed_tool_passes_gate() {
         let config = default_config();
@@ -329,4 +588,160 @@ mod tests {
             assert!(!decision.allowed, "{} should be BLOCKED", tool);
         }
     }
+
+    #[test]
+    fn policy_rules_apply_in_order() {
+        use crate::policy::{PolicyRule, RuleAction};
+        let mut config = default_config();
+        config.policy.rules = vec![
+            PolicyRule {
+                tool: "spf_brain_*".to_string(),
+                path: None,
+                min_tier: None,
+                action: RuleAction::Deny,
+                reason: "brain offline".to_string(),
+            },
+            PolicyRule {
+                tool: "*".to_string(),
+                path: None,
+                min_tier: None,
+                action: RuleAction::RequireApproval,
+                reason: String::new(),
+            },
+        ];
+        let session = Session::new();
+        let params = ToolParams::default();
+
+        let decision = process("spf_brain_search", &params, &config, &session);
+        assert!(!decision.allowed);
+        assert!(decision.errors.iter().any(|e| e.contains("brain offline")));
+
+        let decision = process("spf_status", &params, &config, &session);
+        assert!(decision.allowed);
+        assert!(decision.complexity.requires_approval);
+    }
+
+    #[test]
+    fn audit_mode_records_disagreement_but_active_governs() {
+        use crate::policy::{PolicyRule, RuleAction};
+        let mut config = default_config();
+        let mut candidate = config.policy.clone();
+        candidate.rules.push(PolicyRule {
+            tool: "spf_status".to_string(),
+            path: None,
+            min_tier: None,
+            action: RuleAction::Deny,
+            reason: "trial".to_string(),
+        });
+        config.candidate_policy = Some(candidate);
+        let session = Session::new();
+        let params = ToolParams::default();
+
+        // Max mode ignores the candidate
+        let decision = process("spf_status", &params, &config, &session);
+        assert!(decision.shadow.is_none());
+
+        config.enforce_mode = EnforceMode::Audit;
+        let decision = process("spf_status", &params, &config, &session);
+        assert!(decision.allowed, "active policy must govern");
+        let shadow = decision.shadow.expect("disagreement recorded");
+        assert!(!shadow.candidate.allowed);
+        assert_eq!(shadow.change(), "ALLOW→BLOCK");
+
+        // Agreement — nothing recorded
+        let decision = process("spf_session", &params, &config, &session);
+        assert!(decision.shadow.is_none());
+    }
+
+    #[test]
+    fn session_budget_escalates_then_blocks() {
+        use crate::config::BudgetAction;
+        let mut config = default_config();
+        config.session_budget.max_per_tier.insert("SIMPLE".to_string(), 2);
+        let mut session = Session::new();
+        let params = ToolParams::default();
+
+        assert_eq!(process("spf_status", &params, &config, &session).complexity.tier, "SIMPLE");
+        session.record_complexity("spf_status", 1, "SIMPLE");
+        session.record_complexity("spf_status", 1, "SIMPLE");
+
+        let decision = explain("spf_status", &params, &config, &session);
+        assert!(decision.allowed);
+        assert_eq!(decision.complexity.tier, "CRITICAL");
+        assert!(decision.complexity.requires_approval);
+        assert!(decision.warnings.iter().any(|w| w.starts_with("BUDGET: session SIMPLE budget")));
+        assert!(decision.trace.unwrap().steps.iter().any(|s| s.stage == "budget"));
+
+        config.session_budget.on_exhausted = BudgetAction::Block;
+        let decision = process("spf_status", &params, &config, &session);
+        assert!(!decision.allowed);
+        assert!(decision.errors.iter().any(|e| e.contains("budget exhausted")));
+    }
+
+    #[test]
+    fn explain_traces_each_stage() {
+        let config = default_config();
+        let session = Session::new();
+        let params = ToolParams {
+            file_path: Some("/etc/hosts".to_string()),
+            content: Some("x".to_string()),
+            ..Default::default()
+        };
+
+        assert!(process("spf_write", &params, &config, &session).trace.is_none());
+
+        let decision = explain("spf_write", &params, &config, &session);
+        let trace = decision.trace.expect("explain attaches a trace");
+        assert_eq!(trace.terms.c(), decision.complexity.c);
+        let stages: Vec<&str> = trace.steps.iter().map(|s| s.stage.as_str()).collect();
+        assert_eq!(&stages[..4], &["rate_limit", "calculate", "validate", "inspect"]);
+        let validate = &trace.steps[2];
+        assert_eq!(validate.name, "Write (compiled core)");
+        assert_eq!(validate.outcome, "block");
+        assert!(!validate.detail.is_empty());
+        assert!(trace.render(&config).contains("[validate] Write (compiled core) → BLOCK"));
+    }
+
+    #[test]
+    fn bash_start_follows_its_own_policy_entry() {
+        let mut config = default_config();
+        let session = Session::new();
+        let params = ToolParams { command: Some("ls".to_string()), ..Default::default() };
+        assert!(process("spf_bash_start", &params, &config, &session).allowed);
+
+        // Removing only the background entry denies it; spf_bash is unaffected
+        config.policy.tools.retain(|t| !t.names.iter().any(|n| n == "spf_bash_start"));
+        assert!(!process("spf_bash_start", &params, &config, &session).allowed);
+        assert!(process("spf_bash", &params, &config, &session).allowed);
+    }
+
+    #[test]
+    fn policy_cannot_override_compiled_core() {
+        use crate::policy::{InspectField, ToolPolicy, Validator};
+        let mut config = default_config();
+        // Try to allow a hard-blocked tool and strip the write validator
+        config.policy.tools.push(ToolPolicy {
+            names: vec!["spf_fs_write".to_string()],
+            validator: Validator::None,
+            inspect: InspectField::None,
+            rate_limit: 120,
+        });
+        for entry in config.policy.tools.iter_mut() {
+            if entry.names.iter().any(|n| n == "spf_write") {
+                entry.validator = Validator::None;
+            }
+        }
+        let session = Session::new();
+
+        let decision = process("spf_fs_write", &ToolParams::default(), &config, &session);
+        assert!(!decision.allowed, "hard block must survive policy");
+
+        let params = ToolParams {
+            file_path: Some("/etc/passwd".to_string()),
+            content: Some("x".to_string()),
+            ..Default::default()
+        };
+        let decision = process("spf_write", &params, &config, &session);
+        assert!(!decision.allowed, "write allowlist must survive policy");
+    }
 }
@@ -81,7 +81,7 @@ fn check_credentials(
     for (pattern, description) in CREDENTIAL_PATTERNS {
         if content.contains(pattern) {
             match config.enforce_mode {
-                EnforceMode::Max => {
+                EnforceMode::Max | EnforceMode::Audit => {
                     result.warn(format!("MAX TIER: CREDENTIAL DETECTED — {}", description));
                 }
                 EnforceMode::Soft => {
@@ -100,7 +100,7 @@ fn check_path_traversal(
 ) {
     if content.contains("../") || content.contains("..\\") {
         match config.enforce_mode {
-            EnforceMode::Max => {
+            EnforceMode::Max | EnforceMode::Audit => {
                 result.warn("MAX TIER: PATH TRAVERSAL — content contains ../ sequences".to_string());
             }
             EnforceMode::Soft => {
@@ -119,7 +119,7 @@ fn check_shell_injection(
     for (pattern, description) in SHELL_INJECTION_PATTERNS {
         if content.contains(pattern) {
             match config.enforce_mode {
-                EnforceMode::Max => {
+                EnforceMode::Max | EnforceMode::Audit => {
                     result.warn(format!("MAX TIER: SHELL INJECTION — {}", description));
                 }
                 EnforceMode::Soft => {
@@ -0,0 +1,427 @@
+// SPF Smart Gateway - Background Jobs
+// Copyright 2026 Joseph Stone - All Rights Reserved
+//
+// Long-running spf_bash commands. spf_bash_start spawns the (already limited
+// and confined) command and returns a job ID instead of blocking the MCP loop.
+// A watcher thread per job enforces the wall-clock deadline and kill requests;
+// reader threads copy stdout/stderr into bounded logs that spf_bash_output
+// reads from a byte offset.
+//
+// Storage: LIVE/TMP/JOBS/<server pid>/<job id>/{job.json,stdout.log,stderr.log}
+// — outside the sandbox write roots, so a job cannot rewrite its own record.
+// Every running job is killed when the server exits (session end).
+
+use crate::sandbox::{self, Finished};
+use crate::tmp_db::ExecLimits;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Bytes kept on disk per stream; older output is dropped in halves
+pub const OUTPUT_CAP: u64 = 1 << 20;
+/// Most bytes returned per stream by one `output` call
+pub const READ_MAX: usize = 64 * 1024;
+/// Concurrently running jobs per server
+pub const MAX_RUNNING: usize = 8;
+
+/// Job lifecycle
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum JobStatus {
+    Running,
+    Exited { code: Option<i32> },
+    /// Stopped by spf_bash_kill or session end
+    Killed,
+    TimedOut,
+}
+
+impl std::fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Running => write!(f, "running"),
+            Self::Exited { code: Some(code) } => write!(f, "exited {}", code),
+            Self::Exited { code: None } => write!(f, "exited (signal)"),
+            Self::Killed => write!(f, "killed"),
+            Self::TimedOut => write!(f, "timed out"),
+        }
+    }
+}
+
+/// Persisted job record (job.json)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobInfo {
+    pub id: String,
+    pub command: String,
+    pub cwd: String,
+    pub pid: u32,
+    /// Unix seconds
+    pub started_at: u64,
+    pub timeout_secs: u64,
+    pub status: JobStatus,
+    pub finished_at: Option<u64>,
+    /// Resource limits the job ran into (sandbox::limits_hit)
+    #[serde(default)]
+    pub limits_hit: Vec<String>,
+}
+
+/// A slice of one output stream
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamChunk {
+    pub data: Vec<u8>,
+    /// Absolute offset of `data` — later than requested if output was dropped
+    pub start: u64,
+    /// Offset to pass next time
+    pub next: u64,
+    /// Total bytes produced so far
+    pub total: u64,
+}
+
+/// On-disk log capped at OUTPUT_CAP; offsets stay absolute across drops
+struct StreamLog {
+    path: PathBuf,
+    /// Bytes discarded from the front
+    dropped: u64,
+    /// Bytes currently in the file
+    len: u64,
+}
+
+impl StreamLog {
+    fn create(path: PathBuf) -> io::Result<Self> {
+        File::create(&path)?;
+        Ok(Self { path, dropped: 0, len: 0 })
+    }
+
+    fn append(&mut self, data: &[u8]) -> io::Result<()> {
+        OpenOptions::new().append(true).open(&self.path)?.write_all(data)?;
+        self.len += data.len() as u64;
+        if self.len > OUTPUT_CAP {
+            let keep = OUTPUT_CAP / 2;
+            let mut file = File::open(&self.path)?;
+            file.seek(SeekFrom::Start(self.len - keep))?;
+            let mut tail = Vec::with_capacity(keep as usize);
+            file.read_to_end(&mut tail)?;
+            fs::write(&self.path, &tail)?;
+            self.dropped += self.len - tail.len() as u64;
+            self.len = tail.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn read(&self, offset: u64, max: usize) -> io::Result<StreamChunk> {
+        let total = self.dropped + self.len;
+        let start = offset.clamp(self.dropped, total);
+        let mut data = Vec::new();
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(start - self.dropped))?;
+        file.take(max as u64).read_to_end(&mut data)?;
+        let next = start + data.len() as u64;
+        Ok(StreamChunk { data, start, next, total })
+    }
+
+    fn tail(&self, max: usize) -> Vec<u8> {
+        let total = self.dropped + self.len;
+        self.read(total.saturating_sub(max as u64), max).map(|c| c.data).unwrap_or_default()
+    }
+}
+
+struct Job {
+    info: Arc<Mutex<JobInfo>>,
+    stdout: Arc<Mutex<StreamLog>>,
+    stderr: Arc<Mutex<StreamLog>>,
+    kill: Arc<AtomicBool>,
+    watcher: Option<JoinHandle<()>>,
+}
+
+/// Background jobs of one server process
+pub struct JobManager {
+    dir: PathBuf,
+    jobs: BTreeMap<String, Job>,
+    next_id: u64,
+}
+
+impl JobManager {
+    /// Create this server's job directory under `base` (LIVE/TMP/JOBS) and
+    /// remove directories left by servers that are no longer running
+    pub fn open(base: &Path) -> io::Result<Self> {
+        let dir = crate::paths::server_dir(base)?;
+        Ok(Self { dir, jobs: BTreeMap::new(), next_id: 1 })
+    }
+
+    /// Spawn `command` (limits and confinement already applied) as a job
+    pub fn start(
+        &mut self,
+        command: &mut Command,
+        text: &str,
+        cwd: &str,
+        timeout: Duration,
+        limits: ExecLimits,
+    ) -> io::Result<JobInfo> {
+        let running = self.list().iter().filter(|j| j.status == JobStatus::Running).count();
+        if running >= MAX_RUNNING {
+            return Err(io::Error::other(format!("{} jobs already running (max {})", running, MAX_RUNNING)));
+        }
+
+        let id = format!("job-{}", self.next_id);
+        let job_dir = self.dir.join(&id);
+        fs::create_dir_all(&job_dir)?;
+        let stdout = Arc::new(Mutex::new(StreamLog::create(job_dir.join("stdout.log"))?));
+        let stderr = Arc::new(Mutex::new(StreamLog::create(job_dir.join("stderr.log"))?));
+
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        self.next_id += 1;
+
+        let info = Arc::new(Mutex::new(JobInfo {
+            id: id.clone(),
+            command: text.to_string(),
+            cwd: cwd.to_string(),
+            pid: child.id(),
+            started_at: now(),
+            timeout_secs: timeout.as_secs(),
+            status: JobStatus::Running,
+            finished_at: None,
+            limits_hit: Vec::new(),
+        }));
+        save(&job_dir, &lock(&info))?;
+
+        let readers = [
+            copy_into(child.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>), stdout.clone()),
+            copy_into(child.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>), stderr.clone()),
+        ];
+        let kill = Arc::new(AtomicBool::new(false));
+        let watcher = {
+            let (info, stderr, kill) = (info.clone(), stderr.clone(), kill.clone());
+            std::thread::spawn(move || {
+                watch(child, readers, &job_dir, &info, &stderr, &kill, timeout, &limits);
+            })
+        };
+
+        let snapshot = lock(&info).clone();
+        self.jobs.insert(id, Job { info, stdout, stderr, kill, watcher: Some(watcher) });
+        Ok(snapshot)
+    }
+
+    pub fn info(&self, id: &str) -> Option<JobInfo> {
+        self.jobs.get(id).map(|job| lock(&job.info).clone())
+    }
+
+    pub fn list(&self) -> Vec<JobInfo> {
+        self.jobs.values().map(|job| lock(&job.info).clone()).collect()
+    }
+
+    /// Output since the given offsets, at most READ_MAX bytes per stream
+    pub fn output(&self, id: &str, stdout_offset: u64, stderr_offset: u64) -> Option<io::Result<(StreamChunk, StreamChunk)>> {
+        let job = self.jobs.get(id)?;
+        let stdout = lock(&job.stdout).read(stdout_offset, READ_MAX);
+        let stderr = lock(&job.stderr).read(stderr_offset, READ_MAX);
+        Some(stdout.and_then(|out| Ok((out, stderr?))))
+    }
+
+    /// Kill a running job's process group and wait for it to be reaped
+    pub fn kill(&mut self, id: &str) -> Option<JobInfo> {
+        let job = self.jobs.get_mut(id)?;
+        job.kill.store(true, Ordering::SeqCst);
+        if let Some(watcher) = job.watcher.take() {
+            let _ = watcher.join();
+        }
+        Some(lock(&job.info).clone())
+    }
+
+    /// Session end — kill everything still running
+    pub fn shutdown(&mut self) {
+        let ids: Vec<String> = self.jobs.keys().cloned().collect();
+        for id in ids {
+            self.kill(&id);
+        }
+    }
+}
+
+impl Drop for JobManager {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn copy_into(pipe: Option<Box<dyn Read + Send>>, log: Arc<Mutex<StreamLog>>) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let Some(mut pipe) = pipe else { return };
+        let mut buf = [0u8; 8192];
+        loop {
+            match pipe.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if lock(&log).append(&buf[..n]).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn watch(
+    mut child: Child,
+    readers: [JoinHandle<()>; 2],
+    job_dir: &Path,
+    info: &Mutex<JobInfo>,
+    stderr: &Mutex<StreamLog>,
+    kill: &AtomicBool,
+    timeout: Duration,
+    limits: &ExecLimits,
+) {
+    let deadline = Instant::now() + timeout;
+    let (status, outcome) = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break (Some(status), None),
+            Ok(None) => {}
+            Err(_) => break (None, None),
+        }
+        let stop = if kill.load(Ordering::SeqCst) {
+            Some(JobStatus::Killed)
+        } else if Instant::now() >= deadline {
+            Some(JobStatus::TimedOut)
+        } else {
+            None
+        };
+        if let Some(outcome) = stop {
+            sandbox::kill_group(child.id());
+            let _ = child.kill();
+            break (child.wait().ok(), Some(outcome));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+
+    // Give the readers a moment to drain; a surviving grandchild may keep a pipe open
+    let drained = Instant::now() + Duration::from_millis(500);
+    while readers.iter().any(|r| !r.is_finished()) && Instant::now() < drained {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut info = lock(info);
+    info.status = outcome.unwrap_or(JobStatus::Exited { code: status.and_then(|s| s.code()) });
+    info.finished_at = Some(now());
+    if let Some(status) = status {
+        let finished = Finished {
+            output: Output { status, stdout: Vec::new(), stderr: lock(stderr).tail(READ_MAX) },
+            timed_out: info.status == JobStatus::TimedOut,
+        };
+        info.limits_hit = sandbox::limits_hit(&finished, limits, timeout);
+    }
+    let _ = save(job_dir, &info);
+}
+
+fn save(job_dir: &Path, info: &JobInfo) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(info).map_err(io::Error::other)?;
+    fs::write(job_dir.join("job.json"), json)
+}
+
+/// Poisoning only means a reader thread panicked — the data is still usable
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unlimited() -> ExecLimits {
+        ExecLimits {
+            timeout_secs: 0, cpu_secs: 0, address_space_mb: 0, max_processes: 0,
+            max_file_size_mb: 0, max_open_files: 0, no_network: false,
+        }
+    }
+
+    fn sh(script: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        sandbox::limit(&mut command, &unlimited()).unwrap();
+        command
+    }
+
+    fn wait_done(jobs: &JobManager, id: &str) -> JobInfo {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let info = jobs.info(id).unwrap();
+            if info.status != JobStatus::Running || Instant::now() > deadline {
+                return info;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn output_is_read_incrementally_and_jobs_are_killed() {
+        let base = tempfile::tempdir().unwrap();
+        let mut jobs = JobManager::open(base.path()).unwrap();
+        let ten_secs = Duration::from_secs(10);
+
+        let info = jobs.start(&mut sh("printf abc; printf err >&2"), "printf", "/", ten_secs, unlimited()).unwrap();
+        assert_eq!(info.id, "job-1");
+        assert_eq!(wait_done(&jobs, "job-1").status, JobStatus::Exited { code: Some(0) });
+        let (out, err) = jobs.output("job-1", 1, 0).unwrap().unwrap();
+        assert_eq!((out.data.as_slice(), out.start, out.next, out.total), (&b"bc"[..], 1, 3, 3));
+        assert_eq!(err.data, b"err");
+        let (out, _) = jobs.output("job-1", 3, 3).unwrap().unwrap();
+        assert!(out.data.is_empty());
+
+        jobs.start(&mut sh("sleep 30 & sleep 30"), "sleep", "/", ten_secs, unlimited()).unwrap();
+        let started = Instant::now();
+        assert_eq!(jobs.kill("job-2").unwrap().status, JobStatus::Killed);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        jobs.start(&mut sh("sleep 30"), "sleep", "/", Duration::from_millis(200), unlimited()).unwrap();
+        let info = wait_done(&jobs, "job-3");
+        assert_eq!(info.status, JobStatus::TimedOut);
+        assert_eq!(info.limits_hit, vec!["wall-clock timeout (0s)"]);
+
+        let record = fs::read_to_string(jobs.dir.join("job-3/job.json")).unwrap();
+        assert!(record.contains("\"state\": \"timed_out\""));
+
+        // Session end kills what is still running
+        jobs.start(&mut sh("sleep 30"), "sleep", "/", ten_secs, unlimited()).unwrap();
+        jobs.shutdown();
+        assert_eq!(jobs.info("job-4").unwrap().status, JobStatus::Killed);
+    }
+
+    #[test]
+    fn stream_log_drops_oldest_output_past_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = StreamLog::create(dir.path().join("out.log")).unwrap();
+        let block = vec![b'x'; (OUTPUT_CAP / 4) as usize];
+        for _ in 0..5 {
+            log.append(&block).unwrap();
+        }
+        log.append(b"end").unwrap();
+        let total = 5 * block.len() as u64 + 3;
+        assert!(log.len <= OUTPUT_CAP);
+        assert_eq!(log.dropped + log.len, total);
+
+        // Offsets before the kept window resume at its start
+        let chunk = log.read(0, 16).unwrap();
+        assert_eq!(chunk.start, log.dropped);
+        assert_eq!(chunk.total, total);
+        assert_eq!(log.tail(3), b"end");
+    }
+}
@@ -4,12 +4,26 @@
 // All modules exported here for use by the binary and tests.
 
 pub mod paths;
+pub mod pathmatch;
+pub mod approval;
 pub mod calculate;
 pub mod config;
 pub mod gate;
 pub mod inspect;
+pub mod jobs;
 pub mod mcp;
+pub mod notebook;
+pub mod output;
+pub mod patch;
+pub mod policy;
+pub mod regression;
+pub mod sandbox;
 pub mod session;
+pub mod shadow;
+pub mod shell;
+pub mod shells;
+pub mod snapshot;
+pub mod sniff;
 pub mod storage;
 pub mod validate;
 pub mod web;
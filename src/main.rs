@@ -4,19 +4,34 @@
 // CLI and MCP stdio server. All tool calls route through this gateway.
 // Usage:
 //   spf-smart-gate serve                                        # Run MCP server (stdio)
-//   spf-smart-gate gate <tool> <params>                         # One-shot gate check
+//   spf-smart-gate gate <tool> <params> [--explain]             # One-shot gate check
 //   spf-smart-gate status                                       # Show gateway status
 //   spf-smart-gate session                                      # Show session state
 //   spf-smart-gate fs-import <virtual_path> <device_file>       # Import file to LMDB
 //   spf-smart-gate fs-export <virtual_path> <device_file>       # Export file from LMDB
 //   spf-smart-gate config-import <json_file>                    # Import config to CONFIG.DB
 //   spf-smart-gate config-export <json_file>                    # Export config from CONFIG.DB
+//   spf-smart-gate config history|diff <a> [b]|rollback <rev>   # CONFIG.DB revisions
+//   spf-smart-gate approvals list [--all]                       # Show held tool calls
+//   spf-smart-gate approvals approve|deny <id>                  # Decide a held call
+//   spf-smart-gate policy check [json_file]                     # Validate policy document
+//   spf-smart-gate policy show                                  # Print active policy
+//   spf-smart-gate policy candidate <json_file> | --clear       # Set policy trialled in Audit mode
+//   spf-smart-gate shadow-report [--clear]                      # Summarise Audit-mode disagreements
+//   spf-smart-gate policy-test <fixtures.jsonl> [--config f]    # Replay golden gate fixtures
+//   spf-smart-gate limits show|set <trust> [--cpu n …]|reset    # spf_bash resource limits
+//   spf-smart-gate env show [dir] | set <project> [NAME[=v]…]   # Spawned-command environment
+//   spf-smart-gate rollback --session <id> [--to <n>]           # Revert agent writes from snapshots
 
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use spf_smart_gate::{
-    agent_state::AgentStateDb, calculate, config_db::SpfConfigDb, fs::SpfFs,
-    gate, mcp, paths, session::Session, storage::SpfStorage,
+    agent_state::AgentStateDb, approval::ApprovalStatus, calculate,
+    config::{mask_env_value, EnforceMode, ExecEnv, OutputLimits, SessionBudget, SeverityThresholds},
+    config_db::SpfConfigDb,
+    fs::SpfFs, gate, mcp, pathmatch::PathPrecedence, paths, policy::Policy, regression, session::Session,
+    snapshot,
+    storage::SpfStorage, tmp_db::{SpfTmpDb, TrustLevel},
 };
 use std::path::PathBuf;
 
@@ -24,6 +39,14 @@ fn default_storage_path() -> PathBuf {
     paths::spf_root().join("LIVE/SESSION/SESSION.DB")
 }
 
+/// Revision actor for CLI-driven config changes: "<command> (<user>)"
+fn cli_actor(command: &str) -> String {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    format!("{} ({})", command, user)
+}
+
 #[derive(Parser)]
 #[command(name = "spf-smart-gate")]
 #[command(author = "Joseph Stone")]
@@ -50,6 +73,9 @@ enum Commands {
 
         /// Parameters as JSON string
         params: String,
+        /// Attach a structured trace: validators, inspectors, rules and C breakdown
+        #[arg(long)]
+        explain: bool,
     },
 
     /// Calculate complexity without executing
@@ -123,6 +149,192 @@ enum Commands {
         /// Device file to write JSON to
         json_file: PathBuf,
     },
+
+    /// Inspect and restore CONFIG.DB revisions
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Inspect the declarative gate policy
+    Policy {
+        #[command(subcommand)]
+        action: PolicyAction,
+    },
+
+    /// Replay golden-file fixtures through the gate and diff the outcomes.
+    /// Exits non-zero when any fixture does not match.
+    PolicyTest {
+        /// JSONL fixtures: {"tool", "params", "expect": {"allowed", "tier", "errors"}}
+        fixtures: PathBuf,
+
+        /// Evaluate against a config-export file instead of CONFIG.DB
+        #[arg(long)]
+        config: Option<PathBuf>,
+    },
+
+    /// Summarise calls where the Audit-mode candidate policy disagreed
+    ShadowReport {
+        /// Delete recorded disagreements after printing
+        #[arg(long)]
+        clear: bool,
+    },
+
+    /// Manage tool calls held for approval (requires_approval tiers)
+    Approvals {
+        #[command(subcommand)]
+        action: ApprovalsAction,
+    },
+
+    /// Resource limits for spf_bash per project trust level (TMP_DB)
+    Limits {
+        #[command(subcommand)]
+        action: LimitsAction,
+    },
+
+    /// Environment passed to spawned commands (CONFIG.DB allowlist + TMP_DB project additions)
+    Env {
+        #[command(subcommand)]
+        action: EnvAction,
+    },
+
+    /// Revert a session's agent writes from their pre-write snapshots (SPF_FS), newest first
+    Rollback {
+        /// Session ID as shown by `status`
+        #[arg(long)]
+        session: String,
+
+        /// Keep writes up to and including this write number; revert the rest
+        #[arg(long)]
+        to: Option<u64>,
+
+        /// Show what would be reverted without changing files
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum EnvAction {
+    /// Show the effective environment (values masked) for commands run from a directory
+    Show {
+        /// Directory the command would run from (default: current directory)
+        dir: Option<PathBuf>,
+    },
+
+    /// Replace a project's environment additions — "NAME" passes NAME through,
+    /// "NAME=value" sets it. No entries clears them.
+    Set {
+        /// Registered project root
+        project: PathBuf,
+        entries: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum LimitsAction {
+    /// Show limits for every trust level
+    Show,
+
+    /// Change limits for a trust level — unset options keep their value, 0 = unlimited
+    Set {
+        /// untrusted, low, medium, high or full
+        trust: TrustLevel,
+        /// Wall-clock seconds (caps the spf_bash timeout argument)
+        #[arg(long)]
+        timeout: Option<u64>,
+        /// CPU seconds
+        #[arg(long)]
+        cpu: Option<u64>,
+        /// Address space in MiB
+        #[arg(long)]
+        memory: Option<u64>,
+        /// Max processes for the user
+        #[arg(long)]
+        processes: Option<u64>,
+        /// Max file size in MiB
+        #[arg(long)]
+        file_size: Option<u64>,
+        /// Max open files
+        #[arg(long)]
+        open_files: Option<u64>,
+        /// Allow or deny network access
+        #[arg(long)]
+        network: Option<bool>,
+    },
+
+    /// Restore the compiled defaults for a trust level
+    Reset {
+        trust: TrustLevel,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// List recorded config revisions, newest first
+    History {
+        /// Number of revisions to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Show what changed between two revisions
+    Diff {
+        /// Older revision
+        from: u64,
+        /// Newer revision (defaults to the latest)
+        to: Option<u64>,
+    },
+
+    /// Restore tiers, formula, weights, policy, path rules and dangerous
+    /// patterns to a revision in one transaction (recorded as a new revision)
+    Rollback {
+        rev: u64,
+    },
+}
+
+#[derive(Subcommand)]
+enum PolicyAction {
+    /// Check a policy document for syntax errors and shadowed rules.
+    /// Without a file, checks the policy stored in CONFIG.DB.
+    Check {
+        /// Policy JSON file (or config-export file with a "policy" key)
+        json_file: Option<PathBuf>,
+    },
+
+    /// Print the active policy as JSON
+    Show,
+
+    /// Set (or clear) the candidate policy evaluated in Audit mode
+    Candidate {
+        /// Candidate policy JSON file
+        json_file: Option<PathBuf>,
+        /// Remove the candidate policy
+        #[arg(long)]
+        clear: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ApprovalsAction {
+    /// List pending approvals
+    List {
+        /// Include approved, denied and consumed entries
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Approve a pending call — the next identical call runs once
+    Approve {
+        /// Approval ID from the PENDING message
+        id: String,
+    },
+
+    /// Deny a pending call
+    Deny {
+        /// Approval ID from the PENDING message
+        id: String,
+    },
 }
 
 fn main() -> Result<()> {
@@ -157,13 +369,20 @@ fn main() -> Result<()> {
             // Unreachable
         }
 
-        Commands::Gate { tool, params } => {
+        Commands::Gate { tool, params, explain } => {
             let params: calculate::ToolParams = serde_json::from_str(params)
                 .with_context(|| format!("Invalid params JSON: {}", params))?;
 
-            let decision = gate::process(tool, &params, &config, &session);
+            let decision = if *explain {
+                gate::explain(tool, &params, &config, &session)
+            } else {
+                gate::process(tool, &params, &config, &session)
+            };
 
             println!("{}", serde_json::to_string_pretty(&decision)?);
+            if let Some(ref trace) = decision.trace {
+                eprintln!("\n{}", trace.render(&config));
+            }
 
             if !decision.allowed {
                 std::process::exit(1);
@@ -192,6 +411,8 @@ fn main() -> Result<()> {
             println!("Config: LMDB (CONFIG/CONFIG.DB)");
             println!();
             println!("Session: {}", session.status_summary());
+            println!("Session ID: {}", session.id());
+            println!("Budget: {}", config.session_budget.remaining(&session));
             println!();
             println!("Tiers:");
             println!("  SIMPLE   < 500    | 40% analyze / 60% build");
@@ -273,17 +494,20 @@ fn main() -> Result<()> {
                 println!();
                 println!("[DRY RUN] No changes written.");
             } else {
-                // Remove all existing path rules
-                for (rule_type, path) in &current_rules {
-                    config_db.remove_path_rule(rule_type, path)?;
-                }
-                // Write new rules
-                for p in &new_allowed {
-                    config_db.allow_path(p)?;
-                }
-                for p in &new_blocked {
-                    config_db.block_path(p)?;
-                }
+                config_db.with_revision(&cli_actor("refresh-paths"), |config_db| {
+                    // Remove all existing path rules
+                    for (rule_type, path) in &current_rules {
+                        config_db.remove_path_rule(rule_type, path)?;
+                    }
+                    // Write new rules
+                    for p in &new_allowed {
+                        config_db.allow_path(p)?;
+                    }
+                    for p in &new_blocked {
+                        config_db.block_path(p)?;
+                    }
+                    Ok(())
+                })?;
                 println!();
                 println!("Path rules updated. {} allowed, {} blocked.",
                     new_allowed.len(), new_blocked.len());
@@ -404,88 +628,149 @@ fn main() -> Result<()> {
 
             println!("config-import: {:?}", json_file);
 
-            // Enforce mode
-            if let Some(mode) = json.get("enforce_mode").and_then(|v| v.as_str()) {
-                println!("  enforce_mode: {}", mode);
-                if !dry_run {
-                    let mode = serde_json::from_value(json["enforce_mode"].clone())?;
-                    config_db.set_enforce_mode(&mode)?;
+            let actor = cli_actor(&format!("config-import {}", json_file.display()));
+            config_db.with_revision(&actor, |config_db| {
+                // Enforce mode
+                if let Some(mode) = json.get("enforce_mode").and_then(|v| v.as_str()) {
+                    println!("  enforce_mode: {}", mode);
+                    if !dry_run {
+                        let mode = serde_json::from_value(json["enforce_mode"].clone())?;
+                        config_db.set_enforce_mode(&mode)?;
+                    }
                 }
-            }
 
-            // Tiers
-            if let Some(tiers_val) = json.get("tiers") {
-                println!("  tiers: present");
-                if !dry_run {
-                    let tiers = serde_json::from_value(tiers_val.clone())?;
-                    config_db.set_tiers(&tiers)?;
+                // Tiers
+                if let Some(tiers_val) = json.get("tiers") {
+                    println!("  tiers: present");
+                    if !dry_run {
+                        let tiers = serde_json::from_value(tiers_val.clone())?;
+                        config_db.set_tiers(&tiers)?;
+                    }
                 }
-            }
 
-            // Formula
-            if let Some(formula_val) = json.get("formula") {
-                println!("  formula: present");
-                if !dry_run {
-                    let formula = serde_json::from_value(formula_val.clone())?;
-                    config_db.set_formula(&formula)?;
+                // Formula
+                if let Some(formula_val) = json.get("formula") {
+                    println!("  formula: present");
+                    if !dry_run {
+                        let formula = serde_json::from_value(formula_val.clone())?;
+                        config_db.set_formula(&formula)?;
+                    }
                 }
-            }
 
-            // Weights
-            if let Some(weights_val) = json.get("weights") {
-                println!("  weights: present");
-                if !dry_run {
-                    let weights = serde_json::from_value(weights_val.clone())?;
-                    config_db.set_weights(&weights)?;
+                // Weights
+                if let Some(weights_val) = json.get("weights") {
+                    println!("  weights: present");
+                    if !dry_run {
+                        let weights = serde_json::from_value(weights_val.clone())?;
+                        config_db.set_weights(&weights)?;
+                    }
+                }
+
+                // Path rule precedence
+                if let Some(val) = json.get("path_precedence") {
+                    let precedence: PathPrecedence = serde_json::from_value(val.clone())?;
+                    println!("  path_precedence: {:?}", precedence);
+                    if !dry_run {
+                        config_db.set_path_precedence(precedence)?;
+                    }
+                }
+
+                // Severity thresholds
+                if let Some(val) = json.get("severity_thresholds") {
+                    let thresholds: SeverityThresholds = serde_json::from_value(val.clone())?;
+                    println!("  severity_thresholds: block >= {}, critical >= {}", thresholds.block, thresholds.critical);
+                    if !dry_run {
+                        config_db.set_severity_thresholds(&thresholds)?;
+                    }
+                }
+
+                // Session complexity budget
+                if let Some(val) = json.get("session_budget") {
+                    let budget: SessionBudget = serde_json::from_value(val.clone())?;
+                    println!("  session_budget: max_total_c {}, {} tier caps, {:?} when spent",
+                        budget.max_total_c, budget.max_per_tier.len(), budget.on_exhausted);
+                    if !dry_run {
+                        config_db.set_session_budget(&budget)?;
+                    }
                 }
-            }
 
-            // Allowed paths
-            if let Some(paths) = json.get("allowed_paths").and_then(|v| v.as_array()) {
-                println!("  allowed_paths: {} entries", paths.len());
-                if !dry_run {
-                    for path in paths {
-                        if let Some(p) = path.as_str() {
-                            config_db.allow_path(p)?;
+                // Spawned-command environment
+                if let Some(val) = json.get("exec_env") {
+                    let exec_env: ExecEnv = serde_json::from_value(val.clone())?;
+                    println!("  exec_env: {} allowed names, deny /{}/", exec_env.allow.len(), exec_env.deny_pattern);
+                    if !dry_run {
+                        config_db.set_exec_env(&exec_env)?;
+                    }
+                }
+
+                // Tool output budget
+                if let Some(val) = json.get("output_limits") {
+                    let limits: OutputLimits = serde_json::from_value(val.clone())?;
+                    println!("  output_limits: {} tool caps, {} bytes/token, floor {} bytes",
+                        limits.tools.len(), limits.bytes_per_token, limits.min_bytes);
+                    if !dry_run {
+                        config_db.set_output_limits(&limits)?;
+                    }
+                }
+
+                // Policy
+                if let Some(policy_val) = json.get("policy") {
+                    let policy = Policy::parse(&policy_val.to_string())
+                        .map_err(|e| anyhow::anyhow!("policy: {}", e))?;
+                    println!("  policy: {} tools, {} rules", policy.tools.len(), policy.rules.len());
+                    if !dry_run {
+                        config_db.set_policy(&policy)?;
+                    }
+                }
+
+                // Allowed paths
+                if let Some(paths) = json.get("allowed_paths").and_then(|v| v.as_array()) {
+                    println!("  allowed_paths: {} entries", paths.len());
+                    if !dry_run {
+                        for path in paths {
+                            if let Some(p) = path.as_str() {
+                                config_db.allow_path(p)?;
+                            }
                         }
                     }
                 }
-            }
 
-            // Blocked paths
-            if let Some(paths) = json.get("blocked_paths").and_then(|v| v.as_array()) {
-                println!("  blocked_paths: {} entries", paths.len());
-                if !dry_run {
-                    for path in paths {
-                        if let Some(p) = path.as_str() {
-                            config_db.block_path(p)?;
+                // Blocked paths
+                if let Some(paths) = json.get("blocked_paths").and_then(|v| v.as_array()) {
+                    println!("  blocked_paths: {} entries", paths.len());
+                    if !dry_run {
+                        for path in paths {
+                            if let Some(p) = path.as_str() {
+                                config_db.block_path(p)?;
+                            }
                         }
                     }
                 }
-            }
 
-            // Dangerous patterns
-            if let Some(patterns) = json.get("dangerous_patterns").and_then(|v| v.as_object()) {
-                println!("  dangerous_patterns: {} entries", patterns.len());
-                if !dry_run {
-                    for (pattern, severity) in patterns {
-                        let sev = severity.as_u64().unwrap_or(5) as u8;
-                        config_db.add_dangerous_pattern(pattern, sev)?;
+                // Dangerous patterns
+                if let Some(patterns) = json.get("dangerous_patterns").and_then(|v| v.as_object()) {
+                    println!("  dangerous_patterns: {} entries", patterns.len());
+                    if !dry_run {
+                        for (pattern, severity) in patterns {
+                            let sev = severity.as_u64().unwrap_or(5) as u8;
+                            config_db.add_dangerous_pattern(pattern, sev)?;
+                        }
                     }
                 }
-            }
 
-            // Scalar config values
-            if let Some(obj) = json.get("config").and_then(|v| v.as_object()) {
-                println!("  config scalars: {} entries", obj.len());
-                if !dry_run {
-                    for (key, value) in obj {
-                        if let Some(v) = value.as_str() {
-                            config_db.set("spf", key, v)?;
+                // Scalar config values
+                if let Some(obj) = json.get("config").and_then(|v| v.as_object()) {
+                    println!("  config scalars: {} entries", obj.len());
+                    if !dry_run {
+                        for (key, value) in obj {
+                            if let Some(v) = value.as_str() {
+                                config_db.set("spf", key, v)?;
+                            }
                         }
                     }
                 }
-            }
+                Ok(())
+            })?;
 
             if *dry_run {
                 println!("  [DRY RUN] No changes made.");
@@ -521,9 +806,15 @@ fn main() -> Result<()> {
                 "tiers": config.tiers,
                 "formula": config.formula,
                 "weights": config.complexity_weights,
+                "policy": config.policy,
                 "allowed_paths": allowed_paths,
                 "blocked_paths": blocked_paths,
+                "path_precedence": config.path_precedence,
                 "dangerous_patterns": patterns_map,
+                "severity_thresholds": config.severity_thresholds,
+                "session_budget": config.session_budget,
+                "exec_env": config.exec_env,
+                "output_limits": config.output_limits,
                 "config": {
                     "require_read_before_edit": config.require_read_before_edit.to_string(),
                     "max_write_size": config.max_write_size.to_string(),
@@ -545,6 +836,273 @@ fn main() -> Result<()> {
             println!("  {} bytes written", json_str.len());
             println!("  OK");
         }
+
+        Commands::Config { action } => match action {
+            ConfigAction::History { limit } => {
+                let revisions = config_db.list_revisions()?;
+                if revisions.is_empty() {
+                    println!("No config revisions recorded.");
+                }
+                for rev in revisions.iter().rev().take(*limit) {
+                    println!("r{:<5} {}  {}  ({} changes)",
+                        rev.rev, rev.timestamp.format("%Y-%m-%d %H:%M:%S"), rev.actor, rev.changes.len());
+                    for change in rev.changes.iter().take(5) {
+                        println!("         {}", change);
+                    }
+                    if rev.changes.len() > 5 && rev.rev > 1 {
+                        println!("         … {} more (config diff {} {})", rev.changes.len() - 5, rev.rev - 1, rev.rev);
+                    } else if rev.changes.len() > 5 {
+                        println!("         … {} more", rev.changes.len() - 5);
+                    }
+                }
+            }
+            ConfigAction::Diff { from, to } => {
+                let older = config_db.get_revision(*from)?
+                    .ok_or_else(|| anyhow::anyhow!("Revision {} not found", from))?;
+                let newer = match to {
+                    Some(rev) => config_db.get_revision(*rev)?
+                        .ok_or_else(|| anyhow::anyhow!("Revision {} not found", rev))?,
+                    None => config_db.latest_revision()?
+                        .ok_or_else(|| anyhow::anyhow!("No config revisions recorded"))?,
+                };
+                println!("config diff r{} r{}", older.rev, newer.rev);
+                let changes = older.snapshot.diff(&newer.snapshot);
+                if changes.is_empty() {
+                    println!("  (no differences)");
+                }
+                for change in &changes {
+                    println!("  {}", change);
+                }
+            }
+            ConfigAction::Rollback { rev } => {
+                match config_db.rollback(*rev, &cli_actor(&format!("config rollback {}", rev)))? {
+                    Some(new_rev) => println!("Restored r{} as r{}.", rev, new_rev),
+                    None => println!("Config already matches r{}.", rev),
+                }
+            }
+        },
+
+        Commands::Policy { action } => match action {
+            PolicyAction::Check { json_file } => {
+                let (source, policy) = match json_file {
+                    Some(file) => {
+                        let json_str = std::fs::read_to_string(file)
+                            .with_context(|| format!("Failed to read policy file: {:?}", file))?;
+                        // Accept either a bare policy or a config-export file
+                        let parsed = match serde_json::from_str::<serde_json::Value>(&json_str) {
+                            Ok(v) if v.get("policy").is_some() => Policy::parse(&v["policy"].to_string()),
+                            _ => Policy::parse(&json_str),
+                        };
+                        (format!("{:?}", file), parsed)
+                    }
+                    None => ("CONFIG.DB".to_string(), Ok(config_db.get_policy()?)),
+                };
+
+                println!("policy check: {}", source);
+                let problems = match policy {
+                    Ok(policy) => {
+                        println!("  {} tools, {} rules", policy.tools.len(), policy.rules.len());
+                        policy.check()
+                    }
+                    Err(e) => vec![e],
+                };
+                if problems.is_empty() {
+                    println!("  OK");
+                } else {
+                    for p in &problems {
+                        println!("  ERROR {}", p);
+                    }
+                    std::process::exit(1);
+                }
+            }
+            PolicyAction::Show => {
+                println!("{}", serde_json::to_string_pretty(&config.policy)?);
+            }
+            PolicyAction::Candidate { json_file, clear } => {
+                if *clear {
+                    let removed = config_db.with_revision(&cli_actor("policy candidate --clear"), |db| {
+                        db.clear_candidate_policy()
+                    })?;
+                    println!("policy candidate: {}", if removed { "cleared" } else { "none set" });
+                } else if let Some(file) = json_file {
+                    let json_str = std::fs::read_to_string(file)
+                        .with_context(|| format!("Failed to read policy file: {:?}", file))?;
+                    let policy = Policy::parse(&json_str).map_err(|e| anyhow::anyhow!("{}", e))?;
+                    let actor = cli_actor(&format!("policy candidate {}", file.display()));
+                    config_db.with_revision(&actor, |db| db.set_candidate_policy(&policy))?;
+                    println!("policy candidate: {} tools, {} rules", policy.tools.len(), policy.rules.len());
+                    if config.enforce_mode != EnforceMode::Audit {
+                        println!("  NOTE: enforce_mode is {:?} — candidate is only evaluated in Audit mode", config.enforce_mode);
+                    }
+                } else {
+                    match &config.candidate_policy {
+                        Some(policy) => println!("{}", serde_json::to_string_pretty(policy)?),
+                        None => println!("policy candidate: none set"),
+                    }
+                }
+            }
+        },
+
+        Commands::PolicyTest { fixtures, config: export_file } => {
+            let text = std::fs::read_to_string(fixtures)
+                .with_context(|| format!("Failed to read fixtures: {:?}", fixtures))?;
+            let fixtures = regression::parse_fixtures(&text)?;
+
+            let test_config = match export_file {
+                Some(path) => {
+                    let json_str = std::fs::read_to_string(path)
+                        .with_context(|| format!("Failed to read config file: {:?}", path))?;
+                    let json: serde_json::Value = serde_json::from_str(&json_str)
+                        .with_context(|| "Invalid JSON in config file")?;
+                    regression::config_from_export(&json)?
+                }
+                None => config.clone(),
+            };
+
+            let report = regression::run(&fixtures, &test_config);
+            println!("{}", report.render());
+            if !report.passed() {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::ShadowReport { clear } => {
+            let records = storage.list_shadow()?;
+            println!("shadow-report: mode {:?}", config.enforce_mode);
+            println!("{}", spf_smart_gate::shadow::report(&records));
+            if *clear {
+                let removed = storage.clear_shadow()?;
+                println!("Cleared {} records.", removed);
+            }
+        }
+
+        Commands::Approvals { action } => match action {
+            ApprovalsAction::List { all } => {
+                let entries: Vec<_> = storage.list_approvals()?
+                    .into_iter()
+                    .filter(|r| *all || r.status == ApprovalStatus::Pending)
+                    .collect();
+                if entries.is_empty() {
+                    println!("No {}approvals.", if *all { "" } else { "pending " });
+                }
+                for req in &entries {
+                    println!("{}", req.summary());
+                }
+            }
+            ApprovalsAction::Approve { id } | ApprovalsAction::Deny { id } => {
+                let status = match action {
+                    ApprovalsAction::Approve { .. } => ApprovalStatus::Approved,
+                    _ => ApprovalStatus::Denied,
+                };
+                match storage.get_approval(id)? {
+                    None => {
+                        eprintln!("approvals: no request with id {}", id);
+                        std::process::exit(1);
+                    }
+                    Some(req) if req.status != ApprovalStatus::Pending => {
+                        eprintln!("approvals: {} is already {:?}", id, req.status);
+                        std::process::exit(1);
+                    }
+                    Some(_) => {
+                        let req = storage.decide_approval(id, status)?
+                            .with_context(|| format!("Approval {} vanished", id))?;
+                        println!("{}", req.summary());
+                    }
+                }
+            }
+        },
+
+        Commands::Env { action } => {
+            let tmp_db_path = paths::spf_root().join("LIVE/TMP/TMP.DB");
+            let tmp_db = SpfTmpDb::open(&tmp_db_path)
+                .with_context(|| format!("Failed to open TMP_DB at {:?}", tmp_db_path))?;
+            match action {
+                EnvAction::Show { dir } => {
+                    let dir = match dir {
+                        Some(dir) => dir.clone(),
+                        None => std::env::current_dir()?,
+                    };
+                    let extra = tmp_db.project_env_for_path(&dir.to_string_lossy())?;
+                    let vars = std::env::vars_os()
+                        .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)));
+                    println!("Allow: {}", config.exec_env.allow.join(" "));
+                    println!("Deny:  /{}/", config.exec_env.deny_pattern);
+                    if !extra.is_empty() {
+                        println!("Project additions: {}", extra.join(" "));
+                    }
+                    for (name, value) in config.exec_env.build(vars, &extra) {
+                        println!("  {}={}", name, mask_env_value(&value));
+                    }
+                }
+                EnvAction::Set { project, entries } => {
+                    tmp_db.set_project_env(&project.to_string_lossy(), entries)?;
+                    println!("{} environment additions set for {}", entries.len(), project.display());
+                }
+            }
+        }
+
+        Commands::Limits { action } => {
+            let tmp_db_path = paths::spf_root().join("LIVE/TMP/TMP.DB");
+            let tmp_db = SpfTmpDb::open(&tmp_db_path)
+                .with_context(|| format!("Failed to open TMP_DB at {:?}", tmp_db_path))?;
+            match action {
+                LimitsAction::Show => {
+                    for level in TrustLevel::ALL {
+                        println!("{:<10} {}", format!("{:?}", level), tmp_db.get_exec_limits(level)?.summary());
+                    }
+                }
+                LimitsAction::Set { trust, timeout, cpu, memory, processes, file_size, open_files, network } => {
+                    let mut limits = tmp_db.get_exec_limits(*trust)?;
+                    limits.timeout_secs = timeout.unwrap_or(limits.timeout_secs);
+                    limits.cpu_secs = cpu.unwrap_or(limits.cpu_secs);
+                    limits.address_space_mb = memory.unwrap_or(limits.address_space_mb);
+                    limits.max_processes = processes.unwrap_or(limits.max_processes);
+                    limits.max_file_size_mb = file_size.unwrap_or(limits.max_file_size_mb);
+                    limits.max_open_files = open_files.unwrap_or(limits.max_open_files);
+                    limits.no_network = network.map(|on| !on).unwrap_or(limits.no_network);
+                    tmp_db.set_exec_limits(*trust, &limits)?;
+                    println!("{:?}: {}", trust, limits.summary());
+                }
+                LimitsAction::Reset { trust } => {
+                    tmp_db.reset_exec_limits(*trust)?;
+                    println!("{:?}: {}", trust, tmp_db.get_exec_limits(*trust)?.summary());
+                }
+            }
+        }
+
+        Commands::Rollback { session: session_id, to, dry_run } => {
+            let fs_path = paths::spf_root().join("LIVE/SPF_FS");
+            let spf_fs = SpfFs::open(&fs_path)
+                .with_context(|| format!("Failed to open SPF_FS at {:?}", fs_path))?;
+            let entries = snapshot::journal(&spf_fs, session_id)?;
+            if entries.is_empty() {
+                anyhow::bail!("No agent writes recorded for session {}", session_id);
+            }
+            let plan = snapshot::plan_rollback(&entries, *to);
+            if plan.is_empty() {
+                println!("Nothing to roll back: no unreverted writes after #{}", to.unwrap_or(0));
+                return Ok(());
+            }
+            let reverted = snapshot::revert(&spf_fs, session_id, &plan, |_, _| Ok(()), *dry_run)?;
+            for outcome in &reverted {
+                println!("{}", outcome);
+            }
+            let failed = reverted.iter().filter(|r| r.error.is_some()).count();
+            let changed = reverted.iter().filter(|r| r.changed).count();
+            let summary = format!(
+                "session {} to {}: {} of {} writes reverted, {} changed outside the gateway",
+                session_id, to.map_or("start".to_string(), |n| format!("#{}", n)),
+                reverted.len() - failed, reverted.len(), changed
+            );
+            if *dry_run {
+                println!("[DRY RUN] {}", summary);
+                return Ok(());
+            }
+            let mut current = session.clone();
+            current.record_manifest("Rollback", 0, "ALLOWED", Some(&summary));
+            storage.save_session(&current)?;
+            println!("{}", summary);
+        }
     }
 
     Ok(())
@@ -6,20 +6,31 @@
 //          spf_calculate, spf_session, spf_brain_search, spf_brain_store
 
 use crate::calculate::{self, ToolParams};
-use crate::config::SpfConfig;
+use crate::config::{self, SpfConfig};
 use crate::config_db::SpfConfigDb;
 use crate::paths::{spf_root, actual_home};
 use crate::projects_db::SpfProjectsDb;
-use crate::tmp_db::SpfTmpDb;
+use crate::tmp_db::{ExecLimits, SpfTmpDb, TrustLevel};
 use crate::agent_state::AgentStateDb;
+use crate::approval::{self, ApprovalRequest, ApprovalStatus};
 use crate::fs::SpfFs;
-use crate::gate;
+use crate::gate::{self, GateDecision};
+use crate::jobs::JobManager;
+use crate::notebook;
+use crate::output;
+use crate::patch;
+use crate::shells::{self, ShellState};
+use crate::sandbox;
+use crate::snapshot::{self, Snapshot};
+use crate::sniff;
 use crate::session::Session;
 use crate::storage::SpfStorage;
+use crate::validate;
 use crate::web::WebClient;
 use serde_json::{json, Value};
 use std::io::{self, BufRead, Write};
 use std::process::Command;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use chrono::{DateTime, Local, Utc};
 use std::fs::OpenOptions;
@@ -118,6 +129,11 @@ fn run_rag(args: &[&str]) -> (bool, String) {
     }
 }
 
+/// Gateway environment as UTF-8 pairs — anything else is never passed on
+fn gateway_env() -> impl Iterator<Item = (String, String)> {
+    std::env::vars_os().filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+}
+
 /// Log to stderr (stdout is JSON-RPC)
 fn log(msg: &str) {
     eprintln!("[spf-smart-gate] {}", msg);
@@ -244,6 +260,15 @@ fn tool_definitions() -> Vec<Value> {
             }),
             vec!["tool", "params"],
         ),
+        tool_def(
+            "spf_explain",
+            "Explain how the gate would decide a tool call without executing it: each validator, inspector and policy rule that ran, and the per-term breakdown of C.",
+            json!({
+                "tool": {"type": "string", "description": "Tool name"},
+                "params": {"type": "object", "description": "Tool parameters"}
+            }),
+            vec!["tool", "params"],
+        ),
         tool_def(
             "spf_status",
             "Get current SPF gateway status: session metrics, enforcement mode, complexity budget.",
@@ -260,11 +285,11 @@ fn tool_definitions() -> Vec<Value> {
         // ====== GATED FILE OPERATIONS ======
         tool_def(
             "spf_read",
-            "Read a file through SPF gateway. Tracks read for Build Anchor Protocol.",
+            "Read a file through SPF gateway. Tracks read for Build Anchor Protocol. Images come back as image content, other binaries as a hexdump with size and sha256; Latin-1/UTF-16 text is decoded.",
             json!({
                 "file_path": {"type": "string", "description": "Absolute path to file"},
-                "limit": {"type": "integer", "description": "Max lines to read (optional)"},
-                "offset": {"type": "integer", "description": "Line offset to start from (optional)"}
+                "limit": {"type": "integer", "description": "Max lines to read (bytes for binary files, optional)"},
+                "offset": {"type": "integer", "description": "Line offset to start from (byte offset for binary files, optional)"}
             }),
             vec!["file_path"],
         ),
@@ -279,24 +304,75 @@ fn tool_definitions() -> Vec<Value> {
         ),
         tool_def(
             "spf_edit",
-            "Edit a file through SPF gateway. Validates: Build Anchor, blocked paths, change size.",
+            "Edit a file through SPF gateway. old_string must match exactly once unless replace_all, expected_occurrences or a line range says otherwise. Validates: Build Anchor, blocked paths, change size.",
             json!({
                 "file_path": {"type": "string", "description": "Absolute path to file"},
                 "old_string": {"type": "string", "description": "Text to replace"},
                 "new_string": {"type": "string", "description": "Replacement text"},
-                "replace_all": {"type": "boolean", "description": "Replace all occurrences", "default": false}
+                "replace_all": {"type": "boolean", "description": "Replace all occurrences", "default": false},
+                "expected_occurrences": {"type": "integer", "description": "Replace exactly this many occurrences (all of them); fails if the count differs"},
+                "start_line": {"type": "integer", "description": "Only match occurrences starting on or after this line (1-based)"},
+                "end_line": {"type": "integer", "description": "Only match occurrences starting on or before this line (1-based)"}
             }),
             vec!["file_path", "old_string", "new_string"],
         ),
+        tool_def(
+            "spf_apply_patch",
+            "Apply a unified diff touching one or more files through SPF gateway. Every hunk must match current content; applies all-or-nothing and returns the resulting diff. Validates each file like spf_edit.",
+            json!({
+                "patch": {"type": "string", "description": "Unified diff (diff -u / git diff). Relative paths resolve against the gateway's cwd; /dev/null as old path creates a file"}
+            }),
+            vec!["patch"],
+        ),
+        tool_def(
+            "spf_undo",
+            "Revert the last N agent writes (spf_write, spf_edit, spf_notebook_edit) of this session from their pre-write snapshots. Warns when a file changed outside the gateway since.",
+            json!({
+                "count": {"type": "integer", "description": "Number of writes to revert, newest first (default: 1)", "default": 1},
+                "dry_run": {"type": "boolean", "description": "Only show what would be reverted", "default": false}
+            }),
+            vec![],
+        ),
         tool_def(
             "spf_bash",
             "Execute a bash command through SPF gateway. Validates: dangerous commands, /tmp access, git force.",
             json!({
                 "command": {"type": "string", "description": "Bash command to execute"},
-                "timeout": {"type": "integer", "description": "Timeout in seconds (default: 30)", "default": 30}
+                "timeout": {"type": "integer", "description": "Timeout in seconds (default: 30)", "default": 30},
+                "show_env": {"type": "boolean", "description": "Append the command's environment (values masked); with an empty command, only list it", "default": false},
+                "session": {"type": "string", "description": "Named shell session: keeps cwd and exported variables between calls"},
+                "reset": {"type": "boolean", "description": "Start the named session over from the server's cwd and base environment", "default": false}
             }),
             vec!["command"],
         ),
+        tool_def(
+            "spf_bash_start",
+            "Start a bash command as a background job through SPF gateway (gated like spf_bash). Returns a job ID; poll with spf_bash_output.",
+            json!({
+                "command": {"type": "string", "description": "Bash command to execute"},
+                "timeout": {"type": "integer", "description": "Timeout in seconds (default: the trust level's wall-clock limit)"},
+                "session": {"type": "string", "description": "Start in this shell session's cwd and environment (the job does not change the session)"}
+            }),
+            vec!["command"],
+        ),
+        tool_def(
+            "spf_bash_output",
+            "Read a background job's status and its stdout/stderr from byte offsets. Without job_id, list jobs.",
+            json!({
+                "job_id": {"type": "string", "description": "Job ID from spf_bash_start"},
+                "stdout_offset": {"type": "integer", "description": "Byte offset into stdout (use NEXT from the last call)", "default": 0},
+                "stderr_offset": {"type": "integer", "description": "Byte offset into stderr (use NEXT from the last call)", "default": 0}
+            }),
+            vec![],
+        ),
+        tool_def(
+            "spf_bash_kill",
+            "Kill a background job and its whole process group.",
+            json!({
+                "job_id": {"type": "string", "description": "Job ID from spf_bash_start"}
+            }),
+            vec!["job_id"],
+        ),
 
         // ====== SEARCH/GLOB TOOLS ======
         tool_def(
@@ -364,15 +440,16 @@ fn tool_definitions() -> Vec<Value> {
         // ====== NOTEBOOK TOOL ======
         tool_def(
             "spf_notebook_edit",
-            "Edit a Jupyter notebook cell.",
+            "Edit a Jupyter notebook cell by id or index. Code-cell edits clear outputs; the result is checked against nbformat and a cell diff returned.",
             json!({
                 "notebook_path": {"type": "string", "description": "Absolute path to .ipynb file"},
-                "cell_number": {"type": "integer", "description": "Cell index (0-based)"},
-                "new_source": {"type": "string", "description": "New cell content"},
-                "cell_type": {"type": "string", "description": "Cell type: code or markdown"},
+                "cell_id": {"type": "string", "description": "Cell id (nbformat 4.5+); insert goes after this cell"},
+                "cell_number": {"type": "integer", "description": "Cell index (0-based, default 0)"},
+                "new_source": {"type": "string", "description": "New cell content (not needed for delete)"},
+                "cell_type": {"type": "string", "description": "Cell type: code, markdown or raw (replace keeps the cell's type by default)"},
                 "edit_mode": {"type": "string", "description": "Mode: replace, insert, or delete", "default": "replace"}
             }),
-            vec!["notebook_path", "new_source"],
+            vec!["notebook_path"],
         ),
 
         // ====== BRAIN PASSTHROUGH ======
@@ -919,7 +996,7 @@ fn route_device_dir(
                 if let Some(parent) = device_path.parent() {
                     let _ = std::fs::create_dir_all(parent);
                 }
-                match std::fs::write(&device_path, data) {
+                match snapshot::write_atomic(&device_path, data.as_bytes()) {
                     Ok(()) => {
                         // Log write to TMP_DB
                         if let Some(db) = tmp_db {
@@ -978,7 +1055,7 @@ fn route_device_dir(
     }
 }
 
-/// LMDB 5 — AGENT_STATE mount at /home/agent/
+// LMDB 5 — AGENT_STATE mount at /home/agent/
 // ============================================================================
 // ROUTE_AGENT REPLACEMENT — Dynamic reads from LMDB5.DB state db
 // Copyright 2026 Joseph Stone - All Rights Reserved
@@ -1308,7 +1385,367 @@ fn route_agent(path: &str, op: &str, agent_db: &Option<AgentStateDb>) -> Value {
     }
 }
 
+/// Run the gate for an MCP call. Audit-mode disagreements are persisted
+/// to SESSION.DB here — before the handler acts on the active decision.
+fn run_gate(
+    tool: &str,
+    params: &ToolParams,
+    config: &SpfConfig,
+    session: &Session,
+    storage: &SpfStorage,
+) -> GateDecision {
+    let decision = gate::process(tool, params, config, session);
+    if let Some(ref shadow) = decision.shadow {
+        if let Err(e) = storage.record_shadow(shadow) {
+            log(&format!("Warning: shadow record failed: {}", e));
+        }
+    }
+    decision
+}
+
+/// Approval hold — runs after the gate allowed a call.
+/// Tiers with requires_approval never execute on first sight: the call is
+/// queued and the agent receives "PENDING <id>". Once a human approves it,
+/// the next identical call (same tool, same arguments) runs exactly once.
+/// Returns Some(response) when the call must not execute.
+fn post_gate(
+    name: &str,
+    args: &Value,
+    decision: &GateDecision,
+    session: &mut Session,
+    storage: &SpfStorage,
+) -> Option<Value> {
+    if !decision.allowed {
+        return None;
+    }
+    if !decision.complexity.requires_approval {
+        charge_budget(name, decision, session, storage);
+        return None;
+    }
+
+    let fingerprint = approval::fingerprint(name, args);
+    let existing = match storage.find_approval(&fingerprint) {
+        Ok(existing) => existing,
+        Err(e) => {
+            // Fail closed — an unreadable queue must not let the call through
+            return Some(json!({"type": "text", "text": format!(
+                "BLOCKED: approval queue unavailable: {}", e)}));
+        }
+    };
+
+    match existing {
+        Some(req) if req.status == ApprovalStatus::Approved => {
+            if let Err(e) = storage.decide_approval(&req.id, ApprovalStatus::Consumed) {
+                return Some(json!({"type": "text", "text": format!(
+                    "BLOCKED: failed to consume approval {}: {}", req.id, e)}));
+            }
+            session.record_manifest(name, decision.complexity.c, "APPROVED", Some(&req.id));
+            charge_budget(name, decision, session, storage);
+            cmd_log(&format!("APPROVAL CONSUMED {} | {}", req.id, name));
+            None
+        }
+        Some(req) if req.status == ApprovalStatus::Denied => {
+            session.record_manifest(name, decision.complexity.c, "DENIED", Some(&req.id));
+            let _ = storage.save_session(session);
+            Some(json!({"type": "text", "text": format!(
+                "DENIED {}: {} call was denied by the user. Do not retry.", req.id, name)}))
+        }
+        Some(req) => {
+            Some(json!({"type": "text", "text": format!(
+                "PENDING {}: {} still awaiting approval ({} tier, C={}).\nUser: spf-smart-gate approvals approve {}",
+                req.id, name, req.tier, req.c, req.id)}))
+        }
+        None => {
+            let req = ApprovalRequest::new(
+                name, args, &decision.complexity.tier, decision.complexity.c, &decision.message,
+            );
+            if let Err(e) = storage.put_approval(&req) {
+                return Some(json!({"type": "text", "text": format!(
+                    "BLOCKED: failed to queue approval: {}", e)}));
+            }
+            session.record_manifest(name, decision.complexity.c, "PENDING", Some(&req.id));
+            let _ = storage.save_session(session);
+            cmd_log(&format!("APPROVAL PENDING {} | {} | {} C={}",
+                req.id, name, req.tier, req.c));
+            Some(json!({"type": "text", "text": format!(
+                "PENDING {}: {} requires approval ({} tier, C={}).\nThe call has NOT run. Ask the user to approve, then retry with identical parameters.\nUser: spf-smart-gate approvals approve {}",
+                req.id, name, req.tier, req.c, req.id)}))
+        }
+    }
+}
+
+/// Charge a call that is about to run against the session budget
+fn charge_budget(name: &str, decision: &GateDecision, session: &mut Session, storage: &SpfStorage) {
+    session.record_complexity(name, decision.complexity.c, &decision.complexity.tier);
+    let _ = storage.save_session(session);
+}
+
+/// Project trust check (TMP_DB) for file tools and bash.
+/// Resolves the containing project and applies its trust level, tool lists,
+/// protected paths and write limits. Denials are written to the access log.
+/// Ok(Some(project_path)) — allowed inside a project; log access after execution.
+/// Err(reason) — blocked with a project-specific reason.
+fn project_gate(
+    tmp_db: &Option<SpfTmpDb>,
+    tool: &str,
+    file_path: &str,
+    operation: &str,
+    size: u64,
+    session: &Session,
+) -> Result<Option<String>, String> {
+    let db = match tmp_db {
+        Some(db) => db,
+        None => return Ok(None),
+    };
+    match db.check_tool_call(file_path, tool, operation, size) {
+        Ok((None, _)) => Ok(None),
+        Ok((Some(project), None)) => Ok(Some(project.path)),
+        Ok((Some(project), Some(reason))) => {
+            let _ = db.log_access(file_path, &project.path, operation, &session.id(),
+                size, false, Some(&reason));
+            Err(format!("project — {}", reason))
+        }
+        // Fail closed — an unreadable project registry must not let the call through
+        Err(e) => Err(format!("project check failed: {}", e)),
+    }
+}
+
+/// Record a completed project access — bumps read/write and session counters
+fn project_log(
+    tmp_db: &Option<SpfTmpDb>,
+    project: &Option<String>,
+    file_path: &str,
+    operation: &str,
+    size: u64,
+    session: &Session,
+) {
+    if let (Some(db), Some(project_path)) = (tmp_db, project) {
+        if let Err(e) = db.log_access(file_path, project_path, operation, &session.id(), size, true, None) {
+            log(&format!("Warning: TMP_DB access log failed: {}", e));
+        }
+    }
+}
+
+/// Snapshot a file's prior content into SPF_FS, then replace it atomically.
+/// Fails closed: without a snapshot the file is left untouched, and a write
+/// the journal cannot record is put back. Returns the snapshot and the
+/// write's journal number for spf_undo and `rollback`.
+fn guarded_write(
+    fs_db: &Option<SpfFs>,
+    session: &Session,
+    tool: &str,
+    file_path: &str,
+    data: &[u8],
+) -> Result<(Snapshot, u64), String> {
+    let spf_fs = fs_db.as_ref().ok_or("SPF_FS unavailable — no snapshot, write refused")?;
+    let path = std::path::Path::new(file_path);
+    let snap = snapshot::take(spf_fs, &session.id(), path)
+        .map_err(|e| format!("snapshot failed, write refused: {:#}", e))?;
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    snapshot::write_atomic(path, data).map_err(|e| e.to_string())?;
+    match snapshot::record(spf_fs, &session.id(), tool, &snap, path, data) {
+        Ok(seq) => Ok((snap, seq)),
+        Err(e) => match snapshot::restore(spf_fs, &session.id(), path, &snap) {
+            Ok(()) => Err(format!("snapshot journal failed, write reverted: {:#}", e)),
+            Err(undo) => Err(format!("snapshot journal failed ({:#}) and the write could not be reverted: {:#}", e, undo)),
+        },
+    }
+}
+
+/// spf_read's reply, by what the file holds: numbered text lines (decoded
+/// and labelled when not UTF-8), an image block, or a hexdump for binaries.
+/// `offset`/`limit` count lines for text and bytes for binaries.
+fn read_sniffed(file_path: &str, offset: usize, limit: usize, budget: config::OutputCap) -> io::Result<Value> {
+    let path = std::path::Path::new(file_path);
+    let size = std::fs::metadata(path)?.len();
+    let binary_summary = |note: &str| -> io::Result<Value> {
+        let len = if limit > 0 { limit.min(sniff::HEXDUMP_BYTES * 8) } else { sniff::HEXDUMP_BYTES };
+        let data = sniff::read_range(path, offset as u64, len)?;
+        let mut text = format!("Binary file: {} | {} bytes | sha256 {}{}", file_path, size, sniff::sha256_file(path)?, note);
+        if !data.is_empty() {
+            text.push('\n');
+            text.push_str(&sniff::hexdump(&data, offset as u64));
+        }
+        let shown = offset as u64 + data.len() as u64;
+        if shown < size {
+            text.push_str(&format!("\n[showed bytes {}-{} of {} — continue with offset={}]", offset, shown, size, shown));
+        }
+        Ok(json!({"type": "text", "text": text}))
+    };
+
+    let (page, label) = match sniff::sniff(&sniff::head(path)?) {
+        sniff::Kind::Image(mime) if size <= sniff::MAX_IMAGE_BYTES => {
+            return Ok(json!([
+                {"type": "text", "text": format!("Image: {} ({}, {} bytes, sha256 {})", file_path, mime, size, sniff::sha256_file(path)?)},
+                {"type": "image", "data": sniff::base64_file(path)?, "mimeType": mime},
+            ]));
+        }
+        sniff::Kind::Image(mime) => {
+            return binary_summary(&format!(" | {} over the {} byte inline image cap", mime, sniff::MAX_IMAGE_BYTES));
+        }
+        sniff::Kind::Binary => return binary_summary(""),
+        sniff::Kind::Text(sniff::Encoding::Utf8) => (output::read_page(path, offset, limit, budget)?, String::new()),
+        sniff::Kind::Text(encoding) => {
+            let decoded = sniff::Decoder::new(std::fs::File::open(path)?, encoding);
+            let page = output::page_lines(io::BufReader::new(decoded), offset, limit, budget)?;
+            (page, format!(", decoded from {}", encoding.label()))
+        }
+    };
+
+    let mut text = format!("File: {} ({} lines{})\n{}", file_path, page.total, label, page.text);
+    if page.cut {
+        text.push_str(&format!(
+            "\n[OUTPUT TRUNCATED: showed lines {}-{} of {} ({} bytes / {} lines budget) — continue with offset={}]",
+            page.start + 1, page.end, page.total, budget.max_bytes, budget.max_lines, page.end
+        ));
+    }
+    Ok(json!({"type": "text", "text": text}))
+}
+
+/// A bash command that cleared every gate, with what it runs under
+struct BashRun {
+    decision: GateDecision,
+    /// Where the command runs — the shell session's cwd, else the server's.
+    /// Decides the project.
+    cwd: String,
+    project: Option<String>,
+    limits: ExecLimits,
+    /// Environment the command starts with (shell session applied)
+    env: BTreeMap<String, String>,
+    /// The same before the shell session's exports
+    base_env: BTreeMap<String, String>,
+}
+
+/// Gate a bash command for spf_bash / spf_bash_start: SPF gate, shell
+/// session cwd, project trust, the project's limits and environment (fail
+/// closed), then approval and budget. Err carries the reply to send instead.
+#[allow(clippy::too_many_arguments)]
+fn bash_gate(
+    name: &str,
+    args: &Value,
+    command: &str,
+    shell: Option<&ShellState>,
+    config: &SpfConfig,
+    session: &mut Session,
+    storage: &SpfStorage,
+    tmp_db: &Option<SpfTmpDb>,
+) -> Result<BashRun, Value> {
+    let cwd = match shell {
+        Some(state) => state.cwd.clone(),
+        None => std::env::current_dir()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    };
+    let params = ToolParams {
+        command: Some(command.to_string()),
+        cwd: Some(cwd.clone()),
+        ..Default::default()
+    };
+
+    let decision = run_gate(name, &params, config, session, storage);
+    if !decision.allowed {
+        session.record_manifest("Bash", decision.complexity.c, "BLOCKED", decision.errors.first().map(|s| s.as_str()));
+        let _ = storage.save_session(session);
+        return Err(json!({"type": "text", "text": format!("BLOCKED: {}", decision.errors.join(", "))}));
+    }
+    // A session's cwd is re-checked on every call — allowed paths may have changed
+    let project = match shell {
+        Some(_) if !shell_cwd_allowed(&cwd, config) => Err(format!(
+            "shell session cwd {} is outside allowed paths — start over with reset=true", cwd
+        )),
+        _ => project_gate(tmp_db, "Bash", &cwd, "bash", 0, session),
+    }.and_then(|project| {
+        // Every write target answers to its own project — trust level, protected paths
+        for target in validate::bash_write_targets(command, Some(&cwd)) {
+            project_gate(tmp_db, "Bash", &target, "write", 0, session)
+                .map_err(|reason| format!("write to {}: {}", target, reason))?;
+        }
+        Ok(project)
+    });
+    let project_exec = match tmp_db {
+        Some(db) => db.exec_limits_for_path(&cwd)
+            .and_then(|limits| Ok((limits, db.project_env_for_path(&cwd)?)))
+            .map_err(|e| format!("project exec settings unreadable: {}", e)),
+        None => Ok((ExecLimits::defaults(TrustLevel::default()), Vec::new())),
+    };
+    let (project, (limits, extra_env)) = match project.and_then(|p| Ok((p, project_exec?))) {
+        Ok(found) => found,
+        Err(reason) => {
+            session.record_manifest("Bash", decision.complexity.c, "BLOCKED", Some(&reason));
+            let _ = storage.save_session(session);
+            return Err(json!({"type": "text", "text": format!("BLOCKED: {}", reason)}));
+        }
+    };
+    if let Some(held) = post_gate(name, args, &decision, session, storage) {
+        return Err(held);
+    }
+    let base_env = config.exec_env.build(gateway_env(), &extra_env);
+    let mut env = base_env.clone();
+    if let Some(state) = shell {
+        state.apply(&mut env);
+    }
+    Ok(BashRun { decision, cwd, project, limits, env, base_env })
+}
+
+/// The shell session named by `session`, new sessions starting in the
+/// server's cwd; `reset=true` starts it over. Err carries the reply to send.
+fn shell_session(args: &Value, shells: &mut BTreeMap<String, ShellState>) -> Result<Option<(String, ShellState)>, Value> {
+    let Some(name) = args["session"].as_str() else {
+        return Ok(None);
+    };
+    if !shells::valid_name(name) {
+        return Err(json!({"type": "text", "text": format!("Invalid session name '{}' (1-32 of A-Z a-z 0-9 _ -)", name)}));
+    }
+    if args["reset"].as_bool().unwrap_or(false) {
+        shells.remove(name);
+    }
+    if let Some(state) = shells.get(name) {
+        return Ok(Some((name.to_string(), state.clone())));
+    }
+    if shells.len() >= shells::MAX_SESSIONS {
+        return Err(json!({"type": "text", "text": format!(
+            "Too many shell sessions (max {}) — reuse one: {}",
+            shells::MAX_SESSIONS, shells.keys().cloned().collect::<Vec<_>>().join(", ")
+        )}));
+    }
+    let cwd = std::env::current_dir()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok(Some((name.to_string(), ShellState::new(&cwd))))
+}
+
+/// Whether a shell session may sit in `cwd`: an existing directory
+/// inside the allowed paths
+fn shell_cwd_allowed(cwd: &str, config: &SpfConfig) -> bool {
+    std::path::Path::new(cwd).is_dir() && config.is_path_allowed(cwd) && !config.is_path_blocked(cwd)
+}
+
+/// `bash -c script` in the run's cwd from its explicit environment, under
+/// its resource limits and the kernel sandbox
+fn bash_command(script: &str, run: &BashRun, config: &SpfConfig) -> io::Result<(Command, sandbox::Confinement)> {
+    let mut bash = Command::new("bash");
+    bash.arg("-c").arg(script).current_dir(&run.cwd).env_clear().envs(&run.env);
+    sandbox::limit(&mut bash, &run.limits)?;
+    let confinement = sandbox::confine(&mut bash, &sandbox::SandboxPolicy::for_bash(config));
+    if !confinement.is_confined() {
+        cmd_log(&format!("SANDBOX {} | Bash runs unconfined", confinement));
+    }
+    Ok((bash, confinement))
+}
+
+/// Environment listing with values masked
+fn masked_env(env: &BTreeMap<String, String>) -> String {
+    let mut text = format!("ENVIRONMENT ({} vars):", env.len());
+    for (name, value) in env {
+        text.push_str(&format!("\n{}={}", name, crate::config::mask_env_value(value)));
+    }
+    text
+}
+
 /// Handle a tool call
+#[allow(clippy::too_many_arguments)]
 fn handle_tool_call(
     name: &str,
     args: &Value,
@@ -1318,8 +1755,10 @@ fn handle_tool_call(
     config_db: &Option<SpfConfigDb>,
     projects_db: &Option<SpfProjectsDb>,
     tmp_db: &Option<SpfTmpDb>,
-    _fs_db: &Option<SpfFs>,
+    fs_db: &Option<SpfFs>,
     agent_db: &Option<AgentStateDb>,
+    jobs: &mut Option<JobManager>,
+    shells: &mut BTreeMap<String, ShellState>,
 ) -> Value {
     match name {
         // ====== spf_gate ======
@@ -1337,32 +1776,69 @@ fn handle_tool_call(
                 ..Default::default()
             });
             let gate_params = ToolParams { command: Some(tool.to_string()), ..Default::default() };
-            let decision = gate::process("spf_calculate", &gate_params, config, session);
+            let decision = run_gate("spf_calculate", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_calculate", decision.complexity.c, "BLOCKED",
                     decision.errors.first().map(|s| s.as_str()));
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             let result = calculate::calculate(tool, &params, config);
             json!({"type": "text", "text": serde_json::to_string_pretty(&result).unwrap()})
         }
 
+        // ====== spf_explain ======
+        "spf_explain" => {
+            let tool = args["tool"].as_str().unwrap_or("unknown");
+            let params: ToolParams = serde_json::from_value(
+                args.get("params").cloned().unwrap_or(json!({}))
+            ).unwrap_or_default();
+            let gate_params = ToolParams { command: Some(tool.to_string()), ..Default::default() };
+            let decision = run_gate("spf_explain", &gate_params, config, session, storage);
+            if !decision.allowed {
+                session.record_manifest("spf_explain", decision.complexity.c, "BLOCKED",
+                    decision.errors.first().map(|s| s.as_str()));
+                let _ = storage.save_session(session);
+                return json!({"type": "text", "text": decision.message});
+            }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
+            // Dry run — explains the decision without executing or recording the call
+            let explained = gate::explain(tool, &params, config, session);
+            let mut text = explained.message.clone();
+            text.push('\n');
+            if let Some(ref trace) = explained.trace {
+                text.push_str(&trace.render(config));
+            }
+            if let Some(ref shadow) = explained.shadow {
+                text.push_str(&format!("[shadow] candidate policy: {}\n", shadow.change()));
+            }
+            json!({"type": "text", "text": text})
+        }
+
         // ====== spf_status ======
         "spf_status" => {
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_status", &gate_params, config, session);
+            let decision = run_gate("spf_status", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_status", decision.complexity.c, "BLOCKED",
                     decision.errors.first().map(|s| s.as_str()));
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             let status = format!(
-                "SPF Gateway v{}\nMode: {:?}\nSession: {}\nTiers: SIMPLE(<500) LIGHT(<2000) MEDIUM(<10000) CRITICAL(>10000)\nFormula: a_optimal(C) = {} × (1 - 1/ln(C + e))",
+                "SPF Gateway v{}\nMode: {:?}\nSession: {}\nBudget: {}\nTiers: SIMPLE(<500) LIGHT(<2000) MEDIUM(<10000) CRITICAL(>10000)\nFormula: a_optimal(C) = {} × (1 - 1/ln(C + e))",
                 SERVER_VERSION,
                 config.enforce_mode,
                 session.status_summary(),
+                config.session_budget.remaining(session),
                 config.formula.w_eff,
             );
             json!({"type": "text", "text": status})
@@ -1371,13 +1847,16 @@ fn handle_tool_call(
         // ====== spf_session ======
         "spf_session" => {
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_session", &gate_params, config, session);
+            let decision = run_gate("spf_session", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_session", decision.complexity.c, "BLOCKED",
                     decision.errors.first().map(|s| s.as_str()));
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             json!({"type": "text", "text": serde_json::to_string_pretty(session).unwrap()})
         }
 
@@ -1390,37 +1869,36 @@ fn handle_tool_call(
                 ..Default::default()
             };
 
-            let decision = gate::process("Read", &params, config, session);
+            let decision = run_gate("Read", &params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("Read", decision.complexity.c, "BLOCKED", decision.errors.first().map(|s| s.as_str()));
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": format!("BLOCKED: {}", decision.errors.join(", "))});
             }
+            let project = match project_gate(tmp_db, "Read", file_path, "read", std::fs::metadata(file_path).map(|m| m.len()).unwrap_or(0), session) {
+                Ok(project) => project,
+                Err(reason) => {
+                    session.record_manifest("Read", decision.complexity.c, "BLOCKED", Some(&reason));
+                    let _ = storage.save_session(session);
+                    return json!({"type": "text", "text": format!("BLOCKED: {}", reason)});
+                }
+            };
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
 
-            // Execute read
-            match std::fs::read_to_string(file_path) {
-                Ok(content) => {
+            // Execute read — sniffed, text streamed within budget
+            let offset = args.get("offset").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+            let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+            let budget = config.output_limits.budget("spf_read", decision.complexity.a_optimal_tokens);
+            match read_sniffed(file_path, offset, limit, budget) {
+                Ok(reply) => {
                     session.track_read(file_path);
                     session.record_action("Read", "success", Some(file_path));
+                    let size = std::fs::metadata(file_path).map(|m| m.len()).unwrap_or(0);
+                    project_log(tmp_db, &project, file_path, "read", size, session);
                     let _ = storage.save_session(session);
-
-                    // Apply limit/offset if specified
-                    let offset = args.get("offset").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
-                    let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
-
-                    let lines: Vec<&str> = content.lines().collect();
-                    let total = lines.len();
-                    let start = offset.min(total);
-                    let end = if limit > 0 { (start + limit).min(total) } else { total };
-
-                    let numbered: String = lines[start..end]
-                        .iter()
-                        .enumerate()
-                        .map(|(i, line)| format!("{:>6}\t{}", start + i + 1, line))
-                        .collect::<Vec<_>>()
-                        .join("\n");
-
-                    json!({"type": "text", "text": format!("File: {} ({} lines)\n{}", file_path, total, numbered)})
+                    reply
                 }
                 Err(e) => {
                     session.record_action("Read", "failed", Some(file_path));
@@ -1442,28 +1920,35 @@ fn handle_tool_call(
                 ..Default::default()
             };
 
-            let decision = gate::process("Write", &params, config, session);
+            let decision = run_gate("Write", &params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("Write", decision.complexity.c, "BLOCKED", decision.errors.first().map(|s| s.as_str()));
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": format!("BLOCKED: {}", decision.errors.join(", "))});
             }
-
-            // Execute write
-            // Ensure parent directory exists
-            if let Some(parent) = std::path::Path::new(file_path).parent() {
-                let _ = std::fs::create_dir_all(parent);
+            let project = match project_gate(tmp_db, "Write", file_path, "write", content.len() as u64, session) {
+                Ok(project) => project,
+                Err(reason) => {
+                    session.record_manifest("Write", decision.complexity.c, "BLOCKED", Some(&reason));
+                    let _ = storage.save_session(session);
+                    return json!({"type": "text", "text": format!("BLOCKED: {}", reason)});
+                }
+            };
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
             }
 
-            match std::fs::write(file_path, content) {
-                Ok(()) => {
+            // Execute write — prior content snapshotted first
+            match guarded_write(fs_db, session, name, file_path, content.as_bytes()) {
+                Ok((snap, _)) => {
                     session.track_write(file_path);
                     session.record_action("Write", "success", Some(file_path));
+                    project_log(tmp_db, &project, file_path, "write", content.len() as u64, session);
                     session.record_manifest("Write", decision.complexity.c, "ALLOWED", None);
                     let _ = storage.save_session(session);
                     json!({"type": "text", "text": format!(
-                        "Written: {} ({} bytes) | C={} {}",
-                        file_path, content.len(), decision.complexity.c, decision.complexity.tier
+                        "Written: {} ({} bytes) | C={} {} | snapshot v{}",
+                        file_path, content.len(), decision.complexity.c, decision.complexity.tier, snap.version
                     )})
                 }
                 Err(e) => {
@@ -1481,6 +1966,15 @@ fn handle_tool_call(
             let old_string = args["old_string"].as_str().unwrap_or("");
             let new_string = args["new_string"].as_str().unwrap_or("");
             let replace_all = args["replace_all"].as_bool().unwrap_or(false);
+            let line = |key: &str| args[key].as_u64().map(|n| n as usize);
+            let scope = patch::EditScope {
+                replace_all,
+                expected: line("expected_occurrences"),
+                lines: match (line("start_line"), line("end_line")) {
+                    (None, None) => None,
+                    (start, end) => Some((start.unwrap_or(1), end.unwrap_or(usize::MAX))),
+                },
+            };
 
             let params = ToolParams {
                 file_path: Some(file_path.to_string()),
@@ -1490,34 +1984,48 @@ fn handle_tool_call(
                 ..Default::default()
             };
 
-            let decision = gate::process("Edit", &params, config, session);
+            let decision = run_gate("Edit", &params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("Edit", decision.complexity.c, "BLOCKED", decision.errors.first().map(|s| s.as_str()));
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": format!("BLOCKED: {}", decision.errors.join(", "))});
             }
+            // Projected size after the edit, for the project write limit
+            let edit_size = std::fs::metadata(file_path).map(|m| m.len()).unwrap_or(0)
+                .saturating_sub(old_string.len() as u64) + new_string.len() as u64;
+            let project = match project_gate(tmp_db, "Edit", file_path, "edit", edit_size, session) {
+                Ok(project) => project,
+                Err(reason) => {
+                    session.record_manifest("Edit", decision.complexity.c, "BLOCKED", Some(&reason));
+                    let _ = storage.save_session(session);
+                    return json!({"type": "text", "text": format!("BLOCKED: {}", reason)});
+                }
+            };
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
 
             // Execute edit
             match std::fs::read_to_string(file_path) {
                 Ok(content) => {
-                    let new_content = if replace_all {
-                        content.replace(old_string, new_string)
-                    } else {
-                        content.replacen(old_string, new_string, 1)
-                    };
-
-                    if new_content == content {
-                        json!({"type": "text", "text": format!("Edit: old_string not found in {}", file_path)})
-                    } else {
-                        match std::fs::write(file_path, &new_content) {
-                            Ok(()) => {
+                    match patch::replace(&content, old_string, new_string, scope) {
+                        Err(reason) => {
+                            session.record_failure("Edit", &reason);
+                            let _ = storage.save_session(session);
+                            json!({"type": "text", "text": format!("Edit rejected, nothing written: {}: {}", file_path, reason)})
+                        }
+                        Ok(new_content) => match guarded_write(fs_db, session, name, file_path, new_content.as_bytes()) {
+                            Ok((snap, _)) => {
                                 session.track_write(file_path);
                                 session.record_action("Edit", "success", Some(file_path));
+                                project_log(tmp_db, &project, file_path, "edit", new_content.len() as u64, session);
                                 session.record_manifest("Edit", decision.complexity.c, "ALLOWED", None);
                                 let _ = storage.save_session(session);
+                                let budget = config.output_limits.budget(name, decision.complexity.a_optimal_tokens);
                                 json!({"type": "text", "text": format!(
-                                    "Edited: {} | C={} {}",
-                                    file_path, decision.complexity.c, decision.complexity.tier
+                                    "Edited: {} | C={} {} | snapshot v{}\n{}",
+                                    file_path, decision.complexity.c, decision.complexity.tier, snap.version,
+                                    output::limit(name, patch::diff(file_path, &content, &new_content).trim_end(), budget)
                                 )})
                             }
                             Err(e) => {
@@ -1525,7 +2033,7 @@ fn handle_tool_call(
                                 let _ = storage.save_session(session);
                                 json!({"type": "text", "text": format!("Edit write failed: {}", e)})
                             }
-                        }
+                        },
                     }
                 }
                 Err(e) => {
@@ -1540,45 +2048,71 @@ fn handle_tool_call(
         "spf_bash" => {
             let command = args["command"].as_str().unwrap_or("");
             let timeout_secs = args["timeout"].as_u64().unwrap_or(30).min(300);
+            let show_env = args["show_env"].as_bool().unwrap_or(false);
 
-            let params = ToolParams {
-                command: Some(command.to_string()),
-                ..Default::default()
+            let shell = match shell_session(args, shells) {
+                Ok(shell) => shell,
+                Err(reply) => return reply,
             };
-
-            let decision = gate::process("Bash", &params, config, session);
-            if !decision.allowed {
-                session.record_manifest("Bash", decision.complexity.c, "BLOCKED", decision.errors.first().map(|s| s.as_str()));
+            if let (Some((shell_name, state)), true) = (&shell, command.trim().is_empty() && !show_env) {
+                // Nothing to run — open (or reset) the session only
+                shells.insert(shell_name.clone(), state.clone());
+                return json!({"type": "text", "text": format!("SESSION {} CWD: {}", shell_name, state.cwd)});
+            }
+            let run = match bash_gate(name, args, command, shell.as_ref().map(|(_, s)| s), config, session, storage, tmp_db) {
+                Ok(run) => run,
+                Err(reply) => return reply,
+            };
+            let timeout_secs = match run.limits.timeout_secs {
+                0 => timeout_secs,
+                cap => timeout_secs.min(cap),
+            };
+            if show_env && command.trim().is_empty() {
+                session.record_action("Bash", "show_env", None);
                 let _ = storage.save_session(session);
-                return json!({"type": "text", "text": format!("BLOCKED: {}", decision.errors.join(", "))});
+                return json!({"type": "text", "text": masked_env(&run.env)});
             }
 
-            // Execute bash with timeout enforcement
-            let output_result = Command::new("timeout")
-                .arg("--signal=KILL")
-                .arg(format!("{}s", timeout_secs))
-                .arg("bash")
-                .arg("-c")
-                .arg(command)
-                .output()
-                .or_else(|_| {
-                    // timeout binary not found — fall back to direct execution
-                    Command::new("bash")
-                        .arg("-c")
-                        .arg(command)
-                        .output()
-                });
+            // Execute bash under native limits, confined to the write allowlist,
+            // from an explicit environment
+            let timeout = std::time::Duration::from_secs(timeout_secs);
+            let marker = shells::marker();
+            let script = match shell {
+                Some(_) => shells::script(command, &marker),
+                None => command.to_string(),
+            };
+            let mut confinement = sandbox::Confinement::Unavailable("not started".to_string());
+            let output_result = bash_command(&script, &run, config).and_then(|(mut bash, confined)| {
+                confinement = confined;
+                sandbox::run(&mut bash, timeout)
+            });
             match output_result {
-                Ok(output) => {
+                Ok(finished) => {
+                    let hit = sandbox::limits_hit(&finished, &run.limits, timeout);
+                    let mut output = finished.output;
+                    let mut cwd_note = String::new();
+                    if let Some((shell_name, mut state)) = shell {
+                        if let Some((cwd, env)) = shells::take_report(&mut output.stdout, &marker) {
+                            if shell_cwd_allowed(&cwd, config) {
+                                state.cwd = cwd;
+                            } else {
+                                cwd_note = format!(" ({} is outside allowed paths — not kept)", cwd);
+                            }
+                            state.record_env(&env, &run.base_env);
+                        }
+                        cwd_note = format!("\nSESSION {} CWD: {}{}", shell_name, state.cwd, cwd_note);
+                        shells.insert(shell_name, state);
+                    }
                     let stdout = String::from_utf8_lossy(&output.stdout).to_string();
                     let stderr = String::from_utf8_lossy(&output.stderr).to_string();
                     let success = output.status.success();
 
                     session.record_action("Bash", if success { "success" } else { "failed" }, None);
+                    project_log(tmp_db, &run.project, &run.cwd, "bash", 0, session);
                     if !success {
                         session.record_failure("Bash", &stderr);
                     }
-                    session.record_manifest("Bash", decision.complexity.c, "ALLOWED", None);
+                    session.record_manifest("Bash", run.decision.complexity.c, "ALLOWED", None);
                     let _ = storage.save_session(session);
 
                     let mut result = String::new();
@@ -1592,6 +2126,20 @@ fn handle_tool_call(
                     if result.is_empty() {
                         result = format!("Exit code: {}", output.status.code().unwrap_or(-1));
                     }
+                    let budget = config.output_limits.budget("spf_bash", run.decision.complexity.a_optimal_tokens);
+                    result = output::limit("spf_bash", &result, budget);
+                    if !hit.is_empty() {
+                        cmd_log(&format!("LIMIT {} | Bash", hit.join(", ")));
+                        result.push_str(&format!("\nLIMIT HIT: {}", hit.join(", ")));
+                    }
+                    if !confinement.is_confined() {
+                        result.push_str(&format!("\nSANDBOX: {}", confinement));
+                    }
+                    result.push_str(&cwd_note);
+                    if show_env {
+                        result.push('\n');
+                        result.push_str(&masked_env(&run.env));
+                    }
 
                     json!({"type": "text", "text": result})
                 }
@@ -1603,13 +2151,346 @@ fn handle_tool_call(
             }
         }
 
+        // ====== spf_apply_patch ======
+        "spf_apply_patch" => {
+            let patch_text = args["patch"].as_str().unwrap_or("");
+            let files = match patch::parse(patch_text) {
+                Ok(files) => files,
+                Err(e) => return json!({"type": "text", "text": format!("Patch rejected: {:#}", e)}),
+            };
+
+            // Targets: relative paths resolve against the gateway's cwd
+            let cwd = std::env::current_dir().unwrap_or_default();
+            let mut targets: Vec<(String, &patch::FilePatch)> = Vec::new();
+            for file in &files {
+                if file.deletes() {
+                    return json!({"type": "text", "text": format!(
+                        "Patch rejected: {} would be deleted — spf_apply_patch only creates and modifies files", file.target())});
+                }
+                let path = cwd.join(file.target()).to_string_lossy().to_string();
+                if targets.iter().any(|(p, _)| *p == path) {
+                    return json!({"type": "text", "text": format!("Patch rejected: {} appears twice", path)});
+                }
+                targets.push((path, file));
+            }
+
+            // Every target clears the gate (validate_edit, inspection of added lines)
+            // and its project's limits before anything is read or written
+            let mut gated = Vec::new();
+            for (path, file) in &targets {
+                let params = ToolParams {
+                    file_path: Some(path.clone()),
+                    patch: Some(file.render()),
+                    new_string: Some(file.added()),
+                    old_string: Some(file.removed()),
+                    ..Default::default()
+                };
+                let decision = run_gate(name, &params, config, session, storage);
+                if !decision.allowed {
+                    session.record_manifest(name, decision.complexity.c, "BLOCKED", decision.errors.first().map(|s| s.as_str()));
+                    let _ = storage.save_session(session);
+                    return json!({"type": "text", "text": format!("BLOCKED: {}: {}", path, decision.errors.join(", "))});
+                }
+                let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+                    .saturating_sub(file.removed().len() as u64) + file.added().len() as u64;
+                match project_gate(tmp_db, "Edit", path, "edit", size, session) {
+                    Ok(project) => gated.push((decision, project)),
+                    Err(reason) => {
+                        session.record_manifest(name, decision.complexity.c, "BLOCKED", Some(&reason));
+                        let _ = storage.save_session(session);
+                        return json!({"type": "text", "text": format!("BLOCKED: {}", reason)});
+                    }
+                }
+            }
+            // Apply in memory before the approval hold and budget charge, so a patch
+            // that does not apply costs nothing; any hunk that does not match rejects it whole
+            let mut changes = Vec::new();
+            for (path, file) in &targets {
+                let current = match std::fs::read_to_string(path) {
+                    Ok(content) if !file.creates() => content,
+                    Ok(_) => return json!({"type": "text", "text": format!(
+                        "Patch rejected: {} already exists but the patch creates it", path)}),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound && file.creates() => String::new(),
+                    Err(e) => return json!({"type": "text", "text": format!("Patch rejected: {}: {}", path, e)}),
+                };
+                match patch::apply(&current, file) {
+                    Ok(updated) => changes.push((path.as_str(), current, updated)),
+                    Err(e) => {
+                        session.record_failure(name, &e.to_string());
+                        let _ = storage.save_session(session);
+                        return json!({"type": "text", "text": format!("Patch rejected, nothing written: {}", e)});
+                    }
+                }
+            }
+
+            // One approval covers the patch: the target needing it (else the costliest) leads;
+            // every target is charged to the budget
+            let lead = (0..gated.len())
+                .max_by_key(|&i| (gated[i].0.complexity.requires_approval, gated[i].0.complexity.c))
+                .unwrap_or(0);
+            if let Some(held) = post_gate(name, args, &gated[lead].0, session, storage) {
+                return held;
+            }
+            for (i, (decision, _)) in gated.iter().enumerate() {
+                if i != lead {
+                    charge_budget(name, decision, session, storage);
+                }
+            }
+
+            // Write; a failure part way reverts the files already written
+            let mut written = Vec::new();
+            for (path, _, updated) in &changes {
+                match guarded_write(fs_db, session, name, path, updated.as_bytes()) {
+                    Ok(done) => written.push(done),
+                    Err(e) => {
+                        // Revert exactly this patch's writes, newest first
+                        let seqs: Vec<u64> = written.iter().rev().map(|(_, seq)| *seq).collect();
+                        let kept: Vec<String> = match fs_db.as_ref() {
+                            Some(spf_fs) if !seqs.is_empty() => {
+                                match snapshot::revert(spf_fs, &session.id(), &seqs, |_, _| Ok(()), false) {
+                                    Ok(reverted) => reverted.iter()
+                                        .filter(|r| r.error.is_some())
+                                        .map(|r| r.to_string())
+                                        .collect(),
+                                    Err(e) => vec![format!("revert failed: {:#}", e)],
+                                }
+                            }
+                            _ => Vec::new(),
+                        };
+                        let text = if kept.is_empty() {
+                            format!("Patch failed at {}, nothing kept: {}", path, e)
+                        } else {
+                            format!("Patch failed at {}: {}\nEarlier files could not be reverted:\n{}", path, e, kept.join("\n"))
+                        };
+                        session.record_failure(name, &text);
+                        let _ = storage.save_session(session);
+                        return json!({"type": "text", "text": text});
+                    }
+                }
+            }
+
+            let mut diffs = String::new();
+            let mut summary = Vec::new();
+            for (((path, current, updated), (snap, _)), (_, project)) in changes.iter().zip(&written).zip(&gated) {
+                session.track_write(path);
+                project_log(tmp_db, project, path, "edit", updated.len() as u64, session);
+                summary.push(format!("{} | snapshot v{}", path, snap.version));
+                diffs.push_str(&patch::diff(path, current, updated));
+            }
+            let stats = patch::stats(patch_text);
+            let c: u64 = gated.iter().map(|(d, _)| d.complexity.c).sum();
+            session.record_action(name, "success", changes.first().map(|(p, _, _)| *p));
+            session.record_manifest(name, c, "ALLOWED", None);
+            let _ = storage.save_session(session);
+            let budget = config.output_limits.budget(name, gated[lead].0.complexity.a_optimal_tokens);
+            json!({"type": "text", "text": format!(
+                "Patched: {} files, {} hunks (+{} -{}) | C={} {}\n{}\n{}",
+                changes.len(), stats.hunks, stats.added, stats.removed, c, gated[lead].0.complexity.tier,
+                summary.join("\n"), output::limit(name, diffs.trim_end(), budget)
+            )})
+        }
+
+        // ====== spf_undo ======
+        "spf_undo" => {
+            let count = args["count"].as_u64().unwrap_or(1).max(1) as usize;
+            let dry_run = args["dry_run"].as_bool().unwrap_or(false);
+            let gate_params = ToolParams { command: Some(format!("undo {}", count)), ..Default::default() };
+            let decision = run_gate(name, &gate_params, config, session, storage);
+            if !decision.allowed {
+                session.record_manifest(name, decision.complexity.c, "BLOCKED",
+                    decision.errors.first().map(|s| s.as_str()));
+                let _ = storage.save_session(session);
+                return json!({"type": "text", "text": format!("BLOCKED: {}", decision.errors.join(", "))});
+            }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
+            let Some(spf_fs) = fs_db.as_ref() else {
+                return json!({"type": "text", "text": "SPF_FS unavailable — no snapshots to undo from"});
+            };
+
+            let sid = session.id();
+            // Restores go where a fresh spf_write could write now, not where one once could:
+            // write allowlist, blocked paths, then the project's protected paths and trust level
+            let projects = std::cell::RefCell::new(BTreeMap::new());
+            let check = |path: &std::path::Path, snap: &Snapshot| {
+                let path = path.to_string_lossy();
+                if config.is_path_blocked(&path) || !validate::is_write_allowed(&path) {
+                    return Err("path no longer writable under current config".to_string());
+                }
+                let operation = if snap.existed { "write" } else { "delete" };
+                let project = project_gate(tmp_db, "Write", &path, operation, snap.size, session)
+                    .map_err(|reason| format!("BLOCKED: {}", reason))?;
+                projects.borrow_mut().insert(path.into_owned(), (project, operation, snap.size));
+                Ok(())
+            };
+            let reverted = snapshot::journal(spf_fs, &sid)
+                .and_then(|entries| snapshot::revert(spf_fs, &sid, &snapshot::plan_undo(&entries, count), check, dry_run));
+            match reverted {
+                Ok(reverted) if reverted.is_empty() => {
+                    json!({"type": "text", "text": "Nothing to undo: no agent writes left in this session"})
+                }
+                Ok(reverted) => {
+                    let failed = reverted.iter().filter(|r| r.error.is_some()).count();
+                    let changed = reverted.iter().filter(|r| r.changed).count();
+                    let summary = format!(
+                        "{} of {} writes reverted, {} changed outside the gateway",
+                        reverted.len() - failed, reverted.len(), changed
+                    );
+                    if !dry_run {
+                        let projects = projects.into_inner();
+                        for r in reverted.iter().filter(|r| r.error.is_none()) {
+                            if let Some((project, operation, size)) = projects.get(&r.path) {
+                                project_log(tmp_db, project, &r.path, operation, *size, session);
+                            }
+                        }
+                        session.record_action(name, "reverted", None);
+                        session.record_manifest(name, decision.complexity.c, "ALLOWED", Some(&summary));
+                        let _ = storage.save_session(session);
+                    }
+                    let lines = reverted.iter().map(|r| r.to_string()).collect::<Vec<_>>().join("\n");
+                    json!({"type": "text", "text": format!(
+                        "{}{} | C={} {}\n{}",
+                        if dry_run { "DRY RUN: " } else { "" }, summary,
+                        decision.complexity.c, decision.complexity.tier, lines
+                    )})
+                }
+                Err(e) => {
+                    session.record_failure(name, &format!("{:#}", e));
+                    let _ = storage.save_session(session);
+                    json!({"type": "text", "text": format!("Undo failed: {:#}", e)})
+                }
+            }
+        }
+
+        // ====== spf_bash_start ======
+        "spf_bash_start" => {
+            let command = args["command"].as_str().unwrap_or("");
+            let Some(jobs) = jobs.as_mut() else {
+                return json!({"type": "text", "text": "Background jobs unavailable (LIVE/TMP/JOBS could not be created)"});
+            };
+            // A session sets where the job starts; the job does not update it
+            let shell = match shell_session(args, shells) {
+                Ok(shell) => shell,
+                Err(reply) => return reply,
+            };
+            let run = match bash_gate(name, args, command, shell.as_ref().map(|(_, s)| s), config, session, storage, tmp_db) {
+                Ok(run) => run,
+                Err(reply) => return reply,
+            };
+            // Default to the longest run the trust level allows
+            let cap = match run.limits.timeout_secs {
+                0 => 3600,
+                cap => cap,
+            };
+            let timeout_secs = args["timeout"].as_u64().unwrap_or(cap).min(cap);
+            let timeout = std::time::Duration::from_secs(timeout_secs);
+
+            let started = bash_command(command, &run, config).and_then(|(mut bash, confinement)| {
+                let info = jobs.start(&mut bash, command, &run.cwd, timeout, run.limits)?;
+                Ok((info, confinement))
+            });
+            match started {
+                Ok((info, confinement)) => {
+                    if let Some((shell_name, state)) = shell {
+                        shells.entry(shell_name).or_insert(state);
+                    }
+                    session.record_action("Bash", "job_started", Some(&info.id));
+                    project_log(tmp_db, &run.project, &run.cwd, "bash", 0, session);
+                    session.record_manifest("Bash", run.decision.complexity.c, "ALLOWED", None);
+                    let _ = storage.save_session(session);
+                    cmd_log(&format!("JOB {} START pid {} | {}", info.id, info.pid, command));
+
+                    let mut text = format!(
+                        "STARTED {} (pid {}, timeout {}s)\nPoll with spf_bash_output job_id={} stdout_offset=0 stderr_offset=0",
+                        info.id, info.pid, timeout_secs, info.id
+                    );
+                    if !confinement.is_confined() {
+                        text.push_str(&format!("\nSANDBOX: {}", confinement));
+                    }
+                    json!({"type": "text", "text": text})
+                }
+                Err(e) => {
+                    session.record_failure("Bash", &e.to_string());
+                    let _ = storage.save_session(session);
+                    json!({"type": "text", "text": format!("Bash job failed to start: {}", e)})
+                }
+            }
+        }
+
+        // ====== spf_bash_output / spf_bash_kill ======
+        "spf_bash_output" | "spf_bash_kill" => {
+            let job_id = args["job_id"].as_str().unwrap_or("");
+            let gate_params = ToolParams { command: Some(job_id.to_string()), ..Default::default() };
+            let decision = run_gate(name, &gate_params, config, session, storage);
+            if !decision.allowed {
+                session.record_manifest(name, decision.complexity.c, "BLOCKED",
+                    decision.errors.first().map(|s| s.as_str()));
+                let _ = storage.save_session(session);
+                return json!({"type": "text", "text": decision.message});
+            }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
+            let Some(jobs) = jobs.as_mut() else {
+                return json!({"type": "text", "text": "Background jobs unavailable (LIVE/TMP/JOBS could not be created)"});
+            };
+            session.record_action(name, if name == "spf_bash_kill" { "kill" } else { "output" }, Some(job_id));
+            let _ = storage.save_session(session);
+
+            if job_id.is_empty() {
+                let list = jobs.list().iter()
+                    .map(|j| format!("{} | {} | pid {} | {}", j.id, j.status, j.pid, j.command))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                return json!({"type": "text", "text": if list.is_empty() { "No jobs".to_string() } else { list }});
+            }
+
+            if name == "spf_bash_kill" {
+                return match jobs.kill(job_id) {
+                    Some(info) => {
+                        cmd_log(&format!("JOB {} KILL | {}", info.id, info.status));
+                        json!({"type": "text", "text": format!("{} {}", info.id, info.status)})
+                    }
+                    None => json!({"type": "text", "text": format!("Job not found: {}", job_id)}),
+                };
+            }
+
+            let stdout_offset = args["stdout_offset"].as_u64().unwrap_or(0);
+            let stderr_offset = args["stderr_offset"].as_u64().unwrap_or(0);
+            let (Some(info), Some(output)) = (jobs.info(job_id), jobs.output(job_id, stdout_offset, stderr_offset)) else {
+                return json!({"type": "text", "text": format!("Job not found: {}", job_id)});
+            };
+            match output {
+                Ok((stdout, stderr)) => {
+                    let mut text = format!("JOB {} {}", info.id, info.status);
+                    for (label, requested, chunk) in [("STDOUT", stdout_offset, &stdout), ("STDERR", stderr_offset, &stderr)] {
+                        text.push_str(&format!("\n{} [{}..{} of {}]", label, chunk.start, chunk.next, chunk.total));
+                        if chunk.start > requested {
+                            text.push_str(&format!(" ({} bytes dropped)", chunk.start - requested));
+                        }
+                        if !chunk.data.is_empty() {
+                            text.push('\n');
+                            text.push_str(&String::from_utf8_lossy(&chunk.data));
+                        }
+                    }
+                    if !info.limits_hit.is_empty() {
+                        text.push_str(&format!("\nLIMIT HIT: {}", info.limits_hit.join(", ")));
+                    }
+                    text.push_str(&format!("\nNEXT: stdout_offset={} stderr_offset={}", stdout.next, stderr.next));
+                    json!({"type": "text", "text": text})
+                }
+                Err(e) => json!({"type": "text", "text": format!("Job output unreadable: {}", e)}),
+            }
+        }
+
         // ====== spf_glob ======
         "spf_glob" => {
             let pattern = args["pattern"].as_str().unwrap_or("");
             let path = args["path"].as_str().unwrap_or(".");
 
             let gate_params = ToolParams { command: Some(pattern.to_string()), file_path: Some(path.to_string()), ..Default::default() };
-            let decision = gate::process("spf_glob", &gate_params, config, session);
+            let decision = run_gate("spf_glob", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_glob", decision.complexity.c,
                     "BLOCKED",
@@ -1617,6 +2498,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("Glob", "called", None);
 
             // Validate search path is within allowed boundaries
@@ -1644,18 +2528,19 @@ fn handle_tool_call(
                 .arg(path)
                 .arg("-name")
                 .arg(pattern)
+                .env_clear()
+                .envs(config.exec_env.build(gateway_env(), &[]))
                 .stderr(std::process::Stdio::null())
                 .output()
             {
                 Ok(output) => {
                     let stdout = String::from_utf8_lossy(&output.stdout);
-                    // Limit to first 100 results (replaces piped head -100)
-                    let truncated: String = stdout.lines().take(100).collect::<Vec<_>>().join("\n");
                     let _ = storage.save_session(session);
-                    if truncated.is_empty() {
+                    if stdout.trim().is_empty() {
                         json!({"type": "text", "text": "No matches found"})
                     } else {
-                        json!({"type": "text", "text": truncated})
+                        let budget = config.output_limits.budget("spf_glob", decision.complexity.a_optimal_tokens);
+                        json!({"type": "text", "text": output::limit("spf_glob", stdout.trim_end(), budget)})
                     }
                 }
                 Err(e) => {
@@ -1675,7 +2560,7 @@ fn handle_tool_call(
             let context = args["context_lines"].as_u64().unwrap_or(0);
 
             let gate_params = ToolParams { command: Some(pattern.to_string()), file_path: Some(path.to_string()), ..Default::default() };
-            let decision = gate::process("spf_grep", &gate_params, config, session);
+            let decision = run_gate("spf_grep", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_grep", decision.complexity.c,
                     "BLOCKED",
@@ -1683,6 +2568,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("Grep", "called", None);
 
             // Validate search path is within allowed boundaries
@@ -1718,18 +2606,18 @@ fn handle_tool_call(
             }
             // "--" prevents pattern from being interpreted as a flag
             rg.arg("--").arg(pattern).arg(path);
+            rg.env_clear().envs(config.exec_env.build(gateway_env(), &[]));
             rg.stderr(std::process::Stdio::null());
 
             match rg.output() {
                 Ok(output) => {
                     let stdout = String::from_utf8_lossy(&output.stdout);
-                    // Limit to first 500 lines (replaces piped head -500)
-                    let truncated: String = stdout.lines().take(500).collect::<Vec<_>>().join("\n");
                     let _ = storage.save_session(session);
-                    if truncated.is_empty() {
+                    if stdout.trim().is_empty() {
                         json!({"type": "text", "text": "No matches found"})
                     } else {
-                        json!({"type": "text", "text": truncated})
+                        let budget = config.output_limits.budget("spf_grep", decision.complexity.a_optimal_tokens);
+                        json!({"type": "text", "text": output::limit("spf_grep", stdout.trim_end(), budget)})
                     }
                 }
                 Err(e) => {
@@ -1751,13 +2639,16 @@ fn handle_tool_call(
                 query: Some(prompt.to_string()),
                 ..Default::default()
             };
-            let decision = gate::process("spf_web_fetch", &params, config, session);
+            let decision = run_gate("spf_web_fetch", &params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("web_fetch", decision.complexity.c, "BLOCKED",
                     decision.errors.first().map(|s| s.as_str()));
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": format!("BLOCKED: {}", decision.errors.join(", "))});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
 
             session.record_action("WebFetch", "called", None);
             match WebClient::new() {
@@ -1798,13 +2689,16 @@ fn handle_tool_call(
                 query: Some(query.to_string()),
                 ..Default::default()
             };
-            let decision = gate::process("spf_web_search", &params, config, session);
+            let decision = run_gate("spf_web_search", &params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("web_search", decision.complexity.c, "BLOCKED",
                     decision.errors.first().map(|s| s.as_str()));
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": format!("BLOCKED: {}", decision.errors.join(", "))});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
 
             session.record_action("WebSearch", "called", None);
             match WebClient::new() {
@@ -1846,13 +2740,16 @@ fn handle_tool_call(
                 file_path: Some(save_path.to_string()),
                 ..Default::default()
             };
-            let decision = gate::process("spf_web_download", &params, config, session);
+            let decision = run_gate("spf_web_download", &params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("web_download", decision.complexity.c, "BLOCKED",
                     decision.errors.first().map(|s| s.as_str()));
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": format!("BLOCKED: {}", decision.errors.join(", "))});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
 
             session.record_action("WebDownload", "called", Some(save_path));
             match WebClient::new() {
@@ -1896,13 +2793,16 @@ fn handle_tool_call(
                 query: Some(method.to_string()),
                 ..Default::default()
             };
-            let decision = gate::process("spf_web_api", &params, config, session);
+            let decision = run_gate("spf_web_api", &params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("web_api", decision.complexity.c, "BLOCKED",
                     decision.errors.first().map(|s| s.as_str()));
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": format!("BLOCKED: {}", decision.errors.join(", "))});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
 
             session.record_action("WebAPI", "called", None);
             match WebClient::new() {
@@ -1936,84 +2836,114 @@ fn handle_tool_call(
         // ====== spf_notebook_edit ======
         "spf_notebook_edit" => {
             let notebook_path = args["notebook_path"].as_str().unwrap_or("");
-            let new_source = args["new_source"].as_str().unwrap_or("");
-            let cell_number = args["cell_number"].as_u64().unwrap_or(0) as usize;
-            let cell_type = args["cell_type"].as_str().unwrap_or("code");
+            let new_source = args["new_source"].as_str();
+            let cell_type = args["cell_type"].as_str().map(str::to_string);
             let edit_mode = args["edit_mode"].as_str().unwrap_or("replace");
+            let target = notebook::Target {
+                id: args["cell_id"].as_str().map(str::to_string),
+                index: args["cell_number"].as_u64().map(|n| n as usize),
+            };
 
             // HARDCODE: Gate check — NO BYPASS
             let params = ToolParams {
                 file_path: Some(notebook_path.to_string()),
-                content: Some(new_source.to_string()),
+                content: Some(new_source.unwrap_or("").to_string()),
                 ..Default::default()
             };
 
-            let decision = gate::process("spf_notebook_edit", &params, config, session);
+            let decision = run_gate("spf_notebook_edit", &params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("NotebookEdit", decision.complexity.c, "BLOCKED",
                     decision.errors.first().map(|s| s.as_str()));
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": format!("BLOCKED: {}", decision.errors.join(", "))});
             }
+            // Read notebook JSON
+            let content = match std::fs::read_to_string(notebook_path) {
+                Ok(content) => content,
+                Err(e) => {
+                    session.record_failure("NotebookEdit", &e.to_string());
+                    let _ = storage.save_session(session);
+                    return json!({"type": "text", "text": format!("Read failed: {}", e)});
+                }
+            };
+            let mut nb = match serde_json::from_str::<Value>(&content) {
+                Ok(nb) => nb,
+                Err(e) => return json!({"type": "text", "text": format!("JSON parse error: {}", e)}),
+            };
 
-            session.record_action("NotebookEdit", "called", Some(notebook_path));
+            // Projected size after the edit, for the project write limit: a replace
+            // swaps the addressed cell's source for the new one
+            let replaced = match edit_mode {
+                "replace" => notebook::cell(&nb, &target).map_or(0, |cell| notebook::source_text(cell).len()),
+                _ => 0,
+            };
+            let edit_size = (content.len() + new_source.map_or(0, str::len)).saturating_sub(replaced) as u64;
+            let project = match project_gate(tmp_db, "NotebookEdit", notebook_path, "edit", edit_size, session) {
+                Ok(project) => project,
+                Err(reason) => {
+                    session.record_manifest("NotebookEdit", decision.complexity.c, "BLOCKED", Some(&reason));
+                    let _ = storage.save_session(session);
+                    return json!({"type": "text", "text": format!("BLOCKED: {}", reason)});
+                }
+            };
+            // Malformed calls are answered before they are held for approval or charged
+            let edit = match (edit_mode, new_source) {
+                ("delete", _) => notebook::Edit::Delete,
+                ("replace", Some(source)) => notebook::Edit::Replace { source: source.to_string(), cell_type },
+                ("insert", Some(source)) => notebook::Edit::Insert {
+                    source: source.to_string(),
+                    cell_type: cell_type.unwrap_or_else(|| "code".to_string()),
+                },
+                ("replace" | "insert", None) => return json!({"type": "text", "text": format!("new_source is required for {}", edit_mode)}),
+                _ => return json!({"type": "text", "text": "Invalid edit_mode"}),
+            };
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
 
-            // Read notebook JSON
-            match std::fs::read_to_string(notebook_path) {
-                Ok(content) => {
-                    match serde_json::from_str::<Value>(&content) {
-                        Ok(mut notebook) => {
-                            if let Some(cells) = notebook.get_mut("cells").and_then(|c| c.as_array_mut()) {
-                                match edit_mode {
-                                    "replace" => {
-                                        if cell_number < cells.len() {
-                                            cells[cell_number]["source"] = json!([new_source]);
-                                            cells[cell_number]["cell_type"] = json!(cell_type);
-                                        } else {
-                                            return json!({"type": "text", "text": format!("Cell {} not found", cell_number)});
-                                        }
-                                    }
-                                    "insert" => {
-                                        let new_cell = json!({
-                                            "cell_type": cell_type,
-                                            "source": [new_source],
-                                            "metadata": {},
-                                            "outputs": []
-                                        });
-                                        cells.insert(cell_number, new_cell);
-                                    }
-                                    "delete" => {
-                                        if cell_number < cells.len() {
-                                            cells.remove(cell_number);
-                                        }
-                                    }
-                                    _ => return json!({"type": "text", "text": "Invalid edit_mode"})
-                                }
+            session.record_action("NotebookEdit", "called", Some(notebook_path));
 
-                                // Write back
-                                match std::fs::write(notebook_path, serde_json::to_string_pretty(&notebook).unwrap()) {
-                                    Ok(()) => {
-                                        session.track_write(notebook_path);
-                                        let _ = storage.save_session(session);
-                                        json!({"type": "text", "text": format!("Notebook edited: {} cell {} ({})", notebook_path, cell_number, edit_mode)})
-                                    }
-                                    Err(e) => {
-                                        session.record_failure("NotebookEdit", &e.to_string());
-                                        let _ = storage.save_session(session);
-                                        json!({"type": "text", "text": format!("Write failed: {}", e)})
-                                    }
-                                }
-                            } else {
-                                json!({"type": "text", "text": "Invalid notebook: no cells array"})
-                            }
-                        }
-                        Err(e) => json!({"type": "text", "text": format!("JSON parse error: {}", e)})
+            // Edit, then check the result against nbformat before anything is written
+            let checked = notebook::edit(&mut nb, &target, edit)
+                .map_err(|e| e.to_string())
+                .and_then(|change| {
+                    let errors = notebook::validate(&nb);
+                    if errors.is_empty() {
+                        Ok(change)
+                    } else {
+                        Err(format!("result is not valid nbformat 4: {}", errors.join("; ")))
                     }
+                });
+            let change = match checked {
+                Ok(change) => change,
+                Err(reason) => {
+                    session.record_failure("NotebookEdit", &reason);
+                    let _ = storage.save_session(session);
+                    return json!({"type": "text", "text": format!("Notebook edit rejected, nothing written: {}: {}", notebook_path, reason)});
+                }
+            };
+
+            // Write back as nbformat does
+            let written = notebook::to_json(&nb).map_err(|e| e.to_string()).and_then(|json| {
+                guarded_write(fs_db, session, name, notebook_path, json.as_bytes()).map(|done| (done, json.len()))
+            });
+            match written {
+                Ok(((snap, _), size)) => {
+                    session.track_write(notebook_path);
+                    project_log(tmp_db, &project, notebook_path, "edit", size as u64, session);
+                    let _ = storage.save_session(session);
+                    let budget = config.output_limits.budget(name, decision.complexity.a_optimal_tokens);
+                    json!({"type": "text", "text": format!(
+                        "Notebook edited: {} cell {} ({}) | snapshot v{}\n{}",
+                        notebook_path, change.index, change.mode, snap.version,
+                        output::limit(name, &notebook::diff(&change), budget)
+                    )})
                 }
                 Err(e) => {
-                    session.record_failure("NotebookEdit", &e.to_string());
+                    session.record_failure("NotebookEdit", &e);
                     let _ = storage.save_session(session);
-                    json!({"type": "text", "text": format!("Read failed: {}", e)})
+                    json!({"type": "text", "text": format!("Write failed: {}", e)})
                 }
             }
         }
@@ -2025,7 +2955,7 @@ fn handle_tool_call(
             let limit = args["limit"].as_u64().unwrap_or(5);
 
             let gate_params = ToolParams { query: Some(query.to_string()), ..Default::default() };
-            let decision = gate::process("spf_brain_search", &gate_params, config, session);
+            let decision = run_gate("spf_brain_search", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_brain_search", decision.complexity.c,
                     "BLOCKED",
@@ -2033,6 +2963,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
 
             session.record_action("brain_search", "called", None);
 
@@ -2060,7 +2993,7 @@ fn handle_tool_call(
             let tags = args["tags"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { content: Some(text.to_string()), ..Default::default() };
-            let decision = gate::process("spf_brain_store", &gate_params, config, session);
+            let decision = run_gate("spf_brain_store", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_brain_store", decision.complexity.c,
                     "BLOCKED",
@@ -2068,6 +3001,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
 
             session.record_action("brain_store", "called", None);
 
@@ -2093,7 +3029,7 @@ fn handle_tool_call(
             let max_tokens = args["max_tokens"].as_u64().unwrap_or(2000);
 
             let gate_params = ToolParams { query: Some(query.to_string()), ..Default::default() };
-            let decision = gate::process("spf_brain_context", &gate_params, config, session);
+            let decision = run_gate("spf_brain_context", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_brain_context", decision.complexity.c,
                     "BLOCKED",
@@ -2101,6 +3037,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("brain_context", "called", None);
             let (success, output) = run_brain(&["context", query, "--max-tokens", &max_tokens.to_string()]);
             let _ = storage.save_session(session);
@@ -2116,7 +3055,7 @@ fn handle_tool_call(
             let path = args["path"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { file_path: Some(path.to_string()), ..Default::default() };
-            let decision = gate::process("spf_brain_index", &gate_params, config, session);
+            let decision = run_gate("spf_brain_index", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_brain_index", decision.complexity.c,
                     "BLOCKED",
@@ -2124,6 +3063,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("brain_index", "called", Some(path));
             let (success, output) = run_brain(&["index", path]);
             let _ = storage.save_session(session);
@@ -2138,7 +3080,7 @@ fn handle_tool_call(
         "spf_brain_list" => {
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_brain_list", &gate_params, config, session);
+            let decision = run_gate("spf_brain_list", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_brain_list", decision.complexity.c,
                     "BLOCKED",
@@ -2146,6 +3088,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("brain_list", "called", None);
             let (success, output) = run_brain(&["list"]);
             let _ = storage.save_session(session);
@@ -2160,7 +3105,7 @@ fn handle_tool_call(
         "spf_brain_status" => {
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_brain_status", &gate_params, config, session);
+            let decision = run_gate("spf_brain_status", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_brain_status", decision.complexity.c,
                     "BLOCKED",
@@ -2168,6 +3113,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("brain_status", "called", None);
             let brain = brain_path();
             let mut parts = vec![format!("Binary: {:?} ({})", brain, if brain.exists() { "OK" } else { "NOT FOUND" })];
@@ -2192,7 +3140,7 @@ fn handle_tool_call(
             let collection = args["collection"].as_str().unwrap_or("default");
 
             let gate_params = ToolParams { query: Some(query.to_string()), ..Default::default() };
-            let decision = gate::process("spf_brain_recall", &gate_params, config, session);
+            let decision = run_gate("spf_brain_recall", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_brain_recall", decision.complexity.c,
                     "BLOCKED",
@@ -2200,6 +3148,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("brain_recall", "called", None);
             let (success, output) = run_brain(&["recall", query, "-c", collection]);
             let _ = storage.save_session(session);
@@ -2215,7 +3166,7 @@ fn handle_tool_call(
             let collection = args["collection"].as_str().unwrap_or("default");
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_brain_list_docs", &gate_params, config, session);
+            let decision = run_gate("spf_brain_list_docs", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_brain_list_docs", decision.complexity.c,
                     "BLOCKED",
@@ -2223,6 +3174,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("brain_list_docs", "called", None);
             let (success, output) = run_brain(&["list-docs", "-c", collection]);
             let _ = storage.save_session(session);
@@ -2239,7 +3193,7 @@ fn handle_tool_call(
             let collection = args["collection"].as_str().unwrap_or("default");
 
             let gate_params = ToolParams { command: Some(doc_id.to_string()), ..Default::default() };
-            let decision = gate::process("spf_brain_get_doc", &gate_params, config, session);
+            let decision = run_gate("spf_brain_get_doc", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_brain_get_doc", decision.complexity.c,
                     "BLOCKED",
@@ -2247,6 +3201,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("brain_get_doc", "called", None);
             let (success, output) = run_brain(&["get-doc", doc_id, "-c", collection]);
             let _ = storage.save_session(session);
@@ -2264,7 +3221,7 @@ fn handle_tool_call(
             let topic = args["topic"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { command: Some(topic.to_string()), ..Default::default() };
-            let decision = gate::process("spf_rag_collect_web", &gate_params, config, session);
+            let decision = run_gate("spf_rag_collect_web", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_rag_collect_web", decision.complexity.c,
                     "BLOCKED",
@@ -2272,6 +3229,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("rag_collect_web", "called", None);
             let mut cmd_args = vec!["collect"];
             if !topic.is_empty() {
@@ -2292,7 +3252,7 @@ fn handle_tool_call(
             let path = args["path"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { file_path: Some(path.to_string()), ..Default::default() };
-            let decision = gate::process("spf_rag_collect_file", &gate_params, config, session);
+            let decision = run_gate("spf_rag_collect_file", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_rag_collect_file", decision.complexity.c,
                     "BLOCKED",
@@ -2300,6 +3260,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("rag_collect_file", "called", Some(path));
             let (success, output) = run_rag(&["collect", "--path", path]);
             let _ = storage.save_session(session);
@@ -2315,7 +3278,7 @@ fn handle_tool_call(
             let path = args["path"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { file_path: Some(path.to_string()), ..Default::default() };
-            let decision = gate::process("spf_rag_collect_folder", &gate_params, config, session);
+            let decision = run_gate("spf_rag_collect_folder", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_rag_collect_folder", decision.complexity.c,
                     "BLOCKED",
@@ -2323,6 +3286,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("rag_collect_folder", "called", Some(path));
             let (success, output) = run_rag(&["collect", "--path", path]);
             let _ = storage.save_session(session);
@@ -2337,7 +3303,7 @@ fn handle_tool_call(
         "spf_rag_collect_drop" => {
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_rag_collect_drop", &gate_params, config, session);
+            let decision = run_gate("spf_rag_collect_drop", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_rag_collect_drop", decision.complexity.c,
                     "BLOCKED",
@@ -2345,6 +3311,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("rag_collect_drop", "called", None);
             let (success, output) = run_rag(&["drop"]);
             let _ = storage.save_session(session);
@@ -2360,7 +3329,7 @@ fn handle_tool_call(
             let category = args["category"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_rag_index_gathered", &gate_params, config, session);
+            let decision = run_gate("spf_rag_index_gathered", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_rag_index_gathered", decision.complexity.c,
                     "BLOCKED",
@@ -2368,6 +3337,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("rag_index_gathered", "called", None);
             let mut cmd_args = vec!["index"];
             if !category.is_empty() {
@@ -2388,7 +3360,7 @@ fn handle_tool_call(
             let category = args["category"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { command: Some(category.to_string()), ..Default::default() };
-            let decision = gate::process("spf_rag_dedupe", &gate_params, config, session);
+            let decision = run_gate("spf_rag_dedupe", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_rag_dedupe", decision.complexity.c,
                     "BLOCKED",
@@ -2396,6 +3368,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("rag_dedupe", "called", None);
             // Dedupe goes through brain binary directly
             let (success, output) = run_brain(&["dedup", "-c", category]);
@@ -2411,7 +3386,7 @@ fn handle_tool_call(
         "spf_rag_status" => {
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_rag_status", &gate_params, config, session);
+            let decision = run_gate("spf_rag_status", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_rag_status", decision.complexity.c,
                     "BLOCKED",
@@ -2419,6 +3394,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("rag_status", "called", None);
             let (success, output) = run_rag(&["status"]);
             let _ = storage.save_session(session);
@@ -2434,7 +3412,7 @@ fn handle_tool_call(
             let category = args["category"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_rag_list_gathered", &gate_params, config, session);
+            let decision = run_gate("spf_rag_list_gathered", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_rag_list_gathered", decision.complexity.c,
                     "BLOCKED",
@@ -2442,6 +3420,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("rag_list_gathered", "called", None);
             let mut cmd_args = vec!["list-gathered"];
             if !category.is_empty() {
@@ -2461,7 +3442,7 @@ fn handle_tool_call(
         "spf_rag_bandwidth_status" => {
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_rag_bandwidth_status", &gate_params, config, session);
+            let decision = run_gate("spf_rag_bandwidth_status", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_rag_bandwidth_status", decision.complexity.c,
                     "BLOCKED",
@@ -2469,6 +3450,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("rag_bandwidth_status", "called", None);
             let (success, output) = run_rag(&["bandwidth"]);
             let _ = storage.save_session(session);
@@ -2484,7 +3468,7 @@ fn handle_tool_call(
             let url = args["url"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { url: Some(url.to_string()), ..Default::default() };
-            let decision = gate::process("spf_rag_fetch_url", &gate_params, config, session);
+            let decision = run_gate("spf_rag_fetch_url", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_rag_fetch_url", decision.complexity.c,
                     "BLOCKED",
@@ -2492,6 +3476,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("rag_fetch_url", "called", None);
             // Fetch URL through collect with path (URL handling)
             let (success, output) = run_rag(&["collect", "--path", url]);
@@ -2508,7 +3495,7 @@ fn handle_tool_call(
             let feed_name = args["feed_name"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_rag_collect_rss", &gate_params, config, session);
+            let decision = run_gate("spf_rag_collect_rss", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_rag_collect_rss", decision.complexity.c,
                     "BLOCKED",
@@ -2516,6 +3503,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("rag_collect_rss", "called", None);
             let mut cmd_args = vec!["rss"];
             if !feed_name.is_empty() {
@@ -2535,7 +3525,7 @@ fn handle_tool_call(
         "spf_rag_list_feeds" => {
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_rag_list_feeds", &gate_params, config, session);
+            let decision = run_gate("spf_rag_list_feeds", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_rag_list_feeds", decision.complexity.c,
                     "BLOCKED",
@@ -2543,6 +3533,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("rag_list_feeds", "called", None);
             // Read RSS config directly
             let rss_path = rag_collector_dir().join("sources/rss_sources.json");
@@ -2567,7 +3560,7 @@ fn handle_tool_call(
             let collection = args["collection"].as_str().unwrap_or("default");
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_rag_pending_searches", &gate_params, config, session);
+            let decision = run_gate("spf_rag_pending_searches", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_rag_pending_searches", decision.complexity.c,
                     "BLOCKED",
@@ -2575,6 +3568,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("rag_pending_searches", "called", None);
             let (success, output) = run_brain(&["pending-searches", "-c", collection, "-f", "json"]);
             let _ = storage.save_session(session);
@@ -2591,7 +3587,7 @@ fn handle_tool_call(
             let collection = args["collection"].as_str().unwrap_or("default");
 
             let gate_params = ToolParams { command: Some(seeker_id.to_string()), ..Default::default() };
-            let decision = gate::process("spf_rag_fulfill_search", &gate_params, config, session);
+            let decision = run_gate("spf_rag_fulfill_search", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_rag_fulfill_search", decision.complexity.c,
                     "BLOCKED",
@@ -2599,6 +3595,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("rag_fulfill_search", "called", None);
             let (success, output) = run_brain(&["fulfill-search", seeker_id, "-c", collection]);
             let _ = storage.save_session(session);
@@ -2615,7 +3614,7 @@ fn handle_tool_call(
             let collection = args["collection"].as_str().unwrap_or("default");
 
             let gate_params = ToolParams { query: Some(query.to_string()), ..Default::default() };
-            let decision = gate::process("spf_rag_smart_search", &gate_params, config, session);
+            let decision = run_gate("spf_rag_smart_search", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_rag_smart_search", decision.complexity.c,
                     "BLOCKED",
@@ -2623,6 +3622,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("rag_smart_search", "called", None);
             let (success, output) = run_brain(&["smart-search", query, "-c", collection, "-f", "json"]);
             let _ = storage.save_session(session);
@@ -2639,7 +3641,7 @@ fn handle_tool_call(
             let max_fetches = args["max_fetches"].as_u64().unwrap_or(5);
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_rag_auto_fetch_gaps", &gate_params, config, session);
+            let decision = run_gate("spf_rag_auto_fetch_gaps", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_rag_auto_fetch_gaps", decision.complexity.c,
                     "BLOCKED",
@@ -2647,6 +3649,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("rag_auto_fetch_gaps", "called", None);
             // Auto-fetch uses pending-searches then fetches URLs
             let (success, output) = run_brain(&["auto-fetch", "-c", collection, "--max", &max_fetches.to_string()]);
@@ -2667,7 +3672,7 @@ fn handle_tool_call(
         "spf_config_paths" => {
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_config_paths", &gate_params, config, session);
+            let decision = run_gate("spf_config_paths", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_config_paths", decision.complexity.c,
                     "BLOCKED",
@@ -2675,6 +3680,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("config_paths", "list", None);
             let _ = storage.save_session(session);
 
@@ -2696,7 +3704,7 @@ fn handle_tool_call(
         "spf_config_stats" => {
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_config_stats", &gate_params, config, session);
+            let decision = run_gate("spf_config_stats", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_config_stats", decision.complexity.c,
                     "BLOCKED",
@@ -2704,6 +3712,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("config_stats", "get", None);
             let _ = storage.save_session(session);
 
@@ -2725,7 +3736,7 @@ fn handle_tool_call(
         "spf_projects_list" => {
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_projects_list", &gate_params, config, session);
+            let decision = run_gate("spf_projects_list", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_projects_list", decision.complexity.c,
                     "BLOCKED",
@@ -2733,6 +3744,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("projects_list", "list", None);
             let _ = storage.save_session(session);
 
@@ -2755,7 +3769,7 @@ fn handle_tool_call(
             let key = args["key"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { file_path: Some(key.to_string()), ..Default::default() };
-            let decision = gate::process("spf_projects_get", &gate_params, config, session);
+            let decision = run_gate("spf_projects_get", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_projects_get", decision.complexity.c,
                     "BLOCKED",
@@ -2763,6 +3777,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("projects_get", "get", Some(key));
             let _ = storage.save_session(session);
 
@@ -2781,7 +3798,7 @@ fn handle_tool_call(
             let value = args["value"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { file_path: Some(key.to_string()), ..Default::default() };
-            let decision = gate::process("spf_projects_set", &gate_params, config, session);
+            let decision = run_gate("spf_projects_set", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_projects_set", decision.complexity.c,
                     "BLOCKED",
@@ -2789,6 +3806,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("projects_set", "write", Some(key));
             let _ = storage.save_session(session);
 
@@ -2805,7 +3825,7 @@ fn handle_tool_call(
             let key = args["key"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { file_path: Some(key.to_string()), ..Default::default() };
-            let decision = gate::process("spf_projects_delete", &gate_params, config, session);
+            let decision = run_gate("spf_projects_delete", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_projects_delete", decision.complexity.c,
                     "BLOCKED",
@@ -2813,6 +3833,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("projects_delete", "write", Some(key));
             let _ = storage.save_session(session);
 
@@ -2829,7 +3852,7 @@ fn handle_tool_call(
         "spf_projects_stats" => {
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_projects_stats", &gate_params, config, session);
+            let decision = run_gate("spf_projects_stats", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_projects_stats", decision.complexity.c,
                     "BLOCKED",
@@ -2837,6 +3860,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("projects_stats", "get", None);
             let _ = storage.save_session(session);
 
@@ -2857,7 +3883,7 @@ fn handle_tool_call(
         "spf_tmp_list" => {
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_tmp_list", &gate_params, config, session);
+            let decision = run_gate("spf_tmp_list", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_tmp_list", decision.complexity.c,
                     "BLOCKED",
@@ -2865,6 +3891,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("tmp_list", "list", None);
             let _ = storage.save_session(session);
 
@@ -2888,7 +3917,7 @@ fn handle_tool_call(
         "spf_tmp_stats" => {
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_tmp_stats", &gate_params, config, session);
+            let decision = run_gate("spf_tmp_stats", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_tmp_stats", decision.complexity.c,
                     "BLOCKED",
@@ -2896,6 +3925,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("tmp_stats", "get", None);
             let _ = storage.save_session(session);
 
@@ -2917,7 +3949,7 @@ fn handle_tool_call(
             let path_arg = args["path"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { file_path: Some(path_arg.to_string()), ..Default::default() };
-            let decision = gate::process("spf_tmp_get", &gate_params, config, session);
+            let decision = run_gate("spf_tmp_get", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_tmp_get", decision.complexity.c,
                     "BLOCKED",
@@ -2925,18 +3957,24 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("tmp_get", "get", Some(path_arg));
             let _ = storage.save_session(session);
 
             match tmp_db {
                 Some(db) => match db.get_project(path_arg) {
                     Ok(Some(proj)) => {
+                        let limits = db.get_exec_limits(proj.trust_level)
+                            .map(|l| l.summary())
+                            .unwrap_or_else(|e| format!("unreadable ({})", e));
                         json!({"type": "text", "text": format!(
-                            "Project: {}\nPath: {}\nTrust: {:?}\nActive: {}\nReads: {} | Writes: {} | Session writes: {}/{}\nMax write size: {} | Total C: {}\nProtected: {:?}\nCreated: {} | Last accessed: {}\nNotes: {}",
+                            "Project: {}\nPath: {}\nTrust: {:?}\nActive: {}\nReads: {} | Writes: {} | Session writes: {}/{}\nMax write size: {} | Total C: {}\nProtected: {:?}\nBash limits: {}\nCreated: {} | Last accessed: {}\nNotes: {}",
                             proj.name, proj.path, proj.trust_level, proj.is_active,
                             proj.total_reads, proj.total_writes, proj.session_writes, proj.max_writes_per_session,
                             proj.max_write_size, proj.total_complexity,
-                            proj.protected_paths,
+                            proj.protected_paths, limits,
                             format_timestamp(proj.created_at), format_timestamp(proj.last_accessed),
                             if proj.notes.is_empty() { "None" } else { &proj.notes }
                         )})
@@ -2951,7 +3989,7 @@ fn handle_tool_call(
         "spf_tmp_active" => {
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_tmp_active", &gate_params, config, session);
+            let decision = run_gate("spf_tmp_active", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_tmp_active", decision.complexity.c,
                     "BLOCKED",
@@ -2959,6 +3997,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("tmp_active", "get", None);
             let _ = storage.save_session(session);
 
@@ -2992,7 +4033,7 @@ fn handle_tool_call(
         "spf_agent_stats" => {
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_agent_stats", &gate_params, config, session);
+            let decision = run_gate("spf_agent_stats", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_agent_stats", decision.complexity.c,
                     "BLOCKED",
@@ -3000,6 +4041,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("agent_stats", "get", None);
             let _ = storage.save_session(session);
 
@@ -3022,7 +4066,7 @@ fn handle_tool_call(
             let limit = args["limit"].as_u64().unwrap_or(10) as usize;
 
             let gate_params = ToolParams { query: Some(query.to_string()), ..Default::default() };
-            let decision = gate::process("spf_agent_memory_search", &gate_params, config, session);
+            let decision = run_gate("spf_agent_memory_search", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_agent_memory_search", decision.complexity.c,
                     "BLOCKED",
@@ -3030,6 +4074,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("agent_memory_search", "search", Some(query));
             let _ = storage.save_session(session);
 
@@ -3058,7 +4105,7 @@ fn handle_tool_call(
             let tag = args["tag"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { command: Some(tag.to_string()), ..Default::default() };
-            let decision = gate::process("spf_agent_memory_by_tag", &gate_params, config, session);
+            let decision = run_gate("spf_agent_memory_by_tag", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_agent_memory_by_tag", decision.complexity.c,
                     "BLOCKED",
@@ -3066,6 +4113,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("agent_memory_by_tag", "search", Some(tag));
             let _ = storage.save_session(session);
 
@@ -3092,7 +4142,7 @@ fn handle_tool_call(
         "spf_agent_session_info" => {
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_agent_session_info", &gate_params, config, session);
+            let decision = run_gate("spf_agent_session_info", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_agent_session_info", decision.complexity.c,
                     "BLOCKED",
@@ -3100,6 +4150,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("agent_session_info", "get", None);
             let _ = storage.save_session(session);
 
@@ -3129,7 +4182,7 @@ fn handle_tool_call(
         "spf_agent_context" => {
 
             let gate_params = ToolParams { ..Default::default() };
-            let decision = gate::process("spf_agent_context", &gate_params, config, session);
+            let decision = run_gate("spf_agent_context", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_agent_context", decision.complexity.c,
                     "BLOCKED",
@@ -3137,6 +4190,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("agent_context", "get", None);
             let _ = storage.save_session(session);
 
@@ -3156,7 +4212,7 @@ fn handle_tool_call(
             let path = args["path"].as_str().unwrap_or("/");
 
             let gate_params = ToolParams { file_path: Some(path.to_string()), ..Default::default() };
-            let decision = gate::process("spf_fs_exists", &gate_params, config, session);
+            let decision = run_gate("spf_fs_exists", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_fs_exists", decision.complexity.c,
                     "BLOCKED",
@@ -3164,6 +4220,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("fs_exists", "check", Some(path));
             let _ = storage.save_session(session);
 
@@ -3177,7 +4236,7 @@ fn handle_tool_call(
             let path = args["path"].as_str().unwrap_or("/");
 
             let gate_params = ToolParams { file_path: Some(path.to_string()), ..Default::default() };
-            let decision = gate::process("spf_fs_stat", &gate_params, config, session);
+            let decision = run_gate("spf_fs_stat", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_fs_stat", decision.complexity.c,
                     "BLOCKED",
@@ -3185,6 +4244,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("fs_stat", "get", Some(path));
             let _ = storage.save_session(session);
 
@@ -3198,7 +4260,7 @@ fn handle_tool_call(
             let path = args["path"].as_str().unwrap_or("/");
 
             let gate_params = ToolParams { file_path: Some(path.to_string()), ..Default::default() };
-            let decision = gate::process("spf_fs_ls", &gate_params, config, session);
+            let decision = run_gate("spf_fs_ls", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_fs_ls", decision.complexity.c,
                     "BLOCKED",
@@ -3206,6 +4268,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("fs_ls", "list", Some(path));
             let _ = storage.save_session(session);
 
@@ -3219,7 +4284,7 @@ fn handle_tool_call(
             let path = args["path"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { file_path: Some(path.to_string()), ..Default::default() };
-            let decision = gate::process("spf_fs_read", &gate_params, config, session);
+            let decision = run_gate("spf_fs_read", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_fs_read", decision.complexity.c,
                     "BLOCKED",
@@ -3227,6 +4292,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("fs_read", "read", Some(path));
             let _ = storage.save_session(session);
 
@@ -3241,7 +4309,7 @@ fn handle_tool_call(
             let content = args["content"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { file_path: Some(path.to_string()), content: Some(content.to_string()), ..Default::default() };
-            let decision = gate::process("spf_fs_write", &gate_params, config, session);
+            let decision = run_gate("spf_fs_write", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_fs_write", decision.complexity.c,
                     "BLOCKED",
@@ -3249,6 +4317,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("fs_write", "write", Some(path));
             let _ = storage.save_session(session);
 
@@ -3262,7 +4333,7 @@ fn handle_tool_call(
             let path = args["path"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { file_path: Some(path.to_string()), ..Default::default() };
-            let decision = gate::process("spf_fs_mkdir", &gate_params, config, session);
+            let decision = run_gate("spf_fs_mkdir", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_fs_mkdir", decision.complexity.c,
                     "BLOCKED",
@@ -3270,6 +4341,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("fs_mkdir", "create", Some(path));
             let _ = storage.save_session(session);
 
@@ -3283,7 +4357,7 @@ fn handle_tool_call(
             let path = args["path"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { file_path: Some(path.to_string()), ..Default::default() };
-            let decision = gate::process("spf_fs_rm", &gate_params, config, session);
+            let decision = run_gate("spf_fs_rm", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_fs_rm", decision.complexity.c,
                     "BLOCKED",
@@ -3291,6 +4365,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("fs_rm", "remove", Some(path));
             let _ = storage.save_session(session);
 
@@ -3305,7 +4382,7 @@ fn handle_tool_call(
             let new_path = args["new_path"].as_str().unwrap_or("");
 
             let gate_params = ToolParams { file_path: Some(old_path.to_string()), ..Default::default() };
-            let decision = gate::process("spf_fs_rename", &gate_params, config, session);
+            let decision = run_gate("spf_fs_rename", &gate_params, config, session, storage);
             if !decision.allowed {
                 session.record_manifest("spf_fs_rename", decision.complexity.c,
                     "BLOCKED",
@@ -3313,6 +4390,9 @@ fn handle_tool_call(
                 let _ = storage.save_session(session);
                 return json!({"type": "text", "text": decision.message});
             }
+            if let Some(held) = post_gate(name, args, &decision, session, storage) {
+                return held;
+            }
             session.record_action("fs_rename", "rename", Some(old_path));
             let _ = storage.save_session(session);
 
@@ -3355,10 +4435,54 @@ fn handle_tool_call(
     }
 }
 
+/// Swap in a freshly read config if CONFIG.DB's generation moved.
+/// An unreadable or invalid config is logged and the current one stays in service;
+/// that generation is not retried until CONFIG.DB changes again.
+fn reload_config(db: &SpfConfigDb, config: &mut SpfConfig, generation: &mut u64) {
+    let current = match db.generation() {
+        Ok(g) => g,
+        Err(e) => {
+            log(&format!("Config reload: generation read failed: {}", e));
+            return;
+        }
+    };
+    if current == *generation {
+        return;
+    }
+
+    let msg = match db.read_full_config() {
+        Ok(fresh) => {
+            let problems = fresh.validate();
+            if problems.is_empty() {
+                let msg = format!(
+                    "CONFIG reloaded: generation {} → {} (mode {:?})",
+                    generation, current, fresh.enforce_mode
+                );
+                *config = fresh;
+                msg
+            } else {
+                format!(
+                    "CONFIG generation {} rejected, keeping generation {}: {}",
+                    current, generation, problems.join("; ")
+                )
+            }
+        }
+        Err(e) => format!(
+            "CONFIG generation {} unreadable, keeping generation {}: {}",
+            current, generation, e
+        ),
+    };
+    log(&msg);
+    cmd_log(&msg);
+    *generation = current;
+}
+
 /// Main MCP server loop — runs forever on stdio
 pub fn run(config: SpfConfig, config_db: SpfConfigDb, mut session: Session, storage: SpfStorage) {
     log(&format!("Starting {} v{}", SERVER_NAME, SERVER_VERSION));
     log(&format!("Mode: {:?}", config.enforce_mode));
+    let mut config = config;
+    let mut generation = config_db.generation().unwrap_or(0);
 
     // LIVE/ base — all LMDBs live here, outside Claude's writable zone
     let live_base = spf_root().join("LIVE");
@@ -3391,6 +4515,10 @@ pub fn run(config: SpfConfig, config_db: SpfConfigDb, mut session: Session, stor
 
     let tmp_db = match SpfTmpDb::open(&tmp_db_path) {
         Ok(db) => {
+            // New serve = new session — per-project write counters start at zero
+            if let Err(e) = db.reset_session_counters() {
+                log(&format!("Warning: TMP_DB reset_session_counters failed: {}", e));
+            }
             log(&format!("TMP_DB LMDB initialized at {:?}", tmp_db_path));
             Some(db)
         }
@@ -3433,6 +4561,23 @@ pub fn run(config: SpfConfig, config_db: SpfConfigDb, mut session: Session, stor
         }
     };
 
+    // Background bash jobs — output and state under LIVE/TMP/JOBS
+    let mut jobs = match JobManager::open(&live_base.join("TMP/JOBS")) {
+        Ok(jobs) => Some(jobs),
+        Err(e) => {
+            log(&format!("Warning: background jobs unavailable: {}", e));
+            None
+        }
+    };
+
+    // Over-budget tool output spills to LIVE/TMP/OUTPUT
+    if let Err(e) = output::init(&live_base.join("TMP/OUTPUT")) {
+        log(&format!("Warning: output spill unavailable, long output will be cut: {}", e));
+    }
+
+    // Named spf_bash shell sessions (cwd + exports), for the life of the server
+    let mut shells: BTreeMap<String, ShellState> = BTreeMap::new();
+
     let stdin = io::stdin();
     for line in stdin.lock().lines() {
         let line = match line {
@@ -3462,6 +4607,11 @@ pub fn run(config: SpfConfig, config_db: SpfConfigDb, mut session: Session, stor
 
         log(&format!("Received: {}", method));
 
+        // Pick up CONFIG.DB changes (config-import, refresh-paths, rollback) between requests
+        if let Some(ref db) = config_db {
+            reload_config(db, &mut config, &mut generation);
+        }
+
         match method {
             "initialize" => {
                 send_response(id, json!({
@@ -3488,17 +4638,23 @@ pub fn run(config: SpfConfig, config_db: SpfConfigDb, mut session: Session, stor
 
                 cmd_log(&format!("CALL {} | {}", name, param_summary(name, &args)));
 
-                let result = handle_tool_call(name, &args, &config, &mut session, &storage, &config_db, &projects_db, &tmp_db, &fs_db, &agent_db);
+                let result = handle_tool_call(name, &args, &config, &mut session, &storage, &config_db, &projects_db, &tmp_db, &fs_db, &agent_db, &mut jobs, &mut shells);
+
+                // Several content blocks (spf_read images) come back as an array
+                let content = match result {
+                    Value::Array(blocks) => blocks,
+                    block => vec![block],
+                };
 
                 // Log failures
-                let text = result.get("text").and_then(|v| v.as_str()).unwrap_or("");
+                let text = content.first().and_then(|b| b.get("text")).and_then(|v| v.as_str()).unwrap_or("");
                 if text.starts_with("ERROR") || text.starts_with("BLOCKED") {
                     let snippet: String = text.chars().take(200).collect();
                     cmd_log(&format!("FAIL {} | {}", name, snippet));
                 }
 
                 send_response(id, json!({
-                    "content": [result]
+                    "content": content
                 }));
             }
 
@@ -3513,4 +4669,9 @@ pub fn run(config: SpfConfig, config_db: SpfConfigDb, mut session: Session, stor
             }
         }
     }
+
+    // Session end — no background job outlives the server
+    if let Some(ref mut jobs) = jobs {
+        jobs.shutdown();
+    }
 }
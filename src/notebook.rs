@@ -0,0 +1,397 @@
+// SPF Smart Gateway - Notebook Editing
+// Copyright 2026 Joseph Stone - All Rights Reserved
+//
+// Cell edits for spf_notebook_edit. Cells are addressed by nbformat 4.5 `id`
+// or by index; source is stored as Jupyter stores it (lines keeping their
+// newline); editing a code cell clears its outputs and execution_count.
+// The edited notebook is checked against the nbformat v4 schema before it is
+// written and serialized the way nbformat writes it (1-space indent).
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+/// What spf_notebook_edit does to the addressed cell
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    /// New source; cell_type None keeps the cell's type
+    Replace { source: String, cell_type: Option<String> },
+    /// At the index, or after the cell with the id
+    Insert { source: String, cell_type: String },
+    Delete,
+}
+
+impl Edit {
+    pub fn mode(&self) -> &'static str {
+        match self {
+            Edit::Replace { .. } => "replace",
+            Edit::Insert { .. } => "insert",
+            Edit::Delete => "delete",
+        }
+    }
+}
+
+/// Which cell an edit addresses. Neither set means cell 0.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Target {
+    pub id: Option<String>,
+    pub index: Option<usize>,
+}
+
+/// One applied edit, enough to describe it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub mode: &'static str,
+    /// Index of the cell after the edit (before it, for delete)
+    pub index: usize,
+    pub id: Option<String>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    /// Outputs dropped from a code cell
+    pub cleared_outputs: usize,
+}
+
+/// Jupyter's line split: every line keeps its `\n`, the last may lack one
+pub fn split_source(text: &str) -> Vec<String> {
+    text.split_inclusive('\n').map(str::to_string).collect()
+}
+
+/// A cell's source as one string (nbformat allows a string or line array)
+pub fn source_text(cell: &Value) -> String {
+    match &cell["source"] {
+        Value::String(s) => s.clone(),
+        Value::Array(lines) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+/// Whether cells carry ids (nbformat 4.5+)
+fn has_ids(notebook: &Value) -> bool {
+    notebook["nbformat"].as_u64() == Some(4) && notebook["nbformat_minor"].as_u64().unwrap_or(0) >= 5
+}
+
+/// Apply `edit` at `target`. Nothing is changed on error.
+pub fn edit(notebook: &mut Value, target: &Target, edit: Edit) -> Result<Change> {
+    let with_ids = has_ids(notebook);
+    let cells = notebook.get_mut("cells").and_then(Value::as_array_mut)
+        .ok_or_else(|| anyhow!("invalid notebook: no cells array"))?;
+
+    let (index, by_id) = locate(cells, target)?;
+    let mode = edit.mode();
+
+    match edit {
+        Edit::Insert { source, cell_type } => {
+            // After the addressed id, like Jupyter's "insert below"
+            let at = if by_id { index + 1 } else { index };
+            if at > cells.len() {
+                bail!("cannot insert at cell {}: notebook has {} cells", at, cells.len());
+            }
+            let id = with_ids.then(|| new_id(cells, at));
+            let cell = new_cell(&cell_type, &source, id.as_deref())?;
+            cells.insert(at, cell.clone());
+            Ok(Change { mode, index: at, id, before: None, after: Some(cell), cleared_outputs: 0 })
+        }
+        Edit::Replace { source, cell_type } => {
+            let len = cells.len();
+            let cell = cells.get_mut(index).ok_or_else(|| out_of_range(index, len))?;
+            let before = cell.clone();
+            let cell_type = cell_type.unwrap_or_else(|| before["cell_type"].as_str().unwrap_or("code").to_string());
+            let cleared_outputs = before["outputs"].as_array().map_or(0, Vec::len);
+            let mut replaced = new_cell(&cell_type, &source, before["id"].as_str())?;
+            replaced["metadata"] = before.get("metadata").cloned().unwrap_or_else(|| json!({}));
+            if cell_type != "code" {
+                if let Some(attachments) = before.get("attachments") {
+                    replaced["attachments"] = attachments.clone();
+                }
+            }
+            *cell = replaced.clone();
+            let id = before["id"].as_str().map(str::to_string);
+            Ok(Change { mode, index, id, before: Some(before), after: Some(replaced), cleared_outputs })
+        }
+        Edit::Delete => {
+            if index >= cells.len() {
+                return Err(out_of_range(index, cells.len()));
+            }
+            let before = cells.remove(index);
+            let id = before["id"].as_str().map(str::to_string);
+            Ok(Change { mode, index, id, before: Some(before), after: None, cleared_outputs: 0 })
+        }
+    }
+}
+
+/// Index `target` addresses and whether it was found by id
+fn locate(cells: &[Value], target: &Target) -> Result<(usize, bool)> {
+    let by_id = match &target.id {
+        Some(id) => Some(cells.iter().position(|c| c["id"].as_str() == Some(id.as_str()))
+            .ok_or_else(|| anyhow!("no cell with id {:?}", id))?),
+        None => None,
+    };
+    if let (Some(found), Some(index)) = (by_id, target.index) {
+        if found != index {
+            bail!("cell id {:?} is cell {}, not cell {}", target.id.as_deref().unwrap_or(""), found, index);
+        }
+    }
+    Ok((by_id.or(target.index).unwrap_or(0), by_id.is_some()))
+}
+
+/// The existing cell `target` addresses, if any
+pub fn cell<'a>(notebook: &'a Value, target: &Target) -> Option<&'a Value> {
+    let cells = notebook["cells"].as_array()?;
+    cells.get(locate(cells, target).ok()?.0)
+}
+
+fn out_of_range(index: usize, len: usize) -> anyhow::Error {
+    anyhow!("cell {} out of range: notebook has {} cells", index, len)
+}
+
+fn new_cell(cell_type: &str, source: &str, id: Option<&str>) -> Result<Value> {
+    let mut cell = match cell_type {
+        "code" => json!({"cell_type": "code", "execution_count": null, "metadata": {}, "outputs": [], "source": split_source(source)}),
+        "markdown" | "raw" => json!({"cell_type": cell_type, "metadata": {}, "source": split_source(source)}),
+        other => bail!("cell_type must be code, markdown or raw, not {:?}", other),
+    };
+    if let Some(id) = id {
+        cell["id"] = json!(id);
+    }
+    Ok(cell)
+}
+
+/// 8 hex characters, like Jupyter, unique within the notebook
+fn new_id(cells: &[Value], at: usize) -> String {
+    let nanos = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+    (0u32..)
+        .map(|attempt| {
+            let digest = Sha256::digest(format!("{}:{}:{}:{}", nanos, std::process::id(), at, attempt));
+            hex::encode(&digest[..4])
+        })
+        .find(|id| !cells.iter().any(|c| c["id"].as_str() == Some(id.as_str())))
+        .unwrap_or_default()
+}
+
+/// Problems against the nbformat v4 schema, empty when valid
+pub fn validate(notebook: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    let Some(top) = notebook.as_object() else {
+        return vec!["notebook: not an object".to_string()];
+    };
+    unexpected(top, &["cells", "metadata", "nbformat", "nbformat_minor"], "notebook", &mut errors);
+    if notebook["nbformat"].as_u64() != Some(4) {
+        errors.push("nbformat: must be 4".to_string());
+    }
+    if notebook["nbformat_minor"].as_u64().is_none() {
+        errors.push("nbformat_minor: must be a non-negative integer".to_string());
+    }
+    if !notebook["metadata"].is_object() {
+        errors.push("metadata: must be an object".to_string());
+    }
+    let Some(cells) = notebook["cells"].as_array() else {
+        errors.push("cells: must be an array".to_string());
+        return errors;
+    };
+
+    let with_ids = has_ids(notebook);
+    let mut seen = std::collections::HashSet::new();
+    for (n, cell) in cells.iter().enumerate() {
+        let at = format!("cells[{}]", n);
+        let Some(fields) = cell.as_object() else {
+            errors.push(format!("{}: not an object", at));
+            continue;
+        };
+        match (with_ids, cell.get("id")) {
+            (true, Some(Value::String(id))) => {
+                let well_formed = (1..=64).contains(&id.len())
+                    && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+                if !well_formed {
+                    errors.push(format!("{}.id: {:?} must be 1-64 of [a-zA-Z0-9-_]", at, id));
+                } else if !seen.insert(id.as_str()) {
+                    errors.push(format!("{}.id: {:?} is not unique", at, id));
+                }
+            }
+            (true, _) => errors.push(format!("{}.id: required from nbformat 4.5", at)),
+            (false, Some(_)) => errors.push(format!("{}.id: not allowed before nbformat 4.5", at)),
+            (false, None) => {}
+        }
+        if !cell["metadata"].is_object() {
+            errors.push(format!("{}.metadata: must be an object", at));
+        }
+        let source_ok = match &cell["source"] {
+            Value::String(_) => true,
+            Value::Array(lines) => lines.iter().all(Value::is_string),
+            _ => false,
+        };
+        if !source_ok {
+            errors.push(format!("{}.source: must be a string or array of strings", at));
+        }
+        match cell["cell_type"].as_str() {
+            Some("code") => {
+                unexpected(fields, &["cell_type", "execution_count", "id", "metadata", "outputs", "source"], &at, &mut errors);
+                if !(cell["execution_count"].is_null() || cell["execution_count"].is_u64()) {
+                    errors.push(format!("{}.execution_count: must be a non-negative integer or null", at));
+                }
+                if cell.get("execution_count").is_none() {
+                    errors.push(format!("{}.execution_count: required", at));
+                }
+                match cell["outputs"].as_array() {
+                    Some(outputs) => {
+                        for (k, output) in outputs.iter().enumerate() {
+                            validate_output(output, &format!("{}.outputs[{}]", at, k), &mut errors);
+                        }
+                    }
+                    None => errors.push(format!("{}.outputs: must be an array", at)),
+                }
+            }
+            Some("markdown") | Some("raw") => {
+                unexpected(fields, &["attachments", "cell_type", "id", "metadata", "source"], &at, &mut errors);
+            }
+            _ => errors.push(format!("{}.cell_type: must be code, markdown or raw", at)),
+        }
+    }
+    errors
+}
+
+fn validate_output(output: &Value, at: &str, errors: &mut Vec<String>) {
+    let required: &[&str] = match output["output_type"].as_str() {
+        Some("execute_result") => &["data", "execution_count", "metadata"],
+        Some("display_data") => &["data", "metadata"],
+        Some("stream") => &["name", "text"],
+        Some("error") => &["ename", "evalue", "traceback"],
+        _ => {
+            errors.push(format!("{}.output_type: must be execute_result, display_data, stream or error", at));
+            return;
+        }
+    };
+    for field in required {
+        if output.get(field).is_none() {
+            errors.push(format!("{}.{}: required", at, field));
+        }
+    }
+}
+
+fn unexpected(fields: &Map<String, Value>, allowed: &[&str], at: &str, errors: &mut Vec<String>) {
+    for key in fields.keys().filter(|k| !allowed.contains(&k.as_str())) {
+        errors.push(format!("{}.{}: not allowed", at, key));
+    }
+}
+
+/// The notebook as nbformat writes it: 1-space indent, trailing newline
+pub fn to_json(notebook: &Value) -> Result<String> {
+    let mut out = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b" ");
+    let mut serializer = serde_json::Serializer::with_formatter(&mut out, formatter);
+    serde::Serialize::serialize(notebook, &mut serializer)?;
+    out.push(b'\n');
+    Ok(String::from_utf8(out)?)
+}
+
+/// Cell-level diff: what happened to which cell, then its source diff
+pub fn diff(change: &Change) -> String {
+    let describe = |cell: &Option<Value>| cell.as_ref().and_then(|c| c["cell_type"].as_str()).unwrap_or("").to_string();
+    let (old_type, new_type) = (describe(&change.before), describe(&change.after));
+    let cell_type = match (old_type.as_str(), new_type.as_str()) {
+        (old, new) if old == new || new.is_empty() => old.to_string(),
+        ("", new) => new.to_string(),
+        (old, new) => format!("{} → {}", old, new),
+    };
+    let mut out = format!("cell {} [{}", change.index, cell_type);
+    if let Some(id) = &change.id {
+        out.push_str(&format!(", id {}", id));
+    }
+    out.push_str(&format!("] {}", match change.mode {
+        "replace" => "replaced",
+        "insert" => "inserted",
+        _ => "deleted",
+    }));
+    if change.mode == "replace" && new_type == "code" {
+        out.push_str(&format!(" — outputs cleared ({}), execution_count reset", change.cleared_outputs));
+    }
+
+    let text = |cell: &Option<Value>| cell.as_ref().map(source_text).unwrap_or_default();
+    let source = crate::patch::diff(&format!("cell-{}", change.index), &text(&change.before), &text(&change.after));
+    if !source.is_empty() {
+        out.push('\n');
+        out.push_str(source.trim_end());
+    }
+    out
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notebook() -> Value {
+        json!({
+            "cells": [
+                {"cell_type": "markdown", "id": "intro", "metadata": {}, "source": ["# Title\n", "text"]},
+                {"cell_type": "code", "execution_count": 3, "id": "load", "metadata": {"tags": ["x"]},
+                 "outputs": [{"output_type": "stream", "name": "stdout", "text": ["hi\n"]}], "source": "print('hi')"},
+            ],
+            "metadata": {},
+            "nbformat": 4,
+            "nbformat_minor": 5
+        })
+    }
+
+    #[test]
+    fn edits_by_id_or_index_and_resets_code_cells() {
+        assert_eq!(split_source("a\nb\n\nc"), vec!["a\n", "b\n", "\n", "c"]);
+        assert!(split_source("").is_empty());
+
+        let mut nb = notebook();
+        let target = Target { id: Some("load".into()), index: None };
+        assert_eq!(cell(&nb, &target).map(source_text).as_deref(), Some("print('hi')"));
+        assert!(cell(&nb, &Target { id: None, index: Some(2) }).is_none());
+        let change = edit(&mut nb, &target, Edit::Replace { source: "x = 1\nx".into(), cell_type: None }).unwrap();
+        assert_eq!(change.index, 1);
+        assert_eq!(nb["cells"][1]["source"], json!(["x = 1\n", "x"]));
+        assert_eq!((&nb["cells"][1]["outputs"], &nb["cells"][1]["execution_count"]), (&json!([]), &Value::Null));
+        assert_eq!(nb["cells"][1]["metadata"], json!({"tags": ["x"]}));
+        assert_eq!(
+            diff(&change),
+            "cell 1 [code, id load] replaced — outputs cleared (1), execution_count reset\n\
+             --- a/cell-1\n+++ b/cell-1\n@@ -1,1 +1,2 @@\n-print('hi')\n\\ No newline at end of file\n+x = 1\n+x\n\\ No newline at end of file"
+        );
+
+        let change = edit(&mut nb, &Target { id: Some("intro".into()), index: None },
+            Edit::Insert { source: "y".into(), cell_type: "raw".into() }).unwrap();
+        assert_eq!(change.index, 1);
+        let id = change.id.unwrap();
+        assert_eq!((id.len(), nb["cells"][1]["id"].as_str()), (8, Some(id.as_str())));
+        assert!(validate(&nb).is_empty(), "{:?}", validate(&nb));
+
+        let err = edit(&mut nb, &Target { id: None, index: Some(9) }, Edit::Delete).unwrap_err();
+        assert_eq!(err.to_string(), "cell 9 out of range: notebook has 3 cells");
+        assert!(edit(&mut nb, &Target { id: Some("load".into()), index: Some(0) }, Edit::Delete).is_err());
+        assert!(edit(&mut nb, &Target { id: Some("nope".into()), index: None }, Edit::Delete).is_err());
+        assert_eq!(nb["cells"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn validates_against_nbformat_schema() {
+        assert!(validate(&notebook()).is_empty());
+
+        let mut nb = notebook();
+        nb["cells"][0]["outputs"] = json!([]);
+        nb["cells"][1]["id"] = json!("intro");
+        nb["cells"][1]["outputs"][0] = json!({"output_type": "stream"});
+        assert_eq!(validate(&nb), vec![
+            "cells[0].outputs: not allowed",
+            "cells[1].id: \"intro\" is not unique",
+            "cells[1].outputs[0].name: required",
+            "cells[1].outputs[0].text: required",
+        ]);
+
+        let mut old = notebook();
+        old["nbformat_minor"] = json!(4);
+        assert_eq!(validate(&old).len(), 2);
+        let mut nb = notebook();
+        nb["cells"].as_array_mut().unwrap().iter_mut().for_each(|c| { c.as_object_mut().unwrap().remove("id"); });
+        nb["nbformat_minor"] = json!(4);
+        let change = edit(&mut nb, &Target::default(), Edit::Insert { source: "".into(), cell_type: "code".into() }).unwrap();
+        assert_eq!((change.id, validate(&nb).len()), (None, 0));
+        assert!(to_json(&nb).unwrap().starts_with("{\n \"cells\": [\n  {\n   \"cell_type\": \"code\""));
+    }
+}
@@ -0,0 +1,253 @@
+// SPF Smart Gateway - Output Limiting
+// Copyright 2026 Joseph Stone - All Rights Reserved
+//
+// One budget for what spf_bash, spf_grep, spf_glob and spf_read return
+// inline. The budget per call comes from CONFIG.DB output_limits, scaled by
+// the tier's a_optimal_tokens. Output over budget is cut at a line boundary
+// and the full text spills to LIVE/TMP/OUTPUT/<server pid>/<tool>-<n>.txt,
+// which the agent pages through with spf_read offset/limit. spf_read itself
+// streams the file and never spills — the file already is the full output.
+// Spill files are newest-MAX_SPILLS per server; directories of servers no
+// longer running are removed on the next start.
+
+use crate::config::OutputCap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+/// Spill files kept per server; older ones are deleted
+pub const MAX_SPILLS: usize = 32;
+
+/// This server's spill directory, set by `init`
+static SPILL_DIR: OnceLock<PathBuf> = OnceLock::new();
+static SPILL_SEQ: AtomicU64 = AtomicU64::new(1);
+
+/// Create this server's spill directory under `base` (LIVE/TMP/OUTPUT).
+/// Until called, overflowing output is cut without being saved.
+pub fn init(base: &Path) -> io::Result<()> {
+    let dir = crate::paths::server_dir(base)?;
+    let _ = SPILL_DIR.set(dir);
+    Ok(())
+}
+
+/// Longest prefix of `text` within `cap`: whole lines while both budgets
+/// allow, or a single over-long first line cut at a char boundary
+pub fn cut(text: &str, cap: OutputCap) -> &str {
+    let mut end = 0;
+    for (lines, line) in text.split_inclusive('\n').enumerate() {
+        if lines as u64 >= cap.max_lines {
+            break;
+        }
+        if (end + line.len()) as u64 > cap.max_bytes {
+            if lines == 0 {
+                end = floor_char_boundary(text, cap.max_bytes as usize);
+            }
+            break;
+        }
+        end += line.len();
+    }
+    &text[..end]
+}
+
+/// `text` limited to `cap`; past it the full text spills to a file and a
+/// trailer says what was omitted and where to find it
+pub fn limit(tool: &str, text: &str, cap: OutputCap) -> String {
+    limit_into(SPILL_DIR.get().map(PathBuf::as_path), tool, text, cap)
+}
+
+fn limit_into(dir: Option<&Path>, tool: &str, text: &str, cap: OutputCap) -> String {
+    let shown = cut(text, cap);
+    if shown.len() == text.len() {
+        return text.to_string();
+    }
+    let total_lines = text.lines().count();
+    let omitted = format!(
+        "{} of {} bytes, {} of {} lines omitted",
+        text.len() - shown.len(), text.len(), total_lines - shown.lines().count(), total_lines
+    );
+    let saved = match dir.map(|dir| spill(dir, tool, text)) {
+        Some(Ok(path)) => format!("full output: {} (page with spf_read offset/limit)", path.display()),
+        Some(Err(e)) => format!("full output not saved: {}", e),
+        None => "full output not saved".to_string(),
+    };
+    format!("{}\n[OUTPUT TRUNCATED: {} — {}]", shown.trim_end_matches('\n'), omitted, saved)
+}
+
+/// Save `text` as the next spill file in `dir`, keeping the newest MAX_SPILLS
+fn spill(dir: &Path, tool: &str, text: &str) -> io::Result<PathBuf> {
+    let seq = SPILL_SEQ.fetch_add(1, Ordering::Relaxed);
+    let path = dir.join(format!("{}-{}.txt", tool, seq));
+    fs::write(&path, text)?;
+
+    let mut spills: Vec<(u64, PathBuf)> = fs::read_dir(dir)?
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let seq = name.strip_suffix(".txt")?.rsplit('-').next()?.parse().ok()?;
+            Some((seq, entry.path()))
+        })
+        .collect();
+    if spills.len() > MAX_SPILLS {
+        spills.sort();
+        for (_, old) in &spills[..spills.len() - MAX_SPILLS] {
+            let _ = fs::remove_file(old);
+        }
+    }
+    Ok(path)
+}
+
+/// Lines of a file as spf_read shows them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    /// `{:>6}\t{line}` per line, newline-joined
+    pub text: String,
+    /// First and one-past-last line shown (0-based)
+    pub start: usize,
+    pub end: usize,
+    pub total: usize,
+    /// Stopped by the budget before the requested range ended
+    pub cut: bool,
+}
+
+/// Longest line spf_read shows; the rest is replaced by a marker
+pub const MAX_LINE_BYTES: usize = 2000;
+
+/// Stream `path` and number the lines from `offset` (up to `limit`, 0 = to
+/// the end) that fit in `cap`. Only the shown lines are held in memory.
+pub fn read_page(path: &Path, offset: usize, limit: usize, cap: OutputCap) -> io::Result<Page> {
+    page_lines(BufReader::new(File::open(path)?), offset, limit, cap)
+}
+
+/// `read_page` over any reader. Invalid UTF-8 is replaced, a leading BOM
+/// dropped and lines over MAX_LINE_BYTES cut with a marker.
+pub fn page_lines<R: BufRead>(mut reader: R, offset: usize, limit: usize, cap: OutputCap) -> io::Result<Page> {
+    let want_end = if limit > 0 { offset.saturating_add(limit) } else { usize::MAX };
+    let mut text = String::new();
+    let mut total = 0;
+    let mut end = offset;
+    let mut cut = false;
+    let mut buf = Vec::new();
+
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        let n = total;
+        total += 1;
+        if n < offset || n >= want_end || cut {
+            continue;
+        }
+        let mut bytes = buf.strip_suffix(b"\n").unwrap_or(&buf);
+        bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+        if n == 0 {
+            bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+        }
+        let line = String::from_utf8_lossy(bytes);
+        let numbered = format!("{:>6}\t{}\n", n + 1, shorten_line(&line));
+        if (n - offset) as u64 >= cap.max_lines || (text.len() + numbered.len()) as u64 > cap.max_bytes {
+            if n == offset {
+                text.push_str(&numbered[..floor_char_boundary(&numbered, cap.max_bytes as usize)]);
+                end = n + 1;
+            }
+            cut = true;
+            continue;
+        }
+        text.push_str(&numbered);
+        end = n + 1;
+    }
+
+    let start = offset.min(total);
+    text.truncate(text.trim_end_matches('\n').len());
+    Ok(Page { text, start, end: end.clamp(start, total), total, cut })
+}
+
+fn shorten_line(line: &str) -> std::borrow::Cow<'_, str> {
+    if line.len() <= MAX_LINE_BYTES {
+        return line.into();
+    }
+    let keep = floor_char_boundary(line, MAX_LINE_BYTES);
+    format!("{} … [line truncated: {} more bytes]", &line[..keep], line.len() - keep).into()
+}
+
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::OutputLimits;
+
+    #[test]
+    fn overflow_spills_full_output_and_says_what_was_cut() {
+        let dir = tempfile::tempdir().unwrap();
+        let cap = OutputCap { max_bytes: 20, max_lines: 3 };
+        let text = "one\ntwo\nthree\nfour\nfive\n";
+
+        assert_eq!(cut(text, cap), "one\ntwo\nthree\n");
+        assert_eq!(cut("héllo wörld", OutputCap { max_bytes: 2, max_lines: 3 }), "h");
+        assert_eq!(limit_into(Some(dir.path()), "spf_grep", "short", cap), "short");
+
+        let limited = limit_into(Some(dir.path()), "spf_grep", text, cap);
+        assert!(limited.starts_with("one\ntwo\nthree\n[OUTPUT TRUNCATED: 10 of 24 bytes, 2 of 5 lines omitted"));
+        let path = limited.split("full output: ").nth(1).unwrap().split(' ').next().unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), text);
+
+        for _ in 0..MAX_SPILLS + 3 {
+            limit_into(Some(dir.path()), "spf_bash", text, cap);
+        }
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), MAX_SPILLS);
+        assert!(limit_into(None, "spf_bash", text, cap).ends_with("full output not saved]"));
+    }
+
+    #[test]
+    fn read_page_streams_within_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("f.txt");
+        fs::write(&file, (1..=10).map(|n| format!("line {}\n", n)).collect::<String>()).unwrap();
+        let roomy = OutputCap { max_bytes: 1 << 20, max_lines: 100 };
+
+        let page = read_page(&file, 2, 3, roomy).unwrap();
+        assert_eq!((page.start, page.end, page.total, page.cut), (2, 5, 10, false));
+        assert_eq!(page.text, "     3\tline 3\n     4\tline 4\n     5\tline 5");
+
+        let page = read_page(&file, 0, 0, OutputCap { max_bytes: 1 << 20, max_lines: 4 }).unwrap();
+        assert_eq!((page.end, page.cut), (4, true));
+        let page = read_page(&file, 20, 0, roomy).unwrap();
+        assert_eq!((page.start, page.end, page.text.as_str()), (10, 10, ""));
+    }
+
+    #[test]
+    fn page_lines_decodes_lossily_and_cuts_long_lines() {
+        let roomy = OutputCap { max_bytes: 1 << 20, max_lines: 100 };
+        let page = page_lines(&b"\xef\xbb\xbfok\r\nbad \xff byte\n"[..], 0, 0, roomy).unwrap();
+        assert_eq!(page.text, "     1\tok\n     2\tbad \u{fffd} byte");
+
+        let long = format!("{}é{}", "x".repeat(MAX_LINE_BYTES - 1), "y".repeat(500));
+        let page = page_lines(long.as_bytes(), 0, 0, roomy).unwrap();
+        let expected = format!("     1\t{} … [line truncated: 502 more bytes]", "x".repeat(MAX_LINE_BYTES - 1));
+        assert_eq!((page.text, page.total), (expected, 1));
+    }
+
+    #[test]
+    fn budget_scales_with_a_optimal_tokens() {
+        let limits = OutputLimits::default();
+        let full = limits.budget("spf_grep", 1_000_000);
+        assert_eq!(full, OutputCap { max_bytes: 64 * 1024, max_lines: 500 });
+        let half = limits.budget("spf_grep", 8 * 1024);
+        assert_eq!(half, OutputCap { max_bytes: 32 * 1024, max_lines: 250 });
+        assert_eq!(limits.budget("spf_grep", 0).max_bytes, limits.min_bytes);
+        assert_eq!(limits.budget("spf_unlisted", 1_000_000), limits.default);
+    }
+}
@@ -0,0 +1,587 @@
+// SPF Smart Gateway - Unified Diffs
+// Copyright 2026 Joseph Stone - All Rights Reserved
+//
+// Parsing and strict application of unified diffs for spf_apply_patch, and
+// rendering of the diff an edit produced for spf_edit / spf_apply_patch
+// replies. A hunk applies only where its context and removed lines match the
+// current content exactly (line endings included); it may sit at an offset
+// from its header, never before the previous hunk. No fuzz. A patch either
+// applies to every file or to none — callers apply in memory first.
+//
+// Also the exact-string replacement behind spf_edit: every occurrence of
+// old_string is located by line first, and an edit that would pick one of
+// several occurrences without being told which is refused.
+
+use anyhow::{anyhow, bail, Result};
+
+/// Context lines around each change in rendered diffs
+pub const CONTEXT: usize = 3;
+
+/// Above this many line pairs the changed middle is shown as one block
+/// instead of a minimal diff
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// One line of a hunk, with its line ending unless it had none
+#[derive(Debug, Clone, PartialEq)]
+pub enum Line {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hunk {
+    /// 1-based line of the first old line; for an empty old side, the line
+    /// after which the new lines go (0 = start of file)
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub lines: Vec<Line>,
+}
+
+impl Hunk {
+    fn header(&self) -> String {
+        format!("@@ -{},{} +{},{} @@", self.old_start, self.old_len, self.new_start, self.new_len)
+    }
+
+    /// Lines the hunk expects in the current content
+    fn old_lines(&self) -> Vec<&str> {
+        self.lines.iter().filter_map(|l| match l {
+            Line::Context(s) | Line::Remove(s) => Some(s.as_str()),
+            Line::Add(_) => None,
+        }).collect()
+    }
+}
+
+/// The hunks for one file
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilePatch {
+    /// None for `/dev/null` — the patch creates the file
+    pub old_path: Option<String>,
+    /// None for `/dev/null` — the patch deletes the file
+    pub new_path: Option<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+impl FilePatch {
+    /// The path the patch writes (or deletes)
+    pub fn target(&self) -> &str {
+        self.new_path.as_deref().or(self.old_path.as_deref()).unwrap_or("")
+    }
+
+    pub fn creates(&self) -> bool {
+        self.old_path.is_none()
+    }
+
+    pub fn deletes(&self) -> bool {
+        self.new_path.is_none()
+    }
+
+    /// Every added line, for content inspection
+    pub fn added(&self) -> String {
+        self.hunks.iter()
+            .flat_map(|h| &h.lines)
+            .filter_map(|l| match l {
+                Line::Add(s) => Some(s.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Every removed line
+    pub fn removed(&self) -> String {
+        self.hunks.iter()
+            .flat_map(|h| &h.lines)
+            .filter_map(|l| match l {
+                Line::Remove(s) => Some(s.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// This file's part of the patch as unified diff text
+    pub fn render(&self) -> String {
+        let old = self.old_path.as_deref().map_or("/dev/null".to_string(), |p| side(p, "a/"));
+        let new = self.new_path.as_deref().map_or("/dev/null".to_string(), |p| side(p, "b/"));
+        let mut out = format!("--- {}\n+++ {}\n", old, new);
+        for hunk in &self.hunks {
+            out.push_str(&hunk.header());
+            out.push('\n');
+            for line in &hunk.lines {
+                let (mark, s) = match line {
+                    Line::Context(s) => (' ', s),
+                    Line::Remove(s) => ('-', s),
+                    Line::Add(s) => ('+', s),
+                };
+                out.push(mark);
+                out.push_str(s);
+                if !s.ends_with('\n') {
+                    out.push_str("\n\\ No newline at end of file\n");
+                }
+            }
+        }
+        out
+    }
+
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats { files: 1, hunks: self.hunks.len() as u64, ..Default::default() };
+        for line in self.hunks.iter().flat_map(|h| &h.lines) {
+            match line {
+                Line::Add(_) => stats.added += 1,
+                Line::Remove(_) => stats.removed += 1,
+                Line::Context(_) => {}
+            }
+        }
+        stats
+    }
+}
+
+/// Size of a patch, as complexity sees it
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    pub files: u64,
+    pub hunks: u64,
+    pub added: u64,
+    pub removed: u64,
+}
+
+/// Stats of a patch text; unparseable text counts as empty
+pub fn stats(text: &str) -> Stats {
+    parse(text).unwrap_or_default().iter().fold(Stats::default(), |total, file| {
+        let s = file.stats();
+        Stats {
+            files: total.files + s.files,
+            hunks: total.hunks + s.hunks,
+            added: total.added + s.added,
+            removed: total.removed + s.removed,
+        }
+    })
+}
+
+/// Parse a unified diff (`diff -u` or `git diff` output) touching one or
+/// more files. Anything outside file headers and hunks is ignored.
+pub fn parse(text: &str) -> Result<Vec<FilePatch>> {
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut lines = text.split_inclusive('\n').peekable();
+
+    while let Some(line) = lines.next() {
+        let Some(old) = line.strip_prefix("--- ") else {
+            continue;
+        };
+        let new = lines.next()
+            .and_then(|l| l.strip_prefix("+++ "))
+            .ok_or_else(|| anyhow!("'--- {}' is not followed by a '+++' line", header_path(old)))?;
+        let mut file = FilePatch {
+            old_path: patch_path(old, "a/"),
+            new_path: patch_path(new, "b/"),
+            hunks: Vec::new(),
+        };
+        if file.old_path.is_none() && file.new_path.is_none() {
+            bail!("file header names /dev/null on both sides");
+        }
+
+        while let Some(header) = lines.next_if(|l| l.starts_with("@@ ")) {
+            let mut hunk = parse_header(header)
+                .ok_or_else(|| anyhow!("bad hunk header in {}: {}", file.target(), header.trim_end()))?;
+            let (mut old_seen, mut new_seen) = (0, 0);
+            while old_seen < hunk.old_len || new_seen < hunk.new_len {
+                let line = lines.next().ok_or_else(|| anyhow!(
+                    "{} {} ends early (patch truncated?)", file.target(), hunk.header()
+                ))?;
+                // A bare empty line is an empty context line some tools emit
+                let (kind, body) = match line {
+                    "\n" | "\r\n" => (' ', line),
+                    _ => (line.chars().next().unwrap_or(' '), &line[1..]),
+                };
+                match kind {
+                    ' ' => { hunk.lines.push(Line::Context(body.to_string())); old_seen += 1; new_seen += 1; }
+                    '-' => { hunk.lines.push(Line::Remove(body.to_string())); old_seen += 1; }
+                    '+' => { hunk.lines.push(Line::Add(body.to_string())); new_seen += 1; }
+                    '\\' => no_newline(&mut hunk),
+                    _ => bail!("{} {}: unexpected line: {}", file.target(), hunk.header(), line.trim_end()),
+                }
+            }
+            if lines.next_if(|l| l.starts_with('\\')).is_some() {
+                no_newline(&mut hunk);
+            }
+            if old_seen != hunk.old_len || new_seen != hunk.new_len {
+                bail!("{} {}: line counts do not match the header", file.target(), hunk.header());
+            }
+            file.hunks.push(hunk);
+        }
+        if file.hunks.is_empty() {
+            bail!("{} has no hunks", file.target());
+        }
+        files.push(file);
+    }
+    if files.is_empty() {
+        bail!("no file headers ('--- a/path' / '+++ b/path') found");
+    }
+    Ok(files)
+}
+
+/// `\ No newline at end of file` — the previous line has no line ending
+fn no_newline(hunk: &mut Hunk) {
+    if let Some(Line::Context(s) | Line::Remove(s) | Line::Add(s)) = hunk.lines.last_mut() {
+        s.truncate(s.strip_suffix('\n').unwrap_or(s).len());
+    }
+}
+
+fn header_path(rest: &str) -> &str {
+    // Drop a tab-separated timestamp (diff -u)
+    rest.trim_end_matches(['\n', '\r']).split('\t').next().unwrap_or("")
+}
+
+fn patch_path(rest: &str, prefix: &str) -> Option<String> {
+    let path = header_path(rest);
+    if path == "/dev/null" {
+        return None;
+    }
+    Some(path.strip_prefix(prefix).unwrap_or(path).to_string())
+}
+
+fn parse_header(line: &str) -> Option<Hunk> {
+    let ranges = line.strip_prefix("@@ -")?.split(" @@").next()?;
+    let (old, new) = ranges.split_once(" +")?;
+    let range = |r: &str| -> Option<(usize, usize)> {
+        match r.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((r.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_len) = range(old)?;
+    let (new_start, new_len) = range(new)?;
+    Some(Hunk { old_start, old_len, new_start, new_len, lines: Vec::new() })
+}
+
+/// Apply one file's hunks to its current content
+pub fn apply(content: &str, file: &FilePatch) -> Result<String> {
+    let current: Vec<&str> = content.split_inclusive('\n').collect();
+    let mut out = String::with_capacity(content.len());
+    let mut cursor = 0;
+
+    for (n, hunk) in file.hunks.iter().enumerate() {
+        let expected = hunk.old_lines();
+        let wanted = if hunk.old_len == 0 { hunk.old_start } else { hunk.old_start.saturating_sub(1) };
+        let at = find(&current, &expected, wanted, cursor).ok_or_else(|| anyhow!(
+            "hunk {} ({}) does not match the current content of {}{}",
+            n + 1, hunk.header(), file.target(), mismatch_hint(&current, &expected, wanted.max(cursor))
+        ))?;
+        current[cursor..at].iter().for_each(|l| out.push_str(l));
+        for line in &hunk.lines {
+            if let Line::Context(s) | Line::Add(s) = line {
+                out.push_str(s);
+            }
+        }
+        cursor = at + expected.len();
+    }
+    current[cursor..].iter().for_each(|l| out.push_str(l));
+    Ok(out)
+}
+
+/// Position of `expected` in `lines` closest to `wanted`, not before `from`
+fn find(lines: &[&str], expected: &[&str], wanted: usize, from: usize) -> Option<usize> {
+    let last = lines.len().checked_sub(expected.len())?;
+    let matches = |at: usize| at >= from && at <= last && lines[at..at + expected.len()] == *expected;
+    let wanted = wanted.clamp(from, last.max(from));
+    (0..=lines.len()).find_map(|d| {
+        [wanted.checked_sub(d), wanted.checked_add(d).filter(|_| d > 0)]
+            .into_iter()
+            .flatten()
+            .find(|&at| matches(at))
+    })
+}
+
+/// First line where the hunk disagrees with the file at its stated position
+fn mismatch_hint(lines: &[&str], expected: &[&str], at: usize) -> String {
+    for (i, want) in expected.iter().enumerate() {
+        let have = lines.get(at + i).copied();
+        if have != Some(*want) {
+            return format!(
+                "; at line {} expected {:?}, found {}",
+                at + i + 1, want.trim_end_matches('\n'),
+                have.map_or("end of file".to_string(), |h| format!("{:?}", h.trim_end_matches('\n')))
+            );
+        }
+    }
+    String::new()
+}
+
+/// Which occurrences of old_string an spf_edit may replace
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EditScope {
+    pub replace_all: bool,
+    /// The number of occurrences the caller expects (in range); all are replaced
+    pub expected: Option<usize>,
+    /// Only occurrences starting on these 1-based lines, inclusive
+    pub lines: Option<(usize, usize)>,
+}
+
+/// 1-based line on which each occurrence of `needle` starts
+pub fn occurrence_lines(content: &str, needle: &str) -> Vec<usize> {
+    if needle.is_empty() {
+        return Vec::new();
+    }
+    let mut line = 1;
+    let mut scanned = 0;
+    content.match_indices(needle).map(|(at, _)| {
+        line += content[scanned..at].matches('\n').count();
+        scanned = at;
+        line
+    }).collect()
+}
+
+/// Replace `old` with `new` in `content` within `scope`. Refuses, naming the
+/// line of every occurrence, when the occurrences in scope are not exactly
+/// one (or `expected`), unless `replace_all` is set.
+pub fn replace(content: &str, old: &str, new: &str, scope: EditScope) -> std::result::Result<String, String> {
+    if old.is_empty() {
+        return Err("old_string is empty".to_string());
+    }
+    let lines = occurrence_lines(content, old);
+    let in_scope: Vec<bool> = lines.iter()
+        .map(|&l| scope.lines.is_none_or(|(start, end)| (start..=end).contains(&l)))
+        .collect();
+    let chosen: Vec<usize> = lines.iter().zip(&in_scope).filter(|(_, &hit)| hit).map(|(&l, _)| l).collect();
+    let list = |lines: &[usize]| lines.iter().map(|l| l.to_string()).collect::<Vec<_>>().join(", ");
+    let range = scope.lines.map_or(String::new(), |(start, end)| format!(" in lines {}-{}", start, end));
+
+    if chosen.is_empty() {
+        return Err(match lines.len() {
+            0 => "old_string not found".to_string(),
+            _ => format!("old_string not found{} (occurs on lines {})", range, list(&lines)),
+        });
+    }
+    match scope.expected {
+        Some(expected) if expected != chosen.len() => {
+            return Err(format!(
+                "expected {} occurrences of old_string{}, found {} (lines {})",
+                expected, range, chosen.len(), list(&chosen)
+            ));
+        }
+        None if !scope.replace_all && chosen.len() > 1 => {
+            return Err(format!(
+                "old_string occurs {} times{} (lines {}) — add surrounding lines to make it unique, \
+                 narrow it with start_line/end_line, or set expected_occurrences/replace_all",
+                chosen.len(), range, list(&chosen)
+            ));
+        }
+        _ => {}
+    }
+
+    let mut out = String::with_capacity(content.len());
+    let mut last = 0;
+    for ((at, _), hit) in content.match_indices(old).zip(in_scope) {
+        if hit {
+            out.push_str(&content[last..at]);
+            out.push_str(new);
+            last = at + old.len();
+        }
+    }
+    out.push_str(&content[last..]);
+    Ok(out)
+}
+
+/// Unified diff between two versions of `path`, empty when they are equal
+pub fn diff(path: &str, old: &str, new: &str) -> String {
+    let a: Vec<&str> = old.split_inclusive('\n').collect();
+    let b: Vec<&str> = new.split_inclusive('\n').collect();
+    let ops = line_ops(&a, &b);
+    if ops.iter().all(|op| matches!(op, Op::Same(..))) {
+        return String::new();
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", side(path, "a/"), side(path, "b/"));
+    let mut i = 0;
+    while i < ops.len() {
+        // Next change, then extend while changes are within 2×CONTEXT of each other
+        let Some(first) = (i..ops.len()).find(|&k| !matches!(ops[k], Op::Same(..))) else { break };
+        let start = first.saturating_sub(CONTEXT);
+        let mut end = first;
+        let mut k = first;
+        while k < ops.len() {
+            if !matches!(ops[k], Op::Same(..)) {
+                end = k + 1;
+            } else if k - end >= 2 * CONTEXT {
+                break;
+            }
+            k += 1;
+        }
+        let end = (end + CONTEXT).min(ops.len());
+
+        let old_from = ops[..start].iter().filter(|op| !matches!(op, Op::Add(_))).count();
+        let new_from = ops[..start].iter().filter(|op| !matches!(op, Op::Remove(_))).count();
+        let old_len = ops[start..end].iter().filter(|op| !matches!(op, Op::Add(_))).count();
+        let new_len = ops[start..end].iter().filter(|op| !matches!(op, Op::Remove(_))).count();
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            if old_len == 0 { old_from } else { old_from + 1 }, old_len,
+            if new_len == 0 { new_from } else { new_from + 1 }, new_len
+        ));
+        for op in &ops[start..end] {
+            let (mark, line) = match op {
+                Op::Same(i, _) => (' ', a[*i]),
+                Op::Remove(i) => ('-', a[*i]),
+                Op::Add(j) => ('+', b[*j]),
+            };
+            out.push(mark);
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+        i = end;
+    }
+    out
+}
+
+/// Header path: absolute paths as they are, relative ones git-style
+fn side(path: &str, prefix: &str) -> String {
+    if path.starts_with('/') { path.to_string() } else { format!("{}{}", prefix, path) }
+}
+
+/// Line-level edit script step. Same carries both indices.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Same(usize, usize),
+    Remove(usize),
+    Add(usize),
+}
+
+/// Edit script from `a` to `b`: common prefix and suffix are kept as is,
+/// the middle is diffed by longest common subsequence when small enough
+fn line_ops(a: &[&str], b: &[&str]) -> Vec<Op> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut ops: Vec<Op> = (0..prefix).map(|i| Op::Same(i, i)).collect();
+    if a_mid.len().saturating_mul(b_mid.len()) > MAX_DIFF_CELLS {
+        ops.extend((0..a_mid.len()).map(|i| Op::Remove(prefix + i)));
+        ops.extend((0..b_mid.len()).map(|j| Op::Add(prefix + j)));
+    } else {
+        // lcs[i][j] = LCS length of a_mid[i..] and b_mid[j..]
+        let (n, m) = (a_mid.len(), b_mid.len());
+        let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i][j] = if a_mid[i] == b_mid[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && a_mid[i] == b_mid[j] {
+                ops.push(Op::Same(prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+                ops.push(Op::Add(prefix + j));
+                j += 1;
+            } else {
+                ops.push(Op::Remove(prefix + i));
+                i += 1;
+            }
+        }
+    }
+    let (a_end, b_end) = (a.len() - suffix, b.len() - suffix);
+    ops.extend((0..suffix).map(|k| Op::Same(a_end + k, b_end + k)));
+
+    // Removals first within each change block, as diff tools print them
+    let mut k = 0;
+    while k < ops.len() {
+        let end = (k..ops.len()).find(|&e| matches!(ops[e], Op::Same(..))).unwrap_or(ops.len());
+        ops[k..end].sort_by_key(|op| matches!(op, Op::Add(_)));
+        k = end + 1;
+    }
+    ops
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_multi_file_patch_strictly() {
+        let patch = "\
+diff --git a/src/a.txt b/src/a.txt
+--- a/src/a.txt
++++ b/src/a.txt
+@@ -1,3 +1,3 @@
+ one
+-two
++TWO
+ three
+@@ -6,2 +6,3 @@
+ six
+ seven
++eight
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1,2 @@
++hello
++world
+\\ No newline at end of file
+";
+        let files = parse(patch).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].target(), "src/a.txt");
+        assert!(files[1].creates() && !files[1].deletes());
+        assert_eq!(stats(patch), Stats { files: 2, hunks: 3, added: 4, removed: 1 });
+        assert_eq!(files[1].added(), "hello\nworld");
+        assert_eq!(parse(&files[1].render()).unwrap(), [files[1].clone()]);
+
+        // Second hunk sits one line lower than its header says — still applies
+        let a = "one\ntwo\nthree\nfour\nfive\nextra\nsix\nseven\n";
+        assert_eq!(apply(a, &files[0]).unwrap(), "one\nTWO\nthree\nfour\nfive\nextra\nsix\nseven\neight\n");
+        assert_eq!(apply("", &files[1]).unwrap(), "hello\nworld");
+
+        let err = apply("one\nzwei\nthree\n", &files[0]).unwrap_err().to_string();
+        assert!(err.contains("hunk 1 (@@ -1,3 +1,3 @@)") && err.contains("expected \"two\", found \"zwei\""), "{}", err);
+        assert!(parse("--- a/x\n+++ b/x\n@@ -1,2 +1,2 @@\n-a\n").is_err());
+        assert!(parse("just text\n").is_err());
+    }
+
+    #[test]
+    fn diff_round_trips_through_apply() {
+        let old = (1..=20).map(|n| format!("line {}\n", n)).collect::<String>();
+        let new = old.replace("line 3\n", "line three\n").replace("line 17\n", "").replace("line 20\n", "line 20");
+        let rendered = diff("/w/f.txt", &old, &new);
+        assert!(rendered.starts_with("--- /w/f.txt\n+++ /w/f.txt\n@@ -1,6 +1,6 @@\n line 1\n line 2\n-line 3\n+line three\n"));
+        assert_eq!(rendered.matches("@@ -").count(), 2);
+        assert!(rendered.ends_with("+line 20\n\\ No newline at end of file\n"));
+
+        let files = parse(&rendered).unwrap();
+        assert_eq!(files[0].target(), "/w/f.txt");
+        assert_eq!(apply(&old, &files[0]).unwrap(), new);
+        assert_eq!(diff("f", "same\n", "same\n"), "");
+        assert_eq!(apply("", &parse(&diff("f", "", "a\nb\n")).unwrap()[0]).unwrap(), "a\nb\n");
+    }
+
+    #[test]
+    fn edit_refuses_ambiguous_matches() {
+        let content = "fn a() {\n    run();\n}\nfn b() {\n    run();\n}\n";
+        assert_eq!(occurrence_lines(content, "run();"), [2, 5]);
+
+        let err = replace(content, "run();", "stop();", EditScope::default()).unwrap_err();
+        assert!(err.contains("occurs 2 times (lines 2, 5)"), "{}", err);
+
+        let second = EditScope { lines: Some((4, 6)), ..Default::default() };
+        assert_eq!(replace(content, "run();", "stop();", second).unwrap(),
+            "fn a() {\n    run();\n}\nfn b() {\n    stop();\n}\n");
+        let both = EditScope { expected: Some(2), ..Default::default() };
+        assert_eq!(replace(content, "run();", "go();", both).unwrap().matches("go();").count(), 2);
+        let three = EditScope { expected: Some(3), ..Default::default() };
+        assert!(replace(content, "run();", "go();", three).unwrap_err().contains("expected 3"));
+        let all = EditScope { replace_all: true, ..Default::default() };
+        assert_eq!(replace(content, "run();", "go();", all).unwrap().matches("go();").count(), 2);
+
+        let err = replace(content, "run();", "x", EditScope { lines: Some((3, 4)), ..Default::default() }).unwrap_err();
+        assert_eq!(err, "old_string not found in lines 3-4 (occurs on lines 2, 5)");
+        assert!(replace(content, "", "x", all).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+// SPF Smart Gateway - Path Matching
+// Copyright 2026 Joseph Stone - All Rights Reserved
+//
+// One matcher for every path rule: CONFIG.DB allowed/blocked paths,
+// the validate.rs write allowlist and TMP_DB project protected paths.
+//
+// Pattern syntax:
+//   /home/user/        component-aware prefix — matches /home/user and below,
+//                      never /home/user2 (trailing '/' optional)
+//   **/.env  *.pem     glob — '*' and '?' stay within one component, '**'
+//                      spans components; globs without a leading '/' match at
+//                      any depth; a match also covers everything beneath it
+//   re:^/srv/.*\.key$  regex over the whole path (always anchored)
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// How allowed and blocked rules combine when both match a path
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PathPrecedence {
+    /// Any matching blocked rule wins
+    #[default]
+    DenyOverrides,
+    /// The matching rule with the most literal characters wins; ties deny
+    MostSpecific,
+}
+
+/// Outcome of checking a path against allowed and blocked rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathVerdict {
+    Allowed,
+    Blocked,
+    /// No rule matched
+    Unlisted,
+}
+
+/// A parsed path rule
+#[derive(Debug, Clone)]
+pub enum PathPattern {
+    Prefix(String),
+    Glob { source: String, regex: Regex },
+    Regex { source: String, regex: Regex },
+}
+
+impl PathPattern {
+    /// Parse a rule. Errors name the offending pattern.
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        if let Some(re) = pattern.strip_prefix("re:") {
+            let regex = Regex::new(&format!("^(?:{})$", re))
+                .map_err(|e| format!("invalid regex '{}': {}", re, e))?;
+            return Ok(Self::Regex { source: pattern.to_string(), regex });
+        }
+        if pattern.contains(['*', '?']) {
+            let regex = Regex::new(&glob_to_regex(pattern))
+                .map_err(|e| format!("invalid glob '{}': {}", pattern, e))?;
+            return Ok(Self::Glob { source: pattern.to_string(), regex });
+        }
+        if pattern.is_empty() {
+            return Err("empty path pattern".to_string());
+        }
+        let trimmed = pattern.trim_end_matches('/');
+        Ok(Self::Prefix(if trimmed.is_empty() { "/".to_string() } else { trimmed.to_string() }))
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        match self {
+            Self::Prefix(prefix) => {
+                prefix == "/"
+                    || path == prefix
+                    || path.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.starts_with('/'))
+            }
+            Self::Glob { regex, .. } | Self::Regex { regex, .. } => regex.is_match(path),
+        }
+    }
+
+    /// Literal characters in the pattern — used by MostSpecific precedence
+    pub fn specificity(&self) -> usize {
+        match self {
+            Self::Prefix(prefix) => prefix.chars().count(),
+            Self::Glob { source, .. } => source.chars().filter(|c| !matches!(c, '*' | '?')).count(),
+            Self::Regex { source, .. } => source["re:".len()..]
+                .chars()
+                .filter(|c| c.is_alphanumeric() || matches!(c, '/' | '_' | '-'))
+                .count(),
+        }
+    }
+}
+
+/// Translate a glob into an anchored regex
+fn glob_to_regex(glob: &str) -> String {
+    let mut re = String::from("^");
+    if !glob.starts_with('/') && !glob.starts_with("**") {
+        re.push_str("(?:.*/)?");
+    }
+    let chars: Vec<char> = glob.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                if chars.get(i + 2) == Some(&'/') {
+                    re.push_str("(?:.*/)?");
+                    i += 3;
+                } else {
+                    re.push_str(".*");
+                    i += 2;
+                }
+                continue;
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    // A matched directory covers everything beneath it
+    re.push_str("(?:/.*)?$");
+    re
+}
+
+/// Does any pattern match? Unparseable patterns never match.
+pub fn any_match(patterns: &[String], path: &str) -> bool {
+    patterns.iter().any(|p| PathPattern::parse(p).is_ok_and(|pat| pat.matches(path)))
+}
+
+/// Most specific matching pattern, if any
+fn best_match(patterns: &[String], path: &str) -> Option<usize> {
+    patterns.iter()
+        .filter_map(|p| PathPattern::parse(p).ok())
+        .filter(|pat| pat.matches(path))
+        .map(|pat| pat.specificity())
+        .max()
+}
+
+/// Combine allowed and blocked rules for one path
+pub fn verdict(
+    path: &str,
+    allowed: &[String],
+    blocked: &[String],
+    precedence: PathPrecedence,
+) -> PathVerdict {
+    match precedence {
+        PathPrecedence::DenyOverrides => {
+            if any_match(blocked, path) {
+                PathVerdict::Blocked
+            } else if any_match(allowed, path) {
+                PathVerdict::Allowed
+            } else {
+                PathVerdict::Unlisted
+            }
+        }
+        PathPrecedence::MostSpecific => match (best_match(allowed, path), best_match(blocked, path)) {
+            (None, None) => PathVerdict::Unlisted,
+            (Some(a), Some(b)) if a > b => PathVerdict::Allowed,
+            (Some(_), None) => PathVerdict::Allowed,
+            _ => PathVerdict::Blocked,
+        },
+    }
+}
+
+/// Problems with a list of patterns (for config validation)
+pub fn check(patterns: &[String]) -> Vec<String> {
+    patterns.iter().filter_map(|p| PathPattern::parse(p).err()).collect()
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        PathPattern::parse(pattern).unwrap().matches(path)
+    }
+
+    #[test]
+    fn prefix_is_component_aware() {
+        assert!(matches("/home/user", "/home/user"));
+        assert!(matches("/home/user/", "/home/user/notes.md"));
+        assert!(!matches("/home/user", "/home/user2/notes.md"));
+        assert!(!matches("/tmp", "/tmpfile"));
+        assert!(matches("/", "/anything"));
+        assert!(matches(".git", ".git/config"));
+        assert!(!matches(".git", ".gitignore"));
+    }
+
+    #[test]
+    fn globs_and_regexes() {
+        assert!(matches("**/.env", "/home/u/app/.env"));
+        assert!(matches("**/.env", ".env"));
+        assert!(!matches("**/.env", "/home/u/app/.envrc"));
+        assert!(matches("*.pem", "/etc/ssl/server.pem"));
+        assert!(matches("/srv/*/secrets", "/srv/app/secrets/db.key"));
+        assert!(!matches("/srv/*/secrets", "/srv/a/b/secrets"));
+        assert!(matches("re:/srv/.*\\.key", "/srv/a/b.key"));
+        assert!(!matches("re:/srv/.*\\.key", "/x/srv/a/b.key"));
+        assert!(PathPattern::parse("re:(").is_err());
+    }
+
+    #[test]
+    fn precedence_modes() {
+        let allowed = vec!["/home/u/".to_string(), "/home/u/.ssh/known_hosts".to_string()];
+        let blocked = vec!["/home/u/.ssh/".to_string(), "**/*.pem".to_string()];
+
+        let deny = PathPrecedence::DenyOverrides;
+        assert_eq!(verdict("/home/u/a.txt", &allowed, &blocked, deny), PathVerdict::Allowed);
+        assert_eq!(verdict("/home/u/.ssh/known_hosts", &allowed, &blocked, deny), PathVerdict::Blocked);
+        assert_eq!(verdict("/srv/x", &allowed, &blocked, deny), PathVerdict::Unlisted);
+
+        let specific = PathPrecedence::MostSpecific;
+        assert_eq!(verdict("/home/u/.ssh/known_hosts", &allowed, &blocked, specific), PathVerdict::Allowed);
+        assert_eq!(verdict("/home/u/.ssh/id_rsa", &allowed, &blocked, specific), PathVerdict::Blocked);
+        // "/home/u" has more literal characters than "**/*.pem"
+        assert_eq!(verdict("/home/u/key.pem", &allowed, &blocked, specific), PathVerdict::Allowed);
+        assert_eq!(verdict("/home/u/key.pem", &allowed, &blocked, deny), PathVerdict::Blocked);
+    }
+}
@@ -87,3 +87,22 @@ pub fn system_pkg_path() -> String {
         "/usr".to_string()
     }
 }
+
+/// This server's scratch directory `<base>/<pid>`, created empty.
+/// Directories left under `base` by servers no longer running are removed
+/// (where /proc can tell which are alive).
+pub fn server_dir(base: &Path) -> std::io::Result<PathBuf> {
+    let dir = base.join(std::process::id().to_string());
+    if Path::new("/proc/self").exists() {
+        for entry in std::fs::read_dir(base).into_iter().flatten().flatten() {
+            let name = entry.file_name();
+            let alive = Path::new("/proc").join(&name).exists();
+            if !alive && name.to_string_lossy().parse::<u32>().is_ok() {
+                let _ = std::fs::remove_dir_all(entry.path());
+            }
+        }
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
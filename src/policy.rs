@@ -0,0 +1,540 @@
+// SPF Smart Gateway - Declarative Policy
+// Copyright 2026 Joseph Stone - All Rights Reserved
+//
+// Tool allowlist, validator routing, inspection routing, rate limits and
+// ordered rules — stored in CONFIG.DB under "spf:policy", editable without
+// recompiling. Rules are evaluated top to bottom; the first match wins.
+//
+// COMPILED CORE (not overridable by policy):
+//   - spf_fs_* tools are hard-blocked for MCP callers
+//   - mutating tools always run their write validator (write allowlist)
+
+use crate::pathmatch::PathPattern;
+use serde::{Deserialize, Serialize};
+
+/// Tools that are USER/SYSTEM-ONLY. Policy cannot allow them.
+pub const HARD_BLOCKED_TOOLS: &[&str] = &[
+    "spf_fs_import", "spf_fs_export",
+    "spf_fs_exists", "spf_fs_stat", "spf_fs_ls", "spf_fs_read",
+    "spf_fs_write", "spf_fs_mkdir", "spf_fs_rm", "spf_fs_rename",
+];
+
+/// Version of the built-in policy. Bump when adding tools to TOOLS_SINCE.
+pub const POLICY_VERSION: u32 = 5;
+
+/// Tools added to the built-in allowlist per policy version.
+/// Stored policies older than a version receive these entries on upgrade.
+const TOOLS_SINCE: &[(u32, &[&str])] = &[
+    (2, &["spf_explain"]),
+    (3, &["spf_bash_output", "spf_bash_kill"]),
+    (4, &["spf_undo"]),
+    (5, &["spf_apply_patch", "spf_bash_start"]),
+];
+
+/// Rate limit applied to tools without a policy entry
+const DEFAULT_RATE_LIMIT: u32 = 120;
+
+/// Whether a tool is in the compiled hard-block list
+pub fn is_hard_blocked(tool: &str) -> bool {
+    HARD_BLOCKED_TOOLS.contains(&tool)
+}
+
+/// Validator the compiled core forces for mutating tools.
+/// Policy may route these tools to no other validator.
+pub fn core_validator(tool: &str) -> Option<Validator> {
+    match tool {
+        "Write" | "spf_write" | "spf_notebook_edit" => Some(Validator::Write),
+        "Edit" | "spf_edit" | "spf_apply_patch" => Some(Validator::Edit),
+        "Bash" | "spf_bash" | "spf_bash_start" => Some(Validator::Bash),
+        "spf_web_download" => Some(Validator::Download),
+        _ => None,
+    }
+}
+
+/// Which validate.rs check a tool runs
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Validator {
+    /// Allowed without path/write validation
+    None,
+    Read,
+    Write,
+    Edit,
+    Bash,
+    /// Write validation with unknown content length
+    Download,
+}
+
+/// Which parameter inspect.rs scans
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InspectField {
+    None,
+    Content,
+    NewString,
+}
+
+/// What a matching rule does
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    Deny,
+    Warn,
+    Escalate,
+    RequireApproval,
+}
+
+/// Allowlist entry for one tool (and its aliases)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ToolPolicy {
+    pub names: Vec<String>,
+    #[serde(default = "default_validator")]
+    pub validator: Validator,
+    #[serde(default = "default_inspect")]
+    pub inspect: InspectField,
+    #[serde(default = "default_rate_limit")]
+    pub rate_limit: u32,
+}
+
+fn default_validator() -> Validator { Validator::None }
+fn default_inspect() -> InspectField { InspectField::None }
+fn default_rate_limit() -> u32 { DEFAULT_RATE_LIMIT }
+
+/// Ordered rule: tool X, path matching Y, tier >= Z → action
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyRule {
+    /// Tool name or pattern ("*" wildcard)
+    pub tool: String,
+    /// Path pattern with pathmatch semantics (component-aware prefix, glob
+    /// or `re:`). None matches any call.
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Minimum tier (SIMPLE, LIGHT, MEDIUM, CRITICAL). None matches any tier.
+    #[serde(default)]
+    pub min_tier: Option<String>,
+    pub action: RuleAction,
+    #[serde(default)]
+    pub reason: String,
+}
+
+/// Full policy document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    pub version: u32,
+    pub tools: Vec<ToolPolicy>,
+    #[serde(default)]
+    pub rules: Vec<PolicyRule>,
+}
+
+/// Tier rank for min_tier comparison
+pub fn tier_rank(tier: &str) -> Option<u8> {
+    match tier {
+        "SIMPLE" => Some(0),
+        "LIGHT" => Some(1),
+        "MEDIUM" => Some(2),
+        "CRITICAL" => Some(3),
+        _ => None,
+    }
+}
+
+/// Match `text` against a pattern where `*` matches any run of characters
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if pi < p.len() && p[pi] == t[ti] {
+            pi += 1;
+            ti += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+/// Rule paths use the shared pathmatch semantics; unparseable patterns never match
+fn path_match(pattern: &str, path: &str) -> bool {
+    PathPattern::parse(pattern).is_ok_and(|pat| pat.matches(path))
+}
+
+/// Does path pattern `outer` match everything path pattern `inner` matches?
+/// Conservative: a match covers everything beneath it, so `outer` covers a
+/// plain `inner` it matches, and a glob `inner` whose literal directory stem
+/// it matches. Regexes only cover themselves.
+fn path_covers(outer: &str, inner: &str) -> bool {
+    if outer == inner {
+        return true;
+    }
+    let Ok(pattern) = PathPattern::parse(outer) else { return false };
+    if matches!(pattern, PathPattern::Regex { .. }) || inner.starts_with("re:") {
+        return false;
+    }
+    match inner.find(['*', '?']) {
+        None => pattern.matches(inner.trim_end_matches('/')),
+        // Literal directories before the first wildcard, e.g. "/srv/app" of "/srv/app/*.key"
+        Some(wild) => match inner[..wild].rfind('/') {
+            Some(slash) if inner.starts_with('/') => pattern.matches(&inner[..slash.max(1)]),
+            _ => false,
+        },
+    }
+}
+
+/// Does tool pattern `outer` match everything tool pattern `inner` matches?
+/// Conservative — only reports coverage it can prove.
+fn pattern_covers(outer: &str, inner: &str) -> bool {
+    if outer == "*" || outer == inner {
+        return true;
+    }
+    if !inner.contains('*') {
+        return wildcard_match(outer, inner);
+    }
+    // Both patterns: outer "abc*" covers inner "abcd*..."
+    match outer.strip_suffix('*') {
+        Some(stem) if !stem.contains('*') => inner.starts_with(stem),
+        _ => false,
+    }
+}
+
+impl PolicyRule {
+    /// Whether this rule applies to a call
+    pub fn matches(&self, tool: &str, path: Option<&str>, tier: &str) -> bool {
+        if !wildcard_match(&self.tool, tool) {
+            return false;
+        }
+        if let Some(ref pattern) = self.path {
+            match path {
+                Some(p) if path_match(pattern, p) => {}
+                _ => return false,
+            }
+        }
+        if let Some(ref min) = self.min_tier {
+            match (tier_rank(min), tier_rank(tier)) {
+                (Some(min), Some(cur)) if cur >= min => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// Whether every call this rule matches is already matched by `earlier`
+    fn shadowed_by(&self, earlier: &PolicyRule) -> bool {
+        if !pattern_covers(&earlier.tool, &self.tool) {
+            return false;
+        }
+        let path_ok = match (&earlier.path, &self.path) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(outer), Some(inner)) => path_covers(outer, inner),
+        };
+        let tier_ok = match (&earlier.min_tier, &self.min_tier) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(outer), Some(inner)) => match (tier_rank(outer), tier_rank(inner)) {
+                (Some(o), Some(i)) => o <= i,
+                _ => false,
+            },
+        };
+        path_ok && tier_ok
+    }
+}
+
+impl Policy {
+    /// Allowlist entry for a tool, if any
+    pub fn tool(&self, name: &str) -> Option<&ToolPolicy> {
+        self.tools.iter().find(|t| t.names.iter().any(|n| n == name))
+    }
+
+    /// Per-minute rate limit for a tool
+    pub fn rate_limit(&self, name: &str) -> u32 {
+        self.tool(name).map(|t| t.rate_limit).unwrap_or(DEFAULT_RATE_LIMIT)
+    }
+
+    /// First rule matching the call, in document order, with its index
+    pub fn first_match(&self, tool: &str, path: Option<&str>, tier: &str) -> Option<(usize, &PolicyRule)> {
+        self.rules.iter().enumerate().find(|(_, r)| r.matches(tool, path, tier))
+    }
+
+    /// Add built-in tool entries introduced after this document's version.
+    /// Tools the user removed from an up-to-date policy are not re-added.
+    /// Returns true if the policy changed.
+    pub fn upgrade(&mut self) -> bool {
+        if self.version >= POLICY_VERSION {
+            return false;
+        }
+        let builtin = Policy::default();
+        for (since, names) in TOOLS_SINCE {
+            if *since <= self.version {
+                continue;
+            }
+            for name in *names {
+                if self.tool(name).is_none() {
+                    if let Some(entry) = builtin.tool(name) {
+                        self.tools.push(entry.clone());
+                    }
+                }
+            }
+        }
+        self.version = POLICY_VERSION;
+        true
+    }
+
+    /// Semantic check — returns a list of problems (empty = valid)
+    pub fn check(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut seen: Vec<&str> = Vec::new();
+
+        for (i, entry) in self.tools.iter().enumerate() {
+            if entry.names.is_empty() {
+                problems.push(format!("tools[{}]: empty names list", i));
+            }
+            for name in &entry.names {
+                if seen.contains(&name.as_str()) {
+                    problems.push(format!("tools[{}]: duplicate tool '{}'", i, name));
+                }
+                seen.push(name);
+                if is_hard_blocked(name) {
+                    problems.push(format!(
+                        "tools[{}]: '{}' is hard-blocked by the compiled core and cannot be allowed", i, name));
+                }
+                if let Some(core) = core_validator(name) {
+                    if entry.validator != core {
+                        problems.push(format!(
+                            "tools[{}]: '{}' validator {:?} is overridden by the compiled core ({:?})",
+                            i, name, entry.validator, core));
+                    }
+                }
+            }
+            if entry.rate_limit == 0 {
+                problems.push(format!("tools[{}]: rate_limit 0 blocks every call — remove the entry instead", i));
+            }
+        }
+
+        for (i, rule) in self.rules.iter().enumerate() {
+            if rule.tool.is_empty() {
+                problems.push(format!("rules[{}]: empty tool pattern", i));
+            }
+            if let Some(ref tier) = rule.min_tier {
+                if tier_rank(tier).is_none() {
+                    problems.push(format!(
+                        "rules[{}]: unknown min_tier '{}' (expected SIMPLE, LIGHT, MEDIUM or CRITICAL)", i, tier));
+                }
+            }
+            if let Some(Err(e)) = rule.path.as_deref().map(PathPattern::parse) {
+                problems.push(format!("rules[{}]: {}", i, e));
+            }
+            if let Some(j) = self.rules[..i].iter().position(|earlier| rule.shadowed_by(earlier)) {
+                problems.push(format!(
+                    "rules[{}]: shadowed by rules[{}] — it can never match", i, j));
+            }
+        }
+
+        problems
+    }
+
+    /// Parse a policy document (syntax only — see check() for semantics)
+    pub fn parse(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("syntax: {}", e))
+    }
+}
+
+impl Default for Policy {
+    /// Reproduces the compiled allowlist shipped with 2.0.0
+    fn default() -> Self {
+        let entry = |names: &[&str], validator: Validator, inspect: InspectField, rate_limit: u32| ToolPolicy {
+            names: names.iter().map(|s| s.to_string()).collect(),
+            validator,
+            inspect,
+            rate_limit,
+        };
+        let plain = |name: &str| entry(&[name], Validator::None, InspectField::None, DEFAULT_RATE_LIMIT);
+
+        let mut tools = vec![
+            entry(&["Edit", "spf_edit"], Validator::Edit, InspectField::NewString, 60),
+            entry(&["spf_apply_patch"], Validator::Edit, InspectField::NewString, 60),
+            entry(&["Write", "spf_write"], Validator::Write, InspectField::Content, 60),
+            entry(&["Bash", "spf_bash"], Validator::Bash, InspectField::None, 60),
+            entry(&["spf_bash_start"], Validator::Bash, InspectField::None, 60),
+            entry(&["Read", "spf_read"], Validator::Read, InspectField::None, DEFAULT_RATE_LIMIT),
+            entry(&["spf_web_download"], Validator::Download, InspectField::None, 60),
+            entry(&["spf_notebook_edit"], Validator::Write, InspectField::Content, 60),
+            entry(&["spf_undo"], Validator::None, InspectField::None, 60),
+            entry(&["spf_web_fetch"], Validator::None, InspectField::None, 30),
+            entry(&["spf_web_search"], Validator::None, InspectField::None, 30),
+            entry(&["spf_web_api"], Validator::None, InspectField::None, 30),
+        ];
+
+        tools.extend([
+            "spf_calculate", "spf_status", "spf_session", "spf_explain",
+            "spf_bash_output", "spf_bash_kill",
+            "spf_glob", "spf_grep",
+            "spf_brain_search", "spf_brain_store", "spf_brain_context",
+            "spf_brain_index", "spf_brain_list", "spf_brain_status",
+            "spf_brain_recall", "spf_brain_list_docs", "spf_brain_get_doc",
+            "spf_rag_collect_web", "spf_rag_collect_file", "spf_rag_collect_folder",
+            "spf_rag_collect_drop", "spf_rag_index_gathered", "spf_rag_dedupe",
+            "spf_rag_status", "spf_rag_list_gathered", "spf_rag_bandwidth_status",
+            "spf_rag_fetch_url", "spf_rag_collect_rss", "spf_rag_list_feeds",
+            "spf_rag_pending_searches", "spf_rag_fulfill_search",
+            "spf_rag_smart_search", "spf_rag_auto_fetch_gaps",
+            "spf_config_paths", "spf_config_stats",
+            "spf_projects_list", "spf_projects_get", "spf_projects_set",
+            "spf_projects_delete", "spf_projects_stats",
+            "spf_tmp_list", "spf_tmp_stats", "spf_tmp_get", "spf_tmp_active",
+            "spf_agent_stats", "spf_agent_memory_search", "spf_agent_memory_by_tag",
+            "spf_agent_session_info", "spf_agent_context",
+        ].into_iter().map(plain));
+
+        Self { version: POLICY_VERSION, tools, rules: Vec::new() }
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(tool: &str, path: Option<&str>, min_tier: Option<&str>, action: RuleAction) -> PolicyRule {
+        PolicyRule {
+            tool: tool.to_string(),
+            path: path.map(String::from),
+            min_tier: min_tier.map(String::from),
+            action,
+            reason: String::new(),
+        }
+    }
+
+    #[test]
+    fn default_policy_is_clean() {
+        let policy = Policy::default();
+        assert!(policy.check().is_empty(), "{:?}", policy.check());
+        assert!(policy.tool("spf_status").is_some());
+        assert!(policy.tool("spf_fs_write").is_none());
+        assert_eq!(policy.rate_limit("spf_web_fetch"), 30);
+        assert_eq!(policy.rate_limit("spf_write"), 60);
+        assert_eq!(policy.rate_limit("evil_new_tool"), 120);
+    }
+
+    #[test]
+    fn wildcard_matching() {
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("spf_web_*", "spf_web_fetch"));
+        assert!(!wildcard_match("spf_web_*", "spf_brain_search"));
+        assert!(wildcard_match("*.env", "/home/u/.env"));
+        assert!(wildcard_match("/a/*/c", "/a/b/c"));
+        assert!(!wildcard_match("/a/*/c", "/a/b/d"));
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let policy = Policy {
+            rules: vec![
+                rule("spf_write", Some("/home/u/secrets/"), None, RuleAction::Deny),
+                rule("spf_write", None, Some("MEDIUM"), RuleAction::RequireApproval),
+                rule("*", None, None, RuleAction::Warn),
+            ],
+            ..Default::default()
+        };
+        let (i, m) = policy.first_match("spf_write", Some("/home/u/secrets/k"), "SIMPLE").unwrap();
+        assert_eq!((i, m.action), (0, RuleAction::Deny));
+        let (i, m) = policy.first_match("spf_write", Some("/home/u/x"), "CRITICAL").unwrap();
+        assert_eq!((i, m.action), (1, RuleAction::RequireApproval));
+        let (i, m) = policy.first_match("spf_write", Some("/home/u/x"), "LIGHT").unwrap();
+        assert_eq!((i, m.action), (2, RuleAction::Warn));
+    }
+
+    #[test]
+    fn rule_paths_use_pathmatch() {
+        let home = rule("spf_write", Some("/home/a"), None, RuleAction::Deny);
+        assert!(home.matches("spf_write", Some("/home/a"), "SIMPLE"));
+        assert!(home.matches("spf_write", Some("/home/a/notes.md"), "SIMPLE"));
+        assert!(!home.matches("spf_write", Some("/home/ab/notes.md"), "SIMPLE"));
+
+        // '*' stays within one component; '**' spans them
+        let star = rule("spf_write", Some("/srv/*/.env"), None, RuleAction::Deny);
+        assert!(star.matches("spf_write", Some("/srv/app/.env"), "SIMPLE"));
+        assert!(!star.matches("spf_write", Some("/srv/app/nested/.env"), "SIMPLE"));
+        let deep = rule("spf_write", Some("/srv/**/.env"), None, RuleAction::Deny);
+        assert!(deep.matches("spf_write", Some("/srv/app/nested/.env"), "SIMPLE"));
+
+        // A sibling prefix is not shadowed; a nested path or glob is
+        assert!(!rule("spf_write", Some("/home/ab"), None, RuleAction::Deny).shadowed_by(&home));
+        assert!(rule("spf_write", Some("/home/a/b/"), None, RuleAction::Deny).shadowed_by(&home));
+        assert!(rule("spf_write", Some("/home/a/*.pem"), None, RuleAction::Deny).shadowed_by(&home));
+        assert!(!rule("spf_write", Some("/home/a*"), None, RuleAction::Deny).shadowed_by(&home));
+
+        let bad = Policy { rules: vec![rule("spf_write", Some("re:("), None, RuleAction::Deny)], ..Default::default() };
+        assert!(bad.check().iter().any(|p| p.starts_with("rules[0]: invalid regex")));
+    }
+
+    #[test]
+    fn check_reports_shadowed_rules() {
+        let policy = Policy {
+            rules: vec![
+                rule("spf_*", None, Some("LIGHT"), RuleAction::Warn),
+                rule("spf_write", Some("/home/"), Some("CRITICAL"), RuleAction::Deny),
+                rule("spf_write", None, None, RuleAction::Deny),
+            ],
+            ..Default::default()
+        };
+        let problems = policy.check();
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].starts_with("rules[1]: shadowed by rules[0]"));
+    }
+
+    #[test]
+    fn check_rejects_core_overrides() {
+        let mut policy = Policy::default();
+        policy.tools.push(ToolPolicy {
+            names: vec!["spf_fs_write".to_string()],
+            validator: Validator::None,
+            inspect: InspectField::None,
+            rate_limit: 10,
+        });
+        policy.tools[1].validator = Validator::None; // Write without write validator
+        let problems = policy.check();
+        assert!(problems.iter().any(|p| p.contains("hard-blocked")));
+        assert!(problems.iter().any(|p| p.contains("overridden by the compiled core")));
+    }
+
+    #[test]
+    fn upgrade_adds_new_builtin_tools_once() {
+        let mut policy = Policy { version: 1, ..Default::default() };
+        policy.tools.retain(|t| !t.names.iter().any(|n| n == "spf_explain" || n == "spf_glob"));
+
+        assert!(policy.upgrade());
+        assert_eq!(policy.version, POLICY_VERSION);
+        assert!(policy.tool("spf_explain").is_some());
+        // Removed before the upgrade, not introduced by it — stays removed
+        assert!(policy.tool("spf_glob").is_none());
+        assert!(!policy.upgrade());
+
+        let mut policy = Policy { version: 4, ..Default::default() };
+        policy.tools.retain(|t| !t.names.iter().any(|n| n == "spf_apply_patch" || n == "spf_bash_start"));
+        assert!(policy.upgrade());
+        assert_eq!(policy.tool("spf_bash_start").map(|t| t.validator), Some(Validator::Bash));
+    }
+
+    #[test]
+    fn parse_reports_syntax_errors() {
+        assert!(Policy::parse("{\"version\": 1, \"tools\": [").unwrap_err().starts_with("syntax:"));
+        let err = Policy::parse(r#"{"version":1,"tools":[],"rules":[{"tool":"x","action":"explode"}]}"#)
+            .unwrap_err();
+        assert!(err.contains("explode"));
+    }
+}
@@ -0,0 +1,261 @@
+// SPF Smart Gateway - Policy Regression Fixtures
+// Copyright 2026 Joseph Stone - All Rights Reserved
+//
+// Golden-file tests for gate behaviour. Each JSONL line names a tool, its
+// ToolParams and the expected outcome. `spf-smart-gate policy-test` replays
+// every line through gate::process against a chosen config export and prints
+// a diff of mismatches, so a team policy can live in version control with tests.
+
+use crate::calculate::ToolParams;
+use crate::config::SpfConfig;
+use crate::gate;
+use crate::session::Session;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Expected gate outcome — unset fields are not checked
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Expectation {
+    pub allowed: Option<bool>,
+    pub tier: Option<String>,
+    /// Substrings that must each appear in some gate error
+    #[serde(default)]
+    pub errors: Vec<String>,
+    /// Substrings that must each appear in some gate warning
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+/// One fixture line
+#[derive(Debug, Clone, Deserialize)]
+pub struct Fixture {
+    /// Optional label shown in the diff
+    #[serde(default)]
+    pub name: Option<String>,
+    pub tool: String,
+    #[serde(default)]
+    pub params: ToolParams,
+    /// Files already read this session (satisfies require_read_before_edit)
+    #[serde(default)]
+    pub files_read: Vec<String>,
+    pub expect: Expectation,
+}
+
+/// A fixture whose decision did not match its expectation
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    /// 1-based line number in the fixture file
+    pub line: usize,
+    pub label: String,
+    /// (field, expected, actual)
+    pub fields: Vec<(String, String, String)>,
+}
+
+/// Result of a fixture run
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub total: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+
+    /// Unified-diff style listing of mismatches plus a summary line
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for m in &self.mismatches {
+            out.push_str(&format!("line {}: {}\n", m.line, m.label));
+            for (field, expected, actual) in &m.fields {
+                out.push_str(&format!("  - {}: {}\n", field, expected));
+                out.push_str(&format!("  + {}: {}\n", field, actual));
+            }
+        }
+        out.push_str(&format!(
+            "{} fixtures, {} passed, {} failed",
+            self.total,
+            self.total - self.mismatches.len(),
+            self.mismatches.len()
+        ));
+        out
+    }
+}
+
+/// Parse fixture JSONL. Blank lines and lines starting with '#' are skipped.
+pub fn parse_fixtures(text: &str) -> Result<Vec<(usize, Fixture)>> {
+    let mut fixtures = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let fixture: Fixture = serde_json::from_str(trimmed)
+            .with_context(|| format!("fixture line {}: invalid JSON", i + 1))?;
+        fixtures.push((i + 1, fixture));
+    }
+    Ok(fixtures)
+}
+
+/// Build a config from a `config-export` document.
+/// Keys absent from the export keep their compiled defaults.
+pub fn config_from_export(export: &Value) -> Result<SpfConfig> {
+    let mut config = SpfConfig::default();
+
+    if let Some(v) = export.get("version").and_then(|v| v.as_str()) {
+        config.version = v.to_string();
+    }
+    if let Some(v) = export.get("enforce_mode") {
+        config.enforce_mode = serde_json::from_value(v.clone()).context("enforce_mode")?;
+    }
+    if let Some(v) = export.get("tiers") {
+        config.tiers = serde_json::from_value(v.clone()).context("tiers")?;
+    }
+    if let Some(v) = export.get("formula") {
+        config.formula = serde_json::from_value(v.clone()).context("formula")?;
+    }
+    if let Some(v) = export.get("weights") {
+        config.complexity_weights = serde_json::from_value(v.clone()).context("weights")?;
+    }
+    if let Some(v) = export.get("policy") {
+        config.policy = crate::policy::Policy::parse(&v.to_string())
+            .map_err(|e| anyhow::anyhow!("policy: {}", e))?;
+    }
+    if let Some(v) = export.get("allowed_paths") {
+        config.allowed_paths = serde_json::from_value(v.clone()).context("allowed_paths")?;
+    }
+    if let Some(v) = export.get("blocked_paths") {
+        config.blocked_paths = serde_json::from_value(v.clone()).context("blocked_paths")?;
+    }
+    if let Some(v) = export.get("path_precedence") {
+        config.path_precedence = serde_json::from_value(v.clone()).context("path_precedence")?;
+    }
+    if let Some(map) = export.get("dangerous_patterns").and_then(|v| v.as_object()) {
+        config.dangerous_commands = map.keys().cloned().collect();
+        config.dangerous_severity = map.iter()
+            .map(|(pattern, sev)| (pattern.clone(), sev.as_u64().unwrap_or(10).min(10) as u8))
+            .collect();
+    }
+    if let Some(v) = export.get("severity_thresholds") {
+        config.severity_thresholds = serde_json::from_value(v.clone()).context("severity_thresholds")?;
+    }
+    if let Some(v) = export.get("session_budget") {
+        config.session_budget = serde_json::from_value(v.clone()).context("session_budget")?;
+    }
+    if let Some(v) = export.get("exec_env") {
+        config.exec_env = serde_json::from_value(v.clone()).context("exec_env")?;
+    }
+    if let Some(v) = export.get("output_limits") {
+        config.output_limits = serde_json::from_value(v.clone()).context("output_limits")?;
+    }
+    if let Some(scalars) = export.get("config") {
+        if let Some(v) = scalars.get("require_read_before_edit").and_then(|v| v.as_str()) {
+            config.require_read_before_edit = v == "true";
+        }
+        if let Some(v) = scalars.get("max_write_size").and_then(|v| v.as_str()) {
+            config.max_write_size = v.parse().context("max_write_size")?;
+        }
+    }
+    Ok(config)
+}
+
+/// Replay fixtures through gate::process. Each fixture gets a fresh session.
+pub fn run(fixtures: &[(usize, Fixture)], config: &SpfConfig) -> Report {
+    let mut report = Report { total: fixtures.len(), mismatches: Vec::new() };
+
+    for (line, fixture) in fixtures {
+        let mut session = Session::new();
+        session.files_read = fixture.files_read.clone();
+        let decision = gate::process(&fixture.tool, &fixture.params, config, &session);
+
+        let expect = &fixture.expect;
+        let mut fields = Vec::new();
+        if let Some(allowed) = expect.allowed {
+            if decision.allowed != allowed {
+                fields.push(("allowed".to_string(), allowed.to_string(), decision.allowed.to_string()));
+            }
+        }
+        if let Some(ref tier) = expect.tier {
+            if &decision.complexity.tier != tier {
+                fields.push(("tier".to_string(), tier.clone(), decision.complexity.tier.clone()));
+            }
+        }
+        for (field, wanted, actual) in [
+            ("errors", &expect.errors, &decision.errors),
+            ("warnings", &expect.warnings, &decision.warnings),
+        ] {
+            for needle in wanted {
+                if !actual.iter().any(|e| e.contains(needle.as_str())) {
+                    fields.push((
+                        format!("{} contains", field),
+                        format!("{:?}", needle),
+                        format!("{:?}", actual),
+                    ));
+                }
+            }
+        }
+
+        if !fields.is_empty() {
+            let label = match fixture.name {
+                Some(ref name) => format!("{} ({})", name, fixture.tool),
+                None => fixture.tool.clone(),
+            };
+            report.mismatches.push(Mismatch { line: *line, label, fields });
+        }
+    }
+    report
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures_report_mismatches_by_line() {
+        let text = r#"
+# hard-blocked tools never pass
+{"tool": "spf_fs_write", "params": {"file_path": "/x"}, "expect": {"allowed": false}}
+{"name": "status is simple", "tool": "spf_status", "expect": {"allowed": true, "tier": "SIMPLE"}}
+{"name": "wrong on purpose", "tool": "spf_status", "expect": {"allowed": false, "tier": "CRITICAL", "errors": ["nope"]}}
+"#;
+        let fixtures = parse_fixtures(text).unwrap();
+        assert_eq!(fixtures.len(), 3);
+        assert_eq!(fixtures[0].0, 3);
+
+        let report = run(&fixtures, &SpfConfig::default());
+        assert!(!report.passed());
+        assert_eq!(report.mismatches.len(), 1);
+        let m = &report.mismatches[0];
+        assert_eq!(m.line, 5);
+        assert_eq!(m.fields.len(), 3);
+
+        let text = report.render();
+        assert!(text.contains("line 5: wrong on purpose (spf_status)"));
+        assert!(text.contains("  - allowed: false"));
+        assert!(text.contains("  + allowed: true"));
+        assert!(text.ends_with("3 fixtures, 2 passed, 1 failed"));
+    }
+
+    #[test]
+    fn export_overrides_defaults() {
+        let export = serde_json::json!({
+            "enforce_mode": "soft",
+            "config": {"require_read_before_edit": "false", "max_write_size": "10"},
+            "dangerous_patterns": {"shutdown": 9},
+        });
+        let config = config_from_export(&export).unwrap();
+        assert_eq!(config.enforce_mode, crate::config::EnforceMode::Soft);
+        assert!(!config.require_read_before_edit);
+        assert_eq!(config.max_write_size, 10);
+        assert_eq!(config.dangerous_commands, vec!["shutdown".to_string()]);
+        assert_eq!(config.command_severity("shutdown now"), 9);
+
+        assert!(config_from_export(&serde_json::json!({"policy": {"tools": 3}})).is_err());
+    }
+}
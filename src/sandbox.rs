@@ -0,0 +1,673 @@
+// SPF Smart Gateway - Bash Sandbox
+// Copyright 2026 Joseph Stone - All Rights Reserved
+//
+// Kernel confinement for the spf_bash child — the backstop behind the
+// validate.rs parser, so a write the parser missed still fails.
+//
+// Landlock (Linux 5.13+): reads limited to CONFIG.DB allowed paths plus the
+// runtime a shell needs, writes only to the validate.rs write allowlist
+// (LIVE/PROJECTS/PROJECTS, LIVE/TMP/TMP).
+// Without Landlock, a user + mount namespace remounts every mount read-only
+// except those roots (writes only — reads are not narrowed). A second nested
+// namespace locks the read-only flags so the child cannot remount them.
+// Where neither is available the command runs unconfined and says so.
+//
+// Resource limits (TMP_DB ExecLimits, per project trust level) are applied
+// natively: setrlimit in the child, an optional empty network namespace, and
+// a wall-clock deadline that kills the child's whole process group.
+
+use crate::config::SpfConfig;
+use crate::pathmatch::PathPattern;
+use crate::tmp_db::ExecLimits;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+/// Read-only roots every shell needs to start
+const RUNTIME_READ: &[&str] = &["/bin", "/sbin", "/usr", "/lib", "/lib32", "/lib64", "/etc", "/proc", "/dev", "/run"];
+
+/// Device files the child may write (2>/dev/null, terminals)
+const DEVICE_WRITE: &[&str] = &["/dev/null", "/dev/zero", "/dev/full", "/dev/tty", "/dev/pts"];
+
+/// Paths the child may read and write
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SandboxPolicy {
+    pub read: Vec<PathBuf>,
+    pub write: Vec<PathBuf>,
+}
+
+impl SandboxPolicy {
+    /// spf_bash policy: runtime + allowed-path prefixes readable,
+    /// the write allowlist writable. Globs and `re:` rules cannot become
+    /// kernel rules and stay validator-only, as do blocked paths.
+    pub fn for_bash(config: &SpfConfig) -> Self {
+        let mut read: Vec<PathBuf> = RUNTIME_READ.iter().map(PathBuf::from).collect();
+        for pattern in &config.allowed_paths {
+            if let Ok(PathPattern::Prefix(prefix)) = PathPattern::parse(pattern) {
+                if prefix.starts_with('/') {
+                    read.push(PathBuf::from(prefix));
+                }
+            }
+        }
+        let write = crate::validate::write_roots().into_iter().map(PathBuf::from).collect();
+        Self { read, write }
+    }
+}
+
+/// How a spawned command ended up confined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Confinement {
+    Landlock,
+    MountNamespace,
+    /// Not confined — why
+    Unavailable(String),
+}
+
+impl Confinement {
+    pub fn is_confined(&self) -> bool {
+        !matches!(self, Self::Unavailable(_))
+    }
+}
+
+impl std::fmt::Display for Confinement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Landlock => write!(f, "landlock"),
+            Self::MountNamespace => write!(f, "mount namespace (writes only)"),
+            Self::Unavailable(reason) => write!(f, "none ({})", reason),
+        }
+    }
+}
+
+/// Confine `command` (applied in the child between fork and exec).
+/// Returns what will be enforced; spawning fails if setup fails in the child.
+pub fn confine(command: &mut Command, policy: &SandboxPolicy) -> Confinement {
+    imp::confine(command, policy)
+}
+
+/// Apply resource limits in the child: its own process group (so a timeout
+/// kills the whole tree), an empty network namespace if `no_network`, then
+/// rlimits. Call before `confine` — namespace setup writes /proc, which
+/// Landlock would refuse. Errors if network isolation is required but
+/// unavailable; the command must not run in that case.
+pub fn limit(command: &mut Command, limits: &ExecLimits) -> io::Result<()> {
+    imp::limit(command, limits)
+}
+
+/// A command run to completion (or killed) by `run`
+#[derive(Debug)]
+pub struct Finished {
+    pub output: Output,
+    /// Killed at the wall-clock deadline
+    pub timed_out: bool,
+}
+
+/// Spawn with stdin closed and output captured; at `timeout` the process
+/// group is killed and whatever output arrived is kept.
+pub fn run(command: &mut Command, timeout: Duration) -> io::Result<Finished> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buf);
+            }
+            buf
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let stderr = drain(child.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+
+    let deadline = Instant::now() + timeout;
+    let mut timed_out = false;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            kill_group(child.id());
+            let _ = child.kill();
+            timed_out = true;
+            break child.wait()?;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    Ok(Finished {
+        output: Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        },
+        timed_out,
+    })
+}
+
+/// SIGKILL the process group led by `pid` (children started with `limit`)
+pub fn kill_group(pid: u32) {
+    imp::kill_group(pid)
+}
+
+/// Which limits a finished command ran into, for the tool result.
+/// Signals are definitive; the rest are recognised from stderr.
+pub fn limits_hit(finished: &Finished, limits: &ExecLimits, timeout: Duration) -> Vec<String> {
+    let mut hit = Vec::new();
+    if finished.timed_out {
+        hit.push(format!("wall-clock timeout ({}s)", timeout.as_secs()));
+    }
+    let stderr = String::from_utf8_lossy(&finished.output.stderr);
+    let signal = imp::limit_signal(&finished.output.status);
+    let seen = |needles: &[&str]| needles.iter().any(|n| stderr.contains(n));
+
+    if limits.cpu_secs > 0 && (signal == Some("cpu") || seen(&["CPU time limit exceeded"])) {
+        hit.push(format!("CPU time ({}s)", limits.cpu_secs));
+    }
+    if limits.max_file_size_mb > 0 && (signal == Some("fsize") || seen(&["File size limit exceeded", "File too large"])) {
+        hit.push(format!("file size ({}M)", limits.max_file_size_mb));
+    }
+    if limits.address_space_mb > 0 && seen(&["Cannot allocate memory", "MemoryError", "out of memory"]) {
+        hit.push(format!("address space ({}M)", limits.address_space_mb));
+    }
+    if limits.max_processes > 0 && seen(&["fork: Resource temporarily unavailable", "fork: retry"]) {
+        hit.push(format!("processes ({})", limits.max_processes));
+    }
+    if limits.max_open_files > 0 && seen(&["Too many open files"]) {
+        hit.push(format!("open files ({})", limits.max_open_files));
+    }
+    if limits.no_network && seen(&["Network is unreachable", "Could not resolve host", "Temporary failure in name resolution"]) {
+        hit.push("network disabled".to_string());
+    }
+    hit
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use super::*;
+
+    pub fn confine(_command: &mut Command, _policy: &SandboxPolicy) -> Confinement {
+        Confinement::Unavailable("kernel sandbox requires Linux".to_string())
+    }
+
+    /// rlimits are not applied here — only the wall-clock deadline
+    pub fn limit(_command: &mut Command, limits: &ExecLimits) -> io::Result<()> {
+        if limits.no_network {
+            return Err(io::Error::new(io::ErrorKind::Unsupported,
+                "network isolation requires Linux network namespaces"));
+        }
+        Ok(())
+    }
+
+    pub fn kill_group(_pid: u32) {}
+
+    pub fn limit_signal(_status: &std::process::ExitStatus) -> Option<&'static str> {
+        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::*;
+    use landlock::{
+        Access, AccessFs, PathBeneath, PathFd, Ruleset, RulesetAttr, RulesetCreated,
+        RulesetCreatedAttr, RulesetStatus, ABI,
+    };
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::process::CommandExt;
+    use std::sync::OnceLock;
+
+    pub fn confine(command: &mut Command, policy: &SandboxPolicy) -> Confinement {
+        let landlock_error = if landlock_supported() {
+            match build_ruleset(policy) {
+                Ok(ruleset) => {
+                    let mut ruleset = Some(ruleset);
+                    // SAFETY: restrict_self only issues prctl/landlock syscalls
+                    unsafe {
+                        command.pre_exec(move || {
+                            let Some(ruleset) = ruleset.take() else { return Ok(()) };
+                            match ruleset.restrict_self() {
+                                Ok(status) if status.ruleset != RulesetStatus::NotEnforced => Ok(()),
+                                _ => Err(io::Error::from_raw_os_error(libc::EPERM)),
+                            }
+                        });
+                    }
+                    return Confinement::Landlock;
+                }
+                Err(e) => format!("landlock ruleset: {}", e),
+            }
+        } else {
+            "landlock not supported by kernel".to_string()
+        };
+
+        if !namespaces_supported() {
+            return Confinement::Unavailable(format!("{}; user namespaces unavailable", landlock_error));
+        }
+        match NamespacePlan::new(policy) {
+            Ok(plan) => {
+                // SAFETY: enter() only issues syscalls on memory prepared above
+                unsafe {
+                    command.pre_exec(move || plan.enter());
+                }
+                Confinement::MountNamespace
+            }
+            Err(e) => Confinement::Unavailable(format!("{}; mount plan: {}", landlock_error, e)),
+        }
+    }
+
+    fn landlock_supported() -> bool {
+        // LANDLOCK_CREATE_RULESET_VERSION — returns the ABI version or -1
+        let abi = unsafe {
+            libc::syscall(libc::SYS_landlock_create_ruleset, std::ptr::null::<u8>(), 0usize, 1u32)
+        };
+        abi >= 1
+    }
+
+    fn build_ruleset(policy: &SandboxPolicy) -> Result<RulesetCreated, landlock::RulesetError> {
+        let abi = ABI::V5;
+        let mut ruleset = Ruleset::default().handle_access(AccessFs::from_all(abi))?.create()?;
+        // Missing paths are skipped — they cannot be opened, so they cannot be rules
+        for path in &policy.read {
+            if let Ok(fd) = PathFd::new(path) {
+                ruleset = ruleset.add_rule(PathBeneath::new(fd, AccessFs::from_read(abi)))?;
+            }
+        }
+        let writable = policy.write.iter().map(PathBuf::as_path)
+            .chain(DEVICE_WRITE.iter().map(std::path::Path::new));
+        for path in writable {
+            if let Ok(fd) = PathFd::new(path) {
+                ruleset = ruleset.add_rule(PathBeneath::new(fd, AccessFs::from_all(abi)))?;
+            }
+        }
+        Ok(ruleset)
+    }
+
+    /// Probe once by spawning `true` inside a bare namespace
+    fn namespaces_supported() -> bool {
+        static SUPPORTED: OnceLock<bool> = OnceLock::new();
+        *SUPPORTED.get_or_init(|| {
+            let Ok(plan) = NamespacePlan::new(&SandboxPolicy::default()) else { return false };
+            let plan = NamespacePlan { readonly: Vec::new(), ..plan };
+            let mut probe = Command::new("true");
+            // SAFETY: as in confine()
+            unsafe {
+                probe.pre_exec(move || plan.enter());
+            }
+            probe.status().is_ok_and(|s| s.success())
+        })
+    }
+
+    /// Everything the child needs, prepared before fork so enter() never allocates
+    struct NamespacePlan {
+        uid_map: CString,
+        gid_map: CString,
+        write: Vec<CString>,
+        /// (mount point, flags to keep) for every mount outside the write roots
+        readonly: Vec<(CString, libc::c_ulong)>,
+    }
+
+    impl NamespacePlan {
+        fn new(policy: &SandboxPolicy) -> io::Result<Self> {
+            let uid = unsafe { libc::getuid() };
+            let gid = unsafe { libc::getgid() };
+            let cstr = |bytes: &[u8]| CString::new(bytes).map_err(io::Error::other);
+
+            let write: Vec<PathBuf> = policy.write.iter()
+                .filter_map(|p| p.canonicalize().ok())
+                .collect();
+
+            let mut readonly = Vec::new();
+            for mount_point in mount_points()? {
+                let path = PathBuf::from(std::ffi::OsStr::from_bytes(&mount_point));
+                // /proc stays writable so the nested uid_map can be written
+                if path.starts_with("/proc") || write.iter().any(|w| path.starts_with(w)) {
+                    continue;
+                }
+                let c_path = cstr(path.as_os_str().as_bytes())?;
+                readonly.push((c_path.clone(), kept_flags(&c_path)));
+            }
+
+            Ok(Self {
+                uid_map: cstr(format!("{} {} 1", uid, uid).as_bytes())?,
+                gid_map: cstr(format!("{} {} 1", gid, gid).as_bytes())?,
+                write: write.iter().map(|p| cstr(p.as_os_str().as_bytes())).collect::<io::Result<_>>()?,
+                readonly,
+            })
+        }
+
+        /// Runs in the child between fork and exec
+        fn enter(&self) -> io::Result<()> {
+            // The second round puts the child in a less privileged namespace,
+            // which locks the read-only flags set in the first
+            for round in 0..2 {
+                check(unsafe { libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNS) })?;
+                write_proc(b"/proc/self/setgroups\0", b"deny")?;
+                write_proc(b"/proc/self/uid_map\0", self.uid_map.as_bytes())?;
+                write_proc(b"/proc/self/gid_map\0", self.gid_map.as_bytes())?;
+                if round > 0 {
+                    break;
+                }
+                check(unsafe {
+                    libc::mount(
+                        std::ptr::null(), c"/".as_ptr(), std::ptr::null(),
+                        libc::MS_REC | libc::MS_PRIVATE, std::ptr::null(),
+                    )
+                })?;
+                for root in &self.write {
+                    check(unsafe {
+                        libc::mount(
+                            root.as_ptr(), root.as_ptr(), std::ptr::null(),
+                            libc::MS_BIND | libc::MS_REC, std::ptr::null(),
+                        )
+                    })?;
+                }
+                for (mount_point, flags) in &self.readonly {
+                    let rc = unsafe {
+                        libc::mount(
+                            std::ptr::null(), mount_point.as_ptr(), std::ptr::null(),
+                            libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY | flags, std::ptr::null(),
+                        )
+                    };
+                    if rc != 0 {
+                        let err = io::Error::last_os_error();
+                        // Unreachable or vanished mounts cannot be written either
+                        if !matches!(err.raw_os_error(), Some(libc::ENOENT) | Some(libc::EACCES)) {
+                            return Err(err);
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    pub fn limit(command: &mut Command, limits: &ExecLimits) -> io::Result<()> {
+        let network = if limits.no_network {
+            if !network_supported() {
+                return Err(io::Error::new(io::ErrorKind::Unsupported,
+                    "network isolation requested but network namespaces are unavailable"));
+            }
+            Some(NetworkPlan::new()?)
+        } else {
+            None
+        };
+
+        let mib = |mb: u64| mb.saturating_mul(1 << 20);
+        // CPU: SIGXCPU at the soft limit, SIGKILL a second later if ignored
+        let rlimits: Vec<_> = [
+            (libc::RLIMIT_CPU, limits.cpu_secs, limits.cpu_secs.saturating_add(1)),
+            (libc::RLIMIT_AS, mib(limits.address_space_mb), mib(limits.address_space_mb)),
+            (libc::RLIMIT_NPROC, limits.max_processes, limits.max_processes),
+            (libc::RLIMIT_FSIZE, mib(limits.max_file_size_mb), mib(limits.max_file_size_mb)),
+            (libc::RLIMIT_NOFILE, limits.max_open_files, limits.max_open_files),
+        ]
+        .into_iter()
+        .filter(|(_, soft, _)| *soft > 0)
+        .collect();
+
+        // SAFETY: only syscalls on memory prepared above
+        unsafe {
+            command.pre_exec(move || {
+                check(libc::setpgid(0, 0))?;
+                if let Some(plan) = &network {
+                    plan.enter()?;
+                }
+                for (resource, soft, hard) in &rlimits {
+                    let mut current: libc::rlimit = std::mem::zeroed();
+                    check(libc::getrlimit(*resource, &mut current))?;
+                    // Never try to raise a hard limit — only root may
+                    let max = (*hard as libc::rlim_t).min(current.rlim_max);
+                    let wanted = libc::rlimit { rlim_cur: (*soft as libc::rlim_t).min(max), rlim_max: max };
+                    check(libc::setrlimit(*resource, &wanted))?;
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    pub fn kill_group(pid: u32) {
+        unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+    }
+
+    pub fn limit_signal(status: &std::process::ExitStatus) -> Option<&'static str> {
+        use std::os::unix::process::ExitStatusExt;
+        // Killed directly, or bash reporting a child's death as 128+n
+        let signal = status.signal().or_else(|| status.code().filter(|c| *c > 128).map(|c| c - 128))?;
+        match signal {
+            libc::SIGXCPU => Some("cpu"),
+            libc::SIGXFSZ => Some("fsize"),
+            _ => None,
+        }
+    }
+
+    /// Probe once by spawning `true` inside an empty network namespace
+    fn network_supported() -> bool {
+        static SUPPORTED: OnceLock<bool> = OnceLock::new();
+        *SUPPORTED.get_or_init(|| {
+            let Ok(plan) = NetworkPlan::new() else { return false };
+            let mut probe = Command::new("true");
+            // SAFETY: as in limit()
+            unsafe {
+                probe.pre_exec(move || plan.enter());
+            }
+            probe.status().is_ok_and(|s| s.success())
+        })
+    }
+
+    /// Empty network namespace with loopback up; unprivileged callers get
+    /// it via a user namespace mapping their own uid/gid
+    struct NetworkPlan {
+        uid_map: CString,
+        gid_map: CString,
+    }
+
+    impl NetworkPlan {
+        fn new() -> io::Result<Self> {
+            let uid = unsafe { libc::getuid() };
+            let gid = unsafe { libc::getgid() };
+            Ok(Self {
+                uid_map: CString::new(format!("{} {} 1", uid, uid)).map_err(io::Error::other)?,
+                gid_map: CString::new(format!("{} {} 1", gid, gid)).map_err(io::Error::other)?,
+            })
+        }
+
+        /// Runs in the child between fork and exec
+        fn enter(&self) -> io::Result<()> {
+            if unsafe { libc::unshare(libc::CLONE_NEWNET) } != 0 {
+                check(unsafe { libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) })?;
+                write_proc(b"/proc/self/setgroups\0", b"deny")?;
+                write_proc(b"/proc/self/uid_map\0", self.uid_map.as_bytes())?;
+                write_proc(b"/proc/self/gid_map\0", self.gid_map.as_bytes())?;
+            }
+            // Loopback is down in a fresh namespace; localhost is still useful
+            let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+            if fd >= 0 {
+                let mut req: libc::ifreq = unsafe { std::mem::zeroed() };
+                for (dst, src) in req.ifr_name.iter_mut().zip(b"lo") {
+                    *dst = *src as libc::c_char;
+                }
+                req.ifr_ifru.ifru_flags = (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short;
+                unsafe {
+                    libc::ioctl(fd, libc::SIOCSIFFLAGS as _, &req);
+                    libc::close(fd);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn check(rc: libc::c_int) -> io::Result<()> {
+        if rc == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+    }
+
+    fn write_proc(path: &[u8], data: &[u8]) -> io::Result<()> {
+        let fd = unsafe { libc::open(path.as_ptr().cast(), libc::O_WRONLY | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let written = unsafe { libc::write(fd, data.as_ptr().cast(), data.len()) };
+        let err = io::Error::last_os_error();
+        unsafe { libc::close(fd) };
+        if written == data.len() as isize { Ok(()) } else { Err(err) }
+    }
+
+    /// Mount points from /proc/self/mountinfo (octal escapes decoded)
+    fn mount_points() -> io::Result<Vec<Vec<u8>>> {
+        let info = std::fs::read("/proc/self/mountinfo")?;
+        let mut points = Vec::new();
+        for line in info.split(|b| *b == b'\n') {
+            let Some(field) = line.split(|b| *b == b' ').nth(4) else { continue };
+            let mut point = Vec::with_capacity(field.len());
+            let mut i = 0;
+            while i < field.len() {
+                if field[i] == b'\\' && field.len() >= i + 4 {
+                    let octal = std::str::from_utf8(&field[i + 1..i + 4]).ok()
+                        .and_then(|s| u8::from_str_radix(s, 8).ok());
+                    if let Some(byte) = octal {
+                        point.push(byte);
+                        i += 4;
+                        continue;
+                    }
+                }
+                point.push(field[i]);
+                i += 1;
+            }
+            points.push(point);
+        }
+        Ok(points)
+    }
+
+    /// nosuid/nodev/noexec/atime flags must be kept on remount inside a user namespace
+    fn kept_flags(path: &CString) -> libc::c_ulong {
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+            return 0;
+        }
+        [
+            (libc::ST_NOSUID, libc::MS_NOSUID),
+            (libc::ST_NODEV, libc::MS_NODEV),
+            (libc::ST_NOEXEC, libc::MS_NOEXEC),
+            (libc::ST_NOATIME, libc::MS_NOATIME),
+            (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+            (libc::ST_RELATIME, libc::MS_RELATIME),
+        ]
+        .iter()
+        .filter(|(st, _)| stat.f_flag & st != 0)
+        .fold(0, |flags, (_, ms)| flags | ms)
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bash_policy_mirrors_write_allowlist() {
+        let config = SpfConfig {
+            allowed_paths: vec!["/srv/data/".to_string(), "**/*.md".to_string(), ".git".to_string()],
+            ..Default::default()
+        };
+        let policy = SandboxPolicy::for_bash(&config);
+        let root = crate::paths::spf_root();
+        assert_eq!(policy.write, vec![
+            root.join("LIVE/PROJECTS/PROJECTS"),
+            root.join("LIVE/TMP/TMP"),
+        ]);
+        assert!(policy.read.contains(&PathBuf::from("/srv/data")));
+        assert!(policy.read.contains(&PathBuf::from("/usr")));
+        // Globs and relative rules never become kernel rules
+        assert_eq!(policy.read.len(), RUNTIME_READ.len() + 1);
+    }
+
+    #[test]
+    fn denied_write_fails_in_kernel() {
+        let dir = tempfile::tempdir().unwrap();
+        let writable = dir.path().join("writable");
+        std::fs::create_dir(&writable).unwrap();
+        let policy = SandboxPolicy {
+            read: vec![PathBuf::from("/")],
+            write: vec![writable.clone()],
+        };
+
+        let run = |target: &std::path::Path| {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(format!("echo x > '{}'", target.display()));
+            let confinement = confine(&mut command, &policy);
+            (confinement, command.status().map(|s| s.success()))
+        };
+
+        let (confinement, allowed) = run(&writable.join("ok.txt"));
+        if !confinement.is_confined() {
+            eprintln!("sandbox unavailable here: {}", confinement);
+            return;
+        }
+        assert!(allowed.unwrap(), "write inside a write root must succeed");
+        assert!(writable.join("ok.txt").exists());
+
+        let (_, denied) = run(&dir.path().join("escape.txt"));
+        assert!(!denied.unwrap(), "write outside the write roots must fail ({})", confinement);
+        assert!(!dir.path().join("escape.txt").exists());
+    }
+
+    fn unlimited() -> ExecLimits {
+        ExecLimits {
+            timeout_secs: 0, cpu_secs: 0, address_space_mb: 0, max_processes: 0,
+            max_file_size_mb: 0, max_open_files: 0, no_network: false,
+        }
+    }
+
+    #[test]
+    fn timeout_kills_the_process_group() {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("sleep 30 & echo started; sleep 30");
+        limit(&mut command, &unlimited()).unwrap();
+
+        let started = Instant::now();
+        let finished = run(&mut command, Duration::from_millis(300)).unwrap();
+        // The backgrounded sleep held stdout open — it must have died too
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(finished.timed_out);
+        assert_eq!(String::from_utf8_lossy(&finished.output.stdout), "started\n");
+        assert_eq!(limits_hit(&finished, &unlimited(), Duration::from_secs(1)), vec!["wall-clock timeout (1s)"]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn rlimits_and_network_apply_in_child() {
+        let dir = tempfile::tempdir().unwrap();
+        let limits = ExecLimits { max_file_size_mb: 1, ..unlimited() };
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("head -c 2097152 /dev/zero > big").current_dir(dir.path());
+        limit(&mut command, &limits).unwrap();
+        let finished = run(&mut command, Duration::from_secs(10)).unwrap();
+        assert!(!finished.output.status.success());
+        assert_eq!(std::fs::metadata(dir.path().join("big")).unwrap().len(), 1 << 20);
+        assert_eq!(limits_hit(&finished, &limits, Duration::from_secs(10)), vec!["file size (1M)"]);
+
+        let limits = ExecLimits { no_network: true, ..unlimited() };
+        let mut command = Command::new("cat");
+        command.arg("/proc/net/dev");
+        if let Err(e) = limit(&mut command, &limits) {
+            eprintln!("network isolation unavailable here: {}", e);
+            return;
+        }
+        let finished = run(&mut command, Duration::from_secs(10)).unwrap();
+        let interfaces: Vec<String> = String::from_utf8_lossy(&finished.output.stdout)
+            .lines()
+            .skip(2)
+            .filter_map(|l| l.split(':').next().map(|n| n.trim().to_string()))
+            .collect();
+        assert_eq!(interfaces, vec!["lo"]);
+    }
+}
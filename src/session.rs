@@ -2,16 +2,25 @@
 // Copyright 2026 Joseph Stone - All Rights Reserved
 //
 // In-memory session state. Persisted to LMDB on checkpoints.
-// Tracks: action_count, files_read, files_written, complexity history.
+// Tracks: action_count, files_read, files_written, complexity history
+// and the cumulative C / per-tier counts charged against the session budget.
+// Each read also stamps the file (SHA256, size, mtime) so Build Anchor can
+// tell when it changed on disk after the agent last saw it.
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
 
 /// Active session state — lives in RAM, flushed to LMDB periodically
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
     pub action_count: u64,
     pub files_read: Vec<String>,
+    /// What each file in files_read held when the agent last saw it
+    #[serde(default)]
+    pub read_stamps: BTreeMap<String, ReadStamp>,
     pub files_written: Vec<String>,
     pub last_tool: Option<String>,
     pub last_result: Option<String>,
@@ -19,6 +28,12 @@ pub struct Session {
     pub started: DateTime<Utc>,
     pub last_action: Option<DateTime<Utc>>,
     pub complexity_history: Vec<ComplexityEntry>,
+    /// Total C of every call that ran this session (history keeps only the last 100)
+    #[serde(default)]
+    pub complexity_total: u64,
+    /// Calls that ran this session, per tier
+    #[serde(default)]
+    pub tier_counts: BTreeMap<String, u64>,
     pub manifest: Vec<ManifestEntry>,
     pub failures: Vec<FailureEntry>,
     /// Per-minute action timestamps for rate limiting (circular buffer)
@@ -26,6 +41,46 @@ pub struct Session {
     pub rate_window: Vec<DateTime<Utc>>,
 }
 
+/// A file's content as last seen by the agent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReadStamp {
+    pub sha256: String,
+    pub size: u64,
+    /// Modification time, nanoseconds since the epoch
+    pub mtime_ns: i64,
+}
+
+impl ReadStamp {
+    /// Stamp the file as it is on disk now; None if it cannot be read
+    pub fn of(path: &Path) -> Option<Self> {
+        let meta = std::fs::metadata(path).ok()?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut std::fs::File::open(path).ok()?, &mut hasher).ok()?;
+        Some(Self {
+            sha256: hex::encode(hasher.finalize()),
+            size: meta.len(),
+            mtime_ns: mtime_ns(&meta),
+        })
+    }
+
+    /// Whether the file on disk still holds the stamped content.
+    /// Same size and mtime is trusted; otherwise the content is hashed,
+    /// so a touch without a change does not count.
+    pub fn matches_disk(&self, path: &Path) -> bool {
+        match std::fs::metadata(path) {
+            Ok(meta) if meta.len() == self.size && mtime_ns(&meta) == self.mtime_ns => true,
+            Ok(_) => Self::of(path).is_some_and(|now| now.sha256 == self.sha256),
+            Err(_) => false,
+        }
+    }
+}
+
+fn mtime_ns(meta: &std::fs::Metadata) -> i64 {
+    meta.modified().ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos() as i64)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ComplexityEntry {
     pub timestamp: DateTime<Utc>,
@@ -55,6 +110,7 @@ impl Session {
         Self {
             action_count: 0,
             files_read: Vec::new(),
+            read_stamps: BTreeMap::new(),
             files_written: Vec::new(),
             last_tool: None,
             last_result: None,
@@ -62,16 +118,30 @@ impl Session {
             started: Utc::now(),
             last_action: None,
             complexity_history: Vec::new(),
+            complexity_total: 0,
+            tier_counts: BTreeMap::new(),
             manifest: Vec::new(),
             failures: Vec::new(),
             rate_window: Vec::new(),
         }
     }
 
-    /// Track a file read for Build Anchor Protocol
+    /// Stable session identifier (derived from start time)
+    pub fn id(&self) -> String {
+        self.started.format("%Y%m%dT%H%M%S%.3fZ").to_string()
+    }
+
+    /// Track a file read for Build Anchor Protocol, stamping what was read
     pub fn track_read(&mut self, path: &str) {
         let canonical = match std::fs::canonicalize(path) {
-            Ok(p) => p.to_string_lossy().to_string(),
+            Ok(p) => {
+                let canonical = p.to_string_lossy().to_string();
+                match ReadStamp::of(&p) {
+                    Some(stamp) => { self.read_stamps.insert(canonical.clone(), stamp); }
+                    None => { self.read_stamps.remove(&canonical); }
+                }
+                canonical
+            }
             Err(_) => {
                 if path.contains("..") {
                     let flagged = format!("[TRAVERSAL REJECTED] {}", path);
@@ -88,10 +158,20 @@ impl Session {
         }
     }
 
-    /// Track a file write
+    /// Track a file write. A file read before is re-stamped: the agent
+    /// knows what it just wrote.
     pub fn track_write(&mut self, path: &str) {
         let canonical = match std::fs::canonicalize(path) {
-            Ok(p) => p.to_string_lossy().to_string(),
+            Ok(p) => {
+                let canonical = p.to_string_lossy().to_string();
+                if self.read_stamps.contains_key(&canonical) {
+                    match ReadStamp::of(&p) {
+                        Some(stamp) => { self.read_stamps.insert(canonical.clone(), stamp); }
+                        None => { self.read_stamps.remove(&canonical); }
+                    }
+                }
+                canonical
+            }
             Err(_) => {
                 if path.contains("..") {
                     let flagged = format!("[TRAVERSAL REJECTED] {}", path);
@@ -108,6 +188,13 @@ impl Session {
         }
     }
 
+    /// Whether a file read this session has changed on disk since.
+    /// Reads without a stamp (sessions from before stamping) never count as changed.
+    pub fn changed_since_read(&self, canonical: &str) -> bool {
+        self.read_stamps.get(canonical)
+            .is_some_and(|stamp| !stamp.matches_disk(Path::new(canonical)))
+    }
+
     /// Record an action (called after every tool use)
     pub fn record_action(&mut self, tool: &str, result: &str, file_path: Option<&str>) {
         self.action_count += 1;
@@ -123,8 +210,11 @@ impl Session {
         self.rate_window.retain(|ts| *ts > one_minute_ago);
     }
 
-    /// Record complexity calculation
+    /// Record complexity of a call that is about to run.
+    /// Cumulative totals survive the history truncation below.
     pub fn record_complexity(&mut self, tool: &str, c: u64, tier: &str) {
+        self.complexity_total = self.complexity_total.saturating_add(c);
+        *self.tier_counts.entry(tier.to_string()).or_default() += 1;
         self.complexity_history.push(ComplexityEntry {
             timestamp: Utc::now(),
             tool: tool.to_string(),
@@ -190,3 +280,44 @@ impl Default for Session {
         Self::new()
     }
 }
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_stamps_detect_changes_after_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("lib.rs");
+        std::fs::write(&file, "fn a() {}\n").unwrap();
+        let path = file.to_string_lossy().to_string();
+        let canonical = file.canonicalize().unwrap().to_string_lossy().to_string();
+
+        let mut session = Session::new();
+        assert!(!session.changed_since_read(&canonical), "unread files are Build Anchor's concern");
+        session.track_read(&path);
+        assert!(!session.changed_since_read(&canonical));
+
+        // Same content rewritten (new mtime) is still fresh
+        std::fs::write(&file, "fn a() {}\n").unwrap();
+        assert!(!session.changed_since_read(&canonical));
+
+        std::fs::write(&file, "fn b() {}\n").unwrap();
+        assert!(session.changed_since_read(&canonical));
+        // The agent's own write re-stamps; so does a fresh read
+        session.track_write(&path);
+        assert!(!session.changed_since_read(&canonical));
+        std::fs::remove_file(&file).unwrap();
+        assert!(session.changed_since_read(&canonical));
+
+        // Sessions stored before stamping load without stamps
+        let mut json: serde_json::Value = serde_json::to_value(Session::new()).unwrap();
+        json.as_object_mut().unwrap().remove("read_stamps");
+        let old: Session = serde_json::from_value(json).unwrap();
+        assert!(old.read_stamps.is_empty());
+    }
+}
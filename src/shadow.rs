@@ -0,0 +1,158 @@
+// SPF Smart Gateway - Shadow (Audit) Evaluation
+// Copyright 2026 Joseph Stone - All Rights Reserved
+//
+// Audit mode evaluates a candidate policy alongside the active one.
+// Every call where the two disagree is recorded in SESSION.DB ("shadow").
+// The active decision always governs execution — the candidate is advisory.
+// `spf-smart-gate shadow-report` summarises what would have changed.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Outcome of one policy for a call
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ShadowOutcome {
+    pub allowed: bool,
+    pub tier: String,
+    pub requires_approval: bool,
+}
+
+/// A call where the active and candidate policies disagreed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShadowRecord {
+    pub timestamp: DateTime<Utc>,
+    pub tool: String,
+    pub path: Option<String>,
+    pub active: ShadowOutcome,
+    pub candidate: ShadowOutcome,
+    /// Errors the candidate would have raised (when it blocks)
+    #[serde(default)]
+    pub candidate_errors: Vec<String>,
+}
+
+impl ShadowRecord {
+    /// Compare two outcomes. Returns None when they agree.
+    pub fn compare(
+        tool: &str,
+        path: Option<&str>,
+        active: ShadowOutcome,
+        candidate: ShadowOutcome,
+        candidate_errors: Vec<String>,
+    ) -> Option<Self> {
+        if active == candidate {
+            return None;
+        }
+        Some(Self {
+            timestamp: Utc::now(),
+            tool: tool.to_string(),
+            path: path.map(String::from),
+            active,
+            candidate,
+            candidate_errors,
+        })
+    }
+
+    /// Short description of the outcome change, e.g. "ALLOW→BLOCK"
+    pub fn change(&self) -> String {
+        let verdict = |o: &ShadowOutcome| if o.allowed { "ALLOW" } else { "BLOCK" };
+        let mut parts = Vec::new();
+        if self.active.allowed != self.candidate.allowed {
+            parts.push(format!("{}→{}", verdict(&self.active), verdict(&self.candidate)));
+        }
+        if self.active.tier != self.candidate.tier {
+            parts.push(format!("{}→{}", self.active.tier, self.candidate.tier));
+        }
+        if self.active.requires_approval != self.candidate.requires_approval {
+            parts.push(if self.candidate.requires_approval {
+                "+approval".to_string()
+            } else {
+                "-approval".to_string()
+            });
+        }
+        parts.join(" ")
+    }
+}
+
+/// Human-readable summary of shadow records, grouped by tool and path
+pub fn report(records: &[ShadowRecord]) -> String {
+    if records.is_empty() {
+        return "No disagreements recorded.".to_string();
+    }
+
+    let mut by_tool: BTreeMap<&str, BTreeMap<String, usize>> = BTreeMap::new();
+    let mut by_path: BTreeMap<(String, &str, String), usize> = BTreeMap::new();
+    for rec in records {
+        let change = rec.change();
+        *by_tool.entry(&rec.tool).or_default().entry(change.clone()).or_default() += 1;
+        let path = rec.path.clone().unwrap_or_else(|| "(no path)".to_string());
+        *by_path.entry((path, &rec.tool, change)).or_default() += 1;
+    }
+
+    let first = records.iter().map(|r| r.timestamp).min().unwrap_or_else(Utc::now);
+    let last = records.iter().map(|r| r.timestamp).max().unwrap_or_else(Utc::now);
+
+    let mut out = format!(
+        "{} disagreements ({} → {})\n\nBy tool:\n",
+        records.len(),
+        first.format("%Y-%m-%d %H:%M:%S"),
+        last.format("%Y-%m-%d %H:%M:%S"),
+    );
+    let mut tools: Vec<_> = by_tool.iter().collect();
+    tools.sort_by_key(|(_, changes)| std::cmp::Reverse(changes.values().sum::<usize>()));
+    for (tool, changes) in tools {
+        let detail: Vec<String> = changes.iter().map(|(c, n)| format!("{} ×{}", c, n)).collect();
+        out.push_str(&format!(
+            "  {:<24} {:>5}  {}\n", tool, changes.values().sum::<usize>(), detail.join(", ")
+        ));
+    }
+
+    out.push_str("\nBy path:\n");
+    let mut paths: Vec<_> = by_path.into_iter().collect();
+    paths.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+    for ((path, tool, change), n) in paths {
+        out.push_str(&format!("  {:>5}  {:<20} {:<24} {}\n", n, tool, change, path));
+    }
+    out
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(allowed: bool, tier: &str, requires_approval: bool) -> ShadowOutcome {
+        ShadowOutcome { allowed, tier: tier.to_string(), requires_approval }
+    }
+
+    #[test]
+    fn agreeing_outcomes_are_not_recorded() {
+        let rec = ShadowRecord::compare(
+            "spf_read", Some("/a"), outcome(true, "SIMPLE", false), outcome(true, "SIMPLE", false), vec![],
+        );
+        assert!(rec.is_none());
+    }
+
+    #[test]
+    fn report_groups_by_tool_and_path() {
+        let block = ShadowRecord::compare(
+            "spf_write", Some("/home/u/.env"),
+            outcome(true, "SIMPLE", false), outcome(false, "SIMPLE", false), vec!["policy".into()],
+        ).unwrap();
+        assert_eq!(block.change(), "ALLOW→BLOCK");
+        let escalate = ShadowRecord::compare(
+            "spf_bash", None,
+            outcome(true, "LIGHT", false), outcome(true, "CRITICAL", true), vec![],
+        ).unwrap();
+        assert_eq!(escalate.change(), "LIGHT→CRITICAL +approval");
+
+        let text = report(&[block.clone(), block, escalate]);
+        assert!(text.starts_with("3 disagreements"));
+        assert!(text.contains("spf_write"));
+        assert!(text.contains("/home/u/.env"));
+        assert!(text.contains("(no path)"));
+    }
+}
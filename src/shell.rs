@@ -0,0 +1,1412 @@
+// SPF Smart Gateway - Shell Parser
+// Copyright 2026 Joseph Stone - All Rights Reserved
+//
+// POSIX sh tokenizer and AST for the bash validator. Understands quoting
+// ('…', "…", $'…', backslashes), parameter and arithmetic expansion, command
+// and process substitution, redirections (fd numbers, >|, <>, &>, >&,
+// here-docs, here-strings), pipelines, and/or lists, subshells, brace groups,
+// if/while/until/for/case and function definitions.
+//
+// Nothing is expanded or executed. Words whose value depends on run time
+// ($VAR, $(…)) are marked dynamic so callers can fail closed on them.
+
+/// Maximum nesting of substitutions and compound commands
+const MAX_DEPTH: usize = 64;
+
+/// Piece of a word
+#[derive(Debug, Clone, PartialEq)]
+pub enum WordPart {
+    /// Text after quote removal
+    Literal(String),
+    /// $name, ${…} or $((…)) — known only at run time
+    Param(String),
+    /// $(…), `…`, <(…) or >(…)
+    CommandSub(Script),
+}
+
+/// One shell word
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+}
+
+impl Word {
+    fn literal_word(text: &str) -> Self {
+        Self { parts: vec![WordPart::Literal(text.to_string())] }
+    }
+
+    fn push_str(&mut self, text: &str) {
+        if let Some(WordPart::Literal(last)) = self.parts.last_mut() {
+            last.push_str(text);
+        } else {
+            self.parts.push(WordPart::Literal(text.to_string()));
+        }
+    }
+
+    fn push_char(&mut self, c: char) {
+        let mut buf = [0u8; 4];
+        self.push_str(c.encode_utf8(&mut buf));
+    }
+
+    /// Value after quote removal, or None if it expands at run time
+    pub fn literal(&self) -> Option<String> {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                WordPart::Literal(text) => out.push_str(text),
+                _ => return None,
+            }
+        }
+        Some(out)
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        self.literal().is_none()
+    }
+
+    /// Value with expansions left as written — for messages and path checks
+    pub fn text(&self) -> String {
+        self.parts.iter().map(|part| match part {
+            WordPart::Literal(text) | WordPart::Param(text) => text.clone(),
+            WordPart::CommandSub(_) => "$(…)".to_string(),
+        }).collect()
+    }
+
+    /// Visit commands inside substitutions
+    pub fn walk<F: FnMut(&Command)>(&self, f: &mut F) {
+        for part in &self.parts {
+            if let WordPart::CommandSub(script) = part {
+                script.walk(f);
+            }
+        }
+    }
+}
+
+/// Redirection operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectOp {
+    /// <
+    Read,
+    /// >
+    Write,
+    /// >|
+    Clobber,
+    /// >>
+    Append,
+    /// <>
+    ReadWrite,
+    /// <&
+    DupIn,
+    /// >&
+    DupOut,
+    /// &>
+    WriteAll,
+    /// &>>
+    AppendAll,
+    /// << and <<- (target holds the body)
+    HereDoc,
+    /// <<<
+    HereString,
+}
+
+impl RedirectOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Read => "<",
+            Self::Write => ">",
+            Self::Clobber => ">|",
+            Self::Append => ">>",
+            Self::ReadWrite => "<>",
+            Self::DupIn => "<&",
+            Self::DupOut => ">&",
+            Self::WriteAll => "&>",
+            Self::AppendAll => "&>>",
+            Self::HereDoc => "<<",
+            Self::HereString => "<<<",
+        }
+    }
+}
+
+/// A redirection attached to a command
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    pub fd: Option<u32>,
+    pub op: RedirectOp,
+    pub target: Word,
+}
+
+impl Redirect {
+    /// Does this redirection open its target for writing?
+    /// `>&word` writes to a file unless word is an fd number or '-'.
+    pub fn writes_file(&self) -> bool {
+        match self.op {
+            RedirectOp::Write | RedirectOp::Clobber | RedirectOp::Append
+            | RedirectOp::ReadWrite | RedirectOp::WriteAll | RedirectOp::AppendAll => true,
+            RedirectOp::DupOut => !self.target.literal().is_some_and(|t| {
+                t == "-" || t.trim_end_matches('-').chars().all(|c| c.is_ascii_digit())
+            }),
+            _ => false,
+        }
+    }
+
+    /// Operator as written, including the fd number
+    pub fn display_op(&self) -> String {
+        match self.fd {
+            Some(fd) => format!("{}{}", fd, self.op.as_str()),
+            None => self.op.as_str().to_string(),
+        }
+    }
+}
+
+/// Command name, arguments, leading NAME=value assignments and redirections
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimpleCommand {
+    pub assignments: Vec<(String, Word)>,
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Simple(SimpleCommand),
+    /// ( … )
+    Subshell(Script, Vec<Redirect>),
+    /// { …; }
+    Group(Script, Vec<Redirect>),
+    If {
+        /// (condition, body) for `if` and each `elif`
+        branches: Vec<(Script, Script)>,
+        otherwise: Option<Script>,
+        redirects: Vec<Redirect>,
+    },
+    /// while / until
+    Loop {
+        until: bool,
+        condition: Script,
+        body: Script,
+        redirects: Vec<Redirect>,
+    },
+    For {
+        var: String,
+        /// None iterates "$@"
+        items: Option<Vec<Word>>,
+        body: Script,
+        redirects: Vec<Redirect>,
+    },
+    Case {
+        subject: Word,
+        arms: Vec<(Vec<Word>, Script)>,
+        redirects: Vec<Redirect>,
+    },
+    Function {
+        name: String,
+        body: Box<Command>,
+    },
+    /// (( … )) and for (( … ))
+    Arithmetic(String, Vec<Redirect>),
+}
+
+impl Command {
+    pub fn redirects(&self) -> &[Redirect] {
+        match self {
+            Self::Simple(simple) => &simple.redirects,
+            Self::Subshell(_, redirects)
+            | Self::Group(_, redirects)
+            | Self::Arithmetic(_, redirects)
+            | Self::If { redirects, .. }
+            | Self::Loop { redirects, .. }
+            | Self::For { redirects, .. }
+            | Self::Case { redirects, .. } => redirects,
+            Self::Function { .. } => &[],
+        }
+    }
+
+    /// Visit this command and everything nested in it: compound bodies,
+    /// function bodies and command substitutions in any word
+    pub fn walk<F: FnMut(&Command)>(&self, f: &mut F) {
+        f(self);
+        for redirect in self.redirects() {
+            redirect.target.walk(f);
+        }
+        match self {
+            Self::Simple(simple) => {
+                for (_, value) in &simple.assignments {
+                    value.walk(f);
+                }
+                for word in &simple.words {
+                    word.walk(f);
+                }
+            }
+            Self::Subshell(body, _) | Self::Group(body, _) => body.walk(f),
+            Self::If { branches, otherwise, .. } => {
+                for (condition, body) in branches {
+                    condition.walk(f);
+                    body.walk(f);
+                }
+                if let Some(body) = otherwise {
+                    body.walk(f);
+                }
+            }
+            Self::Loop { condition, body, .. } => {
+                condition.walk(f);
+                body.walk(f);
+            }
+            Self::For { items, body, .. } => {
+                for item in items.iter().flatten() {
+                    item.walk(f);
+                }
+                body.walk(f);
+            }
+            Self::Case { subject, arms, .. } => {
+                subject.walk(f);
+                for (patterns, body) in arms {
+                    for pattern in patterns {
+                        pattern.walk(f);
+                    }
+                    body.walk(f);
+                }
+            }
+            Self::Function { body, .. } => body.walk(f),
+            Self::Arithmetic(..) => {}
+        }
+    }
+}
+
+/// Commands joined by | or |&
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pipeline {
+    pub negated: bool,
+    pub commands: Vec<Command>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connector {
+    And,
+    Or,
+}
+
+/// Pipelines joined by && and ||, optionally run in the background
+#[derive(Debug, Clone, PartialEq)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+    pub background: bool,
+}
+
+impl AndOr {
+    pub fn pipelines(&self) -> impl Iterator<Item = &Pipeline> {
+        std::iter::once(&self.first).chain(self.rest.iter().map(|(_, p)| p))
+    }
+}
+
+/// A command list separated by ;, & or newlines
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Script {
+    pub lists: Vec<AndOr>,
+}
+
+impl Script {
+    /// Visit every command, however deeply nested
+    pub fn walk<F: FnMut(&Command)>(&self, f: &mut F) {
+        for list in &self.lists {
+            for pipeline in list.pipelines() {
+                for command in &pipeline.commands {
+                    command.walk(f);
+                }
+            }
+        }
+    }
+}
+
+/// Parse shell source into a script
+pub fn parse(source: &str) -> Result<Script, String> {
+    Parser::new(source, 0).parse_program()
+}
+
+// ============================================================================
+// TOKENIZER + PARSER
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Parsed word plus its raw source (for reserved words and assignments)
+    Word(Word, String),
+    Op(&'static str),
+    Redirect(Option<u32>, RedirectOp, bool),
+    Arith(String),
+    Eof,
+}
+
+const OPERATORS: &[&str] = &[";;&", ";;", ";&", "&&", "||", "|&", "|", ";", "&", "(", ")"];
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    depth: usize,
+    peeked: Option<Token>,
+    /// Newline after which pending here-doc bodies start, and where they end
+    heredoc_newline: Option<usize>,
+    heredoc_end: Option<usize>,
+}
+
+impl Parser {
+    fn new(source: &str, depth: usize) -> Self {
+        Self {
+            chars: source.chars().collect(),
+            pos: 0,
+            depth,
+            peeked: None,
+            heredoc_newline: None,
+            heredoc_end: None,
+        }
+    }
+
+    fn at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars().enumerate().all(|(i, c)| self.at(i) == Some(c))
+    }
+
+    fn enter(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("nesting too deep".to_string());
+        }
+        Ok(())
+    }
+
+    // ------------------------------------------------------------------------
+    // Tokens
+    // ------------------------------------------------------------------------
+
+    fn peek(&mut self) -> Result<&Token, String> {
+        if self.peeked.is_none() {
+            let token = self.lex()?;
+            self.peeked = Some(token);
+        }
+        Ok(self.peeked.as_ref().expect("peeked token"))
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        match self.peeked.take() {
+            Some(token) => Ok(token),
+            None => self.lex(),
+        }
+    }
+
+    fn skip_blanks(&mut self) {
+        loop {
+            match self.at(0) {
+                Some(' ') | Some('\t') | Some('\r') => self.pos += 1,
+                Some('\\') if self.at(1) == Some('\n') => self.pos += 2,
+                Some('#') => {
+                    while self.at(0).is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn lex(&mut self) -> Result<Token, String> {
+        self.skip_blanks();
+        let Some(c) = self.at(0) else { return Ok(Token::Eof) };
+
+        if c == '\n' {
+            if self.heredoc_newline == Some(self.pos) {
+                self.pos = self.heredoc_end.take().unwrap_or(self.pos + 1);
+                self.heredoc_newline = None;
+            } else {
+                self.pos += 1;
+            }
+            return Ok(Token::Op("\n"));
+        }
+
+        // Process substitution is a word, not a redirection
+        if matches!(c, '<' | '>') && self.at(1) == Some('(') {
+            return self.lex_word();
+        }
+
+        // (( arithmetic ))
+        if self.starts_with("((") {
+            self.pos += 2;
+            let text = self.scan_arith(&mut Word::default())?;
+            return Ok(Token::Arith(text));
+        }
+
+        // [n]redirection
+        let digits = self.chars[self.pos..].iter().take_while(|c| c.is_ascii_digit()).count();
+        if matches!(self.at(digits), Some('<') | Some('>')) {
+            let fd = if digits > 0 {
+                let text: String = self.chars[self.pos..self.pos + digits].iter().collect();
+                Some(text.parse().map_err(|_| format!("bad fd number {}", text))?)
+            } else {
+                None
+            };
+            self.pos += digits;
+            let (op, len, strip) = if self.starts_with("<<<") {
+                (RedirectOp::HereString, 3, false)
+            } else if self.starts_with("<<-") {
+                (RedirectOp::HereDoc, 3, true)
+            } else if self.starts_with("<<") {
+                (RedirectOp::HereDoc, 2, false)
+            } else if self.starts_with("<>") {
+                (RedirectOp::ReadWrite, 2, false)
+            } else if self.starts_with("<&") {
+                (RedirectOp::DupIn, 2, false)
+            } else if self.starts_with(">>") {
+                (RedirectOp::Append, 2, false)
+            } else if self.starts_with(">|") {
+                (RedirectOp::Clobber, 2, false)
+            } else if self.starts_with(">&") {
+                (RedirectOp::DupOut, 2, false)
+            } else if self.at(0) == Some('<') {
+                (RedirectOp::Read, 1, false)
+            } else {
+                (RedirectOp::Write, 1, false)
+            };
+            self.pos += len;
+            return Ok(Token::Redirect(fd, op, strip));
+        }
+        if self.starts_with("&>>") {
+            self.pos += 3;
+            return Ok(Token::Redirect(None, RedirectOp::AppendAll, false));
+        }
+        if self.starts_with("&>") {
+            self.pos += 2;
+            return Ok(Token::Redirect(None, RedirectOp::WriteAll, false));
+        }
+
+        for op in OPERATORS {
+            if self.starts_with(op) {
+                self.pos += op.chars().count();
+                // Case fall-through terminators end an arm like ;;
+                let op = if op.starts_with(";;") || *op == ";&" { ";;" } else { op };
+                return Ok(Token::Op(op));
+            }
+        }
+
+        self.lex_word()
+    }
+
+    fn is_delimiter(c: char) -> bool {
+        matches!(c, ' ' | '\t' | '\r' | '\n' | ';' | '&' | '|' | '<' | '>' | '(' | ')')
+    }
+
+    fn lex_word(&mut self) -> Result<Token, String> {
+        let start = self.pos;
+        let mut word = Word::default();
+
+        while let Some(c) = self.at(0) {
+            if matches!(c, '<' | '>') && self.at(1) == Some('(') && self.pos == start {
+                self.pos += 2;
+                let script = self.parse_substitution()?;
+                word.parts.push(WordPart::CommandSub(script));
+                continue;
+            }
+            // NAME=( array ) assignment stays one word
+            if c == '(' && self.pos > start && self.chars[self.pos - 1] == '=' {
+                let text = self.scan_balanced('(', ')')?;
+                word.push_str(&text);
+                continue;
+            }
+            if Self::is_delimiter(c) {
+                break;
+            }
+            match c {
+                '\\' => {
+                    self.pos += 1;
+                    match self.at(0) {
+                        Some('\n') => self.pos += 1,
+                        Some(escaped) => {
+                            word.push_char(escaped);
+                            self.pos += 1;
+                        }
+                        None => word.push_char('\\'),
+                    }
+                }
+                '\'' => {
+                    self.pos += 1;
+                    let text = self.scan_until('\'')?;
+                    word.push_str(&text);
+                }
+                '"' => {
+                    self.pos += 1;
+                    self.lex_double_quoted(&mut word, Some('"'))?;
+                }
+                '$' => self.lex_dollar(&mut word, true)?,
+                '`' => self.lex_backtick(&mut word)?,
+                _ => {
+                    word.push_char(c);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        let raw: String = self.chars[start..self.pos].iter().collect();
+        if word.parts.is_empty() {
+            // "" and '' are empty words, not nothing
+            word.parts.push(WordPart::Literal(String::new()));
+        }
+        Ok(Token::Word(word, raw))
+    }
+
+    /// Consume up to (and including) `end`, returning the text before it
+    fn scan_until(&mut self, end: char) -> Result<String, String> {
+        let mut text = String::new();
+        loop {
+            match self.at(0) {
+                Some(c) if c == end => {
+                    self.pos += 1;
+                    return Ok(text);
+                }
+                Some(c) => {
+                    text.push(c);
+                    self.pos += 1;
+                }
+                None => return Err(format!("unterminated {}", end)),
+            }
+        }
+    }
+
+    /// Consume a balanced `open … close` run starting at `open`, quotes respected
+    fn scan_balanced(&mut self, open: char, close: char) -> Result<String, String> {
+        let mut text = String::new();
+        let mut depth = 0usize;
+        loop {
+            let Some(c) = self.at(0) else {
+                return Err(format!("unterminated {}", open));
+            };
+            text.push(c);
+            self.pos += 1;
+            if c == '\\' {
+                if let Some(next) = self.at(0) {
+                    text.push(next);
+                    self.pos += 1;
+                }
+            } else if c == '\'' || c == '"' {
+                let quoted = self.scan_until(c)?;
+                text.push_str(&quoted);
+                text.push(c);
+            } else if c == open {
+                depth += 1;
+            } else if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(text);
+                }
+            }
+        }
+    }
+
+    /// Body of "…" (or of an unquoted here-doc when `end` is None)
+    fn lex_double_quoted(&mut self, word: &mut Word, end: Option<char>) -> Result<(), String> {
+        loop {
+            let Some(c) = self.at(0) else {
+                return match end {
+                    Some(_) => Err("unterminated \"".to_string()),
+                    None => Ok(()),
+                };
+            };
+            if Some(c) == end {
+                self.pos += 1;
+                return Ok(());
+            }
+            match c {
+                '\\' => {
+                    self.pos += 1;
+                    match self.at(0) {
+                        Some('\n') => self.pos += 1,
+                        Some(escaped @ ('$' | '`' | '"' | '\\')) if escaped != '"' || end.is_some() => {
+                            word.push_char(escaped);
+                            self.pos += 1;
+                        }
+                        _ => word.push_char('\\'),
+                    }
+                }
+                '$' => self.lex_dollar(word, false)?,
+                '`' => self.lex_backtick(word)?,
+                _ => {
+                    word.push_char(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn lex_dollar(&mut self, word: &mut Word, unquoted: bool) -> Result<(), String> {
+        match self.at(1) {
+            Some('\'') if unquoted => {
+                self.pos += 2;
+                let text = self.scan_ansi_c()?;
+                word.push_str(&text);
+            }
+            Some('"') if unquoted => {
+                self.pos += 2;
+                self.lex_double_quoted(word, Some('"'))?;
+            }
+            Some('(') if self.at(2) == Some('(') => {
+                self.pos += 3;
+                let text = self.scan_arith(word)?;
+                word.parts.push(WordPart::Param(format!("$(({}))", text)));
+            }
+            Some('(') => {
+                self.pos += 2;
+                let script = self.parse_substitution()?;
+                word.parts.push(WordPart::CommandSub(script));
+            }
+            Some('{') => {
+                self.pos += 1;
+                let text = self.scan_brace_param(word)?;
+                word.parts.push(WordPart::Param(format!("${}", text)));
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let len = self.chars[self.pos + 1..].iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                    .count();
+                let name: String = self.chars[self.pos..self.pos + 1 + len].iter().collect();
+                self.pos += 1 + len;
+                word.parts.push(WordPart::Param(name));
+            }
+            Some(c) if c.is_ascii_digit() || "@*#?-$!".contains(c) => {
+                word.parts.push(WordPart::Param(format!("${}", c)));
+                self.pos += 2;
+            }
+            _ => {
+                word.push_char('$');
+                self.pos += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// ${ … } — nested substitutions become their own parts
+    fn scan_brace_param(&mut self, word: &mut Word) -> Result<String, String> {
+        let mut text = String::new();
+        let mut depth = 0usize;
+        loop {
+            let Some(c) = self.at(0) else {
+                return Err("unterminated ${".to_string());
+            };
+            if c == '$' && self.at(1) == Some('(') && self.at(2) != Some('(') {
+                self.pos += 2;
+                let script = self.parse_substitution()?;
+                word.parts.push(WordPart::CommandSub(script));
+                text.push_str("$(…)");
+                continue;
+            }
+            if c == '`' {
+                self.lex_backtick(word)?;
+                text.push_str("`…`");
+                continue;
+            }
+            text.push(c);
+            self.pos += 1;
+            match c {
+                '\\' => {
+                    if let Some(next) = self.at(0) {
+                        text.push(next);
+                        self.pos += 1;
+                    }
+                }
+                '\'' => {
+                    text.push_str(&self.scan_until('\'')?);
+                    text.push('\'');
+                }
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(text);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Inside $(( … )) or (( … )) — returns the expression text
+    fn scan_arith(&mut self, word: &mut Word) -> Result<String, String> {
+        let mut text = String::new();
+        let mut depth = 2usize;
+        loop {
+            let Some(c) = self.at(0) else {
+                return Err("unterminated ((".to_string());
+            };
+            if c == '$' && self.at(1) == Some('(') && self.at(2) != Some('(') {
+                self.pos += 2;
+                let script = self.parse_substitution()?;
+                word.parts.push(WordPart::CommandSub(script));
+                text.push_str("$(…)");
+                continue;
+            }
+            self.pos += 1;
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        // Drop the first of the closing pair
+                        text.pop();
+                        return Ok(text);
+                    }
+                }
+                _ => {}
+            }
+            text.push(c);
+        }
+    }
+
+    /// $'…' with C escapes
+    fn scan_ansi_c(&mut self) -> Result<String, String> {
+        let mut text = String::new();
+        loop {
+            let Some(c) = self.at(0) else {
+                return Err("unterminated $'".to_string());
+            };
+            self.pos += 1;
+            match c {
+                '\'' => return Ok(text),
+                '\\' => {
+                    let Some(e) = self.at(0) else { continue };
+                    self.pos += 1;
+                    match e {
+                        'n' => text.push('\n'),
+                        't' => text.push('\t'),
+                        'r' => text.push('\r'),
+                        'a' => text.push('\x07'),
+                        'b' => text.push('\x08'),
+                        'e' | 'E' => text.push('\x1b'),
+                        'f' => text.push('\x0c'),
+                        'v' => text.push('\x0b'),
+                        '\\' | '\'' | '"' | '?' => text.push(e),
+                        'x' | '0'..='7' => {
+                            let (radix, max) = if e == 'x' { (16, 2) } else { (8, 2) };
+                            let mut digits = if e == 'x' { String::new() } else { e.to_string() };
+                            while digits.len() < max + usize::from(e != 'x')
+                                && self.at(0).is_some_and(|d| d.is_digit(radix))
+                            {
+                                digits.push(self.at(0).expect("digit"));
+                                self.pos += 1;
+                            }
+                            match u32::from_str_radix(&digits, radix).ok().and_then(char::from_u32) {
+                                Some(ch) => text.push(ch),
+                                None => text.push_str(&format!("\\{}", e)),
+                            }
+                        }
+                        other => {
+                            text.push('\\');
+                            text.push(other);
+                        }
+                    }
+                }
+                other => text.push(other),
+            }
+        }
+    }
+
+    /// `…` — unescape, then parse as its own script
+    fn lex_backtick(&mut self, word: &mut Word) -> Result<(), String> {
+        self.pos += 1;
+        let mut body = String::new();
+        loop {
+            match self.at(0) {
+                Some('`') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some('\\') if matches!(self.at(1), Some('`' | '$' | '\\')) => {
+                    body.push(self.at(1).expect("escaped char"));
+                    self.pos += 2;
+                }
+                Some(c) => {
+                    body.push(c);
+                    self.pos += 1;
+                }
+                None => return Err("unterminated `".to_string()),
+            }
+        }
+        let script = Parser::new(&body, self.depth + 1).parse_program()?;
+        word.parts.push(WordPart::CommandSub(script));
+        Ok(())
+    }
+
+    /// After $( or <( / >( — parse up to the matching )
+    fn parse_substitution(&mut self) -> Result<Script, String> {
+        self.enter()?;
+        let script = self.parse_list(&[")"])?;
+        match self.next()? {
+            Token::Op(")") => {}
+            other => return Err(format!("expected ) to close substitution, found {}", describe(&other))),
+        }
+        self.depth -= 1;
+        Ok(script)
+    }
+
+    /// Here-doc body starts after the next newline (or after the previous body)
+    fn read_heredoc(&mut self, delimiter: &str, quoted: bool, strip_tabs: bool) -> Result<Word, String> {
+        let start = match self.heredoc_end {
+            Some(end) => end,
+            None => {
+                let newline = self.chars[self.pos..].iter().position(|c| *c == '\n')
+                    .map(|i| self.pos + i);
+                match newline {
+                    Some(nl) => {
+                        self.heredoc_newline = Some(nl);
+                        nl + 1
+                    }
+                    None => return Err(format!("here-doc '{}' has no body", delimiter)),
+                }
+            }
+        };
+
+        let mut body = String::new();
+        let mut i = start;
+        let mut terminated = false;
+        while i < self.chars.len() {
+            let line_end = self.chars[i..].iter().position(|c| *c == '\n').map_or(self.chars.len(), |n| i + n);
+            let line: String = self.chars[i..line_end].iter().collect();
+            let line = if strip_tabs { line.trim_start_matches('\t').to_string() } else { line };
+            i = (line_end + 1).min(self.chars.len());
+            if line == delimiter {
+                terminated = true;
+                break;
+            }
+            body.push_str(&line);
+            body.push('\n');
+        }
+        if !terminated {
+            return Err(format!("here-doc '{}' is not terminated", delimiter));
+        }
+        self.heredoc_end = Some(i);
+
+        if quoted {
+            return Ok(Word::literal_word(&body));
+        }
+        let mut word = Word::default();
+        let mut sub = Parser::new(&body, self.depth + 1);
+        sub.lex_double_quoted(&mut word, None)?;
+        if word.parts.is_empty() {
+            word.parts.push(WordPart::Literal(String::new()));
+        }
+        Ok(word)
+    }
+
+    // ------------------------------------------------------------------------
+    // Grammar
+    // ------------------------------------------------------------------------
+
+    fn parse_program(&mut self) -> Result<Script, String> {
+        let script = self.parse_list(&[])?;
+        match self.next()? {
+            Token::Eof => Ok(script),
+            other => Err(format!("unexpected {}", describe(&other))),
+        }
+    }
+
+    fn peek_reserved(&mut self, words: &[&str]) -> Result<Option<&'static str>, String> {
+        const RESERVED: &[&str] = &[
+            "if", "then", "elif", "else", "fi", "while", "until", "for", "in", "do", "done",
+            "case", "esac", "function", "{", "}", "!",
+        ];
+        if let Token::Word(_, raw) = self.peek()? {
+            if let Some(word) = RESERVED.iter().find(|r| *r == raw) {
+                if words.contains(word) {
+                    return Ok(Some(word));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn expect_reserved(&mut self, word: &str) -> Result<(), String> {
+        if self.peek_reserved(&[word])?.is_none() {
+            let found = describe(self.peek()?);
+            return Err(format!("expected '{}', found {}", word, found));
+        }
+        self.next()?;
+        Ok(())
+    }
+
+    fn skip_newlines(&mut self) -> Result<(), String> {
+        while self.peek()? == &Token::Op("\n") {
+            self.next()?;
+        }
+        Ok(())
+    }
+
+    fn at_terminator(&mut self, terms: &[&str]) -> Result<bool, String> {
+        if self.peek_reserved(terms)?.is_some() {
+            return Ok(true);
+        }
+        Ok(match self.peek()? {
+            Token::Eof => true,
+            Token::Op(op) => terms.contains(op),
+            _ => false,
+        })
+    }
+
+    fn parse_list(&mut self, terms: &[&str]) -> Result<Script, String> {
+        let mut script = Script::default();
+        loop {
+            self.skip_newlines()?;
+            if self.at_terminator(terms)? {
+                return Ok(script);
+            }
+            let mut list = self.parse_and_or()?;
+            match self.peek()? {
+                Token::Op("&") => {
+                    list.background = true;
+                    self.next()?;
+                }
+                Token::Op(";") | Token::Op("\n") => {
+                    self.next()?;
+                }
+                _ => {
+                    script.lists.push(list);
+                    if !self.at_terminator(terms)? {
+                        let found = describe(self.peek()?);
+                        return Err(format!("unexpected {}", found));
+                    }
+                    return Ok(script);
+                }
+            }
+            script.lists.push(list);
+        }
+    }
+
+    fn parse_and_or(&mut self) -> Result<AndOr, String> {
+        let first = self.parse_pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let connector = match self.peek()? {
+                Token::Op("&&") => Connector::And,
+                Token::Op("||") => Connector::Or,
+                _ => break,
+            };
+            self.next()?;
+            self.skip_newlines()?;
+            rest.push((connector, self.parse_pipeline()?));
+        }
+        Ok(AndOr { first, rest, background: false })
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Pipeline, String> {
+        let mut pipeline = Pipeline::default();
+        if self.peek_reserved(&["!"])?.is_some() {
+            self.next()?;
+            pipeline.negated = true;
+        }
+        pipeline.commands.push(self.parse_command()?);
+        while matches!(self.peek()?, Token::Op("|") | Token::Op("|&")) {
+            self.next()?;
+            self.skip_newlines()?;
+            pipeline.commands.push(self.parse_command()?);
+        }
+        Ok(pipeline)
+    }
+
+    fn parse_command(&mut self) -> Result<Command, String> {
+        self.enter()?;
+        let command = self.parse_command_inner()?;
+        self.depth -= 1;
+        Ok(command)
+    }
+
+    fn parse_command_inner(&mut self) -> Result<Command, String> {
+        if let Token::Arith(_) = self.peek()? {
+            let Token::Arith(text) = self.next()? else { unreachable!() };
+            return Ok(Command::Arithmetic(text, self.parse_redirects()?));
+        }
+        if self.peek()? == &Token::Op("(") {
+            self.next()?;
+            let body = self.parse_list(&[")"])?;
+            self.expect_op(")")?;
+            return Ok(Command::Subshell(body, self.parse_redirects()?));
+        }
+
+        let keyword = self.peek_reserved(&["{", "if", "while", "until", "for", "case", "function"])?;
+        match keyword {
+            Some("{") => {
+                self.next()?;
+                let body = self.parse_list(&["}"])?;
+                self.expect_reserved("}")?;
+                Ok(Command::Group(body, self.parse_redirects()?))
+            }
+            Some("if") => {
+                self.next()?;
+                let mut branches = Vec::new();
+                let mut otherwise = None;
+                loop {
+                    let condition = self.parse_list(&["then"])?;
+                    self.expect_reserved("then")?;
+                    let body = self.parse_list(&["elif", "else", "fi"])?;
+                    branches.push((condition, body));
+                    match self.peek_reserved(&["elif", "else", "fi"])? {
+                        Some("elif") => {
+                            self.next()?;
+                        }
+                        Some("else") => {
+                            self.next()?;
+                            otherwise = Some(self.parse_list(&["fi"])?);
+                            self.expect_reserved("fi")?;
+                            break;
+                        }
+                        _ => {
+                            self.expect_reserved("fi")?;
+                            break;
+                        }
+                    }
+                }
+                Ok(Command::If { branches, otherwise, redirects: self.parse_redirects()? })
+            }
+            Some(kw @ ("while" | "until")) => {
+                self.next()?;
+                let condition = self.parse_list(&["do"])?;
+                self.expect_reserved("do")?;
+                let body = self.parse_list(&["done"])?;
+                self.expect_reserved("done")?;
+                Ok(Command::Loop { until: kw == "until", condition, body, redirects: self.parse_redirects()? })
+            }
+            Some("for") => {
+                self.next()?;
+                let (var, items) = match self.next()? {
+                    Token::Arith(text) => (text, None),
+                    Token::Word(_, raw) => {
+                        self.skip_newlines()?;
+                        let items = if self.peek_reserved(&["in"])?.is_some() {
+                            self.next()?;
+                            let mut items = Vec::new();
+                            while let Token::Word(..) = self.peek()? {
+                                let Token::Word(word, _) = self.next()? else { unreachable!() };
+                                items.push(word);
+                            }
+                            Some(items)
+                        } else {
+                            None
+                        };
+                        (raw, items)
+                    }
+                    other => return Err(format!("expected loop variable, found {}", describe(&other))),
+                };
+                if matches!(self.peek()?, Token::Op(";")) {
+                    self.next()?;
+                }
+                self.skip_newlines()?;
+                self.expect_reserved("do")?;
+                let body = self.parse_list(&["done"])?;
+                self.expect_reserved("done")?;
+                Ok(Command::For { var, items, body, redirects: self.parse_redirects()? })
+            }
+            Some("case") => {
+                self.next()?;
+                let subject = match self.next()? {
+                    Token::Word(word, _) => word,
+                    other => return Err(format!("expected case subject, found {}", describe(&other))),
+                };
+                self.skip_newlines()?;
+                self.expect_reserved("in")?;
+                let mut arms = Vec::new();
+                loop {
+                    self.skip_newlines()?;
+                    if self.peek_reserved(&["esac"])?.is_some() {
+                        break;
+                    }
+                    if self.peek()? == &Token::Op("(") {
+                        self.next()?;
+                    }
+                    let mut patterns = Vec::new();
+                    loop {
+                        match self.next()? {
+                            Token::Word(word, _) => patterns.push(word),
+                            other => return Err(format!("expected case pattern, found {}", describe(&other))),
+                        }
+                        if self.peek()? == &Token::Op("|") {
+                            self.next()?;
+                        } else {
+                            break;
+                        }
+                    }
+                    self.expect_op(")")?;
+                    let body = self.parse_list(&[";;", "esac"])?;
+                    arms.push((patterns, body));
+                    if self.peek()? == &Token::Op(";;") {
+                        self.next()?;
+                    }
+                }
+                self.expect_reserved("esac")?;
+                Ok(Command::Case { subject, arms, redirects: self.parse_redirects()? })
+            }
+            Some("function") => {
+                self.next()?;
+                let name = match self.next()? {
+                    Token::Word(_, raw) => raw,
+                    other => return Err(format!("expected function name, found {}", describe(&other))),
+                };
+                self.skip_fn_parens();
+                self.skip_newlines()?;
+                let body = self.parse_command()?;
+                Ok(Command::Function { name, body: Box::new(body) })
+            }
+            _ => self.parse_simple(),
+        }
+    }
+
+    fn expect_op(&mut self, op: &str) -> Result<(), String> {
+        match self.next()? {
+            Token::Op(found) if found == op => Ok(()),
+            other => Err(format!("expected '{}', found {}", op, describe(&other))),
+        }
+    }
+
+    /// `()` after a function name — checked on raw input, nothing is peeked
+    fn skip_fn_parens(&mut self) -> bool {
+        if self.peeked.is_some() {
+            return false;
+        }
+        let save = self.pos;
+        self.skip_blanks();
+        if self.at(0) == Some('(') {
+            self.pos += 1;
+            self.skip_blanks();
+            if self.at(0) == Some(')') {
+                self.pos += 1;
+                return true;
+            }
+        }
+        self.pos = save;
+        false
+    }
+
+    fn parse_redirects(&mut self) -> Result<Vec<Redirect>, String> {
+        let mut redirects = Vec::new();
+        while let Token::Redirect(..) = self.peek()? {
+            redirects.push(self.parse_redirect()?);
+        }
+        Ok(redirects)
+    }
+
+    fn parse_redirect(&mut self) -> Result<Redirect, String> {
+        let Token::Redirect(fd, op, strip_tabs) = self.next()? else {
+            return Err("expected redirection".to_string());
+        };
+        let (word, raw) = match self.next()? {
+            Token::Word(word, raw) => (word, raw),
+            other => return Err(format!("missing target after {}, found {}", op.as_str(), describe(&other))),
+        };
+        let target = if op == RedirectOp::HereDoc {
+            let delimiter = word.literal().unwrap_or_else(|| word.text());
+            let quoted = raw.contains(['\'', '"', '\\']);
+            self.read_heredoc(&delimiter, quoted, strip_tabs)?
+        } else {
+            word
+        };
+        Ok(Redirect { fd, op, target })
+    }
+
+    fn parse_simple(&mut self) -> Result<Command, String> {
+        let mut simple = SimpleCommand::default();
+        loop {
+            match self.peek()? {
+                Token::Redirect(..) => simple.redirects.push(self.parse_redirect()?),
+                Token::Word(..) => {
+                    let Token::Word(mut word, raw) = self.next()? else { unreachable!() };
+                    if simple.words.is_empty() {
+                        if let Some(name) = assignment_name(&raw) {
+                            if let Some(WordPart::Literal(first)) = word.parts.first_mut() {
+                                let prefix = first.find('=').map_or(0, |i| i + 1);
+                                first.replace_range(..prefix, "");
+                            }
+                            simple.assignments.push((name, word));
+                            continue;
+                        }
+                        if self.skip_fn_parens() {
+                            self.skip_newlines()?;
+                            let body = self.parse_command()?;
+                            return Ok(Command::Function { name: raw, body: Box::new(body) });
+                        }
+                    }
+                    let test_expr = raw == "[[" && simple.words.is_empty();
+                    simple.words.push(word);
+                    if test_expr {
+                        self.parse_test_expr(&mut simple)?;
+                    }
+                }
+                _ => break,
+            }
+        }
+        if simple.words.is_empty() && simple.assignments.is_empty() && simple.redirects.is_empty() {
+            let found = describe(self.peek()?);
+            return Err(format!("unexpected {}", found));
+        }
+        Ok(Command::Simple(simple))
+    }
+
+    /// [[ … ]] — < and > compare strings here, they do not redirect
+    fn parse_test_expr(&mut self, simple: &mut SimpleCommand) -> Result<(), String> {
+        loop {
+            match self.next()? {
+                Token::Word(word, raw) => {
+                    simple.words.push(word);
+                    if raw == "]]" {
+                        return Ok(());
+                    }
+                }
+                Token::Redirect(fd, op, _) => {
+                    let text = match fd {
+                        Some(fd) => format!("{}{}", fd, op.as_str()),
+                        None => op.as_str().to_string(),
+                    };
+                    simple.words.push(Word::literal_word(&text));
+                }
+                Token::Op(op) if op != "\n" => simple.words.push(Word::literal_word(op)),
+                other => return Err(format!("unterminated [[, found {}", describe(&other))),
+            }
+        }
+    }
+}
+
+/// NAME=… or NAME+=… with an unquoted, valid name
+fn assignment_name(raw: &str) -> Option<String> {
+    let eq = raw.find('=')?;
+    let name = raw[..eq].strip_suffix('+').unwrap_or(&raw[..eq]);
+    let mut chars = name.chars();
+    let first = chars.next()?;
+    if (first.is_ascii_alphabetic() || first == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        Some(name.to_string())
+    } else {
+        None
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(_, raw) => format!("'{}'", raw),
+        Token::Op("\n") => "newline".to_string(),
+        Token::Op(op) => format!("'{}'", op),
+        Token::Redirect(_, op, _) => format!("'{}'", op.as_str()),
+        Token::Arith(_) => "'(('".to_string(),
+        Token::Eof => "end of input".to_string(),
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simple_commands(source: &str) -> Vec<Vec<String>> {
+        let script = parse(source).unwrap_or_else(|e| panic!("{}: {}", source, e));
+        let mut out = Vec::new();
+        script.walk(&mut |cmd| {
+            if let Command::Simple(simple) = cmd {
+                out.push(simple.words.iter().map(|w| w.text()).collect());
+            }
+        });
+        out
+    }
+
+    fn redirects(source: &str) -> Vec<(String, String)> {
+        let script = parse(source).unwrap_or_else(|e| panic!("{}: {}", source, e));
+        let mut out = Vec::new();
+        script.walk(&mut |cmd| {
+            for r in cmd.redirects() {
+                out.push((r.display_op(), r.target.text()));
+            }
+        });
+        out
+    }
+
+    #[test]
+    fn quoting_and_escapes() {
+        assert_eq!(simple_commands(r#"echo 'a b' "c $HOME" d\ e $'f\x41\n'"#), vec![vec![
+            "echo".to_string(), "a b".to_string(), "c $HOME".to_string(),
+            "d e".to_string(), "fA\n".to_string(),
+        ]]);
+        let script = parse(r#"echo "a;b" x"#).unwrap();
+        assert_eq!(script.lists.len(), 1);
+        assert!(parse("echo 'open").is_err());
+        assert!(parse("echo \"open").is_err());
+        assert!(parse("echo $(ls").is_err());
+    }
+
+    #[test]
+    fn redirections() {
+        assert_eq!(redirects("cmd >a 2>/b 3>>c >|d <>e &>f &>>g 2>&1 >&h <in"), vec![
+            (">".to_string(), "a".to_string()),
+            ("2>".to_string(), "/b".to_string()),
+            ("3>>".to_string(), "c".to_string()),
+            (">|".to_string(), "d".to_string()),
+            ("<>".to_string(), "e".to_string()),
+            ("&>".to_string(), "f".to_string()),
+            ("&>>".to_string(), "g".to_string()),
+            ("2>&".to_string(), "1".to_string()),
+            (">&".to_string(), "h".to_string()),
+            ("<".to_string(), "in".to_string()),
+        ]);
+        let script = parse("cmd 2>&1 >&out").unwrap();
+        let Command::Simple(simple) = &script.lists[0].first.commands[0] else { panic!() };
+        assert!(!simple.redirects[0].writes_file());
+        assert!(simple.redirects[1].writes_file());
+        // [[ ]] comparisons are not redirections
+        assert!(redirects("[[ a > /b ]] && echo ok").is_empty());
+    }
+
+    #[test]
+    fn substitutions_nest() {
+        let cmds = simple_commands("echo $(cat `which x` <(ls /a)) ${v:-$(rm /b)} $((1 + $(id -u)))");
+        let names: Vec<&str> = cmds.iter().map(|c| c[0].as_str()).collect();
+        assert_eq!(names, vec!["echo", "cat", "which", "ls", "rm", "id"]);
+        let mut nested = String::from("x");
+        for _ in 0..100 {
+            nested = format!("$(echo {})", nested);
+        }
+        assert!(parse(&nested).unwrap_err().contains("too deep"));
+    }
+
+    #[test]
+    fn compound_commands() {
+        let source = "if [ -f a ]; then cp a b; elif true; then :; else rm c; fi\n\
+                      while read l; do echo \"$l\"; done < list\n\
+                      for f in *.txt; do mv \"$f\" d/; done\n\
+                      case $x in a|b) touch e ;; *) : ;; esac\n\
+                      f() { tee g; }\n\
+                      function h { (cd i && rm j) } >k\n\
+                      ! { echo l; } | sort &";
+        let names: Vec<String> = simple_commands(source).into_iter().map(|c| c[0].clone()).collect();
+        assert_eq!(names, vec![
+            "[", "cp", "true", ":", "rm", "read", "echo", "mv", "touch", ":", "tee", "cd", "rm", "echo", "sort",
+        ]);
+        assert_eq!(redirects(source), vec![
+            ("<".to_string(), "list".to_string()),
+            (">".to_string(), "k".to_string()),
+        ]);
+        assert!(parse("if true; then echo").is_err());
+        assert!(parse("echo )").is_err());
+    }
+
+    #[test]
+    fn assignments_and_heredocs() {
+        let script = parse("A=1 B=\"$(pwd)/x\" arr=(1 2) env").unwrap();
+        let Command::Simple(simple) = &script.lists[0].first.commands[0] else { panic!() };
+        let names: Vec<&str> = simple.assignments.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["A", "B", "arr"]);
+        assert_eq!(simple.assignments[0].1.literal().as_deref(), Some("1"));
+        assert!(simple.assignments[1].1.is_dynamic());
+
+        let source = "cat <<EOF >out; cat <<-'END'\nhello $(rm /x)\nEOF\n\tliteral $(y)\n\tEND\necho after";
+        let script = parse(source).unwrap();
+        assert_eq!(script.lists.len(), 3);
+        let names: Vec<String> = simple_commands(source).into_iter().map(|c| c[0].clone()).collect();
+        assert_eq!(names, vec!["cat", "rm", "cat", "echo"]);
+        let Command::Simple(second) = &script.lists[1].first.commands[0] else { panic!() };
+        assert_eq!(second.redirects[0].target.literal().as_deref(), Some("literal $(y)\n"));
+        assert!(parse("cat <<EOF\nno end").is_err());
+    }
+}
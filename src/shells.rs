@@ -0,0 +1,161 @@
+// SPF Smart Gateway - Shell Sessions
+// Copyright 2026 Joseph Stone - All Rights Reserved
+//
+// Named spf_bash sessions that keep the working directory and exported
+// variables between calls, so agents no longer chain `cd x && …` into every
+// command. Each call is still a fresh, limited and confined `bash -c`: the
+// session only decides where it starts and what it exports. An EXIT trap
+// prints the shell's final cwd and environment after a per-call marker on
+// stdout; the handler strips it off and keeps the difference from the base
+// environment. Sessions live in memory for the life of the server.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Named sessions per server
+pub const MAX_SESSIONS: usize = 16;
+
+/// Variables bash maintains itself, or that change what a later call's
+/// `cd` or startup does out of sight of the validator — never carried
+/// between calls
+const SHELL_MANAGED: &[&str] = &[
+    "PWD", "OLDPWD", "SHLVL", "_", "CDPATH", "BASH_ENV", "ENV", "BASHOPTS", "SHELLOPTS",
+];
+
+/// State carried from one call of a session to the next
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShellState {
+    pub cwd: String,
+    /// Variables exported or changed relative to the base environment
+    pub exports: BTreeMap<String, String>,
+    /// Base variables the session unset
+    pub unset: BTreeSet<String>,
+}
+
+impl ShellState {
+    pub fn new(cwd: &str) -> Self {
+        Self { cwd: cwd.to_string(), ..Default::default() }
+    }
+
+    /// Apply the session on top of the base environment
+    pub fn apply(&self, env: &mut BTreeMap<String, String>) {
+        for name in &self.unset {
+            env.remove(name);
+        }
+        env.extend(self.exports.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+
+    /// Take the final environment reported by the shell as the new state,
+    /// kept as its difference from `base`
+    pub fn record_env(&mut self, env: &BTreeMap<String, String>, base: &BTreeMap<String, String>) {
+        self.exports = env.iter()
+            .filter(|(k, v)| !SHELL_MANAGED.contains(&k.as_str()) && base.get(*k) != Some(*v))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        self.unset = base.keys()
+            .filter(|k| !env.contains_key(*k))
+            .cloned()
+            .collect();
+    }
+}
+
+/// Session names: 1-32 of [A-Za-z0-9_-]
+pub fn valid_name(name: &str) -> bool {
+    (1..=32).contains(&name.len())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Per-call marker. Not a secret — the command can read it (e.g. from
+/// $BASH_EXECUTION_STRING) and print a fake report, so the reported cwd is
+/// re-validated against allowed paths before it is kept and on every call.
+pub fn marker() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    format!("__SPF_SHELL_STATE_{:x}_{:x}__", std::process::id(), nanos)
+}
+
+/// The script bash runs: an EXIT trap reporting cwd and environment, then
+/// the command itself on the same line so error line numbers stay put
+pub fn script(command: &str, marker: &str) -> String {
+    format!(
+        "trap 'printf \"\\n{}\\n\"; pwd -P; printf \"\\0\"; env -0' EXIT; {}",
+        marker, command
+    )
+}
+
+/// Split the trap's report off stdout. Returns the final cwd and
+/// environment, or None if the shell never reached its EXIT trap
+/// (killed, exec'd, stdout redirected).
+pub fn take_report(stdout: &mut Vec<u8>, marker: &str) -> Option<(String, BTreeMap<String, String>)> {
+    let needle = format!("\n{}\n", marker);
+    let pos = stdout.windows(needle.len()).rposition(|w| w == needle.as_bytes())?;
+    let report = stdout.split_off(pos);
+    let report = &report[needle.len()..];
+
+    let (cwd, env) = report.split_at(report.iter().position(|&b| b == 0)?);
+    let cwd = String::from_utf8_lossy(cwd).trim_end_matches('\n').to_string();
+    let env = env[1..]
+        .split(|&b| b == 0)
+        .filter_map(|entry| {
+            let entry = std::str::from_utf8(entry).ok()?;
+            let (name, value) = entry.split_once('=')?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect();
+    Some((cwd, env))
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn session_keeps_cwd_and_exports_between_calls() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        let base: BTreeMap<String, String> = [
+            ("PATH".to_string(), std::env::var("PATH").unwrap_or_default()),
+            ("DROP_ME".to_string(), "1".to_string()),
+        ].into();
+        let mut state = ShellState::new(&dir.path().canonicalize().unwrap().to_string_lossy());
+
+        let call = |command: &str, state: &mut ShellState| -> String {
+            let mut env = base.clone();
+            state.apply(&mut env);
+            let marker = marker();
+            let output = Command::new("bash")
+                .arg("-c").arg(script(command, &marker))
+                .current_dir(&state.cwd)
+                .env_clear().envs(&env)
+                .output().unwrap();
+            let mut stdout = output.stdout;
+            if let Some((cwd, env)) = take_report(&mut stdout, &marker) {
+                state.cwd = cwd;
+                state.record_env(&env, &base);
+            }
+            String::from_utf8(stdout).unwrap()
+        };
+
+        assert_eq!(call("cd sub && export GREETING=hi; unset DROP_ME; printf out", &mut state), "out");
+        assert!(state.cwd.ends_with("/sub"));
+        assert_eq!(state.exports.get("GREETING").map(String::as_str), Some("hi"));
+        assert!(!state.exports.contains_key("PWD"));
+        assert!(state.unset.contains("DROP_ME"));
+
+        assert_eq!(call("echo \"$GREETING ${DROP_ME:-gone} $(basename \"$PWD\")\"", &mut state), "hi gone sub\n");
+        // exit still reports; a shell that never reaches its trap changes nothing
+        call("cd .. ; exit 3", &mut state);
+        assert!(!state.cwd.ends_with("/sub"));
+        let before = state.clone();
+        assert_eq!(call("exec echo replaced", &mut state), "replaced\n");
+        assert_eq!(state, before);
+
+        assert!(valid_name("build-1") && !valid_name("") && !valid_name("a b") && !valid_name(&"x".repeat(33)));
+    }
+}
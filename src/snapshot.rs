@@ -0,0 +1,462 @@
+// SPF Smart Gateway - Write Snapshots
+// Copyright 2026 Joseph Stone - All Rights Reserved
+//
+// History of everything the agent changed. Before spf_write, spf_edit or
+// spf_notebook_edit replaces a file, its prior content is copied into SPF_FS:
+//
+//   /snapshots/<session id>/<device path>/<n>         prior content
+//   /snapshots/<session id>/<device path>/<n>.absent  file did not exist
+//   /snapshots/<session id>.journal                   one JSON line per write
+//
+// n counts up per path within a session; FileMetadata.mode keeps the file's
+// permission bits. No snapshot, no write. The journal orders the writes and
+// holds a hash of what each one left on disk, so spf_undo and `rollback` can
+// restore snapshots newest first and tell when a file changed outside the
+// gateway since. Restoring snapshots the current content too, so an external
+// change overwritten by a rollback is not lost.
+//
+// The write itself is atomic: a temp file in the target's directory is
+// written, fsynced and renamed over the target (mode preserved), then the
+// directory is fsynced. A crash leaves either the old or the new file.
+
+use crate::fs::{sha256_hex, SpfFs};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// SPF_FS root of the snapshot tree
+pub const SNAPSHOT_ROOT: &str = "/snapshots";
+
+/// Suffix of a snapshot recording that the file did not exist
+const ABSENT: &str = ".absent";
+
+static TEMP_SEQ: AtomicU64 = AtomicU64::new(1);
+
+/// One recorded prior state of a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// SPF_FS path of the snapshot entry
+    pub path: String,
+    pub version: u64,
+    /// False when the file did not exist before the write
+    pub existed: bool,
+    pub size: u64,
+}
+
+/// Absolute device path used as the snapshot key — symlinks resolved
+pub fn device_key(path: &Path) -> PathBuf {
+    if let Ok(real) = fs::canonicalize(path) {
+        return real;
+    }
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().unwrap_or_default().join(path)
+    };
+    // Not on disk yet: resolve the parent, keep the name
+    match (absolute.parent().and_then(|p| fs::canonicalize(p).ok()), absolute.file_name()) {
+        (Some(parent), Some(name)) => parent.join(name),
+        _ => absolute,
+    }
+}
+
+/// SPF_FS directory holding a file's snapshots for one session
+pub fn snapshot_dir(session_id: &str, device_path: &Path) -> String {
+    format!("{}/{}{}", SNAPSHOT_ROOT, session_id, device_key(device_path).display())
+}
+
+/// Snapshots of a file in this session, oldest first
+pub fn versions(spf_fs: &SpfFs, session_id: &str, device_path: &Path) -> Result<Vec<Snapshot>> {
+    let dir = snapshot_dir(session_id, device_path);
+    if !spf_fs.exists(&dir)? {
+        return Ok(Vec::new());
+    }
+    let mut found: Vec<Snapshot> = spf_fs.ls(&dir)?
+        .into_iter()
+        .filter_map(|(name, meta)| {
+            let (number, existed) = match name.strip_suffix(ABSENT) {
+                Some(number) => (number, false),
+                None => (name.as_str(), true),
+            };
+            Some(Snapshot {
+                path: format!("{}/{}", dir, name),
+                version: number.parse().ok()?,
+                existed,
+                size: meta.size,
+            })
+        })
+        .collect();
+    found.sort_by_key(|s| s.version);
+    Ok(found)
+}
+
+/// Copy the file's current content (or its absence) into the session's
+/// snapshot tree as the next version
+pub fn take(spf_fs: &SpfFs, session_id: &str, device_path: &Path) -> Result<Snapshot> {
+    let dir = snapshot_dir(session_id, device_path);
+    let version = versions(spf_fs, session_id, device_path)?
+        .last()
+        .map_or(1, |s| s.version + 1);
+
+    let snapshot = match fs::read(device_path) {
+        Ok(data) => {
+            let path = format!("{}/{}", dir, version);
+            spf_fs.write(&path, &data)?;
+            spf_fs.set_mode(&path, file_mode(device_path).unwrap_or(0o644))?;
+            Snapshot { path, version, existed: true, size: data.len() as u64 }
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let path = format!("{}/{}{}", dir, version, ABSENT);
+            spf_fs.write(&path, b"")?;
+            Snapshot { path, version, existed: false, size: 0 }
+        }
+        Err(e) => return Err(e).with_context(|| format!("snapshot read {}", device_path.display())),
+    };
+    Ok(snapshot)
+}
+
+/// One agent write, in the order the session made them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Write number within the session, from 1
+    pub seq: u64,
+    pub tool: String,
+    /// Device path as keyed in the snapshot tree
+    pub path: String,
+    /// Snapshot holding the content before this write
+    pub version: u64,
+    /// SHA256 of the content this write left on disk
+    pub after: String,
+    pub at: DateTime<Utc>,
+    /// Undone by spf_undo or rollback
+    #[serde(default)]
+    pub reverted: bool,
+}
+
+/// Outcome of reverting one journal entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reverted {
+    pub seq: u64,
+    pub path: String,
+    /// False when the file did not exist before the write and was removed
+    pub restored: bool,
+    /// The file no longer held what the write left — changed outside the gateway
+    pub changed: bool,
+    /// Why the entry was not reverted
+    pub error: Option<String>,
+}
+
+impl std::fmt::Display for Reverted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let action = if self.restored { "restored" } else { "removed (did not exist before)" };
+        match &self.error {
+            Some(e) => write!(f, "#{} {} NOT REVERTED: {}", self.seq, self.path, e),
+            None if self.changed => write!(
+                f, "#{} {} {} — WARNING: changed outside the gateway since the write (that content is snapshotted)",
+                self.seq, self.path, action
+            ),
+            None => write!(f, "#{} {} {}", self.seq, self.path, action),
+        }
+    }
+}
+
+fn journal_path(session_id: &str) -> String {
+    format!("{}/{}.journal", SNAPSHOT_ROOT, session_id)
+}
+
+/// A session's writes, oldest first
+pub fn journal(spf_fs: &SpfFs, session_id: &str) -> Result<Vec<JournalEntry>> {
+    let path = journal_path(session_id);
+    if !spf_fs.exists(&path)? {
+        return Ok(Vec::new());
+    }
+    spf_fs.read(&path)?
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).context("corrupt snapshot journal"))
+        .collect()
+}
+
+fn save_journal(spf_fs: &SpfFs, session_id: &str, entries: &[JournalEntry]) -> Result<()> {
+    let mut data = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut data, entry)?;
+        data.push(b'\n');
+    }
+    spf_fs.write(&journal_path(session_id), &data)
+}
+
+/// Append a completed write to the session's journal; returns its number
+pub fn record(spf_fs: &SpfFs, session_id: &str, tool: &str, snap: &Snapshot, device_path: &Path, written: &[u8]) -> Result<u64> {
+    let mut entries = journal(spf_fs, session_id)?;
+    let seq = entries.last().map_or(1, |e| e.seq + 1);
+    entries.push(JournalEntry {
+        seq,
+        tool: tool.to_string(),
+        path: device_key(device_path).to_string_lossy().to_string(),
+        version: snap.version,
+        after: sha256_hex(written),
+        at: Utc::now(),
+        reverted: false,
+    });
+    save_journal(spf_fs, session_id, &entries)?;
+    Ok(seq)
+}
+
+/// Numbers of the last `count` writes not yet reverted, newest first
+pub fn plan_undo(entries: &[JournalEntry], count: usize) -> Vec<u64> {
+    entries.iter().rev().filter(|e| !e.reverted).take(count).map(|e| e.seq).collect()
+}
+
+/// Numbers of the writes after `to` (all without it) not yet reverted, newest first
+pub fn plan_rollback(entries: &[JournalEntry], to: Option<u64>) -> Vec<u64> {
+    entries.iter().rev()
+        .filter(|e| !e.reverted && e.seq > to.unwrap_or(0))
+        .map(|e| e.seq)
+        .collect()
+}
+
+/// Revert the given writes in order by restoring their pre-write snapshots.
+/// `check` may refuse a path. With `dry_run` only the outcome is computed.
+pub fn revert(
+    spf_fs: &SpfFs,
+    session_id: &str,
+    seqs: &[u64],
+    check: impl Fn(&Path, &Snapshot) -> std::result::Result<(), String>,
+    dry_run: bool,
+) -> Result<Vec<Reverted>> {
+    let mut entries = journal(spf_fs, session_id)?;
+    let mut outcomes = Vec::new();
+    // Dry run: what each path would hold after the reverts so far
+    let mut planned: BTreeMap<String, Option<String>> = BTreeMap::new();
+    for &seq in seqs {
+        let index = entries.iter().position(|e| e.seq == seq)
+            .ok_or_else(|| anyhow!("no write #{} in session {}", seq, session_id))?;
+        let entry = entries[index].clone();
+        let path = Path::new(&entry.path);
+        let current = match planned.get(&entry.path) {
+            Some(hash) => hash.clone(),
+            None => match fs::read(path) {
+                Ok(data) => Some(sha256_hex(&data)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e).with_context(|| format!("read {}", entry.path)),
+            },
+        };
+        let snap = versions(spf_fs, session_id, path)?
+            .into_iter()
+            .find(|s| s.version == entry.version);
+        let mut outcome = Reverted {
+            seq,
+            path: entry.path.clone(),
+            restored: snap.as_ref().is_none_or(|s| s.existed),
+            changed: current.as_ref() != Some(&entry.after),
+            error: None,
+        };
+        outcome.error = match snap {
+            None => Some(format!("snapshot v{} missing", entry.version)),
+            Some(snap) => match check(path, &snap) {
+                Err(e) => Some(e),
+                Ok(()) if dry_run => {
+                    let hash = if snap.existed { Some(sha256_hex(&spf_fs.read(&snap.path)?)) } else { None };
+                    planned.insert(entry.path.clone(), hash);
+                    None
+                }
+                Ok(()) => restore(spf_fs, session_id, path, &snap).err().map(|e| format!("{:#}", e)),
+            },
+        };
+        if outcome.error.is_none() && !dry_run {
+            entries[index].reverted = true;
+            save_journal(spf_fs, session_id, &entries)?;
+        }
+        outcomes.push(outcome);
+    }
+    Ok(outcomes)
+}
+
+/// Put a snapshot back on disk, snapshotting what is there now first
+pub fn restore(spf_fs: &SpfFs, session_id: &str, path: &Path, snap: &Snapshot) -> Result<()> {
+    take(spf_fs, session_id, path)?;
+    if !snap.existed {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        };
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomic(path, &spf_fs.read(&snap.path)?)?;
+    set_file_mode(path, spf_fs.stat(&snap.path)?.map(|m| m.mode))?;
+    Ok(())
+}
+
+/// Replace `path` with `data` atomically: temp file in the same directory,
+/// fsync, rename over the target (mode preserved), fsync the directory.
+/// A symlinked target is written through; hard links are not preserved.
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let name = target.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no file name"))?
+        .to_string_lossy();
+    let temp = dir.join(format!(
+        ".{}.spf-tmp-{}-{}", name, std::process::id(), TEMP_SEQ.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = (|| {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&temp)?;
+        file.write_all(data)?;
+        if let Ok(meta) = fs::metadata(&target) {
+            file.set_permissions(meta.permissions())?;
+        }
+        file.sync_all()?;
+        fs::rename(&temp, &target)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result?;
+    // Persist the rename itself; not every platform can fsync a directory
+    if let Ok(dir) = File::open(&dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn file_mode(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).ok().map(|m| m.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_path: &Path) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn set_file_mode(path: &Path, mode: Option<u32>) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    match mode {
+        Some(mode) => fs::set_permissions(path, fs::Permissions::from_mode(mode)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn set_file_mode(_path: &Path, _mode: Option<u32>) -> io::Result<()> {
+    Ok(())
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_record_prior_content_and_absence() -> Result<()> {
+        let store = tempfile::tempdir()?;
+        let spf_fs = SpfFs::open(store.path())?;
+        let work = tempfile::tempdir()?;
+        let file = work.path().join("notes.txt");
+
+        let first = take(&spf_fs, "s1", &file)?;
+        assert!(!first.existed);
+        assert_eq!(first.version, 1);
+        assert!(first.path.ends_with("/notes.txt/1.absent"));
+        write_atomic(&file, b"one")?;
+
+        let second = take(&spf_fs, "s1", &file)?;
+        assert_eq!((second.version, second.existed, second.size), (2, true, 3));
+        assert_eq!(spf_fs.read(&second.path)?, b"one");
+        write_atomic(&file, b"two")?;
+
+        assert_eq!(versions(&spf_fs, "s1", &file)?.len(), 2);
+        assert!(versions(&spf_fs, "s2", &file)?.is_empty());
+        assert_eq!(fs::read(&file)?, b"two");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn atomic_write_preserves_mode_and_symlinks() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        let work = tempfile::tempdir()?;
+        let script = work.path().join("run.sh");
+        fs::write(&script, "old")?;
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o750))?;
+        let link = work.path().join("link.sh");
+        std::os::unix::fs::symlink(&script, &link)?;
+
+        write_atomic(&link, b"new")?;
+        assert_eq!(fs::read_to_string(&script)?, "new");
+        assert!(fs::symlink_metadata(&link)?.file_type().is_symlink());
+        assert_eq!(file_mode(&script), Some(0o750));
+        // No temp files left behind
+        assert_eq!(fs::read_dir(work.path())?.count(), 2);
+
+        let store = tempfile::tempdir()?;
+        let spf_fs = SpfFs::open(store.path())?;
+        let snap = take(&spf_fs, "s1", &link)?;
+        assert!(snap.path.contains(&*script.canonicalize()?.to_string_lossy()));
+        assert_eq!(spf_fs.stat(&snap.path)?.unwrap().mode, 0o750);
+        Ok(())
+    }
+
+    #[test]
+    fn revert_restores_newest_first_and_flags_outside_changes() -> Result<()> {
+        let store = tempfile::tempdir()?;
+        let spf_fs = SpfFs::open(store.path())?;
+        let work = tempfile::tempdir()?;
+        let a = work.path().join("a.txt");
+        let b = work.path().join("b.txt");
+        fs::write(&a, "a0")?;
+        let write = |path: &Path, data: &str| -> Result<u64> {
+            let snap = take(&spf_fs, "s1", path)?;
+            write_atomic(path, data.as_bytes())?;
+            record(&spf_fs, "s1", "spf_write", &snap, path, data.as_bytes())
+        };
+        write(&a, "a1")?;
+        write(&b, "b1")?;
+        write(&a, "a2")?;
+        let entries = journal(&spf_fs, "s1")?;
+        assert_eq!(entries.iter().map(|e| e.seq).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(plan_undo(&entries, 2), [3, 2]);
+        assert_eq!(plan_rollback(&entries, Some(1)), [3, 2]);
+
+        // Preview touches nothing and follows the planned state per path
+        let allow = |_: &Path, _: &Snapshot| Ok(());
+        let preview = revert(&spf_fs, "s1", &plan_rollback(&entries, None), allow, true)?;
+        assert!(preview.iter().all(|r| r.error.is_none() && !r.changed));
+        assert_eq!(fs::read_to_string(&a)?, "a2");
+
+        // An edit behind the gateway's back is flagged, reverted anyway and kept
+        fs::write(&a, "outside")?;
+        let undone = revert(&spf_fs, "s1", &plan_undo(&entries, 2), allow, false)?;
+        assert!(undone[0].changed && undone[0].restored);
+        assert!(!undone[1].restored && !undone[1].changed);
+        assert_eq!(fs::read_to_string(&a)?, "a1");
+        assert!(!b.exists());
+        assert!(versions(&spf_fs, "s1", &a)?.iter().any(|s| spf_fs.read(&s.path).ok().as_deref() == Some(&b"outside"[..])));
+
+        let entries = journal(&spf_fs, "s1")?;
+        assert_eq!(plan_rollback(&entries, None), [1]);
+        let refused = revert(&spf_fs, "s1", &[1], |_, _| Err("blocked".to_string()), false)?;
+        assert_eq!(refused[0].error.as_deref(), Some("blocked"));
+        revert(&spf_fs, "s1", &[1], allow, false)?;
+        assert_eq!(fs::read_to_string(&a)?, "a0");
+        assert!(plan_undo(&journal(&spf_fs, "s1")?, 5).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+// SPF Smart Gateway - Content Sniffing
+// Copyright 2026 Joseph Stone - All Rights Reserved
+//
+// What spf_read is looking at, from the first SNIFF_BYTES of a file:
+// images the MCP client can render (PNG, JPEG, GIF, WebP) come back as
+// base64 `image` blocks up to MAX_IMAGE_BYTES; text is UTF-8, UTF-16 (BOM or
+// NUL pattern) or Latin-1 and is decoded and labelled; anything else is
+// binary and gets a hexdump with size and SHA256 instead of mojibake.
+
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Bytes inspected to classify a file
+pub const SNIFF_BYTES: usize = 8192;
+
+/// Largest image returned inline (before base64)
+pub const MAX_IMAGE_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Bytes of a binary file shown per hexdump
+pub const HEXDUMP_BYTES: usize = 512;
+
+/// Text encodings spf_read decodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    pub fn label(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Latin1 => "Latin-1",
+        }
+    }
+}
+
+/// What a file holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Text(Encoding),
+    Image(&'static str),
+    Binary,
+}
+
+/// Classify a file from its first bytes
+pub fn sniff(head: &[u8]) -> Kind {
+    const IMAGES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+    ];
+    if let Some((_, mime)) = IMAGES.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return Kind::Image(mime);
+    }
+    if head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+        return Kind::Image("image/webp");
+    }
+
+    if head.starts_with(b"\xef\xbb\xbf") {
+        return Kind::Text(Encoding::Utf8);
+    }
+    if head.starts_with(b"\xff\xfe") {
+        return Kind::Text(Encoding::Utf16Le);
+    }
+    if head.starts_with(b"\xfe\xff") {
+        return Kind::Text(Encoding::Utf16Be);
+    }
+    if let Some(encoding) = bomless_utf16(head) {
+        return Kind::Text(encoding);
+    }
+    if head.contains(&0) {
+        return Kind::Binary;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => return Kind::Text(Encoding::Utf8),
+        // Cut mid-character at the end of the sample
+        Err(e) if e.error_len().is_none() => return Kind::Text(Encoding::Utf8),
+        Err(_) => {}
+    }
+    // Latin-1 text: control bytes other than whitespace are rare
+    let controls = head.iter()
+        .filter(|&&b| (b < 0x20 && !b"\t\n\r\x0c".contains(&b)) || (0x7f..0xa0).contains(&b))
+        .count();
+    if controls * 100 <= head.len() {
+        Kind::Text(Encoding::Latin1)
+    } else {
+        Kind::Binary
+    }
+}
+
+/// ASCII-range text stored as UTF-16 without a BOM: one half of every
+/// code unit is NUL, the other mostly not
+fn bomless_utf16(head: &[u8]) -> Option<Encoding> {
+    let units = head.len() / 2;
+    if units < 2 {
+        return None;
+    }
+    let zeros = |parity: usize| head.iter().skip(parity).step_by(2).take(units).filter(|&&b| b == 0).count();
+    let (even, odd) = (zeros(0), zeros(1));
+    match (even * 10 >= units * 9, odd * 10 >= units * 9) {
+        (false, true) if even * 10 <= units => Some(Encoding::Utf16Le),
+        (true, false) if odd * 10 <= units => Some(Encoding::Utf16Be),
+        _ => None,
+    }
+}
+
+/// Decode bytes to text, dropping a byte-order mark
+pub fn decode(data: &[u8], encoding: Encoding) -> String {
+    let mut utf8 = Vec::new();
+    // Reading from a slice cannot fail
+    let _ = Decoder::new(data, encoding).read_to_end(&mut utf8);
+    let text = String::from_utf8_lossy(&utf8);
+    text.strip_prefix('\u{feff}').unwrap_or(&text).to_string()
+}
+
+/// Bytes read from the source per decode step
+const DECODE_CHUNK: usize = 8192;
+
+/// Streams text in `encoding` as UTF-8, a chunk at a time, so a large file
+/// is paged without being loaded whole. UTF-8 passes through untouched; a
+/// byte-order mark comes out as U+FEFF (`output::page_lines` drops it);
+/// unpaired surrogates become U+FFFD and a trailing odd byte is dropped.
+pub struct Decoder<R> {
+    inner: R,
+    encoding: Encoding,
+    /// Source bytes not yet decoded (half a code unit or a high surrogate)
+    pending: Vec<u8>,
+    out: Vec<u8>,
+    pos: usize,
+    eof: bool,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(inner: R, encoding: Encoding) -> Self {
+        Self { inner, encoding, pending: Vec::new(), out: Vec::new(), pos: 0, eof: false }
+    }
+
+    /// Decode the next chunk into `out`
+    fn fill(&mut self) -> io::Result<()> {
+        let mut chunk = [0u8; DECODE_CHUNK];
+        let n = self.inner.read(&mut chunk)?;
+        self.eof = n == 0;
+        self.pending.extend_from_slice(&chunk[..n]);
+        self.pos = 0;
+        self.out = match self.encoding {
+            Encoding::Utf8 => std::mem::take(&mut self.pending),
+            Encoding::Latin1 => self.pending.drain(..).map(|b| b as char).collect::<String>().into_bytes(),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let unit = |pair: &[u8]| match self.encoding {
+                    Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                };
+                let mut whole = self.pending.len() / 2 * 2;
+                // A high surrogate may pair with the first unit of the next chunk
+                if !self.eof && whole >= 2 && (0xd800..0xdc00).contains(&unit(&self.pending[whole - 2..whole])) {
+                    whole -= 2;
+                }
+                let text: String = char::decode_utf16(self.pending[..whole].chunks_exact(2).map(unit))
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect();
+                self.pending.drain(..if self.eof { self.pending.len() } else { whole });
+                text.into_bytes()
+            }
+        };
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.out.len() {
+            if self.eof {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let n = buf.len().min(self.out.len() - self.pos);
+        buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// First bytes of a file, up to SNIFF_BYTES
+pub fn head(path: &Path) -> io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    File::open(path)?.take(SNIFF_BYTES as u64).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// SHA256 of a file, streamed
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Base64 of a whole file, for an MCP image block
+pub fn base64_file(path: &Path) -> io::Result<String> {
+    Ok(base64::engine::general_purpose::STANDARD.encode(std::fs::read(path)?))
+}
+
+/// `xxd`-style dump of `data`, whose first byte sits at `offset` in the file
+pub fn hexdump(data: &[u8], offset: u64) -> String {
+    data.chunks(16)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let hex: Vec<String> = chunk.chunks(2)
+                .map(|pair| pair.iter().map(|b| format!("{:02x}", b)).collect())
+                .collect();
+            let ascii: String = chunk.iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                .collect();
+            format!("{:08x}: {:<40} {}", offset + row as u64 * 16, hex.join(" "), ascii)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `len` bytes of a file from `offset`
+pub fn read_range(path: &Path, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    use std::io::{Seek, SeekFrom};
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut data = Vec::with_capacity(len);
+    file.take(len as u64).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_images_text_encodings_and_binary() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Kind::Image("image/png"));
+        assert_eq!(sniff(b"RIFF\x10\0\0\0WEBPVP8 "), Kind::Image("image/webp"));
+        assert_eq!(sniff("héllo\n".as_bytes()), Kind::Text(Encoding::Utf8));
+        assert_eq!(sniff(&"héllo".as_bytes()[..2]), Kind::Text(Encoding::Utf8));
+        assert_eq!(sniff(b"caf\xe9 cr\xe8me\n"), Kind::Text(Encoding::Latin1));
+        assert_eq!(sniff(b"\x7fELF\x02\x01\x01\0\0\0"), Kind::Binary);
+        assert_eq!(sniff(b"\x01\x02\x03\x9c\x85\xfa"), Kind::Binary);
+
+        let le: Vec<u8> = "name=value\r\n".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!(sniff(&le), Kind::Text(Encoding::Utf16Le));
+        assert_eq!(decode(&le, Encoding::Utf16Le), "name=value\r\n");
+        let be: Vec<u8> = "\u{feff}ünï".encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(sniff(&be), Kind::Text(Encoding::Utf16Be));
+        assert_eq!(decode(&be, Encoding::Utf16Be), "ünï");
+        assert_eq!(decode(b"caf\xe9", Encoding::Latin1), "café");
+        assert_eq!(decode(b"\xef\xbb\xbfok", Encoding::Utf8), "ok");
+
+        // Streaming: a surrogate pair split across decode chunks survives
+        let text = format!("{}\u{1f600}end", "a".repeat(DECODE_CHUNK / 2 - 1));
+        let le: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!(decode(&le, Encoding::Utf16Le), text);
+        let latin1 = vec![0xe9; DECODE_CHUNK * 2 + 1];
+        assert_eq!(decode(&latin1, Encoding::Latin1), "é".repeat(DECODE_CHUNK * 2 + 1));
+
+        assert_eq!(
+            hexdump(b"\x7fELF abc\x00\x01\x02\x03\x04\x05\x06\x07\x08", 32),
+            "00000020: 7f45 4c46 2061 6263 0001 0203 0405 0607  .ELF abc........\n00000030: 08                                       ."
+        );
+    }
+}
@@ -2,9 +2,12 @@
 // Copyright 2026 Joseph Stone - All Rights Reserved
 //
 // Persists session state to LMDB at LIVE/SESSION/SESSION.DB.
-// Used for: session checkpoints, complexity history, manifest, failures.
+// Used for: session checkpoints, complexity history, manifest, failures,
+// approval queue, audit-mode shadow records.
 
+use crate::approval::{ApprovalRequest, ApprovalStatus};
 use crate::session::Session;
+use crate::shadow::ShadowRecord;
 use anyhow::Result;
 use heed::types::*;
 use heed::{Database, Env, EnvOpenOptions};
@@ -15,10 +18,16 @@ pub struct SpfStorage {
     env: Env,
     /// Main key-value store: string keys → JSON values
     db: Database<Str, Str>,
+    /// Approval queue: request id → JSON ApprovalRequest
+    approvals: Database<Str, Str>,
+    /// Audit-mode disagreements: zero-padded nanos timestamp → JSON ShadowRecord
+    shadow: Database<Str, Str>,
 }
 
 const SESSION_KEY: &str = "current_session";
 const MAX_DB_SIZE: usize = 50 * 1024 * 1024; // 50MB — plenty for state data
+/// Shadow records kept; older ones are dropped so audit runs cannot fill the map
+const MAX_SHADOW_RECORDS: usize = 5000;
 
 impl SpfStorage {
     /// Open or create LMDB at the given path
@@ -28,16 +37,18 @@ impl SpfStorage {
         let env = unsafe {
             EnvOpenOptions::new()
                 .map_size(MAX_DB_SIZE)
-                .max_dbs(4)
+                .max_dbs(8)
                 .open(path)?
         };
 
         let mut wtxn = env.write_txn()?;
         let db = env.create_database(&mut wtxn, Some("spf_state"))?;
+        let approvals = env.create_database(&mut wtxn, Some("approvals"))?;
+        let shadow = env.create_database(&mut wtxn, Some("shadow"))?;
         wtxn.commit()?;
 
         log::info!("SPF LMDB opened at {:?}", path);
-        Ok(Self { env, db })
+        Ok(Self { env, db, approvals, shadow })
     }
 
     /// Save session state to LMDB
@@ -83,6 +94,107 @@ impl SpfStorage {
         Ok(deleted)
     }
 
+    // ========================================================================
+    // APPROVAL QUEUE
+    // ========================================================================
+
+    /// Insert or update an approval request
+    pub fn put_approval(&self, req: &ApprovalRequest) -> Result<()> {
+        let json = serde_json::to_string(req)?;
+        let mut wtxn = self.env.write_txn()?;
+        self.approvals.put(&mut wtxn, &req.id, &json)?;
+        wtxn.commit()?;
+        Ok(())
+    }
+
+    /// Get an approval request by ID
+    pub fn get_approval(&self, id: &str) -> Result<Option<ApprovalRequest>> {
+        let rtxn = self.env.read_txn()?;
+        match self.approvals.get(&rtxn, id)? {
+            Some(json) => Ok(Some(serde_json::from_str(json)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Find the newest unconsumed request for a call fingerprint
+    pub fn find_approval(&self, fingerprint: &str) -> Result<Option<ApprovalRequest>> {
+        Ok(self.list_approvals()?
+            .into_iter()
+            .filter(|r| r.fingerprint == fingerprint && r.status != ApprovalStatus::Consumed)
+            .max_by_key(|r| r.created_at))
+    }
+
+    /// List all approval requests, oldest first
+    pub fn list_approvals(&self) -> Result<Vec<ApprovalRequest>> {
+        let rtxn = self.env.read_txn()?;
+        let mut requests = Vec::new();
+        for result in self.approvals.iter(&rtxn)? {
+            let (_, json) = result?;
+            requests.push(serde_json::from_str::<ApprovalRequest>(json)?);
+        }
+        requests.sort_by_key(|r| r.created_at);
+        Ok(requests)
+    }
+
+    /// Record a decision on a request. Returns None if the ID is unknown.
+    pub fn decide_approval(&self, id: &str, status: ApprovalStatus) -> Result<Option<ApprovalRequest>> {
+        let mut req = match self.get_approval(id)? {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+        req.status = status;
+        req.decided_at = Some(chrono::Utc::now());
+        self.put_approval(&req)?;
+        Ok(Some(req))
+    }
+
+    // ========================================================================
+    // SHADOW RECORDS (Audit mode)
+    // ========================================================================
+
+    /// Append a shadow disagreement record, keeping the newest MAX_SHADOW_RECORDS
+    pub fn record_shadow(&self, rec: &ShadowRecord) -> Result<()> {
+        self.record_shadow_capped(rec, MAX_SHADOW_RECORDS)
+    }
+
+    fn record_shadow_capped(&self, rec: &ShadowRecord, keep: usize) -> Result<()> {
+        let key = format!("{:020}", rec.timestamp.timestamp_nanos_opt().unwrap_or(0));
+        let json = serde_json::to_string(rec)?;
+        let mut wtxn = self.env.write_txn()?;
+        self.shadow.put(&mut wtxn, &key, &json)?;
+        // Keys sort by time, so the oldest come first
+        let excess = (self.shadow.len(&wtxn)? as usize).saturating_sub(keep);
+        let oldest = self.shadow.iter(&wtxn)?
+            .take(excess)
+            .map(|entry| entry.map(|(key, _)| key.to_string()))
+            .collect::<heed::Result<Vec<_>>>()?;
+        for key in &oldest {
+            self.shadow.delete(&mut wtxn, key)?;
+        }
+        wtxn.commit()?;
+        Ok(())
+    }
+
+    /// List shadow records, oldest first
+    pub fn list_shadow(&self) -> Result<Vec<ShadowRecord>> {
+        let rtxn = self.env.read_txn()?;
+        let mut records = Vec::new();
+        for result in self.shadow.iter(&rtxn)? {
+            let (_, json) = result?;
+            records.push(serde_json::from_str::<ShadowRecord>(json)?);
+        }
+        Ok(records)
+    }
+
+    /// Delete all shadow records. Returns how many were removed.
+    pub fn clear_shadow(&self) -> Result<u64> {
+        let mut wtxn = self.env.write_txn()?;
+        let count = self.shadow.len(&wtxn)?;
+        self.shadow.clear(&mut wtxn)?;
+        wtxn.commit()?;
+        Ok(count)
+    }
+
     /// Get storage size in bytes
     pub fn size_bytes(&self) -> Result<u64> {
         let rtxn = self.env.read_txn()?;
@@ -98,3 +210,30 @@ impl SpfStorage {
         Ok(stat.entries as u64)
     }
 }
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shadow::ShadowOutcome;
+    use tempfile::tempdir;
+
+    #[test]
+    fn shadow_records_keep_only_the_newest() -> Result<()> {
+        let dir = tempdir()?;
+        let storage = SpfStorage::open(dir.path())?;
+        let outcome = |allowed| ShadowOutcome { allowed, tier: "SIMPLE".to_string(), requires_approval: false };
+        for i in 0..7 {
+            let mut rec = ShadowRecord::compare("spf_write", Some(&format!("/f{}", i)), outcome(true), outcome(false), vec![])
+                .expect("outcomes differ");
+            rec.timestamp += chrono::Duration::seconds(i);
+            storage.record_shadow_capped(&rec, 3)?;
+        }
+        let paths: Vec<_> = storage.list_shadow()?.into_iter().filter_map(|r| r.path).collect();
+        assert_eq!(paths, ["/f4", "/f5", "/f6"]);
+        Ok(())
+    }
+}
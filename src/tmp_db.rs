@@ -14,14 +14,17 @@ use serde::{Deserialize, Serialize};
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::pathmatch;
+
 const MAX_DB_SIZE: usize = 50 * 1024 * 1024; // 50MB
 
 /// Project trust level
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum TrustLevel {
     /// Untrusted - maximum restrictions
     Untrusted = 0,
     /// Low trust - basic operations only
+    #[default]
     Low = 1,
     /// Medium trust - most operations allowed with prompts
     Medium = 2,
@@ -31,9 +34,75 @@ pub enum TrustLevel {
     Full = 4,
 }
 
-impl Default for TrustLevel {
-    fn default() -> Self {
-        TrustLevel::Low
+impl std::str::FromStr for TrustLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "untrusted" => Ok(Self::Untrusted),
+            "low" => Ok(Self::Low),
+            "medium" => Ok(Self::Medium),
+            "high" => Ok(Self::High),
+            "full" => Ok(Self::Full),
+            _ => Err(format!("unknown trust level '{}' (untrusted, low, medium, high, full)", s)),
+        }
+    }
+}
+
+impl TrustLevel {
+    pub const ALL: [TrustLevel; 5] = [Self::Untrusted, Self::Low, Self::Medium, Self::High, Self::Full];
+}
+
+/// Resource limits for spf_bash children at one trust level — 0 = unlimited
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ExecLimits {
+    /// Wall-clock seconds; caps the per-call `timeout` argument
+    pub timeout_secs: u64,
+    /// RLIMIT_CPU seconds
+    pub cpu_secs: u64,
+    /// RLIMIT_AS in MiB
+    pub address_space_mb: u64,
+    /// RLIMIT_NPROC — counted per user (per user namespace), not per command
+    pub max_processes: u64,
+    /// RLIMIT_FSIZE in MiB
+    pub max_file_size_mb: u64,
+    /// RLIMIT_NOFILE
+    pub max_open_files: u64,
+    /// Run in an empty network namespace (loopback only)
+    pub no_network: bool,
+}
+
+impl ExecLimits {
+    /// Compiled defaults, used until a level is set in TMP_DB
+    pub fn defaults(level: TrustLevel) -> Self {
+        let (timeout_secs, cpu_secs, address_space_mb, max_processes, max_file_size_mb, max_open_files) = match level {
+            TrustLevel::Untrusted => (30, 10, 512, 64, 16, 64),
+            TrustLevel::Low => (60, 30, 1024, 128, 64, 256),
+            TrustLevel::Medium => (120, 60, 2048, 256, 256, 512),
+            TrustLevel::High => (300, 120, 4096, 512, 1024, 1024),
+            TrustLevel::Full => (300, 0, 0, 0, 0, 0),
+        };
+        Self {
+            timeout_secs,
+            cpu_secs,
+            address_space_mb,
+            max_processes,
+            max_file_size_mb,
+            max_open_files,
+            no_network: level == TrustLevel::Untrusted,
+        }
+    }
+
+    /// Human-readable one-liner for status output
+    pub fn summary(&self) -> String {
+        let show = |v: u64, unit: &str| if v == 0 { "unlimited".to_string() } else { format!("{}{}", v, unit) };
+        format!(
+            "timeout={} cpu={} as={} nproc={} fsize={} nofile={} network={}",
+            show(self.timeout_secs, "s"), show(self.cpu_secs, "s"),
+            show(self.address_space_mb, "M"), show(self.max_processes, ""),
+            show(self.max_file_size_mb, "M"), show(self.max_open_files, ""),
+            if self.no_network { "off" } else { "on" },
+        )
     }
 }
 
@@ -50,7 +119,8 @@ pub struct Project {
     pub allowed_tools: Vec<String>,
     /// Tools explicitly denied for this project
     pub denied_tools: Vec<String>,
-    /// Paths within project that are write-protected
+    /// Paths within project that are write-protected — prefixes, globs
+    /// (e.g. "**/*.pem") or `re:` regexes, relative to the project root
     pub protected_paths: Vec<String>,
     /// Maximum file size for writes (bytes)
     pub max_write_size: usize,
@@ -125,6 +195,10 @@ pub struct SpfTmpDb {
     resources: Database<Str, SerdeBincode<ResourceUsage>>,
     /// Active project marker: "active" → project_path
     active: Database<Str, Str>,
+    /// spf_bash resource limits: trust level name → ExecLimits
+    exec_limits: Database<Str, SerdeBincode<ExecLimits>>,
+    /// Per-project environment additions: project_path → ["NAME" | "NAME=value"]
+    project_env: Database<Str, SerdeBincode<Vec<String>>>,
 }
 
 impl SpfTmpDb {
@@ -144,10 +218,12 @@ impl SpfTmpDb {
         let access_log = env.create_database(&mut wtxn, Some("access_log"))?;
         let resources = env.create_database(&mut wtxn, Some("resources"))?;
         let active = env.create_database(&mut wtxn, Some("active"))?;
+        let exec_limits = env.create_database(&mut wtxn, Some("exec_limits"))?;
+        let project_env = env.create_database(&mut wtxn, Some("project_env"))?;
         wtxn.commit()?;
 
         log::info!("TMP_DB LMDB opened at {:?}", path);
-        Ok(Self { env, projects, access_log, resources, active })
+        Ok(Self { env, projects, access_log, resources, active, exec_limits, project_env })
     }
 
     // ========================================================================
@@ -226,7 +302,7 @@ impl SpfTmpDb {
 
         for result in iter {
             let (project_path, project) = result?;
-            if canonical.starts_with(project_path) && project_path.len() > best_len {
+            if pathmatch::any_match(&[project_path.to_string()], &canonical) && project_path.len() > best_len {
                 best_match = Some(project);
                 best_len = project_path.len();
             }
@@ -257,6 +333,7 @@ impl SpfTmpDb {
         let mut wtxn = self.env.write_txn()?;
         let deleted = self.projects.delete(&mut wtxn, &canonical)?;
         self.resources.delete(&mut wtxn, &canonical)?;
+        self.project_env.delete(&mut wtxn, &canonical)?;
         wtxn.commit()?;
         Ok(deleted)
     }
@@ -307,17 +384,13 @@ impl SpfTmpDb {
             None => return Ok(false),
         };
 
-        // Get relative path
+        // Get relative path — protected patterns are relative to the project root
         let relative = file_path.strip_prefix(&project.path)
+            .filter(|rest| rest.is_empty() || rest.starts_with('/'))
             .unwrap_or(file_path)
             .trim_start_matches('/');
 
-        for protected in &project.protected_paths {
-            if relative.starts_with(protected) || relative == *protected {
-                return Ok(true);
-            }
-        }
-        Ok(false)
+        Ok(pathmatch::any_match(&project.protected_paths, relative))
     }
 
     /// Add a protected path to a project
@@ -391,6 +464,7 @@ impl SpfTmpDb {
     // ========================================================================
 
     /// Log a file access
+    #[allow(clippy::too_many_arguments)]
     pub fn log_access(
         &self,
         file_path: &str,
@@ -539,6 +613,78 @@ impl SpfTmpDb {
         Ok(())
     }
 
+    // ========================================================================
+    // EXEC LIMITS
+    // ========================================================================
+
+    /// spf_bash limits for a trust level (compiled defaults if never set)
+    pub fn get_exec_limits(&self, level: TrustLevel) -> Result<ExecLimits> {
+        let rtxn = self.env.read_txn()?;
+        Ok(self.exec_limits.get(&rtxn, &format!("{:?}", level))?
+            .unwrap_or_else(|| ExecLimits::defaults(level)))
+    }
+
+    /// Store spf_bash limits for a trust level
+    pub fn set_exec_limits(&self, level: TrustLevel, limits: &ExecLimits) -> Result<()> {
+        let mut wtxn = self.env.write_txn()?;
+        self.exec_limits.put(&mut wtxn, &format!("{:?}", level), limits)?;
+        wtxn.commit()?;
+        Ok(())
+    }
+
+    /// Drop stored limits for a trust level, restoring the compiled defaults
+    pub fn reset_exec_limits(&self, level: TrustLevel) -> Result<bool> {
+        let mut wtxn = self.env.write_txn()?;
+        let deleted = self.exec_limits.delete(&mut wtxn, &format!("{:?}", level))?;
+        wtxn.commit()?;
+        Ok(deleted)
+    }
+
+    /// Limits for a bash call run from `cwd` — the containing project's trust
+    /// level, or the default trust level outside any registered project
+    pub fn exec_limits_for_path(&self, cwd: &str) -> Result<ExecLimits> {
+        let level = self.find_project_for_path(cwd)?
+            .map(|p| p.trust_level)
+            .unwrap_or_default();
+        self.get_exec_limits(level)
+    }
+
+    // ========================================================================
+    // PROJECT ENVIRONMENT
+    // ========================================================================
+
+    /// Environment additions for a registered project
+    pub fn get_project_env(&self, project_path: &str) -> Result<Vec<String>> {
+        let project = self.get_project(project_path)?
+            .ok_or_else(|| anyhow!("Project not found: {}", project_path))?;
+        let rtxn = self.env.read_txn()?;
+        Ok(self.project_env.get(&rtxn, &project.path)?.unwrap_or_default())
+    }
+
+    /// Replace a project's environment additions ("NAME" or "NAME=value")
+    pub fn set_project_env(&self, project_path: &str, entries: &[String]) -> Result<()> {
+        let project = self.get_project(project_path)?
+            .ok_or_else(|| anyhow!("Project not found: {}", project_path))?;
+        if let Some(bad) = entries.iter().find(|e| e.split('=').next().is_none_or(str::is_empty)) {
+            return Err(anyhow!("Invalid environment entry: {:?}", bad));
+        }
+        let mut wtxn = self.env.write_txn()?;
+        self.project_env.put(&mut wtxn, &project.path, &entries.to_vec())?;
+        wtxn.commit()?;
+        Ok(())
+    }
+
+    /// Environment additions for a command run from `cwd` (none outside a project)
+    pub fn project_env_for_path(&self, cwd: &str) -> Result<Vec<String>> {
+        match self.find_project_for_path(cwd)? {
+            Some(project) => {
+                let rtxn = self.env.read_txn()?;
+                Ok(self.project_env.get(&rtxn, &project.path)?.unwrap_or_default())
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
     // ========================================================================
     // VALIDATION
     // ========================================================================
@@ -598,6 +744,33 @@ impl SpfTmpDb {
         Ok((true, None))
     }
 
+    /// Check a tool call against the project containing `file_path`.
+    /// Combines the project's tool lists / trust level with validate_operation.
+    /// Returns the project (None = unregistered path) and a deny reason if refused.
+    pub fn check_tool_call(
+        &self,
+        file_path: &str,
+        tool: &str,
+        operation: &str,
+        size: u64,
+    ) -> Result<(Option<Project>, Option<String>)> {
+        let project = match self.find_project_for_path(file_path)? {
+            Some(p) => p,
+            None => return Ok((None, None)),
+        };
+
+        if !self.is_tool_allowed(&project.path, tool)? {
+            let reason = format!(
+                "{} not allowed in project '{}' (trust: {:?})",
+                tool, project.name, project.trust_level
+            );
+            return Ok((Some(project), Some(reason)));
+        }
+
+        let (_, reason) = self.validate_operation(file_path, operation, size)?;
+        Ok((Some(project), reason))
+    }
+
     /// Get database stats
     pub fn db_stats(&self) -> Result<(u64, u64, u64)> {
         let rtxn = self.env.read_txn()?;
@@ -607,3 +780,112 @@ impl SpfTmpDb {
         Ok((projects_stat.entries as u64, access_stat.entries as u64, resources_stat.entries as u64))
     }
 }
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn check_tool_call_applies_project_rules() -> Result<()> {
+        let db_dir = tempdir()?;
+        let project_dir = tempdir()?;
+        let db = SpfTmpDb::open(db_dir.path())?;
+        let root = project_dir.path().to_string_lossy().to_string();
+        let file = format!("{}/main.rs", root);
+
+        // Unregistered path — no project, no restriction
+        let (project, reason) = db.check_tool_call(&file, "Write", "write", 10)?;
+        assert!(project.is_none() && reason.is_none());
+
+        // Low trust — Read only, Write refused with project name in reason
+        db.register_project(&root, "demo", TrustLevel::Low)?;
+        db.set_active(&root)?;
+        let (_, reason) = db.check_tool_call(&file, "Read", "read", 0)?;
+        assert!(reason.is_none(), "{:?}", reason);
+        let (project, reason) = db.check_tool_call(&file, "Write", "write", 10)?;
+        assert_eq!(project.unwrap().name, "demo");
+        assert!(reason.unwrap().contains("'demo'"));
+
+        // High trust — protected paths and session write limit still apply
+        db.set_trust_level(&root, TrustLevel::High)?;
+        let (_, reason) = db.check_tool_call(&format!("{}/.env", root), "Write", "write", 10)?;
+        assert!(reason.unwrap().contains("protected"));
+
+        let mut project = db.get_project(&root)?.unwrap();
+        project.max_writes_per_session = 1;
+        db.update_project(&project)?;
+        db.log_access(&file, &project.path, "write", "s1", 10, true, None)?;
+        let (_, reason) = db.check_tool_call(&file, "Write", "write", 10)?;
+        assert!(reason.unwrap().contains("Session write limit"));
+
+        db.reset_session_counters()?;
+        let (_, reason) = db.check_tool_call(&file, "Write", "write", 10)?;
+        assert!(reason.is_none(), "{:?}", reason);
+        Ok(())
+    }
+
+    #[test]
+    fn protected_paths_use_pathmatch() -> Result<()> {
+        let db_dir = tempdir()?;
+        let db = SpfTmpDb::open(db_dir.path())?;
+        db.register_project("/srv/app", "app", TrustLevel::High)?;
+        db.add_protected_path("/srv/app", "**/*.pem")?;
+
+        assert!(db.is_path_protected("/srv/app", "/srv/app/.git/config")?);
+        assert!(!db.is_path_protected("/srv/app", "/srv/app/.gitignore")?);
+        assert!(db.is_path_protected("/srv/app", "/srv/app/certs/server.pem")?);
+        assert!(!db.is_path_protected("/srv/app", "/srv/app/src/main.rs")?);
+
+        // Sibling directories sharing a name prefix are not inside the project
+        assert!(db.find_project_for_path("/srv/app2/main.rs")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn exec_limits_stored_per_trust_level() -> Result<()> {
+        let db_dir = tempdir()?;
+        let db = SpfTmpDb::open(db_dir.path())?;
+        db.register_project("/srv/app", "app", TrustLevel::Untrusted)?;
+
+        // Unset levels fall back to compiled defaults
+        let limits = db.exec_limits_for_path("/srv/app/src")?;
+        assert_eq!(limits, ExecLimits::defaults(TrustLevel::Untrusted));
+        assert!(limits.no_network);
+        assert_eq!(db.exec_limits_for_path("/elsewhere")?, ExecLimits::defaults(TrustLevel::Low));
+
+        let custom = ExecLimits { cpu_secs: 5, no_network: false, ..limits };
+        db.set_exec_limits(TrustLevel::Untrusted, &custom)?;
+        assert_eq!(db.exec_limits_for_path("/srv/app/src")?, custom);
+        assert_eq!(db.get_exec_limits(TrustLevel::Low)?, ExecLimits::defaults(TrustLevel::Low));
+
+        assert!(db.reset_exec_limits(TrustLevel::Untrusted)?);
+        assert_eq!(db.get_exec_limits(TrustLevel::Untrusted)?, limits);
+        assert_eq!("HIGH".parse::<TrustLevel>(), Ok(TrustLevel::High));
+        assert!("root".parse::<TrustLevel>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn project_env_follows_containing_project() -> Result<()> {
+        let db_dir = tempdir()?;
+        let db = SpfTmpDb::open(db_dir.path())?;
+        db.register_project("/srv/app", "app", TrustLevel::High)?;
+        assert!(db.set_project_env("/srv/other", &["A".to_string()]).is_err());
+        assert!(db.set_project_env("/srv/app", &["=x".to_string()]).is_err());
+
+        let entries = vec!["CARGO_HOME".to_string(), "RUST_LOG=debug".to_string()];
+        db.set_project_env("/srv/app", &entries)?;
+        assert_eq!(db.project_env_for_path("/srv/app/src")?, entries);
+        assert!(db.project_env_for_path("/srv/elsewhere")?.is_empty());
+
+        db.delete_project("/srv/app")?;
+        db.register_project("/srv/app", "app", TrustLevel::High)?;
+        assert!(db.get_project_env("/srv/app")?.is_empty());
+        Ok(())
+    }
+}
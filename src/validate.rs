@@ -9,8 +9,9 @@
 // - File size limits
 // - Git force operation warnings
 
-use crate::config::{EnforceMode, SpfConfig};
+use crate::config::{EnforceMode, SeverityBand, SpfConfig};
 use crate::session::Session;
+use crate::shell::{self, AndOr, Command, Connector, Pipeline, RedirectOp, Script, WordPart};
 use serde::{Deserialize, Serialize};
 
 // ============================================================================
@@ -55,20 +56,24 @@ fn resolve_path(file_path: &str) -> Option<String> {
     }
 }
 
-/// Check if a resolved path is in the write allowlist.
+/// The write allowlist roots — also the spf_bash sandbox write roots.
 /// Paths derived from spf_root() — compiled logic, portable across systems.
-fn is_write_allowed(file_path: &str) -> bool {
+pub fn write_roots() -> Vec<String> {
+    let root = crate::paths::spf_root().to_string_lossy();
+    vec![
+        format!("{}/LIVE/PROJECTS/PROJECTS/", root),
+        format!("{}/LIVE/TMP/TMP/", root),
+    ]
+}
+
+/// Check if a resolved path is in the write allowlist.
+pub fn is_write_allowed(file_path: &str) -> bool {
     let resolved = match resolve_path(file_path) {
         Some(p) => p,
         None => return false, // Unresolvable = blocked
     };
 
-    let root = crate::paths::spf_root().to_string_lossy();
-    let allowed = [
-        format!("{}/LIVE/PROJECTS/PROJECTS/", root),
-        format!("{}/LIVE/TMP/TMP/", root),
-    ];
-    allowed.iter().any(|a| resolved.starts_with(a.as_str()))
+    crate::pathmatch::any_match(&write_roots(), &resolved)
 }
 
 /// Validation result
@@ -77,11 +82,14 @@ pub struct ValidationResult {
     pub valid: bool,
     pub warnings: Vec<String>,
     pub errors: Vec<String>,
+    /// Resolved paths a bash command writes, for per-target project checks
+    #[serde(skip)]
+    pub write_targets: Vec<String>,
 }
 
 impl ValidationResult {
     pub fn ok() -> Self {
-        Self { valid: true, warnings: Vec::new(), errors: Vec::new() }
+        Self { valid: true, warnings: Vec::new(), errors: Vec::new(), write_targets: Vec::new() }
     }
 
     pub fn warn(&mut self, msg: String) {
@@ -119,18 +127,11 @@ pub fn validate_edit(
             file_path.to_string()
         }
     };
-    if config.require_read_before_edit && !session.files_read.contains(&canonical_path) {
-        match config.enforce_mode {
-            EnforceMode::Max => {
-                result.warn(format!(
-                    "MAX TIER: BUILD ANCHOR — must read {} before editing", file_path
-                ));
-            }
-            EnforceMode::Soft => {
-                result.warn(format!("File not read before edit: {}", file_path));
-            }
-        }
-    }
+    // A file the edit creates (spf_apply_patch from /dev/null) has nothing to read
+    build_anchor(&mut result, file_path, &canonical_path, config, session, [
+        format!("MAX TIER: BUILD ANCHOR — must read {} before editing", file_path),
+        format!("File not read before edit: {}", file_path),
+    ]);
 
     // Blocked paths
     if config.is_path_blocked(file_path) {
@@ -179,39 +180,87 @@ pub fn validate_write(
             file_path.to_string()
         }
     };
-    if std::path::Path::new(file_path).exists()
-        && !session.files_read.contains(&canonical_path)
-    {
+    build_anchor(&mut result, file_path, &canonical_path, config, session, [
+        format!("MAX TIER: BUILD ANCHOR — must read existing file before overwrite: {}", file_path),
+        format!("Overwriting without read: {}", file_path),
+    ]);
+
+    result
+}
+
+/// Build Anchor for an existing file about to be edited or overwritten, when
+/// require_read_before_edit is set: it must have been read (`unread` warns —
+/// Max/Audit text, then Soft text) and must still hold what the agent last
+/// read or wrote (stale reads block in Max/Audit, warn in Soft).
+fn build_anchor(
+    result: &mut ValidationResult,
+    file_path: &str,
+    canonical_path: &str,
+    config: &SpfConfig,
+    session: &Session,
+    unread: [String; 2],
+) {
+    if !config.require_read_before_edit || !std::path::Path::new(file_path).exists() {
+        return;
+    }
+    if !session.files_read.iter().any(|p| p == canonical_path) {
+        let [max, soft] = unread;
         match config.enforce_mode {
-            EnforceMode::Max => {
-                result.warn(format!(
-                    "MAX TIER: BUILD ANCHOR — must read existing file before overwrite: {}",
-                    file_path
-                ));
-            }
-            EnforceMode::Soft => {
-                result.warn(format!("Overwriting without read: {}", file_path));
-            }
+            EnforceMode::Max | EnforceMode::Audit => result.warn(max),
+            EnforceMode::Soft => result.warn(soft),
         }
+        return;
+    }
+    if !session.changed_since_read(canonical_path) {
+        return;
+    }
+    let message = format!(
+        "STALE READ: {} changed on disk since it was last read — re-read it with spf_read first",
+        file_path
+    );
+    match config.enforce_mode {
+        EnforceMode::Max | EnforceMode::Audit => result.error(message),
+        EnforceMode::Soft => result.warn(message),
     }
-
-    result
 }
 
 /// Validate a Bash operation
 pub fn validate_bash(
     command: &str,
     config: &SpfConfig,
+) -> ValidationResult {
+    validate_bash_in(command, None, config)
+}
+
+/// Validate a Bash command that runs in `cwd` — relative write targets
+/// resolve against it, and bare file names are checked too.
+/// `None` means the gateway's own working directory. A `cd` in the command
+/// moves the targets after it; one that cannot be followed (run-time value,
+/// $HOME, CDPATH) blocks every relative target after it.
+pub fn validate_bash_in(
+    command: &str,
+    cwd: Option<&str>,
+    config: &SpfConfig,
 ) -> ValidationResult {
     let mut result = ValidationResult::ok();
 
     // Normalize for detection: collapse whitespace, trim
     let normalized: String = command.split_whitespace().collect::<Vec<_>>().join(" ");
 
-    // Check BOTH raw and normalized against config patterns
-    for pattern in &config.dangerous_commands {
-        if command.contains(pattern.as_str()) || normalized.contains(pattern.as_str()) {
-            result.error(format!("DANGEROUS COMMAND: contains '{}'", pattern));
+    // Config patterns (raw and normalized), banded by CONFIG.DB severity.
+    // The CRITICAL band is escalated by the gate; here it only warns.
+    for (pattern, severity) in config.dangerous_matches(command) {
+        match config.severity_thresholds.band(severity) {
+            SeverityBand::Block => result.error(format!(
+                "DANGEROUS COMMAND: contains '{}' (severity {})", pattern, severity
+            )),
+            SeverityBand::Critical => result.warn(format!(
+                "DANGEROUS COMMAND: contains '{}' (severity {}) — CRITICAL tier, approval required",
+                pattern, severity
+            )),
+            SeverityBand::Warn => result.warn(format!(
+                "RISKY COMMAND: contains '{}' (severity {})", pattern, severity
+            )),
         }
     }
 
@@ -255,7 +304,7 @@ pub fn validate_bash(
     let pipe_segments: Vec<&str> = normalized.split('|').collect();
     if pipe_segments.len() > 1 {
         for segment in &pipe_segments[1..] {
-            let receiver = segment.trim()
+            let receiver = segment
                 .split_whitespace().next().unwrap_or("");
             let base = receiver.rsplit('/').next().unwrap_or(receiver);
             if shell_interpreters.contains(&base) {
@@ -269,146 +318,652 @@ pub fn validate_bash(
     // ========================================================================
     // BASH WRITE-DESTINATION ENFORCEMENT
     // Blocks bash commands that write to paths outside PROJECTS/TMP.
-    // Catches: >, >>, tee, cp, mv, mkdir, touch, sed -i, chmod, rm
+    // Parsed, not split: redirects, cp/mv/tee/rm/sed -i/… behind env, sudo,
+    // xargs, find -exec, substitutions and nested sh -c / eval bodies
     // ========================================================================
-    check_bash_write_targets(command, &mut result);
+    check_bash_write_targets(command, cwd, &mut result);
 
     result
 }
 
+/// Nested `sh -c` / `eval` bodies followed before giving up
+const MAX_SHELL_NESTING: usize = 8;
+
+/// Redirect sinks that never persist data
+const HARMLESS_SINKS: &[&str] = &["/dev/null", "/dev/stdout", "/dev/stderr", "/dev/tty"];
+
 /// Extract write-target paths from bash commands and block if outside allowlist.
-fn check_bash_write_targets(command: &str, result: &mut ValidationResult) {
-    // Split on && || ; | to handle compound commands
-    let segments: Vec<&str> = command.split(|c| c == ';' || c == '|')
-        .flat_map(|s| s.split("&&"))
-        .flat_map(|s| s.split("||"))
-        .collect();
-
-    for segment in &segments {
-        let trimmed = segment.trim();
-        if trimmed.is_empty() { continue; }
-
-        // Redirect operators: > and >>
-        for op in &[">>", ">"] {
-            if let Some(pos) = trimmed.find(op) {
-                let after = trimmed[pos + op.len()..].trim();
-                let target = after.split_whitespace().next().unwrap_or("");
-                if !target.is_empty() && looks_like_path(target) && !is_write_allowed(target) {
-                    result.error(format!(
-                        "BASH WRITE BLOCKED: redirect {} to {} (outside PROJECTS/TMP)", op, target
-                    ));
-                }
+/// The command is parsed (shell.rs) and every nested command is checked:
+/// subshells, substitutions, compound bodies and `sh -c` / `eval` strings.
+/// Commands are walked in order so a `cd` moves later relative targets.
+fn check_bash_write_targets(command: &str, cwd: Option<&str>, result: &mut ValidationResult) {
+    check_shell_source(command, &Dirs::new(cwd), 0, result);
+}
+
+/// Every path a bash command run in `cwd` writes, resolved — from each
+/// directory it may run in. Targets the parser cannot place are left out;
+/// validate_bash_in already blocks those.
+pub fn bash_write_targets(command: &str, cwd: Option<&str>) -> Vec<String> {
+    let mut result = ValidationResult::ok();
+    check_bash_write_targets(command, cwd, &mut result);
+    result.write_targets
+}
+
+fn check_shell_source(source: &str, dirs: &Dirs, nesting: usize, result: &mut ValidationResult) {
+    if nesting > MAX_SHELL_NESTING {
+        result.error("BASH WRITE BLOCKED: shell nesting too deep to verify".to_string());
+        return;
+    }
+    match shell::parse(source) {
+        Ok(script) => {
+            let mut moves = false;
+            script.walk(&mut |cmd| moves |= is_dir_change(cmd));
+            let dirs = Dirs {
+                cd_search: dirs.cd_search || source.contains("CDPATH") || source.contains("cdable_vars"),
+                moves,
+                ..dirs.clone()
+            };
+            check_script(&script, &dirs, nesting, result);
+        }
+        Err(e) => result.error(format!(
+            "BASH WRITE BLOCKED: cannot parse command ({}) — write targets unverifiable", e
+        )),
+    }
+}
+
+/// Check a script list by list. Returns where the shell may be afterwards.
+fn check_script(script: &Script, dirs: &Dirs, nesting: usize, result: &mut ValidationResult) -> Dirs {
+    let mut dirs = dirs.clone();
+    for list in &script.lists {
+        let after = check_and_or(list, &dirs, nesting, result);
+        // `… &` runs in a subshell
+        if !list.background {
+            dirs = after;
+        }
+    }
+    dirs
+}
+
+/// In a plain `a && b && …` chain each pipeline runs where the previous one
+/// succeeded; once `||` appears, anywhere the list has been so far
+fn check_and_or(list: &AndOr, dirs: &Dirs, nesting: usize, result: &mut ValidationResult) -> Dirs {
+    let (mut ok, failed) = check_pipeline(&list.first, dirs, nesting, result);
+    let mut seen = dirs.union(&ok).union(&failed);
+    let mut chain = true;
+    for (connector, pipeline) in &list.rest {
+        chain &= *connector == Connector::And;
+        let entry = if chain { ok } else { seen.clone() };
+        let (next_ok, next_failed) = check_pipeline(pipeline, &entry, nesting, result);
+        seen = seen.union(&entry).union(&next_ok).union(&next_failed);
+        ok = next_ok;
+    }
+    seen
+}
+
+/// (where a pipeline leaves the shell on success, on failure)
+fn check_pipeline(pipeline: &Pipeline, dirs: &Dirs, nesting: usize, result: &mut ValidationResult) -> (Dirs, Dirs) {
+    match pipeline.commands.as_slice() {
+        [command] => {
+            let (ok, failed) = check_command(command, dirs, nesting, result);
+            if pipeline.negated { (failed, ok) } else { (ok, failed) }
+        }
+        commands => {
+            // Stages run in subshells; the last may not (shopt -s lastpipe)
+            let mut after = (dirs.clone(), dirs.clone());
+            for command in commands {
+                after = check_command(command, dirs, nesting, result);
             }
+            let either = dirs.union(&after.0).union(&after.1);
+            (either.clone(), either)
+        }
+    }
+}
+
+fn check_command(cmd: &Command, dirs: &Dirs, nesting: usize, result: &mut ValidationResult) -> (Dirs, Dirs) {
+    for redirect in cmd.redirects() {
+        check_word(&redirect.target, dirs, nesting, result);
+        if !redirect.writes_file() {
+            continue;
+        }
+        let target = redirect.target.text();
+        if HARMLESS_SINKS.contains(&target.as_str()) {
+            continue;
         }
+        if let Some(why) = dirs.refuse(&target) {
+            result.error(format!(
+                "BASH WRITE BLOCKED: redirect {} to {} ({})", redirect.display_op(), target, why
+            ));
+        } else if redirect.target.is_dynamic() {
+            result.warn(format!(
+                "BASH WRITE UNVERIFIED: redirect {} to {} is expanded at run time", redirect.display_op(), target
+            ));
+        } else {
+            dirs.note(result, &target);
+        }
+    }
 
-        // Here-doc: << EOF > file or << 'EOF' > file
-        if trimmed.contains("<<") && trimmed.contains(">") {
-            if let Some(pos) = trimmed.rfind('>') {
-                let after = trimmed[pos + 1..].trim();
-                let target = after.split_whitespace().next().unwrap_or("");
-                if !target.is_empty() && !target.starts_with('<') && looks_like_path(target) && !is_write_allowed(target) {
-                    result.error(format!(
-                        "BASH WRITE BLOCKED: here-doc redirect to {} (outside PROJECTS/TMP)", target
-                    ));
+    let after = match cmd {
+        Command::Simple(simple) => {
+            for (_, value) in &simple.assignments {
+                check_word(value, dirs, nesting, result);
+            }
+            for word in &simple.words {
+                check_word(word, dirs, nesting, result);
+            }
+            check_argv(&simple.words, &simple.redirects, false, dirs, nesting, result);
+            return dir_effect(simple, dirs);
+        }
+        Command::Subshell(body, _) => {
+            check_script(body, dirs, nesting, result);
+            dirs.clone()
+        }
+        Command::Group(body, _) => check_script(body, dirs, nesting, result),
+        Command::If { branches, otherwise, .. } => {
+            let mut seen = dirs.clone();
+            for (condition, body) in branches {
+                seen = seen.union(&check_script(condition, &seen, nesting, result));
+                seen = seen.union(&check_script(body, &seen, nesting, result));
+            }
+            if let Some(body) = otherwise {
+                seen = seen.union(&check_script(body, &seen, nesting, result));
+            }
+            seen
+        }
+        Command::Loop { condition, body, .. } => {
+            let mut seen = loop_entry(dirs, &[condition, body]);
+            seen = seen.union(&check_script(condition, &seen, nesting, result));
+            seen.union(&check_script(body, &seen, nesting, result))
+        }
+        Command::For { items, body, .. } => {
+            for item in items.iter().flatten() {
+                check_word(item, dirs, nesting, result);
+            }
+            let entry = loop_entry(dirs, &[body]);
+            entry.union(&check_script(body, &entry, nesting, result))
+        }
+        Command::Case { subject, arms, .. } => {
+            check_word(subject, dirs, nesting, result);
+            let mut seen = dirs.clone();
+            for (patterns, body) in arms {
+                for pattern in patterns {
+                    check_word(pattern, dirs, nesting, result);
                 }
+                seen = seen.union(&check_script(body, dirs, nesting, result));
             }
+            seen
+        }
+        Command::Function { body, .. } => {
+            // The body runs when called — after any cd in the script
+            let entry = if dirs.moves { dirs.or_unknown() } else { dirs.clone() };
+            check_command(body, &entry, nesting, result);
+            let mut moves = false;
+            body.walk(&mut |cmd| moves |= is_dir_change(cmd));
+            if moves { dirs.or_unknown() } else { dirs.clone() }
         }
+        Command::Arithmetic(..) => dirs.clone(),
+    };
+    (after.clone(), after)
+}
 
-        let words: Vec<&str> = trimmed.split_whitespace().collect();
-        if words.is_empty() { continue; }
+/// Command substitutions run in subshells: checked, their cd forgotten
+fn check_word(word: &shell::Word, dirs: &Dirs, nesting: usize, result: &mut ValidationResult) {
+    for part in &word.parts {
+        if let WordPart::CommandSub(script) = part {
+            check_script(script, dirs, nesting, result);
+        }
+    }
+}
 
-        let cmd = words[0].rsplit('/').next().unwrap_or(words[0]);
+/// A loop whose body changes directory starts later passes anywhere
+fn loop_entry(dirs: &Dirs, parts: &[&Script]) -> Dirs {
+    let mut moves = false;
+    for part in parts {
+        part.walk(&mut |cmd| moves |= is_dir_change(cmd));
+    }
+    if moves { dirs.or_unknown() } else { dirs.clone() }
+}
 
-        match cmd {
-            "cp" | "mv" => {
-                // Last non-flag arg is destination
-                let args: Vec<&&str> = words[1..].iter().filter(|w| !w.starts_with('-')).collect();
-                if args.len() >= 2 {
-                    let dest = args[args.len() - 1];
-                    if looks_like_path(dest) && !is_write_allowed(dest) {
-                        result.error(format!(
-                            "BASH WRITE BLOCKED: {} destination {} (outside PROJECTS/TMP)", cmd, dest
-                        ));
-                    }
+/// Name of a simple command as the shell runs it — `builtin`, `command`
+/// and `time` still run their command in this shell. None if expanded at
+/// run time.
+fn shell_builtin(words: &[shell::Word]) -> Option<(String, &[shell::Word])> {
+    let mut i = 0;
+    while let Some(name) = words.get(i).and_then(|w| w.literal()) {
+        if !matches!(name.as_str(), "builtin" | "command" | "time") {
+            return Some((name, &words[i + 1..]));
+        }
+        i += 1;
+        while words.get(i).and_then(|w| w.literal()).is_some_and(|w| w.starts_with('-')) {
+            i += 1;
+        }
+    }
+    None
+}
+
+/// Commands that can move this shell: cd, pushd, popd, and anything that
+/// runs code here (eval, source, traps, a command named at run time)
+fn is_dir_change(cmd: &Command) -> bool {
+    let Command::Simple(simple) = cmd else { return false };
+    if simple.words.is_empty() {
+        return false;
+    }
+    shell_builtin(&simple.words).is_none_or(|(name, _)| {
+        matches!(name.as_str(), "cd" | "pushd" | "popd" | "eval" | "source" | "." | "trap")
+    })
+}
+
+/// (where a simple command leaves the shell on success, on failure)
+fn dir_effect(simple: &shell::SimpleCommand, dirs: &Dirs) -> (Dirs, Dirs) {
+    if simple.words.is_empty() {
+        return (dirs.clone(), dirs.clone());
+    }
+    match shell_builtin(&simple.words) {
+        Some((name, args)) if name == "cd" || name == "pushd" => {
+            // Options (-L, -P, -e, --) come first; no operand means $HOME
+            let operand = args.iter().find(|w| !w.literal().is_some_and(|a| a.starts_with('-') && a.len() > 1));
+            (dirs.cd(operand.and_then(|w| w.literal()).as_deref()), dirs.clone())
+        }
+        Some((name, _)) if !matches!(name.as_str(), "popd" | "eval" | "source" | "." | "trap") => {
+            (dirs.clone(), dirs.clone())
+        }
+        _ => (dirs.or_unknown(), dirs.or_unknown()),
+    }
+}
+
+/// Wrapper commands that run their arguments as a command.
+/// (name, options taking a value, positional args before the command)
+const COMMAND_WRAPPERS: &[(&str, &[&str], usize)] = &[
+    ("command", &[], 0),
+    ("builtin", &[], 0),
+    ("exec", &["-a"], 0),
+    ("nohup", &[], 0),
+    ("setsid", &[], 0),
+    ("busybox", &[], 0),
+    ("time", &["-f", "-o", "--format", "--output"], 0),
+    ("nice", &["-n", "--adjustment"], 0),
+    ("ionice", &["-c", "-n", "--class", "--classdata"], 0),
+    ("stdbuf", &["-i", "-o", "-e", "--input", "--output", "--error"], 0),
+    ("timeout", &["-s", "-k", "--signal", "--kill-after"], 1),
+    ("sudo", &["-u", "-g", "-C", "-D", "-h", "-p", "-r", "-t", "-U", "--user", "--group", "--chdir"], 0),
+    ("doas", &["-u", "-C"], 0),
+    ("env", &["-u", "-C", "--unset", "--chdir"], 0),
+    ("xargs", &["-a", "-d", "-E", "-I", "-L", "-n", "-P", "-s", "--arg-file", "--delimiter", "--max-args", "--max-procs"], 0),
+];
+
+const SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "ksh", "mksh", "ash"];
+
+fn basename(word: &str) -> &str {
+    word.rsplit('/').next().unwrap_or(word)
+}
+
+/// `env -C dir`, `sudo -D dir` and their --chdir forms: Some(directory),
+/// with None inside for a run-time value
+fn wrapper_chdir(cmd: &str, word: &shell::Word, next: Option<&shell::Word>) -> Option<Option<String>> {
+    let flag = match cmd {
+        "env" => "-C",
+        "sudo" => "-D",
+        _ => return None,
+    };
+    let arg = word.text();
+    if arg == flag || arg == "--chdir" {
+        return Some(next.and_then(|w| w.literal()));
+    }
+    let dir = arg.strip_prefix("--chdir=").or_else(|| arg.strip_prefix(flag).filter(|d| !d.is_empty()))?;
+    Some(word.literal().map(|_| dir.to_string()))
+}
+
+/// Check one argv (after assignments) — unwraps env/sudo/xargs/…,
+/// follows sh -c, eval and find -exec, then applies the write-command rules
+fn check_argv(
+    words: &[shell::Word],
+    redirects: &[shell::Redirect],
+    stdin_args: bool,
+    dirs: &Dirs,
+    nesting: usize,
+    result: &mut ValidationResult,
+) {
+    let Some(first) = words.first() else { return };
+    let Some(name) = first.literal() else {
+        result.warn(format!(
+            "BASH WRITE UNVERIFIED: command name {} is expanded at run time", first.text()
+        ));
+        return;
+    };
+    let cmd = basename(&name);
+
+    // Wrappers: skip their options and re-check the wrapped command
+    if let Some((_, value_flags, positional)) = COMMAND_WRAPPERS.iter().find(|(w, _, _)| *w == cmd) {
+        let mut i = 1;
+        let mut positional = *positional;
+        // env -C / sudo -D run the wrapped command in another directory
+        let mut inner = dirs.clone();
+        while i < words.len() {
+            let arg = words[i].text();
+            if let Some(dir) = wrapper_chdir(cmd, &words[i], words.get(i + 1)) {
+                inner = inner.cd(dir.as_deref());
+            }
+            if arg == "--" {
+                i += 1;
+                break;
+            }
+            if cmd == "env" {
+                if let Some(split) = arg.strip_prefix("--split-string=").or_else(|| arg.strip_prefix("-S")) {
+                    // env -S 'cmd args' runs a command line of its own
+                    let split = match split {
+                        "" => words.get(i + 1).map(|w| w.text()).unwrap_or_default(),
+                        inline => inline.to_string(),
+                    };
+                    check_shell_source(&split, &inner, nesting + 1, result);
+                    return;
+                }
+                if !arg.starts_with('-') && arg.contains('=') {
+                    i += 1;
+                    continue;
                 }
             }
-            "tee" => {
-                // tee writes to file args (skip flags)
-                for arg in &words[1..] {
-                    if !arg.starts_with('-') && looks_like_path(arg) && !is_write_allowed(arg) {
-                        result.error(format!(
-                            "BASH WRITE BLOCKED: tee target {} (outside PROJECTS/TMP)", arg
-                        ));
-                    }
+            if arg.starts_with('-') && arg.len() > 1 {
+                i += if value_flags.contains(&arg.as_str()) { 2 } else { 1 };
+            } else if positional > 0 {
+                positional -= 1;
+                i += 1;
+            } else {
+                break;
+            }
+        }
+        if i < words.len() {
+            check_argv(&words[i..], redirects, stdin_args || cmd == "xargs", &inner, nesting, result);
+        }
+        return;
+    }
+
+    let args: Vec<String> = words[1..].iter().map(|w| w.text()).collect();
+
+    match cmd {
+        // sh -c 'body' — parse the body as a script of its own
+        _ if SHELLS.contains(&cmd) => {
+            let mut inline = false;
+            let mut i = 0;
+            while i < args.len() && (args[i].starts_with('-') || args[i].starts_with('+')) {
+                if args[i] == "-o" || args[i] == "+o" {
+                    i += 1;
+                } else if !args[i].starts_with("--") && args[i].contains('c') {
+                    inline = true;
                 }
+                i += 1;
             }
-            "mkdir" | "touch" | "rm" | "rmdir" => {
-                for arg in &words[1..] {
-                    if !arg.starts_with('-') && looks_like_path(arg) && !is_write_allowed(arg) {
-                        result.error(format!(
-                            "BASH WRITE BLOCKED: {} target {} (outside PROJECTS/TMP)", cmd, arg
-                        ));
+            if inline {
+                match words.get(i + 1) {
+                    Some(body) if body.is_dynamic() => result.error(format!(
+                        "BASH WRITE BLOCKED: {} -c script {} is built at run time — cannot verify", cmd, body.text()
+                    )),
+                    Some(body) => check_shell_source(&body.text(), dirs, nesting + 1, result),
+                    None => {}
+                }
+            } else if i >= args.len() {
+                // Script on stdin: follow here-docs and here-strings
+                for redirect in redirects {
+                    if matches!(redirect.op, RedirectOp::HereDoc | RedirectOp::HereString) {
+                        check_shell_source(&redirect.target.text(), dirs, nesting + 1, result);
                     }
                 }
             }
-            "sed" => {
-                if words.contains(&"-i") || words.iter().any(|w| w.starts_with("-i")) {
-                    // sed -i edits files in place — check file targets
-                    for arg in &words[1..] {
-                        if !arg.starts_with('-') && looks_like_path(arg) && !is_write_allowed(arg) {
-                            result.error(format!(
-                                "BASH WRITE BLOCKED: sed -i target {} (outside PROJECTS/TMP)", arg
-                            ));
+        }
+        "eval" => {
+            if words[1..].iter().any(|w| w.is_dynamic()) {
+                result.error("BASH WRITE BLOCKED: eval of a run-time string — cannot verify".to_string());
+            } else {
+                check_shell_source(&args.join(" "), dirs, nesting + 1, result);
+            }
+        }
+        "find" => {
+            let mut i = 0;
+            let roots: Vec<&String> = args.iter()
+                .take_while(|a| !a.starts_with('-') && *a != "(" && *a != "!")
+                .collect();
+            while i < args.len() {
+                match args[i].as_str() {
+                    "-exec" | "-execdir" | "-ok" | "-okdir" => {
+                        let start = i + 1;
+                        let end = args[start..].iter()
+                            .position(|a| a == ";" || a == "+")
+                            .map_or(args.len(), |n| start + n);
+                        check_argv(&words[start + 1..end + 1], &[], false, dirs, nesting, result);
+                        i = end;
+                    }
+                    "-delete" => {
+                        for root in &roots {
+                            block_write(result, dirs, format!("find -delete root {}", root), root);
+                        }
+                    }
+                    "-fprint" | "-fprint0" | "-fls" | "-fprintf" => {
+                        if let Some(file) = args.get(i + 1) {
+                            block_write(result, dirs, format!("find {} {}", args[i], file), file);
                         }
                     }
+                    _ => {}
                 }
+                i += 1;
             }
-            "chmod" | "chown" => {
-                // chmod/chown modify file metadata — block outside allowlist
-                let args: Vec<&&str> = words[1..].iter().filter(|w| !w.starts_with('-')).collect();
-                // First non-flag arg is mode/owner, rest are files
-                for arg in args.iter().skip(1) {
-                    if looks_like_path(arg) && !is_write_allowed(arg) {
-                        result.error(format!(
-                            "BASH WRITE BLOCKED: {} target {} (outside PROJECTS/TMP)", cmd, arg
-                        ));
-                    }
+        }
+        "cp" | "mv" | "install" | "ln" | "rsync" => {
+            // -t DIR / --target-directory=DIR, else the last non-flag arg is the destination
+            let target_dir = args.iter().enumerate().find_map(|(i, a)| {
+                if a == "-t" || a == "--target-directory" {
+                    args.get(i + 1).cloned()
+                } else {
+                    a.strip_prefix("--target-directory=").map(str::to_string)
+                }
+            });
+            let operands: Vec<&String> = args.iter().filter(|a| !a.starts_with('-')).collect();
+            if let Some(dir) = target_dir {
+                block_write(result, dirs, format!("{} destination {}", cmd, dir), &dir);
+            } else if stdin_args {
+                // xargs appends operands — any explicit path may be the destination
+                for arg in &operands {
+                    block_write(result, dirs, format!("{} destination {}", cmd, arg), arg);
                 }
+            } else if operands.len() >= 2 {
+                let dest = operands[operands.len() - 1];
+                block_write(result, dirs, format!("{} destination {}", cmd, dest), dest);
             }
-            "install" => {
-                // install copies files — last non-flag arg is destination
-                let args: Vec<&&str> = words[1..].iter().filter(|w| !w.starts_with('-')).collect();
-                if args.len() >= 2 {
-                    let dest = args[args.len() - 1];
-                    if looks_like_path(dest) && !is_write_allowed(dest) {
-                        result.error(format!(
-                            "BASH WRITE BLOCKED: install destination {} (outside PROJECTS/TMP)", dest
-                        ));
-                    }
+        }
+        "tee" | "mkdir" | "touch" | "rm" | "rmdir" | "unlink" | "shred" | "truncate" | "mkfifo" => {
+            for arg in args.iter().filter(|a| !a.starts_with('-')) {
+                if cmd == "tee" && HARMLESS_SINKS.contains(&arg.as_str()) {
+                    continue;
                 }
+                block_write(result, dirs, format!("{} target {}", cmd, arg), arg);
             }
-            "dd" => {
-                // dd of= writes to a file
-                for arg in &words[1..] {
-                    if let Some(dest) = arg.strip_prefix("of=") {
-                        if looks_like_path(dest) && !is_write_allowed(dest) {
-                            result.error(format!(
-                                "BASH WRITE BLOCKED: dd of={} (outside PROJECTS/TMP)", dest
-                            ));
-                        }
-                    }
+        }
+        // sed -i / perl -i edit files in place — the script argument is not a target
+        "sed" | "perl" if args.iter().any(|a| is_in_place_flag(cmd, a)) => {
+            let mut explicit_script = false;
+            let mut files = Vec::new();
+            let mut i = 0;
+            while i < args.len() {
+                let arg = &args[i];
+                if arg == "-e" || arg == "-f" || arg == "--expression" || arg == "--file" {
+                    explicit_script = true;
+                    i += 2;
+                    continue;
+                }
+                if !arg.starts_with('-') {
+                    files.push(arg);
+                }
+                i += 1;
+            }
+            let skip = usize::from(!explicit_script && cmd == "sed");
+            for file in files.into_iter().skip(skip) {
+                block_write(result, dirs, format!("{} -i target {}", cmd, file), file);
+            }
+        }
+        "chmod" | "chown" | "chgrp" => {
+            // First non-flag arg is mode/owner, rest are files
+            for arg in args.iter().filter(|a| !a.starts_with('-')).skip(1) {
+                block_write(result, dirs, format!("{} target {}", cmd, arg), arg);
+            }
+        }
+        "dd" => {
+            for arg in &args {
+                if let Some(dest) = arg.strip_prefix("of=") {
+                    block_write(result, dirs, format!("dd of={}", dest), dest);
                 }
             }
-            "python" | "python3" | "perl" | "ruby" | "node" => {
-                // Script interpreters with -c flag could write anywhere
-                // Flag as warning (can't parse script content reliably)
-                if words.contains(&"-c") {
-                    result.warn(format!(
-                        "WARNING: {} -c detected — inline script may write outside PROJECTS/TMP", cmd
-                    ));
+        }
+        "curl" | "wget" => {
+            let flags: &[&str] = if cmd == "curl" { &["-o", "--output"] } else { &["-O", "--output-document"] };
+            for (i, arg) in args.iter().enumerate() {
+                if flags.contains(&arg.as_str()) {
+                    if let Some(file) = args.get(i + 1) {
+                        block_write(result, dirs, format!("{} {} {}", cmd, arg, file), file);
+                    }
                 }
             }
-            _ => {}
+        }
+        // Script interpreters with -c/-e could write anywhere
+        // Flag as warning (can't parse script content reliably)
+        "python" | "python3" | "perl" | "ruby" | "node"
+            if args.iter().any(|a| a == "-c" || a == "-e") =>
+        {
+            result.warn(format!(
+                "WARNING: {} inline script detected — may write outside PROJECTS/TMP", cmd
+            ));
+        }
+        _ => {}
+    }
+}
+
+/// Block a write target outside PROJECTS/TMP
+fn block_write(result: &mut ValidationResult, dirs: &Dirs, what: String, target: &str) {
+    match dirs.refuse(target) {
+        Some(why) => result.error(format!("BASH WRITE BLOCKED: {} ({})", what, why)),
+        None => dirs.note(result, target),
+    }
+}
+
+/// -i, -i.bak, --in-place, or a short cluster such as -ni / -pi
+fn is_in_place_flag(cmd: &str, arg: &str) -> bool {
+    if arg.starts_with("--in-place") {
+        return cmd == "sed";
+    }
+    arg.strip_prefix('-').is_some_and(|flags| {
+        !flags.starts_with('-')
+            && (flags.starts_with('i') || (flags.chars().all(|c| c.is_ascii_alphabetic()) && flags.contains('i')))
+    })
+}
+
+/// Directory a bash command may run in, as far as the validator can tell
+#[derive(Debug, Clone, PartialEq)]
+enum Cwd {
+    /// The gateway's own working directory (no shell session): only
+    /// path-like targets are checked
+    Server,
+    At(String),
+    /// Left by a cd the validator cannot follow — relative targets fail closed
+    Unknown,
+}
+
+impl Cwd {
+    /// Whether a bash write target is inside the write allowlist from here.
+    /// With a known cwd every target counts and relative ones resolve against it.
+    fn allows(&self, target: &str) -> bool {
+        match self {
+            Cwd::Server => !looks_like_path(target) || is_write_allowed(target),
+            // "-" is stdout; "$VAR/…" expands to anything, so it is not cwd-relative
+            _ if target == "-" => true,
+            _ if target.starts_with('$') => !looks_like_path(target) || is_write_allowed(target),
+            _ if target.starts_with('/') || target.starts_with('~') => is_write_allowed(target),
+            Cwd::At(cwd) => is_write_allowed(&format!("{}/{}", cwd.trim_end_matches('/'), target)),
+            Cwd::Unknown => false,
+        }
+    }
+}
+
+/// Directories tracked before they collapse to Unknown
+const MAX_CWDS: usize = 8;
+
+/// Every directory the next command may run in. A cd can fail, so after
+/// `cd x; …` both the old directory and x are possible, and a target must be
+/// allowed from each.
+#[derive(Debug, Clone, PartialEq)]
+struct Dirs {
+    cwds: Vec<Cwd>,
+    /// CDPATH or cdable_vars in play — a bare `cd name` may go anywhere
+    cd_search: bool,
+    /// The script changes directory somewhere — function bodies may run after it
+    moves: bool,
+}
+
+impl Dirs {
+    fn new(cwd: Option<&str>) -> Self {
+        let cwd = cwd.map_or(Cwd::Server, |cwd| Cwd::At(cwd.to_string()));
+        Self { cwds: vec![cwd], cd_search: false, moves: false }
+    }
+
+    /// Here or wherever `other` is
+    fn union(&self, other: &Dirs) -> Dirs {
+        let mut dirs = self.clone();
+        for cwd in &other.cwds {
+            if !dirs.cwds.contains(cwd) {
+                dirs.cwds.push(cwd.clone());
+            }
+        }
+        if dirs.cwds.len() > MAX_CWDS {
+            dirs.cwds = vec![Cwd::Unknown];
+        }
+        dirs
+    }
+
+    fn or_unknown(&self) -> Dirs {
+        self.union(&Dirs { cwds: vec![Cwd::Unknown], ..self.clone() })
+    }
+
+    /// After a successful `cd target`; None is $HOME or a run-time value
+    fn cd(&self, target: Option<&str>) -> Dirs {
+        let searched = |t: &str| self.cd_search && !matches!(t, "." | "..") && !t.starts_with("./") && !t.starts_with("../");
+        let cwds = self.cwds.iter().map(|cwd| match (target, cwd) {
+            (Some(t), _) if t.starts_with('/') => Cwd::At(t.to_string()),
+            (Some(t), Cwd::At(base)) if !t.is_empty() && !t.starts_with(['~', '-', '+']) && !searched(t) => {
+                Cwd::At(format!("{}/{}", base.trim_end_matches('/'), t))
+            }
+            _ => Cwd::Unknown,
+        });
+        let mut dirs = Dirs { cwds: Vec::new(), ..self.clone() };
+        for cwd in cwds {
+            dirs = dirs.union(&Dirs { cwds: vec![cwd], ..self.clone() });
+        }
+        dirs
+    }
+
+    /// Why `target` may not be written, if it may not
+    fn refuse(&self, target: &str) -> Option<&'static str> {
+        let blocked: Vec<&Cwd> = self.cwds.iter().filter(|cwd| !cwd.allows(target)).collect();
+        match blocked.as_slice() {
+            [] => None,
+            [Cwd::Unknown] => Some("relative to a directory changed at run time — cannot verify"),
+            _ => Some("outside PROJECTS/TMP"),
+        }
+    }
+
+    /// Record where an allowed `target` lands from each directory
+    fn note(&self, result: &mut ValidationResult, target: &str) {
+        if target == "-" || target.starts_with(['$', '~']) {
+            return;
+        }
+        for cwd in &self.cwds {
+            let path = match cwd {
+                _ if target.starts_with('/') => target.to_string(),
+                Cwd::At(cwd) => format!("{}/{}", cwd.trim_end_matches('/'), target),
+                Cwd::Server if looks_like_path(target) => match std::env::current_dir() {
+                    Ok(dir) => format!("{}/{}", dir.to_string_lossy().trim_end_matches('/'), target),
+                    Err(_) => continue,
+                },
+                _ => continue,
+            };
+            let path = resolve_path(&path).unwrap_or(path);
+            if !result.write_targets.contains(&path) {
+                result.write_targets.push(path);
+            }
         }
     }
 }
@@ -446,6 +1001,49 @@ mod tests {
         SpfConfig::default()
     }
 
+    /// A read file changed on disk since, and an anchor check over it
+    fn anchor_fixture() -> (tempfile::TempDir, String, impl Fn(&SpfConfig, &Session) -> ValidationResult) {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("notes.md");
+        std::fs::write(&file, "one").unwrap();
+        let path = file.to_string_lossy().to_string();
+        let canonical = std::fs::canonicalize(&file).unwrap().to_string_lossy().to_string();
+        let check_path = path.clone();
+        let anchor = move |config: &SpfConfig, session: &Session| {
+            let mut result = ValidationResult::ok();
+            build_anchor(&mut result, &check_path, &canonical, config, session, ["max".to_string(), "soft".to_string()]);
+            result
+        };
+        (dir, path, anchor)
+    }
+
+    #[test]
+    fn build_anchor_runs_when_read_required() {
+        let (_dir, path, anchor) = anchor_fixture();
+        let config = default_config();
+        assert!(config.require_read_before_edit);
+        let mut session = Session::new();
+        assert_eq!(anchor(&config, &session).warnings, ["max"]);
+        session.track_read(&path);
+        assert!(anchor(&config, &session).valid);
+        std::fs::write(&path, "changed behind the agent's back").unwrap();
+        let stale = anchor(&config, &session);
+        assert!(!stale.valid && stale.errors[0].starts_with("STALE READ"), "{:?}", stale.errors);
+    }
+
+    #[test]
+    fn build_anchor_skipped_when_read_not_required() {
+        let (_dir, path, anchor) = anchor_fixture();
+        let mut config = default_config();
+        config.require_read_before_edit = false;
+        assert!(anchor(&config, &Session::new()).warnings.is_empty());
+        let mut session = Session::new();
+        session.track_read(&path);
+        std::fs::write(&path, "changed behind the agent's back").unwrap();
+        let result = anchor(&config, &session);
+        assert!(result.valid && result.warnings.is_empty(), "{:?}", result);
+    }
+
     #[test]
     fn bash_detects_dangerous_commands() {
         let config = default_config();
@@ -454,6 +1052,25 @@ mod tests {
         assert!(!result.errors.is_empty());
     }
 
+    #[test]
+    fn bash_bands_dangerous_by_severity() {
+        let mut config = default_config();
+        config.dangerous_commands.extend(["reboot".to_string(), "killall".to_string()]);
+        config.dangerous_severity.insert("reboot".to_string(), 6);
+        config.dangerous_severity.insert("killall".to_string(), 2);
+
+        let mid = validate_bash("reboot", &config);
+        assert!(mid.valid, "mid band should not block: {:?}", mid.errors);
+        assert!(mid.warnings.iter().any(|w| w.contains("severity 6") && w.contains("CRITICAL")));
+
+        let low = validate_bash("killall node", &config);
+        assert!(low.valid);
+        assert!(low.warnings.iter().any(|w| w.starts_with("RISKY COMMAND")));
+
+        config.severity_thresholds.block = 6;
+        assert!(!validate_bash("reboot", &config).valid);
+    }
+
     #[test]
     fn bash_blocks_tmp_access() {
         let config = default_config();
@@ -501,6 +1118,195 @@ mod tests {
         assert!(!r3.valid, "Pipe to /bin/bash should be blocked");
     }
 
+    fn write_errors(command: &str) -> Vec<String> {
+        validate_bash(command, &default_config()).errors.into_iter()
+            .filter(|e| e.starts_with("BASH WRITE BLOCKED"))
+            .collect()
+    }
+
+    #[test]
+    fn bash_write_targets_survive_obfuscation() {
+        let corpus = [
+            // redirections
+            "echo x > /etc/passwd",
+            "echo x >| /etc/passwd",
+            "echo x 2>/etc/passwd",
+            "echo x &>> /etc/passwd",
+            "echo x >&/etc/passwd",
+            "cat <> /etc/passwd",
+            "echo x>'/etc/pa'\"sswd\"",
+            "{ echo; } > /etc/x",
+            "echo x > $HOME/.bashrc",
+            // quoting and separators
+            "echo \"a;b\" && cp a '/etc/x y'",
+            "echo 'a|b' | tee -a /etc/x",
+            "true|(cd / && rm /etc/hosts)",
+            "ls\nrm /etc/x",
+            // substitutions
+            "echo $(cp a /etc/x)",
+            "echo `touch /etc/x`",
+            "echo ${v:-$(rm /etc/x)}",
+            "echo $(( $(rm /etc/x) ))",
+            "diff <(ls) >(tee /etc/x)",
+            "cat <<EOF\n$(rm /etc/x)\nEOF",
+            // wrappers
+            "FOO=1 cp a /etc/x",
+            "env FOO=1 cp a /etc/x",
+            "env -i -u HOME -- cp a /etc/x",
+            "env -S 'cp a /etc/x'",
+            "sudo -u root cp a /etc/x",
+            "timeout 5 nice -n 10 mv a /etc/x",
+            "command exec rm /etc/x",
+            "ls | xargs cp /etc/x",
+            "ls | xargs -I{} cp {} /etc/",
+            "find . -name '*.rs' -exec cp {} /etc/ \\;",
+            "find /etc -name x -delete",
+            "find . -fprint /etc/x",
+            // nested shells
+            "bash -c 'echo x > /etc/x'",
+            "sh -c \"cp a /etc/x\"",
+            "bash -lc 'sh -c \"touch /etc/x\"'",
+            "bash -c \"$CMD\"",
+            "eval 'rm /etc/x'",
+            "eval \"$CMD\"",
+            "bash <<'EOF'\nrm /etc/x\nEOF",
+            "bash <<< 'tee /etc/x'",
+            // compound commands
+            "if true; then cp a /etc/x; fi",
+            "for f in a b; do mv $f /etc/; done",
+            "while true; do touch /etc/x; break; done",
+            "case x in x) rm /etc/x ;; esac",
+            "f() { rm /etc/x; }; f",
+            // write commands
+            "sed -i 's/a/b/' /etc/x",
+            "sed -ni -e 's/a/b/' /etc/x",
+            "perl -pi -e 's/a/b/' /etc/x",
+            "cp -t /etc/ a b",
+            "install -m 644 a /etc/x",
+            "chown root /etc/x",
+            "truncate -s 0 /etc/x",
+            "curl -o /etc/x https://example.com",
+            // unparseable input fails closed
+            "echo 'unterminated",
+        ];
+        for command in corpus {
+            let errors = write_errors(command);
+            assert!(!errors.is_empty(), "not blocked: {:?}", command);
+            let parse_failure = errors.iter().any(|e| e.contains("cannot parse"));
+            assert_eq!(parse_failure, command.contains("unterminated"), "{:?}: {:?}", command, errors);
+        }
+    }
+
+    #[test]
+    fn bash_write_targets_allow_safe_forms() {
+        let project = format!("{}/LIVE/PROJECTS/PROJECTS/demo", crate::paths::spf_root().display());
+        let safe = [
+            "ls -la 2>/dev/null".to_string(),
+            "make 2>&1 | grep error".to_string(),
+            "echo hi >&2".to_string(),
+            "echo x > out.txt".to_string(),
+            "echo x | tee /dev/null".to_string(),
+            "echo '> /etc/passwd'".to_string(),
+            "grep -r 'cp a /etc/x' .".to_string(),
+            "[[ $a > /etc/x ]] && echo gt".to_string(),
+            "sed 's/a/b/' /etc/hosts".to_string(),
+            "sed -i 's/\\/etc\\/x/y/' notes.txt".to_string(),
+            "cat <<'EOF'\n> /etc/x\nEOF".to_string(),
+            format!("cp a {}/b", project),
+            format!("echo x > {}/out.txt", project),
+            format!("bash -c 'echo x > {}/out.txt'", project),
+            format!("find . -exec cp {{}} {}/ \\;", project),
+        ];
+        for command in &safe {
+            assert!(write_errors(command).is_empty(), "{:?}: {:?}", command, write_errors(command));
+        }
+        let dynamic = validate_bash("echo x > \"$OUT\"", &default_config());
+        assert!(dynamic.valid);
+        assert!(dynamic.warnings.iter().any(|w| w.starts_with("BASH WRITE UNVERIFIED")));
+    }
+
+    #[test]
+    fn bash_write_targets_resolve_against_cwd() {
+        let config = default_config();
+        let project = format!("{}/LIVE/PROJECTS/PROJECTS/demo", crate::paths::spf_root().display());
+        for command in ["echo x > out.txt", "touch notes.md", "cp a sub/b", "curl -o - https://example.com"] {
+            let result = validate_bash_in(command, Some(&project), &config);
+            assert!(result.valid, "{:?}: {:?}", command, result.errors);
+        }
+        // Same commands from a directory outside the write allowlist
+        for command in ["echo x > out.txt", "touch notes.md", "mv a ../b", "rm -rf build"] {
+            let result = validate_bash_in(command, Some("/etc"), &config);
+            assert!(!result.valid, "not blocked in /etc: {:?}", command);
+        }
+        assert!(validate_bash("echo x > out.txt", &config).valid);
+
+        // Resolved targets feed the per-project check in bash_gate
+        let targets = bash_write_targets("echo x > out.txt; cd sub; touch a; echo y >> /dev/null", Some(&project));
+        for expected in ["out.txt", "sub/a", "a"] {
+            assert!(targets.contains(&format!("{}/{}", project, expected)), "{} not in {:?}", expected, targets);
+        }
+        assert_eq!(targets.len(), 3);
+    }
+
+    #[test]
+    fn bash_write_targets_follow_cd() {
+        let config = default_config();
+        let project = format!("{}/LIVE/PROJECTS/PROJECTS/demo", crate::paths::spf_root().display());
+        let blocked_in = |command: &str, cwd: Option<&str>| -> Vec<String> {
+            validate_bash_in(command, cwd, &config).errors.into_iter()
+                .filter(|e| e.starts_with("BASH WRITE BLOCKED"))
+                .collect()
+        };
+
+        let escapes = [
+            "cd /etc && rm hosts",
+            "cd /etc; rm hosts",
+            "cd /etc || true; echo x > hosts",
+            "builtin cd /etc && rm hosts",
+            "pushd /etc >/dev/null && sed -i s/a/b/ hosts",
+            "cd ../../../../.. && touch x",
+            "cd \"$DIR\" && rm hosts",
+            "cd && rm .bashrc",
+            "cd - && rm hosts",
+            "popd; rm hosts",
+            "CDPATH=/ ; cd etc && rm hosts",
+            "if true; then cd /etc; fi; rm hosts",
+            "{ cd /etc; } && rm hosts",
+            "while cd /etc; do rm hosts; break; done",
+            "for d in /etc; do cd $d; rm hosts; done",
+            "f() { cd /etc; }; f; rm hosts",
+            "g() { rm hosts; }; cd /etc; g",
+            "eval cd /etc; rm hosts",
+            "c=cd; $c /etc; rm hosts",
+            "sh -c 'cd /etc && rm hosts'",
+            "env -C /etc rm hosts",
+            "sudo --chdir=/etc rm hosts",
+            "echo $(cd /etc && rm hosts)",
+        ];
+        for command in escapes {
+            assert!(!blocked_in(command, Some(&project)).is_empty(), "not blocked from session cwd: {:?}", command);
+        }
+        for command in ["cd /etc && rm hosts", "cd \"$DIR\" && rm hosts", "env -C /etc rm hosts"] {
+            assert!(!blocked_in(command, None).is_empty(), "not blocked from server cwd: {:?}", command);
+        }
+        assert!(blocked_in("cd \"$DIR\" && rm hosts", Some(&project))[0].contains("cannot verify"));
+
+        let safe = [
+            "cd sub && rm -rf build".to_string(),
+            "cd sub; touch notes.md".to_string(),
+            "(cd /etc && cat hosts) && touch out.txt".to_string(),
+            "cd /etc & touch out.txt".to_string(),
+            "echo $(cd /etc; pwd) > out.txt".to_string(),
+            "cd \"$DIR\" && cat a > /dev/null".to_string(),
+            format!("cd {}/sub && echo x > out.txt", project),
+            format!("env -C {} touch notes.md", project),
+        ];
+        for command in &safe {
+            assert!(blocked_in(command, Some(&project)).is_empty(), "{:?}: {:?}", command, blocked_in(command, Some(&project)));
+        }
+        assert!(blocked_in("rm hosts", None).is_empty());
+    }
+
     #[test]
     fn bash_allows_pipe_to_non_shell() {
         let config = default_config();